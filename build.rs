@@ -0,0 +1,38 @@
+//! Build script: records the git commit for the gateway's `/health` endpoint
+
+use std::path::Path;
+use std::process::Command;
+
+/// Trimmed stdout of `git <args>`, if it succeeds with any output
+fn git(args: &[&str]) -> Option<String> {
+    Command::new("git")
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|out| out.trim().to_string())
+        .filter(|out| !out.is_empty())
+}
+
+fn main() {
+    let sha = git(&["rev-parse", "--short", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=EMX_LLM_GIT_SHA={}", sha);
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // Ask git where HEAD and the refs live, so worktrees (where `.git` is a
+    // file) work too. A new commit moves HEAD itself when detached, a loose
+    // ref under refs/heads, or an entry of packed-refs.
+    if let Some(git_dir) = git(&["rev-parse", "--git-dir"]) {
+        let common_dir = git(&["rev-parse", "--git-common-dir"]).unwrap_or_else(|| git_dir.clone());
+        let watched = [
+            Path::new(&git_dir).join("HEAD"),
+            Path::new(&common_dir).join("refs").join("heads"),
+            Path::new(&common_dir).join("packed-refs"),
+        ];
+        // A missing path would make cargo rerun the script on every build
+        for path in watched.iter().filter(|path| path.exists()) {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+}
@@ -118,6 +118,43 @@ async fn validate_config(config: &GatewayConfig, config_file: Option<&str>) -> R
     println!("  Host: {}", config.host);
     println!("  Port: {}", config.port);
     println!("  Timeout: {}s", config.timeout_secs);
+    println!("  Max body size: {} bytes", config.max_body_size_bytes);
+    println!(
+        "  Idempotency TTL: {}s (up to {} responses)",
+        config.idempotency_ttl_secs, config.idempotency_max_entries
+    );
+    println!("  Shutdown drain: {}s", config.shutdown_drain_secs);
+    println!("  Metrics: {}", if config.metrics_enabled { "enabled (/metrics)" } else { "disabled" });
+    if config.sse_keepalive_secs > 0 {
+        println!("  SSE keep-alive: {}s", config.sse_keepalive_secs);
+    } else {
+        println!("  SSE keep-alive: disabled");
+    }
+    if config.coalesce_stream_ms > 0 {
+        println!("  Stream coalescing: {}ms", config.coalesce_stream_ms);
+    }
+    if config.strip_ping_events {
+        println!("  Ping events: stripped");
+    }
+    if !config.forward_response_headers.is_empty() {
+        println!("  Forwarded response headers: {}", config.forward_response_headers.join(", "));
+    }
+    for (provider, filter) in &config.field_filters {
+        println!("  Field filter ({}): allow [{}], deny [{}]", provider, filter.allow.join(", "), filter.deny.join(", "));
+    }
+    if !config.cors_allowed_origins.is_empty() {
+        println!("  CORS origins: {}", config.cors_allowed_origins.join(", "));
+    }
+    if !config.proxy_allowed_paths.is_empty() {
+        println!("  Proxy paths: {}", config.proxy_allowed_paths.join(", "));
+    }
+    if let Some(path) = &config.audit_log_path {
+        let contents = if config.audit_log_contents { "with contents" } else { "metadata only" };
+        println!("  Audit log: {} ({})", path.display(), contents);
+    }
+    if config.allow_mock_responses {
+        println!("  Mock responses: enabled (unconfigured models get fabricated answers)");
+    }
 
     // Validate port range
     if config.port < 1024 {
@@ -1,13 +1,28 @@
 //! Chat command implementation
 
-use std::io::{self, IsTerminal, Read, Write};
+use std::collections::HashMap;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
 use std::path::PathBuf;
 use std::time::Instant;
 
 use anyhow::{anyhow, Result};
-use emx_llm::{create_client, create_client_for_model, load_with_default, load_tools_from_dir, validate_session_name, ProviderConfig, Session, Usage, ToolCall};
+use emx_llm::{create_client, create_client_for_model, ChatResponse, load_with_default, load_tools_from_dir, validate_session_name, Message, ProviderConfig, ProviderType, Session, Usage, ToolCall, ToolDefinition};
 use futures::StreamExt;
 
+use crate::cli::StreamMode;
+
+/// Default for `--max-stdin-bytes` (1 MiB)
+pub const DEFAULT_MAX_STDIN_BYTES: usize = 1024 * 1024;
+
+/// `--endpoint` target: an API base used directly, without config.toml
+pub struct Endpoint {
+    pub url: String,
+    /// Environment variable holding the API key
+    pub api_key_env: Option<String>,
+    /// `openai` or `anthropic`; OpenAI when unset
+    pub provider: Option<String>,
+}
+
 /// Run the chat command
 #[allow(clippy::too_many_arguments)]
 pub async fn run(
@@ -15,33 +30,73 @@ pub async fn run(
     prompt: Option<String>,
     model: Option<String>,
     api_base: Option<String>,
-    stream: bool,
+    endpoint: Option<Endpoint>,
+    stream: StreamMode,
     no_stream: bool,
     system: Option<String>,
+    session_reset: bool,
     dry_run: bool,
     token_stats: bool,
     attach: Vec<PathBuf>,
     tools_dir: Option<PathBuf>,
     raw: bool,
+    vars: Vec<String>,
+    compare: Vec<String>,
+    format: Option<String>,
+    force_newline: bool,
+    show_thinking: bool,
+    repl: bool,
+    max_stdin_bytes: usize,
 ) -> Result<()> {
     // Step 1: Validate session name is safe (before creating any files)
     validate_session_name(&session_name)?;
+    let template_vars = parse_vars(&vars)?;
+    if session_reset {
+        Session::open(&session_name)?.reset()?;
+    }
+
+    let system_prompt = match system {
+        Some(value) => Some(render_template(&resolve_input_value(&value)?, &template_vars)),
+        None => None,
+    };
+
+    if repl {
+        let (client, model_id) = match &endpoint {
+            Some(endpoint) => endpoint_client(endpoint, model.as_deref())?,
+            None => resolve_client(model.as_deref(), api_base.as_deref())?,
+        };
+        return run_repl(
+            client.as_ref(),
+            &model_id,
+            &session_name,
+            system_prompt.as_deref(),
+            tools_dir.as_ref(),
+            raw,
+            show_thinking,
+        )
+        .await;
+    }
 
     // Step 2: Resolve and validate prompt (before creating any files)
-    let prompt_text = resolve_prompt(prompt)?;
+    let prompt_text = render_template(&resolve_prompt(prompt, max_stdin_bytes)?, &template_vars);
     if prompt_text.trim().is_empty() {
         return Err(anyhow!("prompt is empty; provide PROMPT or stdin content"));
     }
 
-    // Step 3: Now that prompt is validated, create the session
-    let (client, model_id) = resolve_client(model.as_deref(), api_base.as_deref())?;
+    if !compare.is_empty() {
+        // Comparing models is a dry run: nothing is written to the session
+        let session = Session::open(&session_name)?;
+        let messages = session.preview_conversation(system_prompt.as_deref(), prompt_text, &attach)?;
+        return run_compare(&compare, &messages, api_base.as_deref(), token_stats).await;
+    }
 
-    let mut session = Session::open(&session_name)?;
-    let system_prompt = match system {
-        Some(value) => Some(resolve_input_value(&value)?),
-        None => None,
+    // Step 3: Now that prompt is validated, create the session
+    let (client, model_id) = match &endpoint {
+        Some(endpoint) => endpoint_client(endpoint, model.as_deref())?,
+        None => resolve_client(model.as_deref(), api_base.as_deref())?,
     };
 
+    let mut session = Session::open(&session_name)?;
     session.ensure_system_prompt(system_prompt.as_deref())?;
 
     if dry_run {
@@ -65,59 +120,65 @@ pub async fn run(
         }
         println!();
         println!("Total: {} messages", messages.len());
+        let usage = session.usage();
+        if usage.total_tokens > 0 {
+            println!(
+                "Session Usage: prompt={} completion={} total={}",
+                usage.prompt_tokens, usage.completion_tokens, usage.total_tokens
+            );
+        }
         return Ok(());
     }
 
-    session.add_user_message(prompt_text, &attach)?;
-
     // Load tools from tools directory
     let tools = load_tools_from_dir(tools_dir.as_deref())?;
 
-    let messages = session.messages().to_vec();
-    let use_stream = stream || !no_stream;
+    let use_stream = !no_stream && stream.resolve(io::stdout().is_terminal());
+    let end_with_newline = wants_trailing_newline(format.as_deref(), io::stdout().is_terminal(), force_newline);
 
     if use_stream {
+        let started = Instant::now();
+        let total_usage = stream_reply(
+            client.as_ref(),
+            &mut session,
+            Some((prompt_text, attach.as_slice())),
+            &model_id,
+            &tools,
+            tools_dir.as_ref(),
+            raw,
+            show_thinking,
+            end_with_newline,
+        )
+        .await?;
+
+        if token_stats {
+            println!();
+            println!("=== Token Stats ===");
+            println!("Prompt tokens: {}", total_usage.prompt_tokens);
+            println!("Completion tokens: {}", total_usage.completion_tokens);
+            println!("Total tokens: {}", total_usage.total_tokens);
+            println!("Session total tokens: {}", session.usage().total_tokens);
+            println!("Duration (ms): {}", started.elapsed().as_millis());
+        }
+    } else {
+        // Non-streaming mode with tool call loop
         let started = Instant::now();
         let tools_ref = if tools.is_empty() { None } else { Some(tools.as_slice()) };
-        let mut total_usage = Usage { prompt_tokens: 0, completion_tokens: 0, total_tokens: 0 };
-        let mut current_messages = messages;
+        let mut total_usage = Usage::default();
+        let mut current_messages = session.preview_user_message(prompt_text.clone(), &attach)?;
+        let mut pending_prompt = Some(prompt_text);
 
         const MAX_TOOL_ROUNDS: usize = 10;
         for _round in 0..MAX_TOOL_ROUNDS {
-            let mut response_stream = client.chat_stream(&current_messages, &model_id, tools_ref);
-            let mut full_response = String::new();
-            let mut round_usage: Option<Usage> = None;
-            let mut round_tool_calls: Option<Vec<ToolCall>> = None;
-
-            while let Some(event) = response_stream.next().await {
-                match event {
-                    Ok(event) => {
-                        print!("{}", event.delta);
-                        io::stdout().flush()?;
-                        full_response.push_str(&event.delta);
-                        if event.done {
-                            round_usage = event.usage;
-                            round_tool_calls = event.tool_calls;
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Stream error: {}", e);
-                        break;
-                    }
-                }
+            let ChatResponse { content: response, tool_calls, usage, .. } =
+                client.chat(&current_messages, &model_id, tools_ref).await?;
+            total_usage.merge(&usage);
+            if let Some(prompt_text) = pending_prompt.take() {
+                session.add_user_message(prompt_text, &attach)?;
             }
 
-            let usage = round_usage.unwrap_or(Usage {
-                prompt_tokens: 0,
-                completion_tokens: 0,
-                total_tokens: 0,
-            });
-            total_usage.prompt_tokens += usage.prompt_tokens;
-            total_usage.completion_tokens += usage.completion_tokens;
-            total_usage.total_tokens += usage.total_tokens;
-
-            if let Some(calls) = round_tool_calls {
-                println!("\n[Tool Calls: {}]", calls.len());
+            if let Some(calls) = tool_calls {
+                println!("[Tool Calls: {}]", calls.len());
                 for (i, call) in calls.iter().enumerate() {
                     println!("  [{}] {}: {}", i + 1, call.name, call.arguments);
                 }
@@ -150,14 +211,14 @@ pub async fn run(
             }
 
             // No tool calls — final text response
-            if !full_response.is_empty() {
-                session.add_assistant_response(
-                    full_response,
-                    &model_id,
-                    &usage,
-                    Some(started.elapsed().as_millis()),
-                )?;
-            }
+            println!("{}", response);
+
+            session.add_assistant_response(
+                response,
+                &model_id,
+                &usage,
+                Some(started.elapsed().as_millis()),
+            )?;
 
             if token_stats {
                 println!();
@@ -165,76 +226,283 @@ pub async fn run(
                 println!("Prompt tokens: {}", total_usage.prompt_tokens);
                 println!("Completion tokens: {}", total_usage.completion_tokens);
                 println!("Total tokens: {}", total_usage.total_tokens);
+                println!("Session total tokens: {}", session.usage().total_tokens);
                 println!("Duration (ms): {}", started.elapsed().as_millis());
             }
             break;
         }
-    } else {
-        // Non-streaming mode with tool call loop
-        let started = Instant::now();
-        let tools_ref = if tools.is_empty() { None } else { Some(tools.as_slice()) };
-        let mut total_usage = Usage { prompt_tokens: 0, completion_tokens: 0, total_tokens: 0 };
-        let mut current_messages = messages;
+    }
 
-        const MAX_TOOL_ROUNDS: usize = 10;
-        for _round in 0..MAX_TOOL_ROUNDS {
-            let (response, tool_calls, usage) = client.chat(&current_messages, &model_id, tools_ref).await?;
-            total_usage.prompt_tokens += usage.prompt_tokens;
-            total_usage.completion_tokens += usage.completion_tokens;
-            total_usage.total_tokens += usage.total_tokens;
+    Ok(())
+}
 
-            if let Some(calls) = tool_calls {
-                println!("[Tool Calls: {}]", calls.len());
-                for (i, call) in calls.iter().enumerate() {
-                    println!("  [{}] {}: {}", i + 1, call.name, call.arguments);
+/// Stream the assistant's reply to the session's conversation, running tool
+/// calls until the model answers in text
+///
+/// `prompt` (text and attachments) is sent after the session history and
+/// recorded together with the reply, so a failed stream leaves no unanswered
+/// user turn behind. The reply and any tool traffic are recorded in the
+/// session. Returns the usage summed over all rounds.
+#[allow(clippy::too_many_arguments)]
+async fn stream_reply(
+    client: &dyn emx_llm::Client,
+    session: &mut Session,
+    prompt: Option<(String, &[PathBuf])>,
+    model_id: &str,
+    tools: &[ToolDefinition],
+    tools_dir: Option<&PathBuf>,
+    raw: bool,
+    show_thinking: bool,
+    end_with_newline: bool,
+) -> Result<Usage> {
+    let started = Instant::now();
+    let tools_ref = if tools.is_empty() { None } else { Some(tools) };
+    let mut total_usage = Usage::default();
+    let mut current_messages = match &prompt {
+        Some((text, attachments)) => session.preview_user_message(text.clone(), attachments)?,
+        None => session.messages().to_vec(),
+    };
+    let mut pending_prompt = prompt;
+
+    const MAX_TOOL_ROUNDS: usize = 10;
+    for _round in 0..MAX_TOOL_ROUNDS {
+        let mut response_stream = client.chat_stream(&current_messages, model_id, tools_ref);
+        let mut full_response = String::new();
+        let mut round_usage: Option<Usage> = None;
+        let mut round_tool_calls: Option<Vec<ToolCall>> = None;
+        let mut thinking = ThinkingOutput::new(show_thinking);
+        let mut failed = false;
+
+        while let Some(event) = response_stream.next().await {
+            match event {
+                Ok(event) => {
+                    if let Some(reasoning) = &event.reasoning {
+                        thinking.write(reasoning)?;
+                    }
+                    if !event.delta.is_empty() {
+                        thinking.finish()?;
+                    }
+                    print!("{}", event.delta);
+                    io::stdout().flush()?;
+                    full_response.push_str(&event.delta);
+                    if event.done {
+                        round_usage = event.usage;
+                        round_tool_calls = event.tool_calls;
+                    }
                 }
+                Err(e) => {
+                    thinking.finish()?;
+                    eprintln!("Stream error: {}", e);
+                    failed = true;
+                    break;
+                }
+            }
+        }
+        thinking.finish()?;
+        // Nothing of a failed round is recorded
+        if failed {
+            break;
+        }
+        if let Some((text, attachments)) = pending_prompt.take() {
+            session.add_user_message(text, attachments)?;
+        }
 
-                session.add_assistant_tool_calls(
-                    calls.clone(),
-                    &model_id,
-                    &usage,
-                    Some(started.elapsed().as_millis()),
-                )?;
+        let usage = round_usage.unwrap_or_default();
+        total_usage.merge(&usage);
 
-                for call in &calls {
-                    let result = match execute_tool_call(call, tools_dir.as_ref()) {
-                        Ok(r) => r,
-                        Err(e) => {
-                            // Return error message to LLM instead of crashing
-                            format!("Error: {}", e)
-                        }
-                    };
-                    if raw {
-                        println!("\n[Tool Result: {}]\n{}", call.name, result);
-                    } else {
-                        println!("[Executed: {}]", call.name);
+        if let Some(calls) = round_tool_calls {
+            println!("\n[Tool Calls: {}]", calls.len());
+            for (i, call) in calls.iter().enumerate() {
+                println!("  [{}] {}: {}", i + 1, call.name, call.arguments);
+            }
+
+            session.add_assistant_tool_calls(
+                calls.clone(),
+                model_id,
+                &usage,
+                Some(started.elapsed().as_millis()),
+            )?;
+
+            for call in &calls {
+                let result = match execute_tool_call(call, tools_dir) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        // Return error message to LLM instead of crashing
+                        format!("Error: {}", e)
                     }
-                    session.add_tool_result(call.id.clone(), result)?;
+                };
+                if raw {
+                    println!("\n[Tool Result: {}]\n{}", call.name, result);
+                } else {
+                    println!("[Executed: {}]", call.name);
                 }
-
-                current_messages = session.messages().to_vec();
-                continue; // Next round
+                session.add_tool_result(call.id.clone(), result)?;
             }
 
-            // No tool calls — final text response
-            println!("{}", response);
+            current_messages = session.messages().to_vec();
+            continue; // Next round
+        }
 
+        // No tool calls — final text response
+        if !full_response.is_empty() {
+            if end_with_newline {
+                println!();
+            }
             session.add_assistant_response(
-                response,
-                &model_id,
+                full_response,
+                model_id,
                 &usage,
                 Some(started.elapsed().as_millis()),
             )?;
+        }
+        break;
+    }
 
-            if token_stats {
-                println!();
-                println!("=== Token Stats ===");
-                println!("Prompt tokens: {}", total_usage.prompt_tokens);
-                println!("Completion tokens: {}", total_usage.completion_tokens);
-                println!("Total tokens: {}", total_usage.total_tokens);
-                println!("Duration (ms): {}", started.elapsed().as_millis());
+    Ok(total_usage)
+}
+
+/// A line typed at the `--repl` prompt
+#[derive(Debug, PartialEq)]
+enum ReplInput<'a> {
+    Exit,
+    Reset,
+    System(&'a str),
+    Tokens,
+    Prompt(&'a str),
+}
+
+impl<'a> ReplInput<'a> {
+    fn parse(line: &'a str) -> Result<Self> {
+        let line = line.trim();
+        let Some(command) = line.strip_prefix('/') else {
+            return Ok(Self::Prompt(line));
+        };
+        let (name, arg) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+        match (name, arg.trim()) {
+            ("exit", _) => Ok(Self::Exit),
+            ("reset", _) => Ok(Self::Reset),
+            ("system", "") => Err(anyhow!("/system needs the prompt text")),
+            ("system", text) => Ok(Self::System(text)),
+            ("tokens", _) => Ok(Self::Tokens),
+            _ => Err(anyhow!("unknown command '/{}' (try /exit, /reset, /system <text>, /tokens)", name)),
+        }
+    }
+}
+
+/// Interactive chat: each line read from stdin is sent as a user turn of the
+/// session and the reply is streamed back, until EOF or `/exit`
+///
+/// `/reset` starts the conversation over, `/system <text>` replaces the
+/// system prompt while keeping the conversation, and `/tokens` prints the
+/// usage so far.
+async fn run_repl(
+    client: &dyn emx_llm::Client,
+    model_id: &str,
+    session_name: &str,
+    system_prompt: Option<&str>,
+    tools_dir: Option<&PathBuf>,
+    raw: bool,
+    show_thinking: bool,
+) -> Result<()> {
+    let mut session = Session::open(session_name)?;
+    session.ensure_system_prompt(system_prompt)?;
+    let tools = load_tools_from_dir(tools_dir.map(|p| p.as_path()))?;
+
+    let interactive = io::stdin().is_terminal();
+    let mut total_usage = Usage::default();
+    let mut lines = io::stdin().lock().lines();
+
+    loop {
+        if interactive {
+            print!("> ");
+            io::stdout().flush()?;
+        }
+        let Some(line) = lines.next() else { break };
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match ReplInput::parse(&line) {
+            Ok(ReplInput::Exit) => break,
+            Ok(ReplInput::Reset) => {
+                session.reset()?;
+                session.ensure_system_prompt(system_prompt)?;
+                eprintln!("[conversation reset]");
             }
-            break;
+            Ok(ReplInput::System(text)) => {
+                session.set_system_prompt(text)?;
+                eprintln!("[system prompt replaced]");
+            }
+            Ok(ReplInput::Tokens) => {
+                println!(
+                    "Tokens: prompt={} completion={} total={}",
+                    total_usage.prompt_tokens, total_usage.completion_tokens, total_usage.total_tokens
+                );
+            }
+            Ok(ReplInput::Prompt(text)) => {
+                let usage = stream_reply(
+                    client,
+                    &mut session,
+                    Some((text.to_string(), &[])),
+                    model_id,
+                    &tools,
+                    tools_dir,
+                    raw,
+                    show_thinking,
+                    true,
+                )
+                .await?;
+                total_usage += usage;
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    if interactive {
+        println!();
+    }
+    Ok(())
+}
+
+/// Send the same conversation to each model and print the answers in turn.
+///
+/// Model refs may include `@group` entries from `[llm.group]`. Responses are
+/// not written to the session, since there is no single answer to keep.
+async fn run_compare(
+    model_refs: &[String],
+    messages: &[Message],
+    api_base: Option<&str>,
+    token_stats: bool,
+) -> Result<()> {
+    let model_refs = ProviderConfig::expand_model_refs(model_refs)?;
+
+    for (i, model_ref) in model_refs.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("=== {} ===", model_ref);
+
+        let result = match resolve_client(Some(model_ref), api_base) {
+            Ok((client, model_id)) => client
+                .chat(messages, &model_id, None)
+                .await
+                .map_err(anyhow::Error::from),
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(response) => {
+                println!("{}", response.content);
+                if token_stats {
+                    println!("[tokens: {} prompt, {} completion, {} ms]",
+                        response.usage.prompt_tokens,
+                        response.usage.completion_tokens,
+                        response.total_latency.as_millis());
+                }
+            }
+            // One failing model should not hide the others' answers
+            Err(e) => eprintln!("Error: {}", e),
         }
     }
 
@@ -249,12 +517,8 @@ fn resolve_client(
         if let Some(api_base) = api_base_override {
             let (model_config, model_id) = ProviderConfig::load_for_model(model_ref)?;
             let client = create_client(ProviderConfig {
-                provider_type: model_config.provider_type,
                 api_base: api_base.to_string(),
-                api_key: model_config.api_key,
-                model: Some(model_id.clone()),
-                max_tokens: model_config.max_tokens,
-                timeout_secs: None,
+                ..model_config.into_provider_config(&model_id)
             })?;
             return Ok((client, model_id));
         }
@@ -276,7 +540,88 @@ fn resolve_client(
     Ok((client, model_id))
 }
 
-fn resolve_prompt(prompt: Option<String>) -> Result<String> {
+/// Whether streamed output should be terminated with a newline.
+///
+/// Only a person at a terminal wants one; piped output and machine-readable
+/// formats must stay byte-exact. `--force-newline` opts back in when piping,
+/// but never for json/ndjson.
+fn wants_trailing_newline(format: Option<&str>, stdout_is_tty: bool, force: bool) -> bool {
+    if matches!(format, Some("json") | Some("ndjson")) {
+        return false;
+    }
+    stdout_is_tty || force
+}
+
+/// Reasoning deltas shown on stderr for `--show-thinking`
+///
+/// stdout stays just the answer. On a terminal the reasoning is dimmed, and
+/// the block is closed with a newline once the answer starts.
+struct ThinkingOutput {
+    enabled: bool,
+    dim: bool,
+    open: bool,
+}
+
+impl ThinkingOutput {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            dim: io::stderr().is_terminal(),
+            open: false,
+        }
+    }
+
+    fn write(&mut self, reasoning: &str) -> io::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let mut stderr = io::stderr().lock();
+        if !self.open && self.dim {
+            write!(stderr, "\x1b[2m")?;
+        }
+        self.open = true;
+        write!(stderr, "{}", reasoning)?;
+        stderr.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if !self.open {
+            return Ok(());
+        }
+        self.open = false;
+        let mut stderr = io::stderr().lock();
+        if self.dim {
+            write!(stderr, "\x1b[0m")?;
+        }
+        writeln!(stderr)?;
+        stderr.flush()
+    }
+}
+
+/// Build a client for `--endpoint` straight from the CLI arguments
+fn endpoint_client(endpoint: &Endpoint, model: Option<&str>) -> Result<(Box<dyn emx_llm::Client>, String)> {
+    let model_id = model.ok_or_else(|| anyhow!("--endpoint requires --model"))?.to_string();
+    let provider_type = match endpoint.provider.as_deref() {
+        None | Some("openai") => ProviderType::OpenAI,
+        Some("anthropic") => ProviderType::Anthropic,
+        Some(other) => return Err(anyhow!("unknown provider '{}': expected openai or anthropic", other)),
+    };
+    let api_key = match &endpoint.api_key_env {
+        Some(var) => std::env::var(var).map_err(|_| anyhow!("--api-key-env: environment variable {} is not set", var))?,
+        None => String::new(),
+    };
+
+    let client = create_client(ProviderConfig {
+        provider_type,
+        api_base: endpoint.url.clone(),
+        api_key,
+        model: Some(model_id.clone()),
+        ..Default::default()
+    })?;
+    Ok((client, model_id))
+}
+
+fn resolve_prompt(prompt: Option<String>, max_stdin_bytes: usize) -> Result<String> {
     match prompt {
         Some(value) => resolve_input_value(&value),
         None => {
@@ -294,14 +639,26 @@ fn resolve_prompt(prompt: Option<String>) -> Result<String> {
                 ));
             }
             // Stdin is piped/redirected, read from it
-            let stdin = io::stdin();
-            let mut buffer = String::new();
-            stdin.lock().read_to_string(&mut buffer)?;
-            Ok(buffer.trim().to_string())
+            let prompt = read_limited(io::stdin().lock(), max_stdin_bytes)?;
+            Ok(prompt.trim().to_string())
         }
     }
 }
 
+/// Read all of `reader` as UTF-8, failing once more than `max_bytes` arrive
+/// instead of buffering an unbounded pipe
+fn read_limited(reader: impl Read, max_bytes: usize) -> Result<String> {
+    let mut buffer = Vec::new();
+    reader.take(max_bytes as u64 + 1).read_to_end(&mut buffer)?;
+    if buffer.len() > max_bytes {
+        return Err(anyhow!(
+            "stdin input exceeds {} bytes; pass a larger --max-stdin-bytes or use PROMPT as @file",
+            max_bytes
+        ));
+    }
+    String::from_utf8(buffer).map_err(|_| anyhow!("stdin input is not valid UTF-8"))
+}
+
 fn resolve_input_value(value: &str) -> Result<String> {
     if let Some(path) = value.strip_prefix('@') {
         return Ok(std::fs::read_to_string(path)?);
@@ -309,6 +666,48 @@ fn resolve_input_value(value: &str) -> Result<String> {
     Ok(value.to_string())
 }
 
+/// Parse `--var name=value` flags into a substitution map
+fn parse_vars(vars: &[String]) -> Result<HashMap<String, String>> {
+    let mut map = HashMap::new();
+    for var in vars {
+        let (name, value) = var
+            .split_once('=')
+            .ok_or_else(|| anyhow!("invalid --var '{}': expected NAME=VALUE", var))?;
+        map.insert(name.trim().to_string(), value.to_string());
+    }
+    Ok(map)
+}
+
+/// Substitute `{{name}}` placeholders; unknown placeholders are left untouched
+fn render_template(content: &str, vars: &HashMap<String, String>) -> String {
+    if vars.is_empty() {
+        return content.to_string();
+    }
+
+    let mut output = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                let name = after[..end].trim();
+                match vars.get(name) {
+                    Some(value) => output.push_str(value),
+                    None => output.push_str(&rest[start..start + 2 + end + 2]),
+                }
+                rest = &after[end + 2..];
+            }
+            None => {
+                output.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
 /// Execute tool calls by calling TCL scripts
 fn execute_tool_call(tool_call: &ToolCall, tools_dir: Option<&PathBuf>) -> Result<String> {
     let args_json: serde_json::Value = serde_json::from_str(&tool_call.arguments)
@@ -317,3 +716,69 @@ fn execute_tool_call(tool_call: &ToolCall, tools_dir: Option<&PathBuf>) -> Resul
     let dir_str = tools_dir.and_then(|p| p.to_str());
     super::tools::call_tool_json(&tool_call.name, &args_json, dir_str)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_template_substitutes_vars() {
+        let vars = parse_vars(&["lang=Rust".to_string(), "topic=lifetimes".to_string()]).unwrap();
+        assert_eq!(
+            render_template("Explain {{topic}} in {{ lang }}.", &vars),
+            "Explain lifetimes in Rust."
+        );
+    }
+
+    #[test]
+    fn render_template_leaves_unknown_placeholders() {
+        let vars = parse_vars(&["lang=Rust".to_string()]).unwrap();
+        assert_eq!(
+            render_template("{{lang}} vs {{other}} and {{unclosed", &vars),
+            "Rust vs {{other}} and {{unclosed"
+        );
+    }
+
+    #[test]
+    fn read_limited_rejects_oversized_stdin() {
+        let input = "x".repeat(DEFAULT_MAX_STDIN_BYTES + 1);
+        let err = read_limited(input.as_bytes(), DEFAULT_MAX_STDIN_BYTES).unwrap_err();
+        assert!(err.to_string().contains("exceeds 1048576 bytes"));
+
+        let input = "x".repeat(DEFAULT_MAX_STDIN_BYTES);
+        assert_eq!(read_limited(input.as_bytes(), DEFAULT_MAX_STDIN_BYTES).unwrap().len(), DEFAULT_MAX_STDIN_BYTES);
+    }
+
+    #[test]
+    fn parse_vars_rejects_missing_equals() {
+        let err = parse_vars(&["lang".to_string()]).unwrap_err();
+        assert!(err.to_string().contains("expected NAME=VALUE"));
+    }
+
+    #[test]
+    fn trailing_newline_only_for_terminals_or_when_forced() {
+        assert!(wants_trailing_newline(None, true, false));
+        assert!(wants_trailing_newline(Some("md"), true, false));
+        assert!(!wants_trailing_newline(None, false, false));
+        assert!(wants_trailing_newline(None, false, true));
+        assert!(!wants_trailing_newline(Some("json"), true, true));
+        assert!(!wants_trailing_newline(Some("ndjson"), false, true));
+    }
+
+    #[test]
+    fn repl_input_parses_commands_and_prompts() {
+        assert_eq!(ReplInput::parse("/exit").unwrap(), ReplInput::Exit);
+        assert_eq!(ReplInput::parse(" /reset ").unwrap(), ReplInput::Reset);
+        assert_eq!(ReplInput::parse("/system  Be terse.").unwrap(), ReplInput::System("Be terse."));
+        assert_eq!(ReplInput::parse("/tokens").unwrap(), ReplInput::Tokens);
+        assert_eq!(ReplInput::parse("What is 2+2?").unwrap(), ReplInput::Prompt("What is 2+2?"));
+        assert!(ReplInput::parse("/system").is_err());
+        assert!(ReplInput::parse("/quit").is_err());
+    }
+
+    #[test]
+    fn parse_vars_keeps_equals_in_value() {
+        let vars = parse_vars(&["expr=a=b".to_string()]).unwrap();
+        assert_eq!(vars["expr"], "a=b");
+    }
+}
@@ -2,16 +2,63 @@
 
 use std::path::PathBuf;
 
-use clap::{ArgAction, Parser, Subcommand};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(name = "emx-llm")]
 #[command(about = "LLM client for EMX with txtar support", long_about = None)]
 pub struct Cli {
+    /// Default output format for commands that support it: text, json, md
+    /// (falls back to EMX_LLM_FORMAT, then md)
+    #[arg(long)]
+    pub format: Option<String>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
+/// Environment variable providing the default output format
+pub const FORMAT_ENV: &str = "EMX_LLM_FORMAT";
+
+/// Default output format when nothing else is specified
+pub const DEFAULT_FORMAT: &str = "md";
+
+/// Resolve the effective output format.
+///
+/// Precedence: per-command `--format` > global `--format` > `EMX_LLM_FORMAT` > `md`.
+pub fn resolve_format(
+    command_format: Option<String>,
+    global_format: Option<String>,
+    env_format: Option<String>,
+) -> String {
+    command_format
+        .or(global_format)
+        .or(env_format.filter(|v| !v.trim().is_empty()))
+        .unwrap_or_else(|| DEFAULT_FORMAT.to_string())
+}
+
+/// When `chat` streams its answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StreamMode {
+    /// Stream only when stdout is a terminal; buffer when piped
+    Auto,
+    /// Always stream
+    On,
+    /// Never stream
+    Off,
+}
+
+impl StreamMode {
+    /// Whether to stream, given whether stdout is a terminal
+    pub fn resolve(self, stdout_is_tty: bool) -> bool {
+        match self {
+            StreamMode::Auto => stdout_is_tty,
+            StreamMode::On => true,
+            StreamMode::Off => false,
+        }
+    }
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// Send a chat completion request
@@ -30,18 +77,44 @@ pub enum Commands {
         #[arg(long)]
         api_base: Option<String>,
 
-        /// Enable streaming output
-        #[arg(long, action = ArgAction::SetTrue, conflicts_with = "no_stream")]
-        stream: bool,
-
-        /// Disable streaming output
-        #[arg(long = "no-stream", action = ArgAction::SetTrue, conflicts_with = "stream")]
+        /// Call this API base directly without reading config.toml (requires --model)
+        #[arg(long, value_name = "URL", requires = "model", conflicts_with_all = ["api_base", "compare"])]
+        endpoint: Option<String>,
+
+        /// Environment variable holding the API key for --endpoint
+        #[arg(long, value_name = "VAR", requires = "endpoint")]
+        api_key_env: Option<String>,
+
+        /// Wire format of --endpoint (default: openai)
+        #[arg(short, long, value_parser = ["openai", "anthropic"], requires = "endpoint")]
+        provider: Option<String>,
+
+        /// Stream output: auto (only when stdout is a terminal), on, or off;
+        /// a bare --stream means on
+        #[arg(
+            long,
+            value_enum,
+            value_name = "WHEN",
+            num_args = 0..=1,
+            default_value = "auto",
+            default_missing_value = "on",
+            require_equals = true,
+            conflicts_with = "no_stream"
+        )]
+        stream: StreamMode,
+
+        /// Disable streaming output (same as --stream=off)
+        #[arg(long = "no-stream", action = ArgAction::SetTrue)]
         no_stream: bool,
 
         /// System prompt text, or @file path (only effective for new session)
         #[arg(short = 's', long)]
         system: Option<String>,
 
+        /// Forget the session's earlier turns before this one
+        #[arg(long)]
+        session_reset: bool,
+
         /// Enable dry run mode (output prompt without sending to API)
         #[arg(long)]
         dry_run: bool,
@@ -61,6 +134,31 @@ pub enum Commands {
         /// Show raw API response (for debugging tool calls)
         #[arg(long)]
         raw: bool,
+
+        /// Template variable for {{name}} placeholders in prompt/system (repeatable, name=value)
+        #[arg(long = "var", value_name = "NAME=VALUE")]
+        vars: Vec<String>,
+
+        /// Send the prompt to several models and print each answer (comma-separated refs or @group)
+        #[arg(long, value_name = "MODELS", value_delimiter = ',', conflicts_with = "model")]
+        compare: Vec<String>,
+
+        /// End streamed output with a newline even when stdout is not a terminal
+        /// (ignored for json/ndjson output)
+        #[arg(long)]
+        force_newline: bool,
+
+        /// Print streamed reasoning ("thinking") to stderr, keeping stdout for the answer
+        #[arg(long)]
+        show_thinking: bool,
+
+        /// Chat interactively, one prompt per line (/exit, /reset, /system <text>, /tokens)
+        #[arg(long, conflicts_with_all = ["prompt", "compare", "dry_run", "no_stream"])]
+        repl: bool,
+
+        /// Largest prompt accepted from stdin, in bytes
+        #[arg(long, value_name = "BYTES", default_value_t = crate::chat::DEFAULT_MAX_STDIN_BYTES)]
+        max_stdin_bytes: usize,
     },
 
     /// Test configuration and API key
@@ -68,23 +166,47 @@ pub enum Commands {
         /// Provider type (openai or anthropic)
         #[arg(short, long, default_value = "openai")]
         provider: String,
+
+        /// Skip the API host reachability check
+        #[arg(long)]
+        offline: bool,
     },
 
+    /// Show the version, supported providers and the active config file
+    Info,
+
     /// Collect environment context for LLM inference
     Env {
-        /// Output format: text, json, md (default: md)
-        #[arg(long, default_value = "md")]
-        format: String,
+        /// Output format: text, json, md (default: global --format, then md)
+        #[arg(long)]
+        format: Option<String>,
 
         /// Include directory listing
         #[arg(short, long)]
         files: bool,
 
+        /// Show a recursive tree instead of the flat listing, down to DEPTH levels (default: 3)
+        #[arg(long, value_name = "DEPTH", num_args = 0..=1, default_missing_value = "3")]
+        tree: Option<usize>,
+
+        /// Skip paths matched by .gitignore in the file listing
+        /// (default: on inside a git repo; pass `--respect-gitignore=false` to disable)
+        #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+        respect_gitignore: Option<bool>,
+
         /// Include git status (if in a git repo)
         #[arg(short, long)]
         git: bool,
 
-        /// Include environment variables (safe ones only)
+        /// Include unstaged and staged `git diff` output in the git section (implies --git)
+        #[arg(long)]
+        diff: bool,
+
+        /// Byte budget for each diff before it is truncated
+        #[arg(long, value_name = "BYTES", default_value_t = 4096)]
+        diff_budget: usize,
+
+        /// Include environment variables (safe ones only, plus [llm.env] safe_vars)
         #[arg(short, long)]
         env_vars: bool,
 
@@ -104,13 +226,29 @@ pub enum Commands {
         #[arg(long)]
         ctime: bool,
 
+        /// Maximum entries in the file listing (per kind) or the tree (in total)
+        #[arg(long, value_name = "N", default_value_t = crate::env::DEFAULT_MAX_ITEMS)]
+        max_items: usize,
+
+        /// Order of the file listing: name (alphabetical), size or mtime (largest/newest first)
+        #[arg(long, value_parser = ["name", "size", "mtime"], default_value = "name")]
+        sort: String,
+
         /// Show all file metadata (shorthand for --size --mtime --ctime)
         #[arg(long)]
         full: bool,
 
-        /// Show ALL environment variables (includes sensitive ones, full PATH)
+        /// Show ALL environment variables (full PATH; [llm.env] redact patterns still masked)
         #[arg(short, long)]
         verbose: bool,
+
+        /// Print one `key=value` line (os, shell, entry counts, git branch) instead of the report
+        #[arg(long)]
+        concise: bool,
+
+        /// Seconds before a hung `git` call is abandoned
+        #[arg(long, value_name = "SECS", default_value_t = crate::subprocess::DEFAULT_TIMEOUT_SECS)]
+        command_timeout: u64,
     },
 
     /// Detect development environment (tools, versions, profiles)
@@ -119,9 +257,13 @@ pub enum Commands {
         #[arg(short, long)]
         all: bool,
 
-        /// Output format: text, json, md (default: md)
-        #[arg(long, default_value = "md")]
-        format: String,
+        /// Output format: text, json, md (default: global --format, then md)
+        #[arg(long)]
+        format: Option<String>,
+
+        /// Seconds before a hung tool version command is abandoned
+        #[arg(long, value_name = "SECS", default_value_t = crate::subprocess::DEFAULT_TIMEOUT_SECS)]
+        command_timeout: u64,
     },
 
     /// Manage and call TCL tools
@@ -149,3 +291,104 @@ pub enum Commands {
         args: Vec<String>,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_format_applies_to_env_command() {
+        let cli = Cli::try_parse_from(["emx-llm", "--format", "json", "env"]).unwrap();
+        let Commands::Env { format, .. } = cli.command else {
+            panic!("expected env command");
+        };
+        assert_eq!(resolve_format(format, cli.format, None), "json");
+    }
+
+    #[test]
+    fn command_format_overrides_global_and_env() {
+        let cli = Cli::try_parse_from(["emx-llm", "--format", "json", "env", "--format", "text"]).unwrap();
+        let Commands::Env { format, .. } = cli.command else {
+            panic!("expected env command");
+        };
+        assert_eq!(resolve_format(format, cli.format, Some("md".to_string())), "text");
+    }
+
+    #[test]
+    fn env_format_used_when_no_flags() {
+        assert_eq!(resolve_format(None, None, Some("json".to_string())), "json");
+        assert_eq!(resolve_format(None, None, None), DEFAULT_FORMAT);
+    }
+
+    #[test]
+    fn chat_compare_accepts_groups_and_model_lists() {
+        let cli = Cli::try_parse_from([
+            "emx-llm", "chat", "review", "Hello", "--compare", "@frontier,glm-5",
+        ])
+        .unwrap();
+        let Commands::Chat { compare, model, .. } = cli.command else {
+            panic!("expected chat command");
+        };
+        assert_eq!(compare, vec!["@frontier", "glm-5"]);
+        assert!(model.is_none());
+
+        let conflict = Cli::try_parse_from([
+            "emx-llm", "chat", "review", "Hello", "--compare", "@frontier", "-m", "gpt-4",
+        ]);
+        assert!(conflict.is_err());
+    }
+
+    #[test]
+    fn chat_endpoint_requires_model_and_gates_its_options() {
+        let cli = Cli::try_parse_from([
+            "emx-llm", "chat", "s", "Hi", "--endpoint", "http://host/v1", "-m", "m",
+            "--api-key-env", "MY_KEY", "-p", "anthropic",
+        ])
+        .unwrap();
+        let Commands::Chat { endpoint, api_key_env, provider, .. } = cli.command else {
+            panic!("expected chat command");
+        };
+        assert_eq!(endpoint.as_deref(), Some("http://host/v1"));
+        assert_eq!(api_key_env.as_deref(), Some("MY_KEY"));
+        assert_eq!(provider.as_deref(), Some("anthropic"));
+
+        assert!(Cli::try_parse_from(["emx-llm", "chat", "s", "Hi", "--endpoint", "http://host/v1"]).is_err());
+        assert!(Cli::try_parse_from(["emx-llm", "chat", "s", "Hi", "-m", "m", "--api-key-env", "MY_KEY"]).is_err());
+    }
+
+    #[test]
+    fn chat_repl_takes_no_prompt() {
+        let cli = Cli::try_parse_from(["emx-llm", "chat", "s", "--repl"]).unwrap();
+        let Commands::Chat { repl, prompt, .. } = cli.command else {
+            panic!("expected chat command");
+        };
+        assert!(repl);
+        assert!(prompt.is_none());
+
+        assert!(Cli::try_parse_from(["emx-llm", "chat", "s", "Hi", "--repl"]).is_err());
+        assert!(Cli::try_parse_from(["emx-llm", "chat", "s", "--repl", "--no-stream"]).is_err());
+    }
+
+    #[test]
+    fn chat_stream_mode_defaults_to_auto() {
+        let stream_mode = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["emx-llm", "chat", "s", "Hi"].iter().chain(args)).unwrap();
+            let Commands::Chat { stream, .. } = cli.command else {
+                panic!("expected chat command");
+            };
+            stream
+        };
+        assert_eq!(stream_mode(&[]), StreamMode::Auto);
+        assert_eq!(stream_mode(&["--stream"]), StreamMode::On);
+        assert_eq!(stream_mode(&["--stream=off"]), StreamMode::Off);
+        assert!(Cli::try_parse_from(["emx-llm", "chat", "s", "Hi", "--stream", "--no-stream"]).is_err());
+    }
+
+    #[test]
+    fn stream_mode_auto_follows_the_terminal() {
+        assert!(StreamMode::Auto.resolve(true));
+        assert!(!StreamMode::Auto.resolve(false));
+        assert!(StreamMode::On.resolve(false));
+        assert!(!StreamMode::Off.resolve(true));
+    }
+}
@@ -1,29 +1,92 @@
 //! Dev command implementation - detect development environment profiles
 
 use anyhow::Result;
+use emx_llm::{DevConfig, DevProfile, DevTool};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
+use std::time::Duration;
 
-/// A development profile definition
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DevProfile {
+use crate::subprocess::output_with_timeout;
+
+/// What was found for one detected profile
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileReport {
     /// Profile name
     pub name: String,
-    /// Files to detect (any one triggers the profile)
-    pub detect: Vec<String>,
-    /// Tools to check versions
-    pub tools: Vec<ToolDef>,
-    /// Environment variables to show
-    pub env_vars: Vec<String>,
+    /// Tools that reported a version
+    pub tools: Vec<ToolVersion>,
+    /// Profile environment variables that are set
+    pub env: Vec<EnvValue>,
 }
 
-/// Tool definition for version checking
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ToolDef {
-    /// Tool name (for display)
+/// Version reported by a tool
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolVersion {
     pub name: String,
-    /// Command to run for version
-    pub cmd: String,
+    pub version: String,
+}
+
+/// A set environment variable
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvValue {
+    pub key: String,
+    pub value: String,
+}
+
+impl ProfileReport {
+    /// Query tool versions and environment variables for `profile`
+    fn collect(profile: &DevProfile, timeout: Duration) -> Self {
+        Self {
+            name: profile.name.clone(),
+            tools: profile
+                .tools
+                .iter()
+                .filter_map(|tool| {
+                    get_tool_version(tool, timeout).map(|version| ToolVersion { name: tool.name.clone(), version })
+                })
+                .collect(),
+            env: profile
+                .env_vars
+                .iter()
+                .filter_map(|var| get_env_var(var).map(|value| EnvValue { key: var.clone(), value }))
+                .collect(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tools.is_empty() && self.env.is_empty()
+    }
+
+    /// Render the tools/env lists used by the text and markdown output
+    fn to_section(&self) -> String {
+        let mut section = String::new();
+
+        if !self.tools.is_empty() {
+            section.push_str("tools:\n");
+            for tool in &self.tools {
+                section.push_str(&format!("  - {}: {}\n", tool.name, tool.version));
+            }
+        }
+
+        if !self.env.is_empty() {
+            section.push_str("env:\n");
+            for env in &self.env {
+                section.push_str(&format!("  - {}: {}\n", env.key, env.value));
+            }
+        }
+
+        section
+    }
+}
+
+/// Reports for the profiles detected in `dir` (or all with `show_all`),
+/// leaving out those with nothing to show
+fn collect_reports(profiles: Vec<DevProfile>, dir: &Path, show_all: bool, timeout: Duration) -> Vec<ProfileReport> {
+    detect_profiles(profiles, dir, show_all)
+        .iter()
+        .map(|profile| ProfileReport::collect(profile, timeout))
+        .filter(|report| !report.is_empty())
+        .collect()
 }
 
 /// Built-in profiles
@@ -34,9 +97,9 @@ fn get_builtin_profiles() -> Vec<DevProfile> {
             name: "rust".to_string(),
             detect: vec!["Cargo.toml".to_string()],
             tools: vec![
-                ToolDef { name: "rustc".to_string(), cmd: "rustc --version".to_string() },
-                ToolDef { name: "cargo".to_string(), cmd: "cargo --version".to_string() },
-                ToolDef { name: "rustup".to_string(), cmd: "rustup --version".to_string() },
+                DevTool { name: "rustc".to_string(), cmd: "rustc --version".to_string() },
+                DevTool { name: "cargo".to_string(), cmd: "cargo --version".to_string() },
+                DevTool { name: "rustup".to_string(), cmd: "rustup --version".to_string() },
             ],
             env_vars: vec![
                 "RUSTUP_HOME".to_string(),
@@ -49,8 +112,8 @@ fn get_builtin_profiles() -> Vec<DevProfile> {
             name: "node".to_string(),
             detect: vec!["package.json".to_string()],
             tools: vec![
-                ToolDef { name: "node".to_string(), cmd: "node --version".to_string() },
-                ToolDef { name: "npm".to_string(), cmd: "npm --version".to_string() },
+                DevTool { name: "node".to_string(), cmd: "node --version".to_string() },
+                DevTool { name: "npm".to_string(), cmd: "npm --version".to_string() },
             ],
             env_vars: vec![
                 "NODE_PATH".to_string(),
@@ -67,9 +130,9 @@ fn get_builtin_profiles() -> Vec<DevProfile> {
                 "requirements.txt".to_string(),
             ],
             tools: vec![
-                ToolDef { name: "python".to_string(), cmd: "python --version".to_string() },
-                ToolDef { name: "python3".to_string(), cmd: "python3 --version".to_string() },
-                ToolDef { name: "pip".to_string(), cmd: "pip --version".to_string() },
+                DevTool { name: "python".to_string(), cmd: "python --version".to_string() },
+                DevTool { name: "python3".to_string(), cmd: "python3 --version".to_string() },
+                DevTool { name: "pip".to_string(), cmd: "pip --version".to_string() },
             ],
             env_vars: vec![
                 "PYTHONPATH".to_string(),
@@ -82,7 +145,7 @@ fn get_builtin_profiles() -> Vec<DevProfile> {
             name: "go".to_string(),
             detect: vec!["go.mod".to_string()],
             tools: vec![
-                ToolDef { name: "go".to_string(), cmd: "go version".to_string() },
+                DevTool { name: "go".to_string(), cmd: "go version".to_string() },
             ],
             env_vars: vec![
                 "GOPATH".to_string(),
@@ -93,10 +156,22 @@ fn get_builtin_profiles() -> Vec<DevProfile> {
     ]
 }
 
-/// Detect which profiles are active in the given directory
-fn detect_profiles(dir: &Path, show_all: bool) -> Vec<DevProfile> {
-    let profiles = get_builtin_profiles();
+/// Built-in profiles with the user's `[[llm.dev.profile]]` entries merged in
+///
+/// A user profile replaces the built-in one of the same name; others are
+/// appended in config order.
+fn merge_profiles(mut profiles: Vec<DevProfile>, user_profiles: Vec<DevProfile>) -> Vec<DevProfile> {
+    for user in user_profiles {
+        match profiles.iter_mut().find(|p| p.name == user.name) {
+            Some(existing) => *existing = user,
+            None => profiles.push(user),
+        }
+    }
+    profiles
+}
 
+/// Detect which profiles are active in the given directory
+fn detect_profiles(profiles: Vec<DevProfile>, dir: &Path, show_all: bool) -> Vec<DevProfile> {
     if show_all {
         return profiles;
     }
@@ -108,16 +183,15 @@ fn detect_profiles(dir: &Path, show_all: bool) -> Vec<DevProfile> {
 }
 
 /// Get tool version by running command
-fn get_tool_version(tool: &ToolDef) -> Option<String> {
+///
+/// A command still running after `timeout` is killed and reports nothing.
+fn get_tool_version(tool: &DevTool, timeout: Duration) -> Option<String> {
     let parts: Vec<&str> = tool.cmd.split_whitespace().collect();
     if parts.is_empty() {
         return None;
     }
 
-    let output = std::process::Command::new(parts[0])
-        .args(&parts[1..])
-        .output()
-        .ok()?;
+    let output = output_with_timeout(std::process::Command::new(parts[0]).args(&parts[1..]), timeout)?;
 
     if output.status.success() {
         let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
@@ -141,79 +215,92 @@ fn get_env_var(name: &str) -> Option<String> {
 }
 
 /// Run the dev command
-pub fn run(show_all: bool, format: String) -> Result<()> {
+pub fn run(show_all: bool, format: String, timeout: Duration) -> Result<()> {
     let current_dir = std::env::current_dir()?;
-    let profiles = detect_profiles(&current_dir, show_all);
+    let profiles = merge_profiles(get_builtin_profiles(), DevConfig::load()?.profiles);
 
-    if profiles.is_empty() {
+    if detect_profiles(profiles.clone(), &current_dir, show_all).is_empty() {
         println!("No development profiles detected in current directory.");
         println!("Use --all to show all available profiles.");
         return Ok(());
     }
 
-    let mut results: Vec<(&str, String)> = Vec::new();
-
-    for profile in &profiles {
-        let mut section = String::new();
-
-        // Collect tool versions
-        let mut tools_found = Vec::new();
-        for tool in &profile.tools {
-            if let Some(version) = get_tool_version(tool) {
-                tools_found.push(format!("{}: {}", tool.name, version));
-            }
-        }
-
-        if !tools_found.is_empty() {
-            section.push_str("tools:\n");
-            for tool_info in &tools_found {
-                section.push_str(&format!("  - {}\n", tool_info));
-            }
-        }
-
-        // Collect environment variables
-        let mut env_found = Vec::new();
-        for var in &profile.env_vars {
-            if let Some(value) = get_env_var(var) {
-                env_found.push(format!("{}: {}", var, value));
-            }
-        }
-
-        if !env_found.is_empty() {
-            section.push_str("env:\n");
-            for env_info in &env_found {
-                section.push_str(&format!("  - {}\n", env_info));
-            }
-        }
-
-        if !section.is_empty() {
-            results.push((&profile.name, section));
-        }
-    }
+    let reports = collect_reports(profiles, &current_dir, show_all, timeout);
 
     // Output
     match format.as_str() {
         "json" => {
-            let mut json_result = serde_json::Map::new();
-            for (name, content) in &results {
-                json_result.insert(name.to_string(), serde_json::json!(content));
-            }
-            println!("{}", serde_json::to_string_pretty(&json_result)?);
+            println!("{}", serde_json::to_string_pretty(&reports)?);
         }
         "text" => {
-            for (name, content) in &results {
-                println!("=== DEV: {} ===", name.to_uppercase());
-                println!("{}", content);
+            for report in &reports {
+                println!("=== DEV: {} ===", report.name.to_uppercase());
+                println!("{}", report.to_section());
             }
         }
         _ => {
             // Default: markdown format
-            for (name, content) in &results {
-                println!("## DEV: {}", name.to_uppercase());
-                println!("{}", content);
+            for report in &reports {
+                println!("## DEV: {}", report.name.to_uppercase());
+                println!("{}", report.to_section());
             }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_output_is_structured_per_profile() {
+        let dir = std::env::temp_dir().join(format!("emx-dev-json-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Cargo.toml"), "[package]\n").unwrap();
+
+        let json = serde_json::to_string(&collect_reports(get_builtin_profiles(), &dir, false, Duration::from_secs(5))).unwrap();
+        let reports: Vec<ProfileReport> = serde_json::from_str(&json).unwrap();
+        let rust = reports.iter().find(|r| r.name == "rust").expect("rust profile detected");
+        let rustc = rust.tools.iter().find(|t| t.name == "rustc").expect("rustc version reported");
+        assert!(rustc.version.starts_with("rustc "));
+        assert!(reports.iter().all(|r| r.name != "node"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn user_profiles_are_detected_and_override_builtins() {
+        let dir = std::env::temp_dir().join(format!("emx-dev-custom-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("team.marker"), "").unwrap();
+        std::fs::write(dir.join("Cargo.toml"), "[package]\n").unwrap();
+
+        let config: DevConfig = toml::from_str(
+            r#"
+            [[profile]]
+            name = "team"
+            detect = ["team.marker"]
+            tools = [{ name = "cargo", cmd = "cargo --version" }]
+
+            [[profile]]
+            name = "rust"
+            detect = ["Cargo.toml"]
+            tools = [{ name = "rustc-verbose", cmd = "rustc -vV" }]
+            "#,
+        )
+        .unwrap();
+        let profiles = merge_profiles(get_builtin_profiles(), config.profiles);
+        assert_eq!(profiles.iter().filter(|p| p.name == "rust").count(), 1);
+
+        let reports = collect_reports(profiles, &dir, false, Duration::from_secs(5));
+        let team = reports.iter().find(|r| r.name == "team").expect("custom profile detected");
+        assert!(team.tools[0].version.starts_with("cargo "));
+        let rust = reports.iter().find(|r| r.name == "rust").unwrap();
+        assert_eq!(rust.tools.len(), 1);
+        assert_eq!(rust.tools[0].name, "rustc-verbose");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
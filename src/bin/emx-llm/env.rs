@@ -1,6 +1,10 @@
 //! Env command implementation - collect environment context for LLM inference
 
 use anyhow::Result;
+use emx_llm::EnvConfig;
+use std::time::Duration;
+
+use crate::subprocess::output_with_timeout;
 
 /// Metadata display options
 pub struct MetadataOptions {
@@ -9,14 +13,38 @@ pub struct MetadataOptions {
     pub show_ctime: bool,
 }
 
+/// Default number of entries shown by the file listing and the tree
+pub const DEFAULT_MAX_ITEMS: usize = 50;
+
+/// Listing size and order options
+pub struct ListingOptions {
+    /// Cap on entries (per kind for the flat listing, in total for the tree)
+    pub max_items: usize,
+    /// `name` (alphabetical), `size` or `mtime` (largest/newest first)
+    pub sort: String,
+}
+
 /// Run the env command
+///
+/// `respect_gitignore` of `None` filters ignored paths only inside a git repo.
+/// `tree` replaces the flat file listing with a recursive one of that depth.
+/// `diff_budget` adds `git diff` output, truncated to that many bytes each.
+/// `concise` prints a single `key=value` line instead of the sections.
+/// Each `git` call is abandoned after `timeout`.
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     format: String,
     include_files: bool,
+    tree: Option<usize>,
+    respect_gitignore: Option<bool>,
     include_git: bool,
+    diff_budget: Option<usize>,
     include_env: bool,
     meta_opts: MetadataOptions,
+    listing: ListingOptions,
     verbose_env: bool,
+    concise: bool,
+    timeout: Duration,
 ) -> Result<()> {
     use std::env;
 
@@ -30,6 +58,13 @@ pub fn run(
         .or_else(|_| env::var("PSModulePath").map(|_| "powershell".to_string()))
         .unwrap_or_else(|_| "unknown".to_string());
 
+    let respect_gitignore = || respect_gitignore.unwrap_or_else(|| inside_git_repo(&current_dir));
+    if concise {
+        let fields = collect_concise_fields(os, arch, &shell, &current_dir, respect_gitignore(), timeout);
+        println!("{}", format_concise(&fields));
+        return Ok(());
+    }
+
     // Build context
     let mut sections: Vec<(&str, String)> = Vec::new();
 
@@ -42,8 +77,14 @@ pub fn run(
     sections.push(("system", system_info));
 
     // Directory listing
-    if include_files {
-        let (dirs_section, files_section) = collect_file_listing(&current_dir, &meta_opts, &format)?;
+    let mut tree_json = None;
+    if let Some(depth) = tree {
+        let tree = collect_tree(&current_dir, depth, respect_gitignore(), listing.max_items);
+        tree_json = Some(serde_json::to_value(&tree)?);
+        sections.push(("tree", format_tree(&tree, format == "md")));
+    } else if include_files {
+        let (dirs_section, files_section) =
+            collect_file_listing(&current_dir, &meta_opts, &listing, &format, respect_gitignore())?;
         if !dirs_section.is_empty() {
             sections.push(("directories", dirs_section));
         }
@@ -56,14 +97,15 @@ pub fn run(
     if include_git {
         let git_dir = current_dir.join(".git");
         if git_dir.exists() {
-            let git_info = collect_git_info(&current_dir);
+            let git_info = collect_git_info(&current_dir, diff_budget, timeout);
             sections.push(("git", git_info));
         }
     }
 
     // Environment variables
     if include_env || verbose_env {
-        let env_info = collect_env_vars(verbose_env);
+        let env_config = EnvConfig::load()?;
+        let env_info = collect_env_vars(verbose_env, &env_config);
         sections.push(("env", env_info));
     }
 
@@ -81,6 +123,9 @@ pub fn run(
                     result.insert(name.to_string(), serde_json::json!(content));
                 }
             }
+            if let Some(tree) = tree_json {
+                result.insert("tree".to_string(), tree);
+            }
 
             println!("{}", serde_json::to_string_pretty(&result)?);
         }
@@ -106,6 +151,62 @@ pub fn run(
     Ok(())
 }
 
+/// The few facts `--concise` reports, in output order
+///
+/// The shell is reduced to its program name; `branch` and `dirty` appear
+/// only inside a git work tree.
+fn collect_concise_fields(
+    os: &str,
+    arch: &str,
+    shell: &str,
+    dir: &std::path::Path,
+    respect_gitignore: bool,
+    timeout: Duration,
+) -> Vec<(&'static str, String)> {
+    let shell = std::path::Path::new(shell)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| shell.to_string());
+    let entries = read_dir_entries(dir, respect_gitignore);
+    let dirs = entries.iter().filter(|(_, meta)| meta.is_dir()).count();
+
+    let mut fields = vec![
+        ("os", os.to_string()),
+        ("arch", arch.to_string()),
+        ("shell", shell),
+        ("files", (entries.len() - dirs).to_string()),
+        ("dirs", dirs.to_string()),
+    ];
+
+    let git = |args: &[&str]| {
+        git_output(dir, args, timeout)
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+    };
+    if let Some(branch) = git(&["rev-parse", "--abbrev-ref", "HEAD"]) {
+        fields.push(("branch", branch));
+        if let Some(status) = git(&["status", "--porcelain"]) {
+            fields.push(("dirty", (!status.is_empty()).to_string()));
+        }
+    }
+    fields
+}
+
+/// `key=value` pairs on one line; values containing whitespace are quoted
+fn format_concise(fields: &[(&str, String)]) -> String {
+    fields
+        .iter()
+        .map(|(key, value)| {
+            if value.is_empty() || value.contains(char::is_whitespace) {
+                format!("{}={:?}", key, value)
+            } else {
+                format!("{}={}", key, value)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 /// Format file size in human-readable format
 fn format_size(size: u64) -> String {
     const KB: u64 = 1024;
@@ -132,58 +233,65 @@ fn format_system_time(time: std::time::SystemTime) -> String {
     datetime.format("%Y-%m-%d %H:%M:%S").to_string()
 }
 
+/// Whether `dir` is inside a git work tree
+fn inside_git_repo(dir: &std::path::Path) -> bool {
+    dir.ancestors().any(|d| d.join(".git").exists())
+}
+
+/// Entries directly inside `dir`, optionally dropping paths matched by
+/// `.gitignore` (including ignore files in parent directories)
+fn read_dir_entries(dir: &std::path::Path, respect_gitignore: bool) -> Vec<(String, std::fs::Metadata)> {
+    if !respect_gitignore {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        return entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| Some((e.file_name().to_string_lossy().to_string(), e.metadata().ok()?)))
+            .collect();
+    }
+
+    ignore::WalkBuilder::new(dir)
+        .max_depth(Some(1))
+        .hidden(false)
+        .git_global(false)
+        .require_git(false)
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.depth() == 1)
+        .filter_map(|e| Some((e.file_name().to_string_lossy().to_string(), e.metadata().ok()?)))
+        .collect()
+}
+
 /// Collect file and directory listing with metadata
+///
+/// The item cap is applied after gitignore filtering and sorting.
 fn collect_file_listing(
     dir: &std::path::Path,
     meta_opts: &MetadataOptions,
+    listing: &ListingOptions,
     format: &str,
+    respect_gitignore: bool,
 ) -> Result<(String, String)> {
-    const MAX_ITEMS: usize = 50;
-
-    let mut dirs: Vec<(String, u64, String, String)> = Vec::new(); // (name, size, modified, created)
-    let mut files: Vec<(String, u64, String, String)> = Vec::new();
-
-    if let Ok(entries) = std::fs::read_dir(dir) {
-        for entry in entries.filter_map(|e| e.ok()) {
-            let name = entry.file_name().to_string_lossy().to_string();
-            let metadata = match entry.metadata() {
-                Ok(m) => m,
-                Err(_) => continue,
-            };
-
-            let size = metadata.len();
-            let modified = metadata.modified()
-                .map(format_system_time)
-                .unwrap_or_else(|_| "unknown".to_string());
-            let created = metadata.created()
-                .map(format_system_time)
-                .unwrap_or_else(|_| "unknown".to_string());
+    let (mut dir_entries, mut file_entries): (Vec<_>, Vec<_>) =
+        read_dir_entries(dir, respect_gitignore).into_iter().partition(|(_, m)| m.is_dir());
 
-            if metadata.is_dir() {
-                dirs.push((name, size, modified, created));
-            } else {
-                files.push((name, size, modified, created));
-            }
-        }
-    }
-
-    // Sort alphabetically (case-insensitive)
-    dirs.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase()));
-    files.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase()));
+    sort_entries(&mut dir_entries, &listing.sort);
+    sort_entries(&mut file_entries, &listing.sort);
 
-    let total_dirs = dirs.len();
-    let total_files = files.len();
+    let total_dirs = dir_entries.len();
+    let total_files = file_entries.len();
 
     // Truncate if needed
-    let dirs_truncated = dirs.len() > MAX_ITEMS;
-    let files_truncated = files.len() > MAX_ITEMS;
+    let max_items = listing.max_items;
+    let dirs_truncated = dir_entries.len() > max_items;
+    let files_truncated = file_entries.len() > max_items;
 
-    if dirs_truncated {
-        dirs.truncate(MAX_ITEMS);
-    }
-    if files_truncated {
-        files.truncate(MAX_ITEMS);
-    }
+    dir_entries.truncate(max_items);
+    file_entries.truncate(max_items);
+
+    let dirs = entry_rows(dir_entries);
+    let files = entry_rows(file_entries);
 
     let use_markdown = format == "md";
 
@@ -192,7 +300,7 @@ fn collect_file_listing(
         &dirs,
         total_dirs,
         dirs_truncated,
-        MAX_ITEMS,
+        max_items,
         meta_opts,
         use_markdown,
         true, // is_dir
@@ -203,7 +311,7 @@ fn collect_file_listing(
         &files,
         total_files,
         files_truncated,
-        MAX_ITEMS,
+        max_items,
         meta_opts,
         use_markdown,
         false, // is_dir
@@ -212,6 +320,135 @@ fn collect_file_listing(
     Ok((dirs_info, files_info))
 }
 
+/// Order entries by `name` (case-insensitive), or by `size`/`mtime` with the
+/// largest/newest first; ties fall back to the name
+fn sort_entries(entries: &mut [(String, std::fs::Metadata)], sort: &str) {
+    entries.sort_by(|a, b| {
+        let by_key = match sort {
+            "size" => b.1.len().cmp(&a.1.len()),
+            "mtime" => b.1.modified().ok().cmp(&a.1.modified().ok()),
+            _ => std::cmp::Ordering::Equal,
+        };
+        by_key.then_with(|| a.0.to_lowercase().cmp(&b.0.to_lowercase()))
+    });
+}
+
+/// Turn entries into `(name, size, modified, created)` table rows
+fn entry_rows(entries: Vec<(String, std::fs::Metadata)>) -> Vec<(String, u64, String, String)> {
+    entries
+        .into_iter()
+        .map(|(name, metadata)| {
+            let modified = metadata.modified()
+                .map(format_system_time)
+                .unwrap_or_else(|_| "unknown".to_string());
+            let created = metadata.created()
+                .map(format_system_time)
+                .unwrap_or_else(|_| "unknown".to_string());
+            (name, metadata.len(), modified, created)
+        })
+        .collect()
+}
+
+/// One entry of the `--tree` listing
+#[derive(Debug, serde::Serialize)]
+struct TreeNode {
+    name: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    /// Directory contents; empty for files and for directories at the depth limit
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    children: Vec<TreeNode>,
+}
+
+/// Recursive directory listing shared by the md, text and json outputs
+#[derive(Debug, serde::Serialize)]
+struct DirTree {
+    entries: Vec<TreeNode>,
+    /// Entries included (never more than the cap)
+    total: usize,
+    truncated: bool,
+}
+
+/// Walk `dir` down to `max_depth` levels, directories first, stopping once
+/// `max_items` entries have been collected
+fn collect_tree(dir: &std::path::Path, max_depth: usize, respect_gitignore: bool, max_items: usize) -> DirTree {
+    fn walk(
+        dir: &std::path::Path,
+        depth_left: usize,
+        respect_gitignore: bool,
+        budget: &mut usize,
+        truncated: &mut bool,
+    ) -> Vec<TreeNode> {
+        let mut entries = read_dir_entries(dir, respect_gitignore);
+        entries.sort_by(|a, b| {
+            b.1.is_dir()
+                .cmp(&a.1.is_dir())
+                .then_with(|| a.0.to_lowercase().cmp(&b.0.to_lowercase()))
+        });
+
+        let mut nodes = Vec::new();
+        for (name, metadata) in entries {
+            if *budget == 0 {
+                *truncated = true;
+                break;
+            }
+            *budget -= 1;
+
+            let is_dir = metadata.is_dir();
+            let children = if is_dir && depth_left > 1 {
+                walk(&dir.join(&name), depth_left - 1, respect_gitignore, budget, truncated)
+            } else {
+                Vec::new()
+            };
+            nodes.push(TreeNode {
+                name,
+                kind: if is_dir { "dir" } else { "file" },
+                children,
+            });
+        }
+        nodes
+    }
+
+    let mut budget = max_items;
+    let mut truncated = false;
+    let entries = if max_depth == 0 {
+        Vec::new()
+    } else {
+        walk(dir, max_depth, respect_gitignore, &mut budget, &mut truncated)
+    };
+    DirTree {
+        entries,
+        total: max_items - budget,
+        truncated,
+    }
+}
+
+/// Render a tree as an indented list (markdown bullets or plain text)
+fn format_tree(tree: &DirTree, use_markdown: bool) -> String {
+    fn render(nodes: &[TreeNode], level: usize, use_markdown: bool, out: &mut String) {
+        for node in nodes {
+            let indent = "  ".repeat(level);
+            let bullet = if use_markdown { "- " } else { "" };
+            let suffix = if node.kind == "dir" { "/" } else { "" };
+            out.push_str(&format!("{}{}{}{}\n", indent, bullet, node.name, suffix));
+            render(&node.children, level + 1, use_markdown, out);
+        }
+    }
+
+    if tree.entries.is_empty() {
+        return "[Empty]\n".to_string();
+    }
+
+    let mut out = String::new();
+    render(&tree.entries, 0, use_markdown, &mut out);
+    if tree.truncated {
+        out.push_str(&format!("\n*[TRUNCATED: showing first {} entries]*\n", tree.total));
+    } else {
+        out.push_str(&format!("\n*[Total: {} entries]*\n", tree.total));
+    }
+    out
+}
+
 /// Format entries as a table (markdown or plain text)
 fn format_table(
     entries: &[(String, u64, String, String)],
@@ -302,28 +539,58 @@ fn format_table(
     result
 }
 
+/// Split a git remote URL into `(host, owner/repo)`
+///
+/// Accepts scp-like SSH (`git@host:owner/repo.git`) and URL forms
+/// (`https://`, `ssh://`, `git://`); the SSH port and any user info are
+/// dropped, as is a trailing `.git`. Nested groups (GitLab) stay in the path.
+fn normalize_remote(remote: &str) -> Option<(String, String)> {
+    let (host, path) = match remote.split_once("://") {
+        Some((scheme, rest)) => {
+            let (authority, path) = rest.split_once('/')?;
+            let host = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+            let host = if scheme == "ssh" || scheme == "git+ssh" {
+                host.split(':').next().unwrap_or(host)
+            } else {
+                host
+            };
+            (host, path)
+        }
+        None => {
+            let (authority, path) = remote.split_once(':')?;
+            (authority.rsplit_once('@').map_or(authority, |(_, h)| h), path)
+        }
+    };
+
+    let path = path.trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    if host.is_empty() || !path.contains('/') {
+        return None;
+    }
+    Some((host.to_string(), path.to_string()))
+}
+
 /// Collect git information
-fn collect_git_info(dir: &std::path::Path) -> String {
+///
+/// With `diff_budget`, unstaged and staged diffs are appended, each cut to
+/// that many bytes. A `git` call that outlives `timeout` is killed and its
+/// part left out.
+fn collect_git_info(dir: &std::path::Path, diff_budget: Option<usize>, timeout: Duration) -> String {
     let mut git_info = String::new();
 
     // Get remote URL
-    if let Ok(output) = std::process::Command::new("git")
-        .args(["remote", "get-url", "origin"])
-        .current_dir(dir)
-        .output()
-    {
+    if let Some(output) = git_output(dir, &["remote", "get-url", "origin"], timeout) {
         let remote = String::from_utf8_lossy(&output.stdout).trim().to_string();
         if !remote.is_empty() {
             git_info.push_str(&format!("remote: {}\n", remote));
+            if let Some((host, repo)) = normalize_remote(&remote) {
+                git_info.push_str(&format!("repo: {} ({})\n", repo, host));
+            }
         }
     }
 
     // Get all local branches, mark current with *
-    if let Ok(output) = std::process::Command::new("git")
-        .args(["branch", "--list"])
-        .current_dir(dir)
-        .output()
-    {
+    if let Some(output) = git_output(dir, &["branch", "--list"], timeout) {
         let branches = String::from_utf8_lossy(&output.stdout);
         if !branches.trim().is_empty() {
             git_info.push_str("branches:\n");
@@ -342,19 +609,11 @@ fn collect_git_info(dir: &std::path::Path) -> String {
     // Get all worktrees
     // Format: /path/to/worktree  COMMIT_HASH [BRANCH]
     // Get current worktree path first
-    let current_wt_path = std::process::Command::new("git")
-        .args(["rev-parse", "--show-toplevel"])
-        .current_dir(dir)
-        .output()
-        .ok()
+    let current_wt_path = git_output(dir, &["rev-parse", "--show-toplevel"], timeout)
         .and_then(|o| String::from_utf8(o.stdout).ok())
         .map(|s| s.trim().to_string());
 
-    if let Ok(output) = std::process::Command::new("git")
-        .args(["worktree", "list"])
-        .current_dir(dir)
-        .output()
-    {
+    if let Some(output) = git_output(dir, &["worktree", "list"], timeout) {
         let worktrees = String::from_utf8_lossy(&output.stdout);
         let lines: Vec<&str> = worktrees.lines().collect();
         if !lines.is_empty() && !lines[0].is_empty() {
@@ -390,11 +649,7 @@ fn collect_git_info(dir: &std::path::Path) -> String {
     }
 
     // Get submodules
-    if let Ok(output) = std::process::Command::new("git")
-        .args(["submodule", "status"])
-        .current_dir(dir)
-        .output()
-    {
+    if let Some(output) = git_output(dir, &["submodule", "status"], timeout) {
         let submodules = String::from_utf8_lossy(&output.stdout);
         if !submodules.trim().is_empty() {
             git_info.push_str("submodules:\n");
@@ -417,11 +672,7 @@ fn collect_git_info(dir: &std::path::Path) -> String {
     }
 
     // Get status (short format)
-    if let Ok(output) = std::process::Command::new("git")
-        .args(["status", "--short"])
-        .current_dir(dir)
-        .output()
-    {
+    if let Some(output) = git_output(dir, &["status", "--short"], timeout) {
         let status = String::from_utf8_lossy(&output.stdout);
         if !status.trim().is_empty() {
             git_info.push_str("status:\n");
@@ -434,11 +685,7 @@ fn collect_git_info(dir: &std::path::Path) -> String {
     }
 
     // Get recent commits
-    if let Ok(output) = std::process::Command::new("git")
-        .args(["log", "--oneline", "-5"])
-        .current_dir(dir)
-        .output()
-    {
+    if let Some(output) = git_output(dir, &["log", "--oneline", "-5"], timeout) {
         let commits = String::from_utf8_lossy(&output.stdout);
         if !commits.trim().is_empty() {
             git_info.push_str("recent_commits:\n");
@@ -448,11 +695,53 @@ fn collect_git_info(dir: &std::path::Path) -> String {
         }
     }
 
+    // Get unstaged and staged changes
+    if let Some(budget) = diff_budget {
+        for (label, args) in [("unstaged_diff", &["diff"][..]), ("staged_diff", &["diff", "--cached"][..])] {
+            if let Some(output) = git_output(dir, args, timeout) {
+                let diff = String::from_utf8_lossy(&output.stdout);
+                if !diff.trim().is_empty() {
+                    let (shown, truncated) = truncate_to_bytes(&diff, budget);
+                    git_info.push_str(&format!("{}:\n", label));
+                    for line in shown.lines() {
+                        git_info.push_str(&format!("  {}\n", line));
+                    }
+                    if truncated {
+                        git_info.push_str(&format!("  [TRUNCATED: {} of {} bytes shown]\n", shown.len(), diff.len()));
+                    }
+                }
+            }
+        }
+    }
+
     git_info
 }
 
+/// Run `git` in `dir`, giving up after `timeout`
+fn git_output(dir: &std::path::Path, args: &[&str], timeout: Duration) -> Option<std::process::Output> {
+    output_with_timeout(std::process::Command::new("git").args(args).current_dir(dir), timeout)
+}
+
+/// Cut `text` to at most `budget` bytes on a char boundary
+fn truncate_to_bytes(text: &str, budget: usize) -> (&str, bool) {
+    if text.len() <= budget {
+        return (text, false);
+    }
+    let mut end = budget;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    (&text[..end], true)
+}
+
+/// Placeholder shown instead of a redacted value
+const REDACTED: &str = "[redacted]";
+
 /// Collect environment variables
-fn collect_env_vars(verbose: bool) -> String {
+///
+/// `config.safe_vars` extends the built-in whitelist; values of variables
+/// matching `config.redact` are masked in both modes.
+fn collect_env_vars(verbose: bool, config: &EnvConfig) -> String {
     use std::env;
 
     if verbose {
@@ -462,8 +751,10 @@ fn collect_env_vars(verbose: bool) -> String {
 
         let mut env_info = String::new();
         for (key, value) in vars {
-            // Multi-line values: show first line + indication
-            if value.contains('\n') {
+            if config.is_redacted(&key) {
+                env_info.push_str(&format!("{}: {}\n", key, REDACTED));
+            } else if value.contains('\n') {
+                // Multi-line values: show first line + indication
                 let first_line = value.lines().next().unwrap_or("");
                 env_info.push_str(&format!("{}: {}...\n", key, first_line));
             } else {
@@ -495,9 +786,16 @@ fn collect_env_vars(verbose: bool) -> String {
             "NUMBER_OF_PROCESSORS", "PROCESSOR_ARCHITECTURE",
         ];
 
+        let extra_vars = config
+            .safe_vars
+            .iter()
+            .map(String::as_str)
+            .filter(|var| !dev_vars.contains(var));
+
         let mut env_info = String::new();
-        for var in dev_vars {
+        for var in dev_vars.into_iter().chain(extra_vars) {
             if let Ok(value) = env::var(var) {
+                let value = if config.is_redacted(var) { REDACTED } else { value.as_str() };
                 env_info.push_str(&format!("{}: {}\n", var, value));
             }
         }
@@ -514,3 +812,177 @@ fn collect_env_vars(verbose: bool) -> String {
         env_info
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concise_output_is_a_single_key_value_line() {
+        let dir = std::env::temp_dir().join(format!("emx-env-concise-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("a.txt"), "a").unwrap();
+        std::fs::write(dir.join("b.txt"), "b").unwrap();
+
+        let fields = collect_concise_fields("linux", "x86_64", "/usr/bin/bash", &dir, false, Duration::from_secs(5));
+        let line = format_concise(&fields);
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert!(line.starts_with("os=linux arch=x86_64 shell=bash files=2 dirs=1"), "{}", line);
+        assert_eq!(line.lines().count(), 1);
+        assert!(!line.contains('|') && !line.contains('#') && !line.contains(": "), "{}", line);
+        assert_eq!(
+            format_concise(&[("branch", "my branch".to_string())]),
+            "branch=\"my branch\""
+        );
+    }
+
+    #[test]
+    fn normalize_remote_handles_ssh_and_https_forms() {
+        let expected = Some(("github.com".to_string(), "org/repo".to_string()));
+        assert_eq!(normalize_remote("git@github.com:org/repo.git"), expected);
+        assert_eq!(normalize_remote("https://github.com/org/repo.git"), expected);
+        assert_eq!(normalize_remote("ssh://git@github.com:22/org/repo"), expected);
+        assert_eq!(
+            normalize_remote("https://gitlab.example.com/group/sub/project.git"),
+            Some(("gitlab.example.com".to_string(), "group/sub/project".to_string()))
+        );
+        assert_eq!(normalize_remote("/srv/git/repo.git"), None);
+    }
+
+    #[test]
+    fn git_info_includes_truncated_diffs() {
+        let dir = std::env::temp_dir().join(format!("emx-env-diff-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let git = |args: &[&str]| std::process::Command::new("git").args(args).current_dir(&dir).output();
+        if git(&["init", "-q"]).map(|o| !o.status.success()).unwrap_or(true) {
+            std::fs::remove_dir_all(&dir).ok();
+            return; // git not available
+        }
+        std::fs::write(dir.join("notes.txt"), "first line\n").unwrap();
+        git(&["add", "notes.txt"]).unwrap();
+        std::fs::write(dir.join("notes.txt"), format!("second line\n{}", "x".repeat(200))).unwrap();
+
+        let info = collect_git_info(&dir, Some(4096), Duration::from_secs(5));
+        assert!(info.contains("staged_diff:\n"));
+        assert!(info.contains("  +first line\n"));
+        assert!(info.contains("unstaged_diff:\n"));
+        assert!(info.contains("  +second line\n"));
+        assert!(!info.contains("TRUNCATED"));
+
+        let info = collect_git_info(&dir, Some(64), Duration::from_secs(5));
+        assert!(info.contains("[TRUNCATED: 64 of"));
+        assert!(!info.contains("xxxxxxxxxx"));
+
+        assert!(!collect_git_info(&dir, None, Duration::from_secs(5)).contains("diff:"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn truncate_to_bytes_respects_char_boundaries() {
+        assert_eq!(truncate_to_bytes("héllo", 2), ("h", true));
+        assert_eq!(truncate_to_bytes("hello", 10), ("hello", false));
+    }
+
+    #[test]
+    fn file_listing_skips_gitignored_paths() {
+        let dir = std::env::temp_dir().join(format!("emx-env-gitignore-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("target")).unwrap();
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join(".gitignore"), "target/\n*.log\n").unwrap();
+        std::fs::write(dir.join("main.rs"), "").unwrap();
+        std::fs::write(dir.join("debug.log"), "").unwrap();
+        let meta_opts = MetadataOptions { show_size: false, show_mtime: false, show_ctime: false };
+        let listing = ListingOptions { max_items: DEFAULT_MAX_ITEMS, sort: "name".to_string() };
+
+        let (dirs, files) = collect_file_listing(&dir, &meta_opts, &listing, "md", true).unwrap();
+        assert!(dirs.contains("- src\n"));
+        assert!(!dirs.contains("target"));
+        assert!(files.contains("- main.rs\n"));
+        assert!(files.contains("- .gitignore\n"));
+        assert!(!files.contains("debug.log"));
+
+        let (dirs, files) = collect_file_listing(&dir, &meta_opts, &listing, "md", false).unwrap();
+        assert!(dirs.contains("- target\n"));
+        assert!(files.contains("- debug.log\n"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn file_listing_sort_and_max_items() {
+        let dir = std::env::temp_dir().join(format!("emx-env-sort-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a-small.txt"), "x").unwrap();
+        std::fs::write(dir.join("m-medium.txt"), "x".repeat(100)).unwrap();
+        std::fs::write(dir.join("z-large.txt"), "x".repeat(10_000)).unwrap();
+        let meta_opts = MetadataOptions { show_size: false, show_mtime: false, show_ctime: false };
+        let first_file = |sort: &str, max_items: usize| {
+            let listing = ListingOptions { max_items, sort: sort.to_string() };
+            let (_, files) = collect_file_listing(&dir, &meta_opts, &listing, "md", false).unwrap();
+            files
+        };
+
+        assert!(first_file("name", 50).starts_with("- a-small.txt\n"));
+        assert!(first_file("size", 50).starts_with("- z-large.txt\n- m-medium.txt\n- a-small.txt\n"));
+
+        let capped = first_file("size", 1);
+        assert!(capped.starts_with("- z-large.txt\n\n"));
+        assert!(capped.contains("TRUNCATED: showing 1 of 3 files"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    fn tree_depth(nodes: &[TreeNode]) -> usize {
+        nodes.iter().map(|n| 1 + tree_depth(&n.children)).max().unwrap_or(0)
+    }
+
+    #[test]
+    fn tree_honors_depth_and_item_cap() {
+        let dir = std::env::temp_dir().join(format!("emx-env-tree-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("a/b/c")).unwrap();
+        std::fs::write(dir.join("a/b/c/deep.txt"), "").unwrap();
+        std::fs::write(dir.join("a/top.txt"), "").unwrap();
+
+        let tree = collect_tree(&dir, 2, false, DEFAULT_MAX_ITEMS);
+        assert_eq!(tree_depth(&tree.entries), 2);
+        let a = &tree.entries[0];
+        assert_eq!(a.name, "a");
+        // Directories come first; `b` is at the depth limit so it is not expanded
+        assert_eq!(a.children.iter().map(|n| n.name.as_str()).collect::<Vec<_>>(), vec!["b", "top.txt"]);
+        assert!(a.children[0].children.is_empty());
+        assert!(format_tree(&tree, false).contains("a/\n  b/\n  top.txt\n"));
+
+        assert_eq!(tree_depth(&collect_tree(&dir, 10, false, DEFAULT_MAX_ITEMS).entries), 4);
+
+        for i in 0..20 {
+            std::fs::write(dir.join(format!("a/b/file{:02}.txt", i)), "").unwrap();
+        }
+        let capped = collect_tree(&dir, 10, false, 8);
+        assert!(capped.truncated);
+        assert_eq!(capped.total, 8);
+        assert!(format_tree(&capped, true).contains("TRUNCATED: showing first 8 entries"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn configured_safe_vars_are_listed_and_redacted_values_masked() {
+        std::env::set_var("EMX_TEST_SAFE_VAR", "visible");
+        std::env::set_var("EMX_TEST_API_TOKEN", "s3cret");
+        let config = EnvConfig {
+            safe_vars: vec!["EMX_TEST_SAFE_VAR".to_string(), "EMX_TEST_API_TOKEN".to_string()],
+            redact: vec!["*_TOKEN".to_string()],
+        };
+
+        let listed = collect_env_vars(false, &config);
+        assert!(listed.contains("EMX_TEST_SAFE_VAR: visible\n"));
+        assert!(listed.contains("EMX_TEST_API_TOKEN: [redacted]\n"));
+
+        let verbose = collect_env_vars(true, &config);
+        assert!(verbose.contains("EMX_TEST_API_TOKEN: [redacted]\n"));
+        assert!(!verbose.contains("s3cret"));
+
+        assert!(!collect_env_vars(false, &EnvConfig::default()).contains("EMX_TEST_SAFE_VAR"));
+    }
+}
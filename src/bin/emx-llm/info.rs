@@ -0,0 +1,41 @@
+//! Info command implementation
+
+use emx_llm::{ProviderConfig, ProviderType};
+
+/// Print the version, supported providers and the config file in use
+pub fn run() {
+    println!("emx-llm {}", env!("CARGO_PKG_VERSION"));
+    println!();
+
+    println!("Providers:");
+    for provider_type in ProviderType::ALL {
+        println!(
+            "  {:<10} {} (chat path {})",
+            provider_type.config_key(),
+            provider_type.default_base_url(),
+            provider_type.default_chat_path()
+        );
+    }
+    println!();
+
+    let active = ProviderConfig::active_config_path();
+    println!("Config files (in search order):");
+    let mut passed_active = false;
+    for path in ProviderConfig::config_search_paths() {
+        let status = if active.as_ref() == Some(&path) {
+            passed_active = true;
+            "active"
+        } else if !path.exists() {
+            "not found"
+        } else if passed_active {
+            "not used"
+        } else {
+            "unreadable or invalid"
+        };
+        println!("  {} ({})", path.display(), status);
+    }
+    match &active {
+        Some(path) => println!("Active config: {}", path.display()),
+        None => println!("Active config: none (using defaults and environment variables)"),
+    }
+}
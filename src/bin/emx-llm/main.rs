@@ -7,12 +7,15 @@ mod chat;
 mod dev;
 mod env;
 mod exec;
+mod info;
+mod subprocess;
 mod test_cmd;
 mod tools;
 
 use clap::Parser;
-use cli::{Cli, Commands};
-use env::MetadataOptions;
+use std::time::Duration;
+use cli::{resolve_format, Cli, Commands, FORMAT_ENV};
+use env::{ListingOptions, MetadataOptions};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -25,6 +28,8 @@ async fn main() -> Result<()> {
         .init();
 
     let cli = Cli::parse();
+    let global_format = cli.format;
+    let env_format = std::env::var(FORMAT_ENV).ok();
 
     match cli.command {
         Commands::Chat {
@@ -32,57 +37,103 @@ async fn main() -> Result<()> {
             prompt,
             model,
             api_base,
+            endpoint,
+            api_key_env,
+            provider,
             stream,
             no_stream,
             system,
+            session_reset,
             dry_run,
             token_stats,
             attach,
             tools,
             raw,
+            vars,
+            compare,
+            force_newline,
+            show_thinking,
+            repl,
+            max_stdin_bytes,
         } => {
             chat::run(
                 session,
                 prompt,
                 model,
                 api_base,
+                endpoint.map(|url| chat::Endpoint { url, api_key_env, provider }),
                 stream,
                 no_stream,
                 system,
+                session_reset,
                 dry_run,
                 token_stats,
                 attach,
                 tools,
                 raw,
+                vars,
+                compare,
+                global_format.or(env_format),
+                force_newline,
+                show_thinking,
+                repl,
+                max_stdin_bytes,
             ).await?;
         }
-        Commands::Test { provider } => {
-            test_cmd::run(provider)?;
+        Commands::Test { provider, offline } => {
+            test_cmd::run(provider, !offline)?;
+        }
+        Commands::Info => {
+            info::run();
         }
         Commands::Env {
             format,
             files,
+            tree,
+            respect_gitignore,
             git,
+            diff,
+            diff_budget,
             env_vars,
             all,
             size,
             mtime,
             ctime,
+            max_items,
+            sort,
             full,
             verbose,
+            concise,
+            command_timeout,
         } => {
             let include_files = files || all || verbose;
-            let include_git = git || all || verbose;
+            let include_git = git || diff || all || verbose;
+            let diff_budget = diff.then_some(diff_budget);
             let include_env = env_vars || all || verbose;
             let meta_opts = MetadataOptions {
                 show_size: size || full || verbose,
                 show_mtime: mtime || full || verbose,
                 show_ctime: ctime || full || verbose,
             };
-            env::run(format, include_files, include_git, include_env, meta_opts, verbose)?;
+            let format = resolve_format(format, global_format, env_format);
+            env::run(
+                format,
+                include_files,
+                tree,
+                respect_gitignore,
+                include_git,
+                diff_budget,
+                include_env,
+                meta_opts,
+                ListingOptions { max_items, sort },
+                verbose,
+                concise,
+                Duration::from_secs(command_timeout),
+            )?;
         }
-        Commands::Dev { all, format } => {
-            dev::run(all, format)?;
+        Commands::Dev { all, format, command_timeout } => {
+            let format = resolve_format(format, global_format, env_format);
+            dev::run(all, format, Duration::from_secs(command_timeout))?;
         }
         Commands::Tools {
             info,
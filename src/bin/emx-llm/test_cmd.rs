@@ -5,8 +5,8 @@ use emx_llm::ProviderType;
 use std::collections::HashMap;
 use tracing::info;
 
-/// Run the test command
-pub fn run(provider: String) -> Result<()> {
+/// Run the test command, probing the API host unless `online` is false
+pub fn run(provider: String, online: bool) -> Result<()> {
     let provider_type = match provider.to_lowercase().as_str() {
         "openai" => ProviderType::OpenAI,
         "anthropic" => ProviderType::Anthropic,
@@ -37,6 +37,18 @@ pub fn run(provider: String) -> Result<()> {
                 println!("  Default Model: {}", model);
             }
             println!();
+
+            let report = config.validate(online);
+            for issue in &report.issues {
+                let level = if issue.is_warning() { "Warning" } else { "Error" };
+                println!("{}: {}", level, issue);
+            }
+            if !report.is_valid() {
+                std::process::exit(1);
+            }
+            if !report.issues.is_empty() {
+                println!();
+            }
             println!("Configuration is valid!");
         }
         Err(e) => {
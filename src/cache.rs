@@ -0,0 +1,252 @@
+//! Response caching for deterministic prompts
+//!
+//! [`CachingClient`] wraps another [`Client`] and answers repeated
+//! non-streaming requests from memory, so identical prompts (typically sent
+//! with temperature 0) don't pay for a second upstream call. Streaming and raw
+//! calls always go to the inner client.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use futures::Stream;
+
+use crate::client::{Capabilities, ChatOptions, ChatResponse, Client, StreamEvent, ToolDefinition};
+use crate::{Message, Result, Usage};
+
+struct CacheEntry {
+    stored_at: Instant,
+    /// Value of `Entries::clock` when last read or written, for LRU eviction
+    last_used: u64,
+    response: ChatResponse,
+}
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<u64, CacheEntry>,
+    clock: u64,
+}
+
+/// [`Client`] decorator that caches successful non-streaming responses
+///
+/// Entries are keyed by a hash of the model, messages, tools and options,
+/// live for `ttl`, and the least recently used one is evicted once
+/// `capacity` is reached. A hit returns the stored response with a zero
+/// `latency`, since no HTTP attempt was made.
+pub struct CachingClient {
+    inner: Box<dyn Client>,
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<Entries>,
+}
+
+impl CachingClient {
+    /// Wrap `inner`, keeping at most `capacity` responses for `ttl` each
+    pub fn new(inner: Box<dyn Client>, capacity: usize, ttl: Duration) -> Self {
+        Self {
+            inner,
+            capacity,
+            ttl,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    fn lookup(&self, key: u64) -> Option<ChatResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        match entries.map.get_mut(&key) {
+            Some(entry) if entry.stored_at.elapsed() < self.ttl => {
+                entry.last_used = clock;
+                Some(entry.response.clone())
+            }
+            Some(_) => {
+                entries.map.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn store(&self, key: u64, response: ChatResponse) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.map.retain(|_, entry| entry.stored_at.elapsed() < self.ttl);
+        if entries.map.len() >= self.capacity && !entries.map.contains_key(&key) {
+            let oldest = entries
+                .map
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                entries.map.remove(&oldest);
+            }
+        }
+        entries.clock += 1;
+        let last_used = entries.clock;
+        entries.map.insert(
+            key,
+            CacheEntry {
+                stored_at: Instant::now(),
+                last_used,
+                response,
+            },
+        );
+    }
+}
+
+/// Hash of everything that shapes a completion
+fn cache_key(
+    messages: &[Message],
+    model: &str,
+    tools: Option<&[ToolDefinition]>,
+    options: &ChatOptions,
+) -> Result<u64> {
+    let request = serde_json::to_string(&(model, messages, tools, options))?;
+    let mut hasher = DefaultHasher::new();
+    request.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+#[async_trait::async_trait]
+impl Client for CachingClient {
+    async fn chat_with_options(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        options: &ChatOptions,
+    ) -> Result<ChatResponse> {
+        let started = Instant::now();
+        let key = cache_key(messages, model, tools, options)?;
+        if let Some(mut response) = self.lookup(key) {
+            response.latency = Duration::ZERO;
+            response.total_latency = started.elapsed();
+            return Ok(response);
+        }
+
+        let response = self.inner.chat_with_options(messages, model, tools, options).await?;
+        self.store(key, response.clone());
+        Ok(response)
+    }
+
+    async fn chat_raw(&self, messages: &[Message], model: &str, tools: Option<&[ToolDefinition]>) -> Result<reqwest::Response> {
+        self.inner.chat_raw(messages, model, tools).await
+    }
+
+    async fn chat_raw_with_options(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        options: &ChatOptions,
+    ) -> Result<reqwest::Response> {
+        self.inner.chat_raw_with_options(messages, model, tools, options).await
+    }
+
+    fn chat_stream_with_options(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        options: &ChatOptions,
+    ) -> Pin<Box<dyn Stream<Item = Result<StreamEvent>> + Send>> {
+        self.inner.chat_stream_with_options(messages, model, tools, options)
+    }
+
+    async fn chat_stream_raw(&self, messages: &[Message], model: &str, tools: Option<&[ToolDefinition]>) -> Result<reqwest::Response> {
+        self.inner.chat_stream_raw(messages, model, tools).await
+    }
+
+    async fn chat_stream_raw_with_options(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        options: &ChatOptions,
+    ) -> Result<reqwest::Response> {
+        self.inner.chat_stream_raw_with_options(messages, model, tools, options).await
+    }
+
+    fn api_base(&self) -> &str {
+        self.inner.api_base()
+    }
+
+    fn max_tokens(&self) -> u32 {
+        self.inner.max_tokens()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    async fn count_tokens(&self, messages: &[Message], model: &str) -> Result<u32> {
+        self.inner.count_tokens(messages, model).await
+    }
+
+    async fn embeddings(&self, input: &[String], model: &str) -> Result<(Vec<Vec<f32>>, Usage)> {
+        self.inner.embeddings(input, model).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::OpenAIMockServer;
+    use crate::{create_caching_client, create_client, ProviderConfig, ProviderType};
+
+    fn mock_client(api_base: String) -> Box<dyn Client> {
+        create_client(ProviderConfig {
+            provider_type: ProviderType::OpenAI,
+            api_base,
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_identical_request_is_served_from_cache() {
+        let mock = OpenAIMockServer::start().await;
+        mock.mock_chat_completion("Cached answer", 30).await;
+        let client = create_caching_client(mock_client(mock.base_url()), 8, Duration::from_secs(60));
+
+        let messages = vec![Message::user("What is 2+2?")];
+        let first = client.chat(&messages, "glm-4-flash", None).await.unwrap();
+        let second = client.chat(&messages, "glm-4-flash", None).await.unwrap();
+
+        assert_eq!(first.content, "Cached answer");
+        assert_eq!(second.content, "Cached answer");
+        assert_eq!(second.usage.total_tokens, 30);
+        assert_eq!(second.latency, Duration::ZERO);
+        assert_eq!(mock.request_count().await, 1);
+
+        // A different prompt misses the cache
+        client.chat(&[Message::user("What is 3+3?")], "glm-4-flash", None).await.unwrap();
+        assert_eq!(mock.request_count().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_expired_and_evicted_entries_are_refetched() {
+        let mock = OpenAIMockServer::start().await;
+        mock.mock_chat_completion("Answer", 30).await;
+
+        let expiring = create_caching_client(mock_client(mock.base_url()), 8, Duration::ZERO);
+        let messages = vec![Message::user("Hello")];
+        expiring.chat(&messages, "glm-4-flash", None).await.unwrap();
+        expiring.chat(&messages, "glm-4-flash", None).await.unwrap();
+        assert_eq!(mock.request_count().await, 2);
+
+        let small = create_caching_client(mock_client(mock.base_url()), 1, Duration::from_secs(60));
+        let (a, b) = (vec![Message::user("a")], vec![Message::user("b")]);
+        small.chat(&a, "glm-4-flash", None).await.unwrap();
+        small.chat(&b, "glm-4-flash", None).await.unwrap();
+        small.chat(&a, "glm-4-flash", None).await.unwrap();
+        assert_eq!(mock.request_count().await, 5);
+    }
+}
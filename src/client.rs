@@ -1,12 +1,19 @@
 //! LLM client implementations
 
-use super::{config::ProviderConfig, message::{Message, ToolCall}, Error, Result, Usage};
+use super::{
+    config::{ProviderConfig, ProviderType},
+    message::{validate_for, Message, ToolCall},
+    reasoning::strip_reasoning_if,
+    sse::{SseBuffer, SseEvent, SseLine},
+    Error, Result, Usage,
+};
 use futures::stream::Stream;
 use reqwest::Client as HttpClient;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::pin::Pin;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 /// Tool definition for function calling
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -199,22 +206,235 @@ fn parse_tcl_bool(s: &str) -> Option<bool> {
     }
 }
 
-/// Maximum retry attempts for rate-limited requests (HTTP 429)
+/// Error message for a stream that closed before signalling completion
+const STREAM_INCOMPLETE: &str = "stream ended without completion";
+
+/// Retries after the first attempt of an upstream request
 const MAX_RETRIES: u32 = 3;
 
-/// Build an HTTP client with specified timeout
-fn build_http_client(timeout: Duration) -> std::result::Result<HttpClient, reqwest::Error> {
-    HttpClient::builder()
-        .timeout(timeout)
-        .connect_timeout(Duration::from_secs(10))
-        .build()
+/// Upper bound on the delay before any one retry
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(8);
+
+/// Upstream attempts made by chat requests and stream openings, retries included
+static UPSTREAM_ATTEMPTS: AtomicU64 = AtomicU64::new(0);
+
+/// Number of HTTP attempts chat requests and stream openings have made
+/// upstream in this process, retries included
+pub fn upstream_attempts() -> u64 {
+    UPSTREAM_ATTEMPTS.load(Ordering::Relaxed)
+}
+
+/// When and how long to wait before re-sending a failed upstream request
+///
+/// Chat requests and the initial request of a stream share this policy;
+/// once stream bytes have been received, errors are final.
+#[derive(Debug, Clone, Copy)]
+struct RetryPolicy {
+    /// Retries after the first attempt
+    max_retries: u32,
+    /// Delay before the first retry, doubled for each one after
+    base_delay: Duration,
+    /// Upper bound on any one delay
+    max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: MAX_RETRIES,
+            base_delay: Duration::from_secs(1),
+            max_delay: MAX_RETRY_DELAY,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `attempt` (from 1): exponential, capped at
+    /// `max_delay`
+    fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32 << attempt.saturating_sub(1).min(16);
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
+/// Build an HTTP client with the configured timeout and extra root certificates
+pub(crate) fn build_http_client(config: &ProviderConfig) -> Result<HttpClient> {
+    let mut builder = HttpClient::builder()
+        .timeout(config.timeout())
+        .connect_timeout(config.connect_timeout())
+        .pool_idle_timeout(config.pool_idle_timeout())
+        .pool_max_idle_per_host(config.pool_max_idle_per_host());
+
+    if let Some(path) = &config.ca_bundle_path {
+        for cert in load_ca_bundle(path)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Read a PEM bundle, failing if it is missing or holds no certificates
+fn load_ca_bundle(path: &str) -> Result<Vec<reqwest::Certificate>> {
+    let pem = std::fs::read(path)
+        .map_err(|e| Error::Config(format!("Failed to read CA bundle '{}': {}", path, e)))?;
+    let certs = reqwest::Certificate::from_pem_bundle(&pem)
+        .map_err(|e| Error::Config(format!("Invalid PEM in CA bundle '{}': {}", path, e)))?;
+    if certs.is_empty() {
+        return Err(Error::Config(format!(
+            "CA bundle '{}' contains no PEM certificates",
+            path
+        )));
+    }
+    Ok(certs)
+}
+
+/// Whether an upstream answer may be retried: rate limits and server errors
+fn is_retryable_status(status: reqwest::StatusCode) -> bool {
+    status.as_u16() == 429 || status.is_server_error()
+}
+
+/// Whether a request failed before any response arrived in a way worth
+/// retrying: a failed connect, a timeout, or a connection the server reset
+/// or closed while the request was being sent
+///
+/// Builder, redirect and body-decoding errors are not retried.
+fn is_retryable_send_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout() || error.is_request()
+}
+
+/// Send the request `build` makes, re-sending it as `policy` allows
+///
+/// Returns the last response whatever its status, together with when the
+/// attempt that produced it started.
+async fn send_with_retry(
+    policy: RetryPolicy,
+    build: impl Fn() -> reqwest::RequestBuilder,
+) -> Result<(reqwest::Response, Instant)> {
+    let mut attempt = 0;
+    loop {
+        let attempt_started = Instant::now();
+        UPSTREAM_ATTEMPTS.fetch_add(1, Ordering::Relaxed);
+        let reason = match build().send().await {
+            Ok(response) if is_retryable_status(response.status()) && attempt < policy.max_retries => {
+                response.status().to_string()
+            }
+            Ok(response) => return Ok((response, attempt_started)),
+            Err(e) if is_retryable_send_error(&e) && attempt < policy.max_retries => e.to_string(),
+            Err(e) => return Err(e.into()),
+        };
+        attempt += 1;
+        let delay = policy.delay(attempt);
+        tracing::warn!(
+            "Upstream request failed ({}), retrying in {:?} (attempt {}/{})",
+            reason, delay, attempt, policy.max_retries
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// `stream_options` asking an OpenAI stream to report usage in its last
+/// chunk, unless the caller set its own through `extra_body`
+fn include_usage(extra_body: &Option<serde_json::Map<String, serde_json::Value>>) -> Option<serde_json::Value> {
+    let caller_set = extra_body.as_ref().is_some_and(|extra| extra.contains_key("stream_options"));
+    (!caller_set).then(|| json!({"include_usage": true}))
+}
+
+/// Whether a response declares a JSON body rather than an event stream
+fn is_json_response(response: &reqwest::Response) -> bool {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.contains("json"))
+}
+
+/// Build a [`ChatResponse`] from a non-streaming OpenAI completion body
+///
+/// Latencies are left at zero for the caller to fill in.
+fn parse_openai_completion(body: &str) -> Result<ChatResponse> {
+    let response: ChatCompletionResponse = parse_success_body(body, "OpenAI response")?;
+    let choice = response
+        .choices
+        .first()
+        .ok_or_else(|| Error::Api("No choices in OpenAI response".to_string()))?;
+
+    let usage = match &response.usage {
+        Some(u) => u.to_usage(),
+        None => {
+            // Some OpenAI-compatible proxies drop the usage object
+            tracing::warn!("OpenAI response has no usage; reporting zero tokens");
+            Usage::default()
+        }
+    };
+
+    if let Some(role) = choice.message.role.as_deref().filter(|role| *role != "assistant") {
+        tracing::warn!("OpenAI response message has role '{}', expected assistant", role);
+    }
+
+    // Parse tool calls if present
+    let tool_calls = if !choice.message.tool_calls.is_empty() {
+        Some(
+            choice.message.tool_calls.iter().map(|tc| ToolCall {
+                id: tc.id.clone(),
+                name: tc.function.name.clone(),
+                arguments: tc.function.arguments.clone(),
+            }).collect()
+        )
+    } else {
+        None
+    };
+
+    Ok(ChatResponse {
+        content: choice.message.content.clone().unwrap_or_default(),
+        reasoning: choice.message.reasoning_content.clone().filter(|r| !r.is_empty()),
+        tool_calls,
+        usage,
+        finish_reason: choice.finish_reason.clone(),
+        latency: Duration::ZERO,
+        total_latency: Duration::ZERO,
+        logprobs: choice.logprobs.clone(),
+        raw_content: None,
+    })
 }
 
-/// Calculate delay for retry attempt using exponential backoff with jitter
-fn retry_delay(attempt: u32) -> Duration {
-    // Exponential backoff: 1s, 2s, 4s
-    let base_secs = 1u64 << attempt.min(4); // Cap at 16s base
-    Duration::from_secs(base_secs)
+/// Longest body excerpt quoted when a success response is not JSON
+const BODY_SNIPPET_CHARS: usize = 200;
+
+/// Parse a success response body, naming `what` in the error
+///
+/// A 200 whose body is not JSON at all is almost always an HTML error page
+/// from a proxy or load balancer in front of the API, so it gets a short
+/// error with the start of the body rather than a serde message.
+fn parse_success_body<T: serde::de::DeserializeOwned>(body: &str, what: &str) -> Result<T> {
+    if serde_json::from_str::<serde::de::IgnoredAny>(body).is_err() {
+        let snippet: String = body.trim().chars().take(BODY_SNIPPET_CHARS).collect();
+        return Err(Error::Api(format!(
+            "upstream returned non-JSON 200 response, likely a proxy error: {}",
+            snippet
+        )));
+    }
+    serde_json::from_str(body).map_err(|e| Error::Api(format!("Failed to parse {}: {}. Body: {}", what, e, body)))
+}
+
+/// Extract the message from an in-band stream error, if the SSE payload is one.
+///
+/// Once the 200 headers are sent, upstreams report failures as a final `data:`
+/// frame instead: `{"error": {...}}` for OpenAI-compatible servers and
+/// `{"type": "error", "error": {...}}` for Anthropic. Some servers put
+/// `"error": null` on every ordinary chunk, which is not an error.
+fn stream_error_message(json_str: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(json_str).ok()?;
+    let error = value.get("error").filter(|error| !error.is_null())?;
+    let message = match error {
+        serde_json::Value::String(s) => s.clone(),
+        other => other
+            .get("message")
+            .and_then(|m| m.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| other.to_string()),
+    };
+    Some(message)
 }
 
 fn normalize_outbound_messages(messages: &[Message]) -> Vec<Message> {
@@ -240,77 +460,606 @@ fn normalize_outbound_messages(messages: &[Message]) -> Vec<Message> {
         .collect()
 }
 
-// ---------------------------------------------------------------------------
-// SSE buffer utility — shared between OpenAI and Anthropic streaming parsers
-// ---------------------------------------------------------------------------
+/// Combine all system messages into one leading system message
+///
+/// Their text is joined with a blank line; the other messages keep their
+/// order. Conversations with at most one system message are left as is.
+fn merge_system_messages(messages: Vec<Message>) -> Vec<Message> {
+    let is_system = |m: &Message| m.role == crate::MessageRole::System;
+    if messages.iter().filter(|m| is_system(m)).count() <= 1 {
+        return messages;
+    }
 
-/// Parsed SSE line types
-enum SseLine {
-    /// `data: [DONE]` — OpenAI stream terminator
-    Done,
-    /// `data: <json>` — JSON payload
-    Data(String),
-    /// `event: <name>` — SSE event name
-    Event(String),
-    /// Empty or non-SSE line (skip)
-    Skip,
+    let prompt = messages
+        .iter()
+        .filter(|m| is_system(m))
+        .filter_map(|m| m.get_content())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let conversation = messages.into_iter().filter(|m| !is_system(m));
+    std::iter::once(Message::system(prompt)).chain(conversation).collect()
 }
 
-/// Accumulates bytes from an HTTP response and yields complete SSE lines.
-struct SseBuffer {
-    buf: Vec<u8>,
+/// Split off the system prompt, which Anthropic takes as a top-level field
+///
+/// Message order is kept, so a trailing assistant message stays last and
+/// acts as a prefill the model continues from. Anthropic rejects a prefill
+/// ending in whitespace, so that is trimmed.
+fn split_anthropic_system(messages: &[Message]) -> (Option<serde_json::Value>, Vec<Message>) {
+    let system = messages
+        .iter()
+        .find(|m| m.role == crate::MessageRole::System)
+        .and_then(|m| {
+            let text = m.get_content()?;
+            // A cacheable system prompt is sent as a marked text block
+            Some(if m.cacheable {
+                json!([crate::message::cached_text_block(text)])
+            } else {
+                json!(text)
+            })
+        });
+    let mut conversation: Vec<Message> = messages
+        .iter()
+        .filter(|m| m.role != crate::MessageRole::System)
+        .cloned()
+        .collect();
+
+    if let Some(last) = conversation.last_mut() {
+        if last.role == crate::MessageRole::Assistant && last.tool_calls.is_none() {
+            if let crate::MessageContent::Text(prefill) = &mut last.content {
+                prefill.truncate(prefill.trim_end().len());
+            }
+        }
+    }
+
+    (system, conversation)
 }
 
-impl SseBuffer {
-    fn new() -> Self {
-        Self { buf: Vec::with_capacity(4096) }
-    }
+/// Parse an OpenAI chat completion SSE byte stream into [`StreamEvent`]s
+///
+/// Shared by the HTTP clients and by fixture replay, which feeds recorded
+/// bytes through the same parser.
+pub(crate) fn openai_sse_events<S, B, E>(stream: S) -> Pin<Box<dyn Stream<Item = Result<StreamEvent>> + Send>>
+where
+    S: Stream<Item = std::result::Result<B, E>> + Send + 'static,
+    B: AsRef<[u8]> + Send + 'static,
+    E: Send + 'static,
+    Error: From<E>,
+{
+    Box::pin(async_stream::stream! {
+        use futures::StreamExt;
+        let mut stream = Box::pin(stream);
+        let mut sse = SseBuffer::new();
+        let mut usage: Option<Usage> = None;
+
+        // Track accumulated tool calls
+        let mut accumulated_tools: std::collections::HashMap<i32, ToolCall> = std::collections::HashMap::new();
+        // Set once a finish_reason arrives; some servers omit the trailing [DONE]
+        let mut finished = false;
+        let mut finish_reason: Option<FinishReason> = None;
+        // The single done event waits for [DONE] or the end of the stream,
+        // since usage arrives in a `choices: []` chunk after the finish_reason
+        let done_event = |tools: &std::collections::HashMap<i32, ToolCall>,
+                          usage: &Option<Usage>,
+                          finish_reason: &Option<FinishReason>| StreamEvent {
+            tool_calls: (!tools.is_empty()).then(|| tools.values().cloned().collect()),
+            delta: String::new(),
+            done: true,
+            usage: usage.clone(),
+            reasoning: None,
+            finish_reason: finish_reason.clone(),
+        };
 
-    fn extend(&mut self, chunk: &[u8]) {
-        self.buf.extend_from_slice(chunk);
-    }
+        loop {
+            let chunk_result = stream.next().await;
+            let at_end = chunk_result.is_none();
+            match chunk_result {
+                Some(Ok(chunk)) => sse.extend(chunk.as_ref()),
+                Some(Err(e)) => {
+                    yield Err(Error::from(e));
+                    return;
+                }
+                // Deliver an event the server left unterminated
+                None => sse.finish(),
+            }
 
-    /// Extract the next complete line (terminated by `\n`) from the buffer.
-    /// Returns `None` when no complete line is available yet.
-    ///
-    /// UTF-8 safety: uses `from_utf8` (strict) instead of `from_utf8_lossy`
-    /// to avoid silently corrupting multi-byte characters split across chunk
-    /// boundaries. Malformed bytes are reported as an error rather than
-    /// replaced with U+FFFD.
-    fn next_line(&mut self) -> Option<SseLine> {
-        let pos = self.buf.iter().position(|&b| b == b'\n')?;
-        let raw: Vec<u8> = self.buf.drain(..=pos).collect();
-        let line = match std::str::from_utf8(&raw) {
-            Ok(s) => s.trim().to_string(),
-            Err(_) => {
-                // Server sent non-UTF-8 data — surface as a parseable error
-                // instead of silently corrupting the stream.
-                return Some(SseLine::Data(
-                    r#"{"error":"SSE stream contains invalid UTF-8"}"#.to_string(),
-                ));
+            // Whole events, so `data:` payloads split over several lines parse
+            while let Some(sse_line) = sse.next_event().map(SseEvent::into_line) {
+                match sse_line {
+                    SseLine::Done => {
+                        // Yield any accumulated tool calls at the end
+                        yield Ok(done_event(&accumulated_tools, &usage, &finish_reason));
+                        return;
+                    }
+                    SseLine::Data(json_str) => {
+                        if let Some(message) = stream_error_message(&json_str) {
+                            yield Err(Error::Api(format!("OpenAI stream error: {}", message)));
+                            return;
+                        }
+                        match serde_json::from_str::<ChatStreamChunk>(&json_str) {
+                            Ok(chunk) => {
+                                // Extract usage when available (final chunk)
+                                if let Some(ref u) = chunk.usage {
+                                    usage = Some(u.to_usage());
+                                }
+
+                                if let Some(delta) = chunk.choices.first() {
+                                    let delta_text = delta.delta.content.clone().unwrap_or_default();
+                                    let reasoning = delta.delta.reasoning_content.clone().filter(|r| !r.is_empty());
+                                    if let Some(reason) = delta.finish_reason.as_deref() {
+                                        finish_reason = Some(FinishReason::parse(reason));
+                                    }
+                                    finished |= delta.finish_reason.is_some();
+
+                                    // Process tool calls
+                                    for tc in &delta.delta.tool_calls {
+                                        let entry = accumulated_tools.entry(tc.index).or_insert_with(|| ToolCall {
+                                            id: tc.tool_id.clone().unwrap_or_default(),
+                                            name: String::new(),
+                                            arguments: String::new(),
+                                        });
+
+                                        if let Some(ref id) = tc.tool_id {
+                                            entry.id = id.clone();
+                                        }
+                                        if let Some(ref func) = tc.function {
+                                            if let Some(ref name) = func.function_name {
+                                                entry.name = name.clone();
+                                            }
+                                            if let Some(ref args) = func.function_arguments {
+                                                entry.arguments.push_str(args);
+                                            }
+                                        }
+                                    }
+
+                                    // Yield reasoning and text deltas if present
+                                    if reasoning.is_some() {
+                                        yield Ok(StreamEvent {
+                                            tool_calls: None,
+                                            delta: String::new(),
+                                            done: false,
+                                            usage: None,
+                                            reasoning,
+                                            finish_reason: None,
+                                        });
+                                    }
+                                    if !delta_text.is_empty() {
+                                        yield Ok(StreamEvent {
+                                            tool_calls: None,
+                                            delta: delta_text,
+                                            done: false,
+                                            usage: None,
+                                            reasoning: None,
+                                            finish_reason: None,
+                                        });
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                tracing::warn!("Failed to parse SSE chunk: {}", e);
+                            }
+                        }
+                    }
+                    _ => {} // Skip empty lines and event: lines
+                }
             }
-        };
 
-        if line.is_empty() {
-            return Some(SseLine::Skip);
+            if at_end {
+                break;
+            }
         }
 
-        if line == "data: [DONE]" {
-            return Some(SseLine::Done);
+        if finished {
+            yield Ok(done_event(&accumulated_tools, &usage, &finish_reason));
+        } else {
+            tracing::warn!("SSE stream ended unexpectedly");
+            yield Err(Error::Api(STREAM_INCOMPLETE.to_string()));
         }
+    })
+}
+
+/// Parse an Anthropic messages SSE byte stream into [`StreamEvent`]s
+///
+/// Shared by the HTTP clients and by fixture replay, which feeds recorded
+/// bytes through the same parser.
+pub(crate) fn anthropic_sse_events<S, B, E>(stream: S) -> Pin<Box<dyn Stream<Item = Result<StreamEvent>> + Send>>
+where
+    S: Stream<Item = std::result::Result<B, E>> + Send + 'static,
+    B: AsRef<[u8]> + Send + 'static,
+    E: Send + 'static,
+    Error: From<E>,
+{
+    Box::pin(async_stream::stream! {
+        use futures::StreamExt;
+        let mut stream = Box::pin(stream);
+        let mut sse = SseBuffer::new();
+        let mut usage: Option<Usage> = None;
+
+        // Track accumulated tool calls for streaming
+        let mut tool_blocks: std::collections::HashMap<u32, ToolCall> = std::collections::HashMap::new();
+        // From message_delta; `refusal` marks a content policy stop
+        let mut stop_reason: Option<FinishReason> = None;
+
+        loop {
+            let chunk_result = stream.next().await;
+            let at_end = chunk_result.is_none();
+            match chunk_result {
+                Some(Ok(chunk)) => sse.extend(chunk.as_ref()),
+                Some(Err(e)) => {
+                    yield Err(Error::from(e));
+                    return;
+                }
+                // Deliver an event the server left unterminated
+                None => sse.finish(),
+            }
+
+            // Whole events, so `data:` payloads split over several lines parse
+            while let Some(sse_line) = sse.next_event().map(SseEvent::into_line) {
+                match sse_line {
+                    SseLine::Event(name) if name == "message_stop" => {
+                        // Yield accumulated tool calls if any
+                        let tool_calls = if !tool_blocks.is_empty() {
+                            let mut calls: Vec<(u32, ToolCall)> = tool_blocks.drain().collect();
+                            calls.sort_by_key(|(idx, _)| *idx);
+                            Some(calls.into_iter().map(|(_, tc)| tc).collect())
+                        } else {
+                            None
+                        };
+                        yield Ok(StreamEvent { tool_calls, delta: String::new(), done: true, usage: usage.clone(), reasoning: None, finish_reason: stop_reason.clone() });
+                        return;
+                    }
+                    SseLine::Data(json_str) => {
+                        if let Some(message) = stream_error_message(&json_str) {
+                            yield Err(Error::Api(format!("Anthropic stream error: {}", message)));
+                            return;
+                        }
+                        match serde_json::from_str::<AnthropicStreamChunk>(&json_str) {
+                            Ok(chunk) => {
+                                // Extract usage from message if available (message_start event)
+                                if let Some(msg) = &chunk.message {
+                                    if let Some(u) = &msg.usage {
+                                        u.accumulate_into(&mut usage);
+                                    }
+                                }
+
+                                // Extract usage from message_delta event (GLM API returns usage here)
+                                if chunk.type_ == "message_delta" {
+                                    if let Some(u) = &chunk.usage_info {
+                                        u.accumulate_into(&mut usage);
+                                    }
+                                    if let Some(StreamDelta::MessageDelta(delta)) = &chunk.delta {
+                                        stop_reason = delta.stop_reason.as_deref().map(FinishReason::parse);
+                                    }
+                                }
+
+                                match chunk.type_.as_str() {
+                                    "content_block_start" => {
+                                        // Start of a new content block — may be text or tool_use
+                                        if let Some(AnthropicStreamContentBlock::ToolUse { id, name, .. }) = &chunk.content_block {
+                                            tool_blocks.insert(chunk.index, ToolCall {
+                                                id: id.clone(),
+                                                name: name.clone(),
+                                                arguments: String::new(),
+                                            });
+                                        }
+                                    }
+                                    "content_block_delta" => {
+                                        if let Some(StreamDelta::ContentBlock(delta)) = &chunk.delta {
+                                            match delta.type_.as_str() {
+                                                "text_delta" if !delta.text.is_empty() => {
+                                                    yield Ok(StreamEvent { tool_calls: None, delta: delta.text.clone(), done: false, usage: None, reasoning: None, finish_reason: None });
+                                                }
+                                                "thinking_delta" if !delta.thinking.is_empty() => {
+                                                    yield Ok(StreamEvent { tool_calls: None, delta: String::new(), done: false, usage: None, reasoning: Some(delta.thinking.clone()), finish_reason: None });
+                                                }
+                                                "input_json_delta" => {
+                                                    // Accumulate partial JSON for tool_use arguments
+                                                    if let Some(ref partial) = delta.partial_json {
+                                                        if let Some(tc) = tool_blocks.get_mut(&chunk.index) {
+                                                            tc.arguments.push_str(partial);
+                                                        }
+                                                    }
+                                                }
+                                                _ => {}
+                                            }
+                                        }
+                                    }
+                                    "message_stop" => {
+                                        let tool_calls = if !tool_blocks.is_empty() {
+                                            let mut calls: Vec<(u32, ToolCall)> = tool_blocks.drain().collect();
+                                            calls.sort_by_key(|(idx, _)| *idx);
+                                            Some(calls.into_iter().map(|(_, tc)| tc).collect())
+                                        } else {
+                                            None
+                                        };
+                                        yield Ok(StreamEvent { tool_calls, delta: String::new(), done: true, usage: usage.clone(), reasoning: None, finish_reason: stop_reason.clone() });
+                                        return;
+                                    }
+                                    _ => {} // message_delta, content_block_stop, ping, etc.
+                                }
+                            }
+                            Err(e) => {
+                                tracing::warn!("Failed to parse SSE chunk: {}", e);
+                            }
+                        }
+                    }
+                    _ => {} // Skip empty lines and other events
+                }
+            }
 
-        if let Some(json_str) = line.strip_prefix("data: ") {
-            return Some(SseLine::Data(json_str.to_string()));
+            if at_end {
+                break;
+            }
         }
 
-        if let Some(event_name) = line.strip_prefix("event: ") {
-            return Some(SseLine::Event(event_name.to_string()));
+        // Reaching here means the connection closed before message_stop
+        tracing::warn!("SSE stream ended unexpectedly");
+        yield Err(Error::Api(STREAM_INCOMPLETE.to_string()));
+    })
+}
+
+/// Per-call request parameters
+///
+/// Every field is optional. `None` inherits the default configured for the
+/// provider/model (`temperature`, `top_p`, `max_tokens` in its config
+/// section); `Some` overrides that default for this call only. Merging is
+/// field-by-field, so a call that sets just `top_p` still gets the configured
+/// `temperature`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChatOptions {
+    /// Sampling temperature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+
+    /// Nucleus sampling probability mass
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+
+    /// Maximum tokens to generate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+
+    /// OpenAI `response_format` object (e.g. a `json_schema` format); not
+    /// supported by the Anthropic API and ignored there
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<serde_json::Value>,
+
+    /// Sequences that end generation; sent as OpenAI `stop` or Anthropic
+    /// `stop_sequences`, and omitted when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+
+    /// Ask for per-token log probabilities (OpenAI only; ignored by Anthropic)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<bool>,
+
+    /// Number of most likely alternatives to report per token, with `logprobs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_logprobs: Option<u32>,
+
+    /// Provider-specific fields added verbatim to the request body (e.g.
+    /// `enable_thinking`, `repetition_penalty`), for parameters that have no
+    /// typed option; keys should not repeat the typed fields above
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra_body: Option<serde_json::Map<String, serde_json::Value>>,
+
+    /// Drop reasoning from the answer: `<think>...</think>` segments of the
+    /// text and, when streaming, [`StreamEvent::reasoning`] deltas. The
+    /// unfiltered text stays in [`ChatResponse::raw_content`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strip_reasoning: Option<bool>,
+}
+
+impl ChatOptions {
+    /// Fill fields left unset here from `defaults`
+    pub fn merged_over(&self, defaults: &ChatOptions) -> ChatOptions {
+        ChatOptions {
+            temperature: self.temperature.or(defaults.temperature),
+            top_p: self.top_p.or(defaults.top_p),
+            max_tokens: self.max_tokens.or(defaults.max_tokens),
+            response_format: self.response_format.clone().or_else(|| defaults.response_format.clone()),
+            stop: if self.stop.is_empty() { defaults.stop.clone() } else { self.stop.clone() },
+            logprobs: self.logprobs.or(defaults.logprobs),
+            top_logprobs: self.top_logprobs.or(defaults.top_logprobs),
+            extra_body: self.extra_body.clone().or_else(|| defaults.extra_body.clone()),
+            strip_reasoning: self.strip_reasoning.or(defaults.strip_reasoning),
         }
+    }
+}
 
-        Some(SseLine::Skip)
+/// Result of a non-streaming chat completion
+#[derive(Debug, Clone)]
+pub struct ChatResponse {
+    /// Assistant text (empty when the model only requested tools)
+    pub content: String,
+
+    /// Reasoning ("thinking") the model returned apart from the answer:
+    /// OpenAI-compatible `reasoning_content` or Anthropic `thinking` blocks
+    pub reasoning: Option<String>,
+
+    /// Tool calls (when assistant requests tool execution)
+    pub tool_calls: Option<Vec<ToolCall>>,
+
+    /// Token usage reported by the provider
+    pub usage: Usage,
+
+    /// Why generation ended, as the provider reported it: OpenAI `stop` /
+    /// `length` / `tool_calls`, Anthropic `end_turn` / `stop_sequence` /
+    /// `max_tokens` / `tool_use`
+    pub finish_reason: Option<String>,
+
+    /// Time spent on the successful HTTP attempt, from sending the request
+    /// to reading the whole body
+    pub latency: Duration,
+
+    /// Wall time of the whole call, including retries and their sleeps
+    pub total_latency: Duration,
+
+    /// Per-token log probabilities, when requested with
+    /// [`ChatOptions::logprobs`] and returned by the provider (OpenAI only)
+    pub logprobs: Option<LogProbs>,
+
+    /// `content` as the provider sent it, when [`ChatOptions::strip_reasoning`]
+    /// filtered it
+    pub raw_content: Option<String>,
+}
+
+impl ChatResponse {
+    /// [`finish_reason`](Self::finish_reason) mapped to a [`FinishReason`]
+    ///
+    /// A [`FinishReason::ContentFilter`] means the provider withheld or cut
+    /// off the answer for policy reasons; `content` holds whatever was
+    /// produced before that.
+    pub fn finish(&self) -> Option<FinishReason> {
+        self.finish_reason.as_deref().map(FinishReason::parse)
+    }
+}
+
+/// Why generation ended, normalized across providers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FinishReason {
+    /// Natural end or a stop sequence (`stop`, `end_turn`, `stop_sequence`)
+    Stop,
+    /// Output token limit reached (`length`, `max_tokens`)
+    Length,
+    /// The model requested tool execution (`tool_calls`, `tool_use`)
+    ToolCalls,
+    /// Refused or filtered for content policy (OpenAI `content_filter`,
+    /// Anthropic `refusal`)
+    ContentFilter,
+    /// Any other provider-specific reason, verbatim
+    Other(String),
+}
+
+impl FinishReason {
+    /// Map a provider's raw finish / stop reason
+    pub fn parse(raw: &str) -> Self {
+        match raw {
+            "stop" | "end_turn" | "stop_sequence" => FinishReason::Stop,
+            "length" | "max_tokens" => FinishReason::Length,
+            "tool_calls" | "tool_use" | "function_call" => FinishReason::ToolCalls,
+            "content_filter" | "refusal" => FinishReason::ContentFilter,
+            other => FinishReason::Other(other.to_string()),
+        }
+    }
+}
+
+/// Log probabilities of the generated tokens (OpenAI `choices[].logprobs`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogProbs {
+    /// One entry per generated content token; `None` when the provider sent `null`
+    #[serde(default)]
+    pub content: Option<Vec<TokenLogProb>>,
+}
+
+/// A generated token with its log probability
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenLogProb {
+    /// Token text
+    pub token: String,
+
+    /// Natural log of the token's probability
+    pub logprob: f64,
+
+    /// UTF-8 bytes of the token, when the provider includes them
+    #[serde(default)]
+    pub bytes: Option<Vec<u8>>,
+
+    /// Most likely alternatives at this position, up to `top_logprobs`
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogProb>,
+}
+
+/// An alternative token considered at one position
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopLogProb {
+    /// Token text
+    pub token: String,
+
+    /// Natural log of the token's probability
+    pub logprob: f64,
+
+    /// UTF-8 bytes of the token, when the provider includes them
+    #[serde(default)]
+    pub bytes: Option<Vec<u8>>,
+}
+
+/// Features a client can serve, so callers can check before sending a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// `chat_stream` is available
+    pub streaming: bool,
+
+    /// Tool definitions are honored
+    pub tools: bool,
+
+    /// Image content parts are understood
+    pub vision: bool,
+
+    /// `embeddings` is available
+    pub embeddings: bool,
+
+    /// `ChatOptions.response_format` (JSON output) is honored
+    pub json_mode: bool,
+}
+
+impl Capabilities {
+    /// What a `provider_type` client serves for `model`
+    pub fn for_model(provider_type: ProviderType, model: &str) -> Self {
+        if is_embedding_model(model) {
+            return Capabilities {
+                streaming: false,
+                tools: false,
+                vision: false,
+                embeddings: provider_type == ProviderType::OpenAI,
+                json_mode: false,
+            };
+        }
+        let legacy = is_text_only_model(model);
+        match provider_type {
+            ProviderType::OpenAI => Capabilities {
+                streaming: true,
+                tools: true,
+                vision: !legacy,
+                embeddings: true,
+                json_mode: true,
+            },
+            ProviderType::Anthropic => Capabilities {
+                streaming: true,
+                tools: !legacy,
+                vision: !legacy,
+                embeddings: false,
+                // `response_format` has no Messages API equivalent
+                json_mode: false,
+            },
+        }
+    }
+
+    /// Value of the capability called `name` (the field name, e.g. `vision`),
+    /// or `None` for an unknown name
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "streaming" => Some(self.streaming),
+            "tools" => Some(self.tools),
+            "vision" => Some(self.vision),
+            "embeddings" => Some(self.embeddings),
+            "json_mode" => Some(self.json_mode),
+            _ => None,
+        }
     }
 }
 
+/// Older models known to accept text only; anything else is assumed to take images
+fn is_text_only_model(model: &str) -> bool {
+    const TEXT_ONLY: [&str; 5] = ["gpt-3.5", "o1-mini", "o3-mini", "claude-2", "claude-instant"];
+    TEXT_ONLY.iter().any(|prefix| model.starts_with(prefix))
+}
+
+/// Embedding models (`text-embedding-3-small`, ...) serve no chat at all
+fn is_embedding_model(model: &str) -> bool {
+    model.contains("embedding")
+}
+
 /// Streaming event from the LLM
 #[derive(Debug, Clone)]
 pub struct StreamEvent {
@@ -325,156 +1074,318 @@ pub struct StreamEvent {
 
     /// Tool calls (when assistant requests tool execution)
     pub tool_calls: Option<Vec<ToolCall>>,
+
+    /// Reasoning ("thinking") delta, streamed separately from the answer text
+    /// by reasoning models (`reasoning_content` / Anthropic `thinking_delta`)
+    pub reasoning: Option<String>,
+
+    /// Why generation ended, on the final event when the provider said
+    pub finish_reason: Option<FinishReason>,
 }
 
 /// Trait for LLM clients
 #[async_trait::async_trait]
 pub trait Client: Send + Sync {
     /// Send a chat completion request (non-streaming)
-    /// Returns (response_content, tool_calls, usage)
-    async fn chat(&self, messages: &[Message], model: &str, tools: Option<&[ToolDefinition]>) -> Result<(String, Option<Vec<ToolCall>>, Usage)>;
+    async fn chat(&self, messages: &[Message], model: &str, tools: Option<&[ToolDefinition]>) -> Result<ChatResponse> {
+        self.chat_with_options(messages, model, tools, &ChatOptions::default()).await
+    }
+
+    /// Send a chat completion request with per-call options merged over the
+    /// configured defaults (see [`ChatOptions`])
+    async fn chat_with_options(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        options: &ChatOptions,
+    ) -> Result<ChatResponse>;
 
     /// Send a chat completion request and return the raw HTTP response.
     /// This allows the gateway to forward the upstream response without parsing/rewriting it.
     async fn chat_raw(&self, messages: &[Message], model: &str, tools: Option<&[ToolDefinition]>) -> Result<reqwest::Response>;
 
+    /// [`Client::chat_raw`] with per-call options merged over the configured
+    /// defaults; clients that cannot apply them ignore `options`
+    async fn chat_raw_with_options(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        options: &ChatOptions,
+    ) -> Result<reqwest::Response> {
+        let _ = options;
+        self.chat_raw(messages, model, tools).await
+    }
+
     /// Send a chat completion request with streaming
     fn chat_stream(
         &self,
         messages: &[Message],
         model: &str,
         tools: Option<&[ToolDefinition]>,
+    ) -> Pin<Box<dyn Stream<Item = Result<StreamEvent>> + Send>> {
+        self.chat_stream_with_options(messages, model, tools, &ChatOptions::default())
+    }
+
+    /// Streaming variant of [`Client::chat_with_options`]
+    fn chat_stream_with_options(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        options: &ChatOptions,
     ) -> Pin<Box<dyn Stream<Item = Result<StreamEvent>> + Send>>;
 
     /// Send a chat completion request and return the raw HTTP response for streaming.
     /// This allows the gateway to forward the upstream response without parsing/rewriting it.
     async fn chat_stream_raw(&self, messages: &[Message], model: &str, tools: Option<&[ToolDefinition]>) -> Result<reqwest::Response>;
 
+    /// Streaming variant of [`Client::chat_raw_with_options`]
+    async fn chat_stream_raw_with_options(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        options: &ChatOptions,
+    ) -> Result<reqwest::Response> {
+        let _ = options;
+        self.chat_stream_raw(messages, model, tools).await
+    }
+
     /// Get the API base URL
     fn api_base(&self) -> &str;
 
     /// Get the max tokens setting
     fn max_tokens(&self) -> u32;
+
+    /// Features this provider (and configured model, where known) supports
+    fn capabilities(&self) -> Capabilities;
+
+    /// Count the input tokens `messages` would use with `model`
+    ///
+    /// Anthropic asks its `count_tokens` endpoint; other providers fall back
+    /// to [`estimate_tokens`], a rough characters-based estimate.
+    async fn count_tokens(&self, messages: &[Message], model: &str) -> Result<u32> {
+        let _ = model;
+        Ok(estimate_tokens(messages))
+    }
+
+    /// Embed each of `input` with `model`
+    /// Returns one vector per input, in input order, and the prompt token usage
+    ///
+    /// Providers without an embeddings endpoint (Anthropic) return
+    /// [`Error::Api`].
+    async fn embeddings(&self, input: &[String], model: &str) -> Result<(Vec<Vec<f32>>, Usage)> {
+        let _ = (input, model);
+        Err(Error::Api("embeddings are unsupported by this provider".to_string()))
+    }
+}
+
+impl dyn Client {
+    /// Request JSON output matching `schema` and deserialize it into `T`
+    ///
+    /// Sends `schema` as an OpenAI `json_schema` response format. On parse
+    /// failure returns [`Error::Json`] whose message includes the raw content.
+    pub async fn chat_json<T: serde::de::DeserializeOwned>(
+        &self,
+        messages: &[Message],
+        model: &str,
+        schema: &serde_json::Value,
+    ) -> Result<(T, Usage)> {
+        let options = ChatOptions {
+            response_format: Some(json!({
+                "type": "json_schema",
+                "json_schema": { "name": "response", "schema": schema }
+            })),
+            ..Default::default()
+        };
+        let ChatResponse { content, usage, .. } = self.chat_with_options(messages, model, None, &options).await?;
+        let value = serde_json::from_str(&content).map_err(|e| {
+            Error::Json(serde::de::Error::custom(format!("{} (raw content: {})", e, content)))
+        })?;
+        Ok((value, usage))
+    }
+
+    /// Run independent conversations against `model`, at most `concurrency`
+    /// at a time
+    ///
+    /// Results are returned in input order. A failed request only fails its
+    /// own slot; the others still run.
+    pub async fn chat_batch(
+        &self,
+        requests: Vec<Vec<Message>>,
+        model: &str,
+        concurrency: usize,
+    ) -> Vec<Result<(String, Usage)>> {
+        use futures::stream::{self, StreamExt};
+
+        let mut results: Vec<(usize, Result<(String, Usage)>)> = stream::iter(requests.into_iter().enumerate())
+            .map(|(index, messages)| async move {
+                let result = self
+                    .chat(&messages, model, None)
+                    .await
+                    .map(|response| (response.content, response.usage));
+                (index, result)
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
+/// Rough input token estimate (about four characters per token, plus a few
+/// tokens of framing per message) for providers without a counting endpoint
+pub fn estimate_tokens(messages: &[Message]) -> u32 {
+    messages
+        .iter()
+        .map(|m| {
+            let chars = m.get_content().unwrap_or_default().chars().count() as u32;
+            chars.div_ceil(4) + 4
+        })
+        .sum()
 }
 
 /// OpenAI client implementation
 pub struct OpenAIClient {
     config: ProviderConfig,
     http_client: HttpClient,
+    retry: RetryPolicy,
 }
 
 impl OpenAIClient {
     /// Create a new OpenAI client
     pub fn new(config: ProviderConfig) -> Result<Self> {
-        let timeout = config.timeout();
         Ok(OpenAIClient {
-            http_client: build_http_client(timeout)?,
+            http_client: build_http_client(&config)?,
             config,
+            retry: RetryPolicy::default(),
         })
     }
+
+    /// Retry failed requests per `retry` instead of the default policy
+    #[cfg(test)]
+    fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Normalized outbound messages, with system prompts merged when
+    /// `merge_system_prompts` is configured
+    fn outbound_messages(&self, messages: &[Message]) -> Vec<Message> {
+        let normalized = normalize_outbound_messages(messages);
+        if self.config.merge_system_prompts {
+            merge_system_messages(normalized)
+        } else {
+            normalized
+        }
+    }
 }
 
 #[async_trait::async_trait]
 impl Client for OpenAIClient {
-    async fn chat(&self, messages: &[Message], model: &str, tools: Option<&[ToolDefinition]>) -> Result<(String, Option<Vec<ToolCall>>, Usage)> {
-        let url = format!(
-            "{}/chat/completions",
-            self.config.api_base.trim_end_matches('/')
-        );
+    async fn chat_with_options(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        options: &ChatOptions,
+    ) -> Result<ChatResponse> {
+        let url = self.config.chat_url();
+        let (auth_name, auth_value) = self.config.openai_auth_header();
 
-        let normalized_messages = normalize_outbound_messages(messages);
+        let normalized_messages = self.outbound_messages(messages);
+        validate_for(&normalized_messages, ProviderType::OpenAI)?;
         let openai_messages = messages_to_openai(&normalized_messages);
         let tools_request = tools.map(|t| t.iter().map(|tool| tool.to_openai()).collect());
+        let options = options.merged_over(&self.config.default_options());
+        let strip_reasoning = options.strip_reasoning.unwrap_or(false);
         let request = ChatRequest {
             model: model.to_string(),
             messages: openai_messages,
             stream: false,
             tools: tools_request,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            max_tokens: options.max_tokens,
+            response_format: options.response_format,
+            stop: options.stop,
+            logprobs: options.logprobs,
+            top_logprobs: options.top_logprobs,
+            stream_options: None,
+            extra_body: options.extra_body,
         };
 
-        // Retry loop for rate limiting (HTTP 429)
-        let mut attempt = 0;
-        loop {
-            let response = self
-                .http_client
-                .post(&url)
-                .header("Authorization", format!("Bearer {}", self.config.api_key))
-                .json(&request)
-                .send()
-                .await?;
-
-            let status = response.status();
+        // Serialized once so every attempt sends the same bytes
+        let body = serde_json::to_vec(&request)?;
 
-            // Handle rate limiting with retry
-            if status.as_u16() == 429 && attempt < MAX_RETRIES {
-                attempt += 1;
-                let delay = retry_delay(attempt);
-                tracing::warn!(
-                    "Rate limited (429), retrying in {:?} (attempt {}/{})",
-                    delay, attempt, MAX_RETRIES
-                );
-                tokio::time::sleep(delay).await;
-                continue;
-            }
-
-            let body = response.text().await?;
-
-            if !status.is_success() {
-                return Err(Error::Api(format!(
-                    "OpenAI API error ({}): {}",
-                    status, body
-                )));
-            }
+        let started = Instant::now();
+        let (response, attempt_started) = send_with_retry(self.retry, || {
+            self.http_client
+                .post(&url)
+                .header(auth_name, auth_value.as_str())
+                .header("content-type", "application/json")
+                .body(body.clone())
+        })
+        .await?;
 
-            let response: ChatResponse = serde_json::from_str(&body)
-                .map_err(|e| Error::Api(format!("Failed to parse OpenAI response: {}. Body: {}", e, body)))?;
-            let choice = response
-                .choices
-                .first()
-                .ok_or_else(|| Error::Api("No choices in OpenAI response".to_string()))?;
-
-            let usage = Usage {
-                prompt_tokens: response.usage.prompt_tokens,
-                completion_tokens: response.usage.completion_tokens,
-                total_tokens: response.usage.total_tokens,
-            };
+        let status = response.status();
+        let body = response.text().await?;
+        let latency = attempt_started.elapsed();
 
-            // Parse tool calls if present
-            let tool_calls = if !choice.message.tool_calls.is_empty() {
-                Some(
-                    choice.message.tool_calls.iter().map(|tc| ToolCall {
-                        id: tc.id.clone(),
-                        name: tc.function.name.clone(),
-                        arguments: tc.function.arguments.clone(),
-                    }).collect()
-                )
-            } else {
-                None
-            };
+        if !status.is_success() {
+            return Err(Error::Upstream { provider: "OpenAI", status, body });
+        }
 
-            return Ok((choice.message.content.clone(), tool_calls, usage));
+        let mut response = parse_openai_completion(&body)?;
+        response.latency = latency;
+        response.total_latency = started.elapsed();
+        if strip_reasoning {
+            response.strip_reasoning();
         }
+        Ok(response)
     }
 
     async fn chat_raw(&self, messages: &[Message], model: &str, tools: Option<&[ToolDefinition]>) -> Result<reqwest::Response> {
-        let url = format!(
-            "{}/chat/completions",
-            self.config.api_base.trim_end_matches('/')
-        );
-        let normalized_messages = normalize_outbound_messages(messages);
+        self.chat_raw_with_options(messages, model, tools, &ChatOptions::default()).await
+    }
+
+    async fn chat_raw_with_options(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        options: &ChatOptions,
+    ) -> Result<reqwest::Response> {
+        let url = self.config.chat_url();
+        let (auth_name, auth_value) = self.config.openai_auth_header();
+        let normalized_messages = self.outbound_messages(messages);
+        validate_for(&normalized_messages, ProviderType::OpenAI)?;
         let openai_messages = messages_to_openai(&normalized_messages);
         let tools_request = tools.map(|t| t.iter().map(|tool| tool.to_openai()).collect());
+        let options = options.merged_over(&self.config.default_options());
         let request = ChatRequest {
             model: model.to_string(),
             messages: openai_messages,
             stream: false,
             tools: tools_request,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            max_tokens: options.max_tokens,
+            response_format: options.response_format,
+            stop: options.stop,
+            logprobs: options.logprobs,
+            top_logprobs: options.top_logprobs,
+            stream_options: None,
+            extra_body: options.extra_body,
         };
 
         let response = self
             .http_client
             .post(&url)
-            .header("Authorization", format!("Bearer {}", self.config.api_key))
+            .header(auth_name, auth_value)
             .json(&request)
             .send()
             .await?;
@@ -482,197 +1393,165 @@ impl Client for OpenAIClient {
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
-            return Err(Error::Api(format!("OpenAI API error ({}): {}", status, body)));
+            return Err(Error::Upstream { provider: "OpenAI", status, body });
         }
 
         Ok(response)
     }
 
-    fn chat_stream(
+    fn chat_stream_with_options(
         &self,
         messages: &[Message],
         model: &str,
         tools: Option<&[ToolDefinition]>,
+        options: &ChatOptions,
     ) -> Pin<Box<dyn futures::Stream<Item = Result<StreamEvent>> + Send>> {
-        let url = format!(
-            "{}/chat/completions",
-            self.config.api_base.trim_end_matches('/')
-        );
-        let normalized_messages = normalize_outbound_messages(messages);
+        let url = self.config.chat_url();
+        let normalized_messages = self.outbound_messages(messages);
+        let validation = validate_for(&normalized_messages, ProviderType::OpenAI);
         let openai_messages = messages_to_openai(&normalized_messages);
         let tools_request = tools.map(|t| t.iter().map(|tool| tool.to_openai()).collect());
+        let options = options.merged_over(&self.config.default_options());
+        let strip_reasoning = options.strip_reasoning.unwrap_or(false);
         let request = ChatRequest {
             model: model.to_string(),
             messages: openai_messages,
             stream: true,
             tools: tools_request,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            max_tokens: options.max_tokens,
+            response_format: options.response_format,
+            stop: options.stop,
+            logprobs: options.logprobs,
+            top_logprobs: options.top_logprobs,
+            stream_options: include_usage(&options.extra_body),
+            extra_body: options.extra_body,
         };
 
-        let api_key = self.config.api_key.clone();
-        let http_client = self.http_client.clone();
+        let (auth_name, auth_value) = self.config.openai_auth_header();
+        let http_client = self.http_client.clone();
+        let retry = self.retry;
+
+        strip_reasoning_if(strip_reasoning, async_stream::stream! {
+            if let Err(e) = validation {
+                yield Err(e);
+                return;
+            }
+
+            // Serialized once so every attempt sends the same bytes
+            let body = match serde_json::to_vec(&request) {
+                Ok(body) => body,
+                Err(e) => {
+                    yield Err(e.into());
+                    return;
+                }
+            };
 
-        Box::pin(async_stream::stream! {
-            let response = match http_client
-                .post(&url)
-                .header("Authorization", format!("Bearer {}", api_key))
-                .json(&request)
-                .send()
-                .await
-            {
-                Ok(r) => r,
+            // Retry the initial request only; once events flow, errors are final
+            let sent = send_with_retry(retry, || {
+                http_client
+                    .post(&url)
+                    .header(auth_name, auth_value.as_str())
+                    .header("content-type", "application/json")
+                    .body(body.clone())
+            })
+            .await;
+            let response = match sent {
+                Ok((response, _)) if response.status().is_success() => response,
+                Ok((response, _)) => {
+                    let status = response.status();
+                    let body = response.text().await.unwrap_or_default();
+                    yield Err(Error::Upstream { provider: "OpenAI", status, body });
+                    return;
+                }
                 Err(e) => {
-                    yield Err(Error::from(e));
+                    yield Err(e);
                     return;
                 }
             };
 
-            if !response.status().is_success() {
-                let status = response.status();
-                let body = response.text().await.unwrap_or_default();
-                yield Err(Error::Api(format!("OpenAI API error ({}): {}", status, body)));
+            // Some OpenAI-compatible servers ignore `stream: true` and send
+            // the whole completion as one JSON body
+            if is_json_response(&response) {
+                tracing::warn!("Streaming request answered with a JSON body; emitting it as one event");
+                let completion = match response.text().await {
+                    Ok(body) => parse_openai_completion(&body),
+                    Err(e) => Err(Error::from(e)),
+                };
+                match completion {
+                    Ok(completion) => {
+                        let finish_reason = completion.finish();
+                        yield Ok(StreamEvent {
+                            delta: completion.content,
+                            done: false,
+                            usage: None,
+                            tool_calls: None,
+                            reasoning: completion.reasoning,
+                            finish_reason: None,
+                        });
+                        yield Ok(StreamEvent {
+                            finish_reason,
+                            delta: String::new(),
+                            done: true,
+                            usage: Some(completion.usage),
+                            tool_calls: completion.tool_calls,
+                            reasoning: None,
+                        });
+                    }
+                    Err(e) => yield Err(e),
+                }
                 return;
             }
 
-            let mut stream = response.bytes_stream();
-
             use futures::StreamExt;
-            let mut sse = SseBuffer::new();
-            let mut usage: Option<Usage> = None;
-
-            // Track accumulated tool calls
-            let mut accumulated_tools: std::collections::HashMap<i32, ToolCall> = std::collections::HashMap::new();
-
-            while let Some(chunk_result) = stream.next().await {
-                let chunk = match chunk_result {
-                    Ok(c) => c,
-                    Err(e) => {
-                        yield Err(Error::from(e));
-                        return;
-                    }
-                };
-
-                sse.extend(&chunk);
-
-                while let Some(sse_line) = sse.next_line() {
-                    match sse_line {
-                        SseLine::Done => {
-                            // Yield any accumulated tool calls at the end
-                            if !accumulated_tools.is_empty() {
-                                let tool_calls: Vec<ToolCall> = accumulated_tools.values().cloned().collect();
-                                yield Ok(StreamEvent {
-                                    tool_calls: Some(tool_calls),
-                                    delta: String::new(),
-                                    done: true,
-                                    usage: usage.clone(),
-                                });
-                            } else {
-                                yield Ok(StreamEvent {
-                                    tool_calls: None,
-                                    delta: String::new(),
-                                    done: true,
-                                    usage: usage.clone(),
-                                });
-                            }
-                            return;
-                        }
-                        SseLine::Data(json_str) => {
-                            match serde_json::from_str::<ChatStreamChunk>(&json_str) {
-                                Ok(chunk) => {
-                                    // Extract usage when available (final chunk)
-                                    if let Some(ref u) = chunk.usage {
-                                        usage = Some(Usage {
-                                            prompt_tokens: u.prompt_tokens,
-                                            completion_tokens: u.completion_tokens,
-                                            total_tokens: u.total_tokens,
-                                        });
-                                    }
-
-                                    if let Some(delta) = chunk.choices.first() {
-                                        let delta_text = delta.delta.content.clone().unwrap_or_default();
-                                        let done = delta.finish_reason.as_deref() == Some("stop") ||
-                                                  delta.finish_reason.as_deref() == Some("tool_calls");
-
-                                        // Process tool calls
-                                        for tc in &delta.delta.tool_calls {
-                                            let entry = accumulated_tools.entry(tc.index).or_insert_with(|| ToolCall {
-                                                id: tc.tool_id.clone().unwrap_or_default(),
-                                                name: String::new(),
-                                                arguments: String::new(),
-                                            });
-
-                                            if let Some(ref id) = tc.tool_id {
-                                                entry.id = id.clone();
-                                            }
-                                            if let Some(ref func) = tc.function {
-                                                if let Some(ref name) = func.function_name {
-                                                    entry.name = name.clone();
-                                                }
-                                                if let Some(ref args) = func.function_arguments {
-                                                    entry.arguments.push_str(args);
-                                                }
-                                            }
-                                        }
-
-                                        // Yield text delta if present
-                                        if !delta_text.is_empty() {
-                                            yield Ok(StreamEvent {
-                                                tool_calls: None,
-                                                delta: delta_text,
-                                                done: false,
-                                                usage: None,
-                                            });
-                                        }
-
-                                        // Yield tool calls if done
-                                        if done && !accumulated_tools.is_empty() {
-                                            let tool_calls: Vec<ToolCall> = accumulated_tools.values().cloned().collect();
-                                            yield Ok(StreamEvent {
-                                                tool_calls: Some(tool_calls),
-                                                delta: String::new(),
-                                                done: true,
-                                                usage: usage.clone(),
-                                            });
-                                        } else if done {
-                                            yield Ok(StreamEvent {
-                                                tool_calls: None,
-                                                delta: String::new(),
-                                                done: true,
-                                                usage: usage.clone(),
-                                            });
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    tracing::warn!("Failed to parse SSE chunk: {}", e);
-                                }
-                            }
-                        }
-                        _ => {} // Skip empty lines and event: lines
-                    }
-                }
+            let mut events = openai_sse_events(response.bytes_stream());
+            while let Some(event) = events.next().await {
+                yield event;
             }
         })
     }
 
     async fn chat_stream_raw(&self, messages: &[Message], model: &str, tools: Option<&[ToolDefinition]>) -> Result<reqwest::Response> {
-        let url = format!(
-            "{}/chat/completions",
-            self.config.api_base.trim_end_matches('/')
-        );
-        let normalized_messages = normalize_outbound_messages(messages);
+        self.chat_stream_raw_with_options(messages, model, tools, &ChatOptions::default()).await
+    }
+
+    async fn chat_stream_raw_with_options(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        options: &ChatOptions,
+    ) -> Result<reqwest::Response> {
+        let url = self.config.chat_url();
+        let (auth_name, auth_value) = self.config.openai_auth_header();
+        let normalized_messages = self.outbound_messages(messages);
+        validate_for(&normalized_messages, ProviderType::OpenAI)?;
         let openai_messages = messages_to_openai(&normalized_messages);
         let tools_request = tools.map(|t| t.iter().map(|tool| tool.to_openai()).collect());
+        let options = options.merged_over(&self.config.default_options());
         let request = ChatRequest {
             model: model.to_string(),
             messages: openai_messages,
             stream: true,
             tools: tools_request,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            max_tokens: options.max_tokens,
+            response_format: options.response_format,
+            stop: options.stop,
+            logprobs: options.logprobs,
+            top_logprobs: options.top_logprobs,
+            // Passed through as the caller sent it; the gateway asks for
+            // usage itself when it needs it
+            stream_options: None,
+            extra_body: options.extra_body,
         };
 
         let response = self
             .http_client
             .post(&url)
-            .header("Authorization", format!("Bearer {}", self.config.api_key))
+            .header(auth_name, auth_value)
             .json(&request)
             .send()
             .await?;
@@ -680,7 +1559,7 @@ impl Client for OpenAIClient {
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
-            return Err(Error::Api(format!("OpenAI API error ({}): {}", status, body)));
+            return Err(Error::Upstream { provider: "OpenAI", status, body });
         }
 
         Ok(response)
@@ -693,145 +1572,224 @@ impl Client for OpenAIClient {
     fn max_tokens(&self) -> u32 {
         self.config.max_tokens()
     }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::for_model(ProviderType::OpenAI, self.config.model.as_deref().unwrap_or_default())
+    }
+
+    async fn embeddings(&self, input: &[String], model: &str) -> Result<(Vec<Vec<f32>>, Usage)> {
+        let (auth_name, auth_value) = self.config.openai_auth_header();
+        let request = EmbeddingRequest { model, input };
+
+        let response = self
+            .http_client
+            .post(self.config.embeddings_url())
+            .header(auth_name, auth_value)
+            .json(&request)
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(Error::Upstream { provider: "OpenAI", status, body });
+        }
+
+        let mut response: EmbeddingResponse = parse_success_body(&body, "OpenAI embeddings response")?;
+        response.data.sort_by_key(|d| d.index);
+
+        let usage = Usage {
+            prompt_tokens: response.usage.prompt_tokens,
+            completion_tokens: 0,
+            total_tokens: response.usage.total_tokens.unwrap_or(response.usage.prompt_tokens),
+            ..Default::default()
+        };
+        Ok((response.data.into_iter().map(|d| d.embedding).collect(), usage))
+    }
 }
 
 /// Anthropic client implementation
 pub struct AnthropicClient {
     config: ProviderConfig,
     http_client: HttpClient,
+    retry: RetryPolicy,
+}
+
+/// Anthropic beta enabling `cache_control` markers
+const PROMPT_CACHING_BETA: &str = "prompt-caching-2024-07-31";
+
+/// Add the Anthropic authentication, version and beta headers from `config`,
+/// enabling the prompt caching beta when the request marks a message
+/// [`cacheable`](Message::cacheable)
+pub(crate) fn anthropic_headers(
+    request: reqwest::RequestBuilder,
+    config: &ProviderConfig,
+    prompt_caching: bool,
+) -> reqwest::RequestBuilder {
+    let mut beta = config.anthropic_beta.clone();
+    if prompt_caching && !beta.iter().any(|b| b == PROMPT_CACHING_BETA) {
+        beta.push(PROMPT_CACHING_BETA.to_string());
+    }
+    let request = request
+        .header("x-api-key", config.api_key.as_str())
+        .header("anthropic-version", config.anthropic_version());
+    if beta.is_empty() {
+        request
+    } else {
+        request.header("anthropic-beta", beta.join(","))
+    }
 }
 
 impl AnthropicClient {
     /// Create a new Anthropic client
     pub fn new(config: ProviderConfig) -> Result<Self> {
-        let timeout = config.timeout();
         Ok(AnthropicClient {
-            http_client: build_http_client(timeout)?,
+            http_client: build_http_client(&config)?,
             config,
+            retry: RetryPolicy::default(),
         })
     }
 }
 
 #[async_trait::async_trait]
 impl Client for AnthropicClient {
-    async fn chat(&self, messages: &[Message], model: &str, tools: Option<&[ToolDefinition]>) -> Result<(String, Option<Vec<ToolCall>>, Usage)> {
-        let url = format!("{}/v1/messages", self.config.api_base.trim_end_matches('/'));
+    async fn chat_with_options(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        options: &ChatOptions,
+    ) -> Result<ChatResponse> {
+        let url = self.config.chat_url();
 
         // Extract system message if present
         let normalized_messages = normalize_outbound_messages(messages);
-        let (system, others): (Vec<_>, Vec<_>) = normalized_messages
-            .iter()
-            .partition(|m| m.role == crate::MessageRole::System);
-
-        let system_content = system.first().and_then(|m| m.get_content().map(|s| s.to_string()));
-        let messages: Vec<_> = others.into_iter().cloned().collect();
+        validate_for(&normalized_messages, ProviderType::Anthropic)?;
+        let prompt_caching = normalized_messages.iter().any(|m| m.cacheable);
+        let (system_content, messages) = split_anthropic_system(&normalized_messages);
 
         let tools_request = tools.map(|t| t.iter().map(|tool| tool.to_anthropic()).collect());
+        let options = options.merged_over(&self.config.default_options());
+        let strip_reasoning = options.strip_reasoning.unwrap_or(false);
         let request = AnthropicMessageRequest {
             model: model.to_string(),
             messages: messages.clone(),
             system: system_content,
-            max_tokens: self.config.max_tokens(),
+            max_tokens: options.max_tokens.unwrap_or_else(|| self.config.max_tokens()),
             stream: None, // No streaming for regular chat
             tools: tools_request,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            stop_sequences: options.stop,
+            extra_body: options.extra_body,
         };
 
-        // Retry loop for rate limiting (HTTP 429)
-        let mut attempt = 0;
-        loop {
-            let response = self
-                .http_client
-                .post(&url)
-                .header("x-api-key", self.config.api_key.clone())
-                .header("anthropic-version", "2023-06-01")
-                .header("content-type", "application/json")
-                .json(&request)
-                .send()
-                .await?;
-
-            let status = response.status();
+        // Serialized once so every attempt sends the same bytes
+        let body = serde_json::to_vec(&request)?;
 
-            // Handle rate limiting with retry
-            if status.as_u16() == 429 && attempt < MAX_RETRIES {
-                attempt += 1;
-                let delay = retry_delay(attempt);
-                tracing::warn!(
-                    "Rate limited (429), retrying in {:?} (attempt {}/{})",
-                    delay, attempt, MAX_RETRIES
-                );
-                tokio::time::sleep(delay).await;
-                continue;
-            }
+        let started = Instant::now();
+        let (response, attempt_started) = send_with_retry(self.retry, || {
+            anthropic_headers(self.http_client.post(&url), &self.config, prompt_caching)
+                .header("content-type", "application/json")
+                .body(body.clone())
+        })
+        .await?;
 
-            let body = response.text().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        let latency = attempt_started.elapsed();
 
-            if !status.is_success() {
-                return Err(Error::Api(format!(
-                    "Anthropic API error ({}): {}",
-                    status, body
-                )));
-            }
+        if !status.is_success() {
+            return Err(Error::Upstream { provider: "Anthropic", status, body });
+        }
 
-            let response: AnthropicMessageResponse = serde_json::from_str(&body)
-                .map_err(|e| Error::Api(format!("Failed to parse Anthropic response: {}. Body: {}", e, body)))?;
-            let usage = Usage {
-                prompt_tokens: response.usage.input_tokens,
-                completion_tokens: response.usage.output_tokens,
-                total_tokens: response.usage.input_tokens + response.usage.output_tokens,
-            };
+        let response: AnthropicMessageResponse = parse_success_body(&body, "Anthropic response")?;
+        let usage = Usage {
+            prompt_tokens: response.usage.input_tokens,
+            completion_tokens: response.usage.output_tokens,
+            total_tokens: response.usage.input_tokens + response.usage.output_tokens,
+            cached_tokens: response.usage.cache_read_input_tokens,
+            cache_creation_tokens: response.usage.cache_creation_input_tokens,
+        };
 
-            // Parse content blocks to extract text and tool calls
-            let mut text_parts = Vec::new();
-            let mut tool_calls = Vec::new();
+        // Parse content blocks to extract text and tool calls
+        let mut text_parts = Vec::new();
+        let mut thinking_parts = Vec::new();
+        let mut tool_calls = Vec::new();
 
-            for block in &response.content {
-                match block {
-                    AnthropicContentBlock::Text { text } => {
-                        text_parts.push(text.clone());
-                    }
-                    AnthropicContentBlock::ToolUse { id, name, input } => {
-                        tool_calls.push(ToolCall {
-                            id: id.clone(),
-                            name: name.clone(),
-                            arguments: serde_json::to_string(input)
-                                .unwrap_or_else(|_| String::new()),
-                        });
-                    }
+        for block in &response.content {
+            match block {
+                AnthropicContentBlock::Text { text } => {
+                    text_parts.push(text.clone());
+                }
+                AnthropicContentBlock::ToolUse { id, name, input } => {
+                    tool_calls.push(ToolCall {
+                        id: id.clone(),
+                        name: name.clone(),
+                        arguments: serde_json::to_string(input)
+                            .unwrap_or_else(|_| String::new()),
+                    });
+                }
+                AnthropicContentBlock::Thinking { thinking } => {
+                    thinking_parts.push(thinking.clone());
                 }
+                AnthropicContentBlock::Other => {}
             }
+        }
 
-            let text = text_parts.join("\n");
-
-            return Ok((text, if tool_calls.is_empty() { None } else { Some(tool_calls) }, usage));
+        let text = text_parts.join("\n");
+
+        let mut response = ChatResponse {
+            content: text,
+            reasoning: (!thinking_parts.is_empty()).then(|| thinking_parts.join("\n")),
+            tool_calls: if tool_calls.is_empty() { None } else { Some(tool_calls) },
+            usage,
+            finish_reason: response.stop_reason.clone(),
+            latency,
+            total_latency: started.elapsed(),
+            logprobs: None,
+            raw_content: None,
+        };
+        if strip_reasoning {
+            response.strip_reasoning();
         }
+        Ok(response)
     }
 
     async fn chat_raw(&self, messages: &[Message], model: &str, tools: Option<&[ToolDefinition]>) -> Result<reqwest::Response> {
-        let url = format!("{}/v1/messages", self.config.api_base.trim_end_matches('/'));
+        self.chat_raw_with_options(messages, model, tools, &ChatOptions::default()).await
+    }
 
-        let normalized_messages = normalize_outbound_messages(messages);
-        let (system, others): (Vec<_>, Vec<_>) = normalized_messages
-            .iter()
-            .partition(|m| m.role == crate::MessageRole::System);
+    async fn chat_raw_with_options(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        options: &ChatOptions,
+    ) -> Result<reqwest::Response> {
+        let url = self.config.chat_url();
 
-        let system_content = system.first().and_then(|m| m.get_content().map(|s| s.to_string()));
-        let messages: Vec<_> = others.into_iter().cloned().collect();
+        let normalized_messages = normalize_outbound_messages(messages);
+        validate_for(&normalized_messages, ProviderType::Anthropic)?;
+        let prompt_caching = normalized_messages.iter().any(|m| m.cacheable);
+        let (system_content, messages) = split_anthropic_system(&normalized_messages);
 
         let tools_request = tools.map(|t| t.iter().map(|tool| tool.to_anthropic()).collect());
+        let options = options.merged_over(&self.config.default_options());
         let request = AnthropicMessageRequest {
             model: model.to_string(),
             messages,
             system: system_content,
-            max_tokens: self.config.max_tokens(),
+            max_tokens: options.max_tokens.unwrap_or_else(|| self.config.max_tokens()),
             stream: None,
             tools: tools_request,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            stop_sequences: options.stop,
+            extra_body: options.extra_body,
         };
 
-        let response = self
-            .http_client
-            .post(&url)
-            .header("x-api-key", self.config.api_key.clone())
-            .header("anthropic-version", "2023-06-01")
+        let response = anthropic_headers(self.http_client.post(&url), &self.config, prompt_caching)
             .header("content-type", "application/json")
             .json(&request)
             .send()
@@ -840,207 +1798,124 @@ impl Client for AnthropicClient {
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
-            return Err(Error::Api(format!("Anthropic API error ({}): {}", status, body)));
+            return Err(Error::Upstream { provider: "Anthropic", status, body });
         }
 
         Ok(response)
     }
 
-    fn chat_stream(
+    fn chat_stream_with_options(
         &self,
         messages: &[Message],
         model: &str,
         tools: Option<&[ToolDefinition]>,
+        options: &ChatOptions,
     ) -> Pin<Box<dyn futures::Stream<Item = Result<StreamEvent>> + Send>> {
-        let url = format!("{}/v1/messages", self.config.api_base.trim_end_matches('/'));
+        let url = self.config.chat_url();
 
         let normalized_messages = normalize_outbound_messages(messages);
-        let (system, others): (Vec<_>, Vec<_>) = normalized_messages
-            .iter()
-            .partition(|m| m.role == crate::MessageRole::System);
-
-        let system_content = system.first().and_then(|m| m.get_content().map(|s| s.to_string()));
-        let messages: Vec<_> = others.into_iter().cloned().collect();
+        let validation = validate_for(&normalized_messages, ProviderType::Anthropic);
+        let prompt_caching = normalized_messages.iter().any(|m| m.cacheable);
+        let (system_content, messages) = split_anthropic_system(&normalized_messages);
 
         let tools_request = tools.map(|t| t.iter().map(|tool| tool.to_anthropic()).collect());
+        let options = options.merged_over(&self.config.default_options());
+        let strip_reasoning = options.strip_reasoning.unwrap_or(false);
         let request = AnthropicMessageRequest {
             model: model.to_string(),
             messages,
             system: system_content,
-            max_tokens: self.config.max_tokens(),
+            max_tokens: options.max_tokens.unwrap_or_else(|| self.config.max_tokens()),
             stream: Some(true),
             tools: tools_request,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            stop_sequences: options.stop,
+            extra_body: options.extra_body,
         };
 
-        let api_key = self.config.api_key.clone();
+        let config = self.config.clone();
         let http_client = self.http_client.clone();
+        let retry = self.retry;
 
-        Box::pin(async_stream::stream! {
-            let response = match http_client
-                .post(&url)
-                .header("x-api-key", api_key)
-                .header("anthropic-version", "2023-06-01")
-                .header("content-type", "application/json")
-                .json(&request)
-                .send()
-                .await
-            {
-                Ok(r) => r,
+        strip_reasoning_if(strip_reasoning, async_stream::stream! {
+            if let Err(e) = validation {
+                yield Err(e);
+                return;
+            }
+
+            // Serialized once so every attempt sends the same bytes
+            let body = match serde_json::to_vec(&request) {
+                Ok(body) => body,
                 Err(e) => {
-                    yield Err(Error::from(e));
+                    yield Err(e.into());
                     return;
                 }
             };
 
-            if !response.status().is_success() {
-                let status = response.status();
-                let body = response.text().await.unwrap_or_default();
-                yield Err(Error::Api(format!("Anthropic API error ({}): {}", status, body)));
-                return;
-            }
-
-            let mut stream = response.bytes_stream();
+            // Retry the initial request only; once events flow, errors are final
+            let sent = send_with_retry(retry, || {
+                anthropic_headers(http_client.post(&url), &config, prompt_caching)
+                    .header("content-type", "application/json")
+                    .body(body.clone())
+            })
+            .await;
+            let response = match sent {
+                Ok((response, _)) if response.status().is_success() => response,
+                Ok((response, _)) => {
+                    let status = response.status();
+                    let body = response.text().await.unwrap_or_default();
+                    yield Err(Error::Upstream { provider: "Anthropic", status, body });
+                    return;
+                }
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            };
 
             use futures::StreamExt;
-            let mut sse = SseBuffer::new();
-            let mut usage: Option<Usage> = None;
-
-            // Track accumulated tool calls for streaming
-            let mut tool_blocks: std::collections::HashMap<u32, ToolCall> = std::collections::HashMap::new();
-
-            while let Some(chunk_result) = stream.next().await {
-                let chunk = match chunk_result {
-                    Ok(c) => c,
-                    Err(e) => {
-                        yield Err(Error::from(e));
-                        return;
-                    }
-                };
-
-                sse.extend(&chunk);
-
-                while let Some(sse_line) = sse.next_line() {
-                    match sse_line {
-                        SseLine::Event(name) if name == "message_stop" => {
-                            // Yield accumulated tool calls if any
-                            let tool_calls = if !tool_blocks.is_empty() {
-                                let mut calls: Vec<(u32, ToolCall)> = tool_blocks.drain().collect();
-                                calls.sort_by_key(|(idx, _)| *idx);
-                                Some(calls.into_iter().map(|(_, tc)| tc).collect())
-                            } else {
-                                None
-                            };
-                            yield Ok(StreamEvent { tool_calls, delta: String::new(), done: true, usage: usage.clone() });
-                            return;
-                        }
-                        SseLine::Data(json_str) => {
-                            match serde_json::from_str::<AnthropicStreamChunk>(&json_str) {
-                                Ok(chunk) => {
-                                    // Extract usage from message if available (message_start event)
-                                    if let Some(msg) = &chunk.message {
-                                        if let Some(u) = &msg.usage {
-                                            usage = Some(Usage {
-                                                prompt_tokens: u.input_tokens,
-                                                completion_tokens: u.output_tokens,
-                                                total_tokens: u.input_tokens + u.output_tokens,
-                                            });
-                                        }
-                                    }
-
-                                    // Extract usage from message_delta event (GLM API returns usage here)
-                                    if chunk.type_ == "message_delta" {
-                                        if let Some(u) = &chunk.usage_info {
-                                            usage = Some(Usage {
-                                                prompt_tokens: u.input_tokens,
-                                                completion_tokens: u.output_tokens,
-                                                total_tokens: u.input_tokens + u.output_tokens,
-                                            });
-                                        }
-                                    }
-
-                                    match chunk.type_.as_str() {
-                                        "content_block_start" => {
-                                            // Start of a new content block — may be text or tool_use
-                                            if let Some(AnthropicStreamContentBlock::ToolUse { id, name, .. }) = &chunk.content_block {
-                                                tool_blocks.insert(chunk.index, ToolCall {
-                                                    id: id.clone(),
-                                                    name: name.clone(),
-                                                    arguments: String::new(),
-                                                });
-                                            }
-                                        }
-                                        "content_block_delta" => {
-                                            if let Some(StreamDelta::ContentBlock(delta)) = &chunk.delta {
-                                                match delta.type_.as_str() {
-                                                    "text_delta" if !delta.text.is_empty() => {
-                                                        yield Ok(StreamEvent { tool_calls: None, delta: delta.text.clone(), done: false, usage: None });
-                                                    }
-                                                    "input_json_delta" => {
-                                                        // Accumulate partial JSON for tool_use arguments
-                                                        if let Some(ref partial) = delta.partial_json {
-                                                            if let Some(tc) = tool_blocks.get_mut(&chunk.index) {
-                                                                tc.arguments.push_str(partial);
-                                                            }
-                                                        }
-                                                    }
-                                                    _ => {}
-                                                }
-                                            }
-                                        }
-                                        "message_stop" => {
-                                            let tool_calls = if !tool_blocks.is_empty() {
-                                                let mut calls: Vec<(u32, ToolCall)> = tool_blocks.drain().collect();
-                                                calls.sort_by_key(|(idx, _)| *idx);
-                                                Some(calls.into_iter().map(|(_, tc)| tc).collect())
-                                            } else {
-                                                None
-                                            };
-                                            yield Ok(StreamEvent { tool_calls, delta: String::new(), done: true, usage: usage.clone() });
-                                            return;
-                                        }
-                                        _ => {} // message_delta, content_block_stop, ping, etc.
-                                    }
-                                }
-                                Err(e) => {
-                                    tracing::warn!("Failed to parse SSE chunk: {}", e);
-                                }
-                            }
-                        }
-                        _ => {} // Skip empty lines and other events
-                    }
-                }
+            let mut events = anthropic_sse_events(response.bytes_stream());
+            while let Some(event) = events.next().await {
+                yield event;
             }
-
-            tracing::warn!("SSE stream ended unexpectedly");
         })
     }
 
     async fn chat_stream_raw(&self, messages: &[Message], model: &str, tools: Option<&[ToolDefinition]>) -> Result<reqwest::Response> {
-        let url = format!("{}/v1/messages", self.config.api_base.trim_end_matches('/'));
+        self.chat_stream_raw_with_options(messages, model, tools, &ChatOptions::default()).await
+    }
 
-        let normalized_messages = normalize_outbound_messages(messages);
-        let (system, others): (Vec<_>, Vec<_>) = normalized_messages
-            .iter()
-            .partition(|m| m.role == crate::MessageRole::System);
+    async fn chat_stream_raw_with_options(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<&[ToolDefinition]>,
+        options: &ChatOptions,
+    ) -> Result<reqwest::Response> {
+        let url = self.config.chat_url();
 
-        let system_content = system.first().and_then(|m| m.get_content().map(|s| s.to_string()));
-        let messages: Vec<_> = others.into_iter().cloned().collect();
+        let normalized_messages = normalize_outbound_messages(messages);
+        validate_for(&normalized_messages, ProviderType::Anthropic)?;
+        let prompt_caching = normalized_messages.iter().any(|m| m.cacheable);
+        let (system_content, messages) = split_anthropic_system(&normalized_messages);
 
         let tools_request = tools.map(|t| t.iter().map(|tool| tool.to_anthropic()).collect());
+        let options = options.merged_over(&self.config.default_options());
         let request = AnthropicMessageRequest {
             model: model.to_string(),
             messages,
             system: system_content,
-            max_tokens: self.config.max_tokens(),
+            max_tokens: options.max_tokens.unwrap_or_else(|| self.config.max_tokens()),
             stream: Some(true),
             tools: tools_request,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            stop_sequences: options.stop,
+            extra_body: options.extra_body,
         };
 
-        let response = self
-            .http_client
-            .post(&url)
-            .header("x-api-key", self.config.api_key.clone())
-            .header("anthropic-version", "2023-06-01")
+        let response = anthropic_headers(self.http_client.post(&url), &self.config, prompt_caching)
             .header("content-type", "application/json")
             .json(&request)
             .send()
@@ -1049,7 +1924,7 @@ impl Client for AnthropicClient {
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
-            return Err(Error::Api(format!("Anthropic API error ({}): {}", status, body)));
+            return Err(Error::Upstream { provider: "Anthropic", status, body });
         }
 
         Ok(response)
@@ -1062,6 +1937,41 @@ impl Client for AnthropicClient {
     fn max_tokens(&self) -> u32 {
         self.config.max_tokens()
     }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::for_model(ProviderType::Anthropic, self.config.model.as_deref().unwrap_or_default())
+    }
+
+    async fn count_tokens(&self, messages: &[Message], model: &str) -> Result<u32> {
+        let url = format!("{}/count_tokens", self.config.chat_url());
+
+        let normalized_messages = normalize_outbound_messages(messages);
+        validate_for(&normalized_messages, ProviderType::Anthropic)?;
+        let prompt_caching = normalized_messages.iter().any(|m| m.cacheable);
+        // Same system/messages split as a chat request, so the count matches
+        let (system, messages) = split_anthropic_system(&normalized_messages);
+
+        let request = AnthropicCountTokensRequest {
+            model: model.to_string(),
+            messages,
+            system,
+        };
+
+        let response = anthropic_headers(self.http_client.post(&url), &self.config, prompt_caching)
+            .header("content-type", "application/json")
+            .json(&request)
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(Error::Upstream { provider: "Anthropic", status, body });
+        }
+
+        let response: AnthropicCountTokensResponse = parse_success_body(&body, "Anthropic count_tokens response")?;
+        Ok(response.input_tokens)
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -1134,6 +2044,25 @@ struct ChatRequest {
     stream: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<OpenAIToolDefinition>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logprobs: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_logprobs: Option<u32>,
+    /// Asks streams to report usage in their last chunk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<serde_json::Value>,
+    #[serde(flatten)]
+    extra_body: Option<serde_json::Map<String, serde_json::Value>>,
 }
 
 #[derive(Debug, Serialize)]
@@ -1152,20 +2081,32 @@ struct OpenAIFunctionDefinition {
 }
 
 #[derive(Debug, Deserialize)]
-struct ChatResponse {
+struct ChatCompletionResponse {
     choices: Vec<ChatChoice>,
-    usage: ChatUsage,
+    #[serde(default)]
+    usage: Option<ChatUsage>,
 }
 
 #[derive(Debug, Deserialize)]
 struct ChatChoice {
     message: ChatMessage,
+    #[serde(default)]
+    finish_reason: Option<String>,
+    #[serde(default)]
+    logprobs: Option<LogProbs>,
 }
 
 #[derive(Debug, Deserialize)]
 struct ChatMessage {
+    /// `assistant` from well-behaved upstreams
+    #[serde(default)]
+    role: Option<String>,
+    /// `null` when the model only requested tools
+    #[serde(default)]
+    content: Option<String>,
+    /// Separate reasoning text of reasoning models
     #[serde(default)]
-    content: String,
+    reasoning_content: Option<String>,
     #[serde(default)]
     tool_calls: Vec<OpenAIToolCall>,
 }
@@ -1190,6 +2131,54 @@ struct ChatUsage {
     prompt_tokens: u32,
     completion_tokens: u32,
     total_tokens: u32,
+    #[serde(default)]
+    prompt_tokens_details: Option<PromptTokensDetails>,
+}
+
+/// Breakdown of `prompt_tokens`; `cached_tokens` were served from OpenAI's
+/// automatic prompt cache
+#[derive(Debug, Deserialize)]
+struct PromptTokensDetails {
+    #[serde(default)]
+    cached_tokens: Option<u32>,
+}
+
+impl ChatUsage {
+    fn to_usage(&self) -> Usage {
+        Usage {
+            prompt_tokens: self.prompt_tokens,
+            completion_tokens: self.completion_tokens,
+            total_tokens: self.total_tokens,
+            cached_tokens: self.prompt_tokens_details.as_ref().and_then(|d| d.cached_tokens),
+            cache_creation_tokens: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+    usage: EmbeddingUsage,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingUsage {
+    prompt_tokens: u32,
+    #[serde(default)]
+    total_tokens: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -1211,6 +2200,8 @@ struct ChatStreamDelta {
     #[serde(default)]
     content: Option<String>,
     #[serde(default)]
+    reasoning_content: Option<String>,
+    #[serde(default)]
     tool_calls: Vec<ChatStreamToolCall>,
 }
 
@@ -1243,12 +2234,33 @@ struct ChatStreamFunctionCall {
 struct AnthropicMessageRequest {
     model: String,
     messages: Vec<Message>,
-    system: Option<String>,
+    system: Option<serde_json::Value>,
     max_tokens: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
     stream: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<AnthropicToolDefinition>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(flatten)]
+    extra_body: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicCountTokensRequest {
+    model: String,
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicCountTokensResponse {
+    input_tokens: u32,
 }
 
 #[derive(Debug, Serialize)]
@@ -1278,12 +2290,21 @@ enum AnthropicContentBlock {
         name: String,
         input: serde_json::Value,
     },
+    #[serde(rename = "thinking")]
+    Thinking { thinking: String },
+    /// Blocks without a counterpart here, e.g. `redacted_thinking`
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Debug, Deserialize)]
 struct AnthropicUsage {
     input_tokens: u32,
     output_tokens: u32,
+    #[serde(default)]
+    cache_read_input_tokens: Option<u32>,
+    #[serde(default)]
+    cache_creation_input_tokens: Option<u32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -1310,7 +2331,6 @@ struct AnthropicStreamChunk {
 #[serde(untagged)]
 enum StreamDelta {
     ContentBlock(AnthropicDelta),
-    #[allow(dead_code)]
     MessageDelta(AnthropicMessageDelta),
 }
 
@@ -1327,6 +2347,9 @@ struct AnthropicDelta {
     type_: String,
     #[serde(default)]
     text: String,
+    /// Reasoning text for thinking_delta events
+    #[serde(default)]
+    thinking: String,
     /// Partial JSON for input_json_delta events (tool_use arguments)
     #[serde(default)]
     partial_json: Option<String>,
@@ -1366,11 +2389,38 @@ struct AnthropicStreamMessage {
     usage: Option<AnthropicStreamUsage>,
 }
 
+/// Token counts on `message_start` and `message_delta` events; either may be
+/// missing (`message_delta` usually carries only `output_tokens`)
 #[derive(Debug, Deserialize)]
-#[allow(dead_code)]
 struct AnthropicStreamUsage {
+    #[serde(default)]
     input_tokens: u32,
+    #[serde(default)]
     output_tokens: u32,
+    #[serde(default)]
+    cache_read_input_tokens: Option<u32>,
+    #[serde(default)]
+    cache_creation_input_tokens: Option<u32>,
+}
+
+impl AnthropicStreamUsage {
+    /// Fold these counts into the stream's running usage. `output_tokens` is
+    /// cumulative, so it replaces the previous value; `input_tokens` from
+    /// `message_start` is kept unless a later event reports a nonzero count.
+    fn accumulate_into(&self, usage: &mut Option<Usage>) {
+        let current = usage.get_or_insert_with(Usage::default);
+        if self.input_tokens > 0 {
+            current.prompt_tokens = self.input_tokens;
+        }
+        current.completion_tokens = self.output_tokens.max(current.completion_tokens);
+        current.total_tokens = current.prompt_tokens + current.completion_tokens;
+        if self.cache_read_input_tokens.is_some() {
+            current.cached_tokens = self.cache_read_input_tokens;
+        }
+        if self.cache_creation_input_tokens.is_some() {
+            current.cache_creation_tokens = self.cache_creation_input_tokens;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1378,6 +2428,46 @@ mod tests {
     use super::*;
     use crate::MessageRole;
 
+    /// The default retry policy without its waits, so retry tests run instantly
+    fn immediate_retry() -> RetryPolicy {
+        RetryPolicy { base_delay: Duration::ZERO, ..RetryPolicy::default() }
+    }
+
+    #[test]
+    fn test_stream_error_message_ignores_null_error() {
+        let chunk = r#"{"choices":[{"index":0,"delta":{"content":"Hi"}}],"error":null}"#;
+        assert_eq!(stream_error_message(chunk), None);
+
+        let openai = r#"{"error":{"message":"overloaded","type":"server_error"}}"#;
+        assert_eq!(stream_error_message(openai).as_deref(), Some("overloaded"));
+        let anthropic = r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        assert_eq!(stream_error_message(anthropic).as_deref(), Some("Overloaded"));
+        assert_eq!(stream_error_message(r#"{"error":"boom"}"#).as_deref(), Some("boom"));
+    }
+
+    #[test]
+    fn test_retry_delay_doubles_up_to_cap() {
+        let policy = RetryPolicy::default();
+        let delays: Vec<u64> = (1..=5).map(|attempt| policy.delay(attempt).as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 8]);
+        assert_eq!(policy.delay(40), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_tool_result_serializes_as_openai_tool_message() {
+        let messages = vec![
+            Message::tool_result("call_1".to_string(), "42"),
+            Message::tool("legacy output"),
+        ];
+        assert_eq!(
+            messages_to_openai(&messages),
+            vec![
+                json!({"role": "tool", "tool_call_id": "call_1", "content": "42"}),
+                json!({"role": "user", "content": "[Tool Output]\nlegacy output"}),
+            ]
+        );
+    }
+
     #[test]
     fn test_parse_openai_sse_chunk() {
         let json = r#"{"choices":[{"delta":{"content":"Hello"}}]}"#;
@@ -1419,6 +2509,1626 @@ mod tests {
         assert!(chunk.delta.is_none());
     }
 
+    #[test]
+    fn test_parse_anthropic_input_json_delta() {
+        let json = r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"path\": \"src"}}"#;
+        let chunk: AnthropicStreamChunk = serde_json::from_str(json).unwrap();
+        assert_eq!(chunk.index, 1);
+        match chunk.delta {
+            Some(StreamDelta::ContentBlock(delta)) => {
+                assert_eq!(delta.type_, "input_json_delta");
+                assert!(delta.text.is_empty());
+                assert_eq!(delta.partial_json.as_deref(), Some(r#"{"path": "src"#));
+            }
+            other => panic!("expected content block delta, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_stream_reassembles_tool_arguments() {
+        use futures::StreamExt;
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let events = [
+            r#"{"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":12,"output_tokens":0}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"tool_use","id":"toolu_1","name":"glob","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"input_json_delta","partial_json":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"input_json_delta","partial_json":"{\"pattern\": \"*.r"}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"input_json_delta","partial_json":"s\", \"path\": \"src\"}"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use","stop_sequence":null},"usage":{"input_tokens":12,"output_tokens":7}}"#,
+        ];
+        let mut body = String::new();
+        for event in events {
+            body.push_str(&format!("data: {}\n\n", event));
+        }
+        body.push_str("event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n");
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&server)
+            .await;
+
+        let client = AnthropicClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::Anthropic,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let messages = vec![Message::user("List rust files")];
+        let mut stream = client.chat_stream(&messages, "claude-test", None);
+        let mut final_event = None;
+        while let Some(event) = stream.next().await {
+            let event = event.unwrap();
+            if event.done {
+                final_event = Some(event);
+                break;
+            }
+        }
+
+        let final_event = final_event.expect("stream should finish");
+        let calls = final_event.tool_calls.expect("tool calls");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id, "toolu_1");
+        assert_eq!(calls[0].name, "glob");
+        assert_eq!(calls[0].arguments, r#"{"pattern": "*.rs", "path": "src"}"#);
+        let args: serde_json::Value = serde_json::from_str(&calls[0].arguments).unwrap();
+        assert_eq!(args["path"], "src");
+    }
+
+    /// Stream `body` from a mock server and return the last item the client yields
+    async fn last_stream_item(provider_type: crate::ProviderType, chat_path: &str, body: String) -> Result<StreamEvent> {
+        use futures::StreamExt;
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(chat_path))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&server)
+            .await;
+
+        let client = crate::create_client(ProviderConfig {
+            provider_type,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let messages = vec![Message::user("Hi")];
+        let mut stream = client.chat_stream(&messages, "test-model", None);
+        let mut last = None;
+        while let Some(item) = stream.next().await {
+            last = Some(item);
+        }
+        last.expect("stream yielded nothing")
+    }
+
+    #[tokio::test]
+    async fn test_openai_truncated_stream_is_reported() {
+        let body = "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hel\"}}]}\n\n".to_string();
+        let err = last_stream_item(crate::ProviderType::OpenAI, "/chat/completions", body)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("stream ended without completion"));
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_truncated_stream_is_reported() {
+        let body = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":3,\"output_tokens\":0}}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hel\"}}\n\n",
+        )
+        .to_string();
+        let err = last_stream_item(crate::ProviderType::Anthropic, "/v1/messages", body)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("stream ended without completion"));
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_stream_combines_start_and_delta_usage() {
+        let body = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":25,\"output_tokens\":1}}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\",\"stop_sequence\":null},\"usage\":{\"output_tokens\":15}}\n\n",
+            "event: message_stop\n",
+            "data: {\"type\":\"message_stop\"}\n\n",
+        )
+        .to_string();
+        let event = last_stream_item(crate::ProviderType::Anthropic, "/v1/messages", body)
+            .await
+            .unwrap();
+        assert_eq!(
+            event.usage,
+            Some(Usage {
+                prompt_tokens: 25,
+                completion_tokens: 15,
+                total_tokens: 40,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_openai_cached_prompt_tokens_are_parsed() {
+        let body = json!({
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "Hi" },
+                "finish_reason": "stop"
+            }],
+            "usage": {
+                "prompt_tokens": 2006,
+                "completion_tokens": 300,
+                "total_tokens": 2306,
+                "prompt_tokens_details": { "cached_tokens": 1920 }
+            }
+        });
+        let usage = parse_openai_completion(&body.to_string()).unwrap().usage;
+        assert_eq!(usage.cached_tokens, Some(1920));
+        assert_eq!(usage.cache_creation_tokens, None);
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_cached_prompt_tokens_are_parsed() {
+        use futures::StreamExt;
+
+        let body = concat!(
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"},\"finish_reason\":\"stop\"}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":2006,\"completion_tokens\":1,\"total_tokens\":2007,",
+            "\"prompt_tokens_details\":{\"cached_tokens\":1920}}}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let chunks = futures::stream::iter(vec![Ok::<_, Error>(body.as_bytes().to_vec())]);
+        let events: Vec<StreamEvent> = openai_sse_events(chunks)
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+
+        let usage = events.last().unwrap().usage.clone().unwrap();
+        assert_eq!(usage.prompt_tokens, 2006);
+        assert_eq!(usage.cached_tokens, Some(1920));
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_stream_cache_tokens_are_parsed() {
+        let body = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":12,\"output_tokens\":1,",
+            "\"cache_creation_input_tokens\":0,\"cache_read_input_tokens\":2048}}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":5}}\n\n",
+            "event: message_stop\n",
+            "data: {\"type\":\"message_stop\"}\n\n",
+        )
+        .to_string();
+        let event = last_stream_item(crate::ProviderType::Anthropic, "/v1/messages", body)
+            .await
+            .unwrap();
+        let usage = event.usage.unwrap();
+        assert_eq!(usage.cached_tokens, Some(2048));
+        assert_eq!(usage.cache_creation_tokens, Some(0));
+        assert_eq!(usage.completion_tokens, 5);
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_joins_json_split_across_data_lines() {
+        use futures::StreamExt;
+
+        let body = concat!(
+            "data: {\"choices\":[{\"index\":0,\n",
+            "data: \"delta\":{\"content\":\"split\"}}]}\n",
+            "\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n",
+        );
+        let chunks = futures::stream::iter(vec![Ok::<_, Error>(body.as_bytes().to_vec())]);
+        let events: Vec<StreamEvent> = openai_sse_events(chunks)
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(events[0].delta, "split");
+        assert!(events.last().unwrap().done);
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_without_done_marker_completes() {
+        let body = "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"},\"finish_reason\":\"stop\"}]}\n\n".to_string();
+        let event = last_stream_item(crate::ProviderType::OpenAI, "/chat/completions", body)
+            .await
+            .unwrap();
+        assert!(event.done);
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_falls_back_to_json_body() {
+        use futures::StreamExt;
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Not streamed" },
+                    "finish_reason": "stop"
+                }],
+                "usage": { "prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7 }
+            })))
+            .mount(&server)
+            .await;
+
+        let client = crate::create_client(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let events: Vec<StreamEvent> = client
+            .chat_stream(&[Message::user("Hi")], "gpt-test", None)
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].delta, "Not streamed");
+        assert!(events[1].done);
+        assert_eq!(events[1].usage.as_ref().map(|u| u.total_tokens), Some(7));
+        assert_eq!(events[1].finish_reason, Some(FinishReason::Stop));
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_content_filter_keeps_partial_output() {
+        use futures::StreamExt;
+
+        let body = concat!(
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Partial\"}}]}\n\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"content_filter\"}]}\n\n",
+        );
+        let chunks = futures::stream::iter(vec![Ok::<_, Error>(body.as_bytes().to_vec())]);
+        let events: Vec<StreamEvent> = openai_sse_events(chunks)
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(events[0].delta, "Partial");
+        assert_eq!(events[0].finish_reason, None);
+        let last = events.last().unwrap();
+        assert!(last.done);
+        assert_eq!(last.finish_reason, Some(FinishReason::ContentFilter));
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_stream_refusal_is_content_filter() {
+        let body = concat!(
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"I\"}}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"refusal\",\"stop_sequence\":null}}\n\n",
+            "event: message_stop\n",
+            "data: {\"type\":\"message_stop\"}\n\n",
+        )
+        .to_string();
+        let event = last_stream_item(crate::ProviderType::Anthropic, "/v1/messages", body)
+            .await
+            .unwrap();
+        assert!(event.done);
+        assert_eq!(event.finish_reason, Some(FinishReason::ContentFilter));
+    }
+
+    #[test]
+    fn test_finish_reason_parse_normalizes_providers() {
+        assert_eq!(FinishReason::parse("end_turn"), FinishReason::Stop);
+        assert_eq!(FinishReason::parse("max_tokens"), FinishReason::Length);
+        assert_eq!(FinishReason::parse("tool_use"), FinishReason::ToolCalls);
+        assert_eq!(FinishReason::parse("content_filter"), FinishReason::ContentFilter);
+        assert_eq!(FinishReason::parse("pause_turn"), FinishReason::Other("pause_turn".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_stream_retries_rate_limited_initial_request() {
+        use futures::StreamExt;
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(429).set_body_string("slow down"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(concat!(
+                "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"after retry\"}}]}\n\n",
+                "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n",
+                "data: [DONE]\n\n",
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = OpenAIClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap()
+        .with_retry(immediate_retry());
+
+        let messages = vec![Message::user("Hi")];
+        let mut stream = client.chat_stream(&messages, "test-model", None);
+        let mut text = String::new();
+        let mut done = false;
+        while let Some(event) = stream.next().await {
+            let event = event.unwrap();
+            text.push_str(&event.delta);
+            done |= event.done;
+        }
+        assert_eq!(text, "after retry");
+        assert!(done);
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_asks_for_usage() {
+        use futures::StreamExt;
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(body_partial_json(json!({"stream": true, "stream_options": {"include_usage": true}})))
+            .respond_with(ResponseTemplate::new(200).set_body_string(concat!(
+                "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"},\"finish_reason\":\"stop\"}]}\n\n",
+                "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":3,\"completion_tokens\":1,\"total_tokens\":4}}\n\n",
+                "data: [DONE]\n\n",
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = crate::create_client(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        let events: Vec<StreamEvent> = client
+            .chat_stream(&[Message::user("Hi")], "test-model", None)
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+        // One done event, after the trailing usage chunk, carrying both
+        let done: Vec<&StreamEvent> = events.iter().filter(|e| e.done).collect();
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].finish_reason, Some(FinishReason::Stop));
+        assert_eq!(done[0].usage.as_ref().unwrap().total_tokens, 4);
+        assert!(events.last().unwrap().done);
+    }
+
+    #[tokio::test]
+    async fn test_strip_reasoning_keeps_raw_content() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{
+                    "index": 0,
+                    "message": {
+                        "role": "assistant",
+                        "reasoning_content": "The user greets me.",
+                        "content": "<think>Say hi back.</think>\n\nHello!"
+                    },
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+            })))
+            .mount(&server)
+            .await;
+
+        let client = crate::create_client(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        let messages = [Message::user("Hi")];
+
+        let options = ChatOptions { strip_reasoning: Some(true), ..Default::default() };
+        let stripped = client.chat_with_options(&messages, "test-model", None, &options).await.unwrap();
+        assert_eq!(stripped.content, "Hello!");
+        assert_eq!(stripped.raw_content.as_deref(), Some("<think>Say hi back.</think>\n\nHello!"));
+
+        let kept = client.chat(&messages, "test-model", None).await.unwrap();
+        assert_eq!(kept.content, "<think>Say hi back.</think>\n\nHello!");
+        assert_eq!(kept.raw_content, None);
+    }
+
+    #[tokio::test]
+    async fn test_retry_resends_identical_body() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{"index": 0, "message": {"role": "assistant", "content": "ok"}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+            })))
+            .mount(&server)
+            .await;
+
+        let client = OpenAIClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap()
+        .with_retry(immediate_retry());
+        let options = ChatOptions { temperature: Some(0.5), ..Default::default() };
+        let attempts_before = upstream_attempts();
+        client
+            .chat_with_options(&[Message::user("Hi")], "test-model", None, &options)
+            .await
+            .unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 2);
+        // Other tests may count attempts concurrently
+        assert!(upstream_attempts() >= attempts_before + 2);
+        assert_eq!(requests[0].body, requests[1].body);
+        assert_eq!(requests[1].headers["content-type"], "application/json");
+    }
+
+    #[tokio::test]
+    async fn test_stream_retries_connection_closed_before_response() {
+        use futures::StreamExt;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 64 * 1024];
+
+            // First connection: read the request, then hang up without answering
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let _ = socket.read(&mut buf).await;
+            drop(socket);
+
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let _ = socket.read(&mut buf).await;
+            let body = concat!(
+                "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"reconnected\"}}]}\n\n",
+                "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n",
+                "data: [DONE]\n\n",
+            );
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let client = OpenAIClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: format!("http://{}", addr),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap()
+        .with_retry(immediate_retry());
+
+        let messages = vec![Message::user("Hi")];
+        let mut stream = client.chat_stream(&messages, "test-model", None);
+        let mut text = String::new();
+        while let Some(event) = stream.next().await {
+            text.push_str(&event.unwrap().delta);
+        }
+        assert_eq!(text, "reconnected");
+    }
+
+    #[tokio::test]
+    async fn test_openai_embeddings_returns_vectors_in_input_order() {
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .and(body_partial_json(json!({ "model": "embed-test", "input": ["first", "second"] })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "data": [
+                    { "object": "embedding", "index": 1, "embedding": [0.5, 0.25, 0.0] },
+                    { "object": "embedding", "index": 0, "embedding": [0.1, 0.2, 0.3] }
+                ],
+                "model": "embed-test",
+                "usage": { "prompt_tokens": 4, "total_tokens": 4 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = OpenAIClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let input = vec!["first".to_string(), "second".to_string()];
+        let (vectors, usage) = client.embeddings(&input, "embed-test").await.unwrap();
+        assert_eq!(vectors.len(), 2);
+        assert!(vectors.iter().all(|v| v.len() == 3));
+        assert_eq!(vectors[0], vec![0.1, 0.2, 0.3]);
+        assert_eq!(usage.prompt_tokens, 4);
+        assert_eq!(usage.completion_tokens, 0);
+
+        let anthropic = AnthropicClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::Anthropic,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        let err = anthropic.embeddings(&input, "embed-test").await.unwrap_err();
+        assert!(err.to_string().contains("unsupported"));
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_count_tokens_uses_endpoint() {
+        use wiremock::matchers::{body_partial_json, header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages/count_tokens"))
+            .and(header("x-api-key", "test-key"))
+            .and(body_partial_json(json!({ "model": "claude-test", "system": "Be brief" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "input_tokens": 42 })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let config = ProviderConfig {
+            provider_type: crate::ProviderType::Anthropic,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        };
+        let messages = vec![Message::system("Be brief"), Message::user("Hi")];
+
+        let anthropic = AnthropicClient::new(config.clone()).unwrap();
+        assert_eq!(anthropic.count_tokens(&messages, "claude-test").await.unwrap(), 42);
+
+        // Other providers estimate locally without a request
+        let openai = OpenAIClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            ..config
+        })
+        .unwrap();
+        assert_eq!(openai.count_tokens(&messages, "gpt-test").await.unwrap(), estimate_tokens(&messages));
+        assert_eq!(estimate_tokens(&messages), 2 + 4 + 1 + 4);
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_count_tokens_sends_chat_system_blocks() {
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages/count_tokens"))
+            .and(body_partial_json(json!({
+                "system": [crate::message::cached_text_block("Long manual")],
+                "messages": [{"role": "user", "content": "Hi"}, {"role": "assistant", "content": "Sure,"}]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "input_tokens": 7 })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let anthropic = AnthropicClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::Anthropic,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        // A cacheable system prompt and a prefill with trailing whitespace,
+        // both shaped as in a chat request
+        let messages = vec![
+            Message::system("Long manual").cacheable(),
+            Message::user("Hi"),
+            Message::assistant("Sure, "),
+        ];
+        assert_eq!(anthropic.count_tokens(&messages, "claude-test").await.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_empty_conversation_fails_before_any_request() {
+        use futures::StreamExt;
+
+        // Nothing listens here: reaching the network would yield Error::Http
+        let config = ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: "http://127.0.0.1:9".to_string(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        };
+        let openai = OpenAIClient::new(config.clone()).unwrap();
+        let anthropic = AnthropicClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::Anthropic,
+            ..config
+        })
+        .unwrap();
+
+        for messages in [vec![], vec![Message::system("Be brief")]] {
+            let err = openai.chat(&messages, "gpt-test", None).await.unwrap_err();
+            assert!(matches!(err, Error::InvalidMessages(_)), "{}", err);
+
+            let mut stream = anthropic.chat_stream(&messages, "claude-test", None);
+            let err = stream.next().await.unwrap().unwrap_err();
+            assert!(matches!(err, Error::InvalidMessages(_)), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_capabilities_per_provider() {
+        let config = ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: "http://localhost".to_string(),
+            api_key: "test-key".to_string(),
+            model: Some("gpt-4o".to_string()),
+            ..Default::default()
+        };
+
+        let openai = OpenAIClient::new(config.clone()).unwrap().capabilities();
+        assert!(openai.embeddings);
+        assert!(openai.json_mode);
+        assert!(openai.vision);
+
+        let anthropic = AnthropicClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::Anthropic,
+            model: Some("claude-3-5-sonnet".to_string()),
+            ..config.clone()
+        })
+        .unwrap()
+        .capabilities();
+        assert!(!anthropic.embeddings);
+        assert!(anthropic.tools);
+        assert!(anthropic.vision);
+
+        // Refined by the configured model where it is known to be text-only
+        let legacy = OpenAIClient::new(ProviderConfig {
+            model: Some("gpt-3.5-turbo".to_string()),
+            ..config
+        })
+        .unwrap()
+        .capabilities();
+        assert!(!legacy.vision);
+    }
+
+    #[test]
+    fn test_embedding_models_only_embed() {
+        let caps = Capabilities::for_model(crate::ProviderType::OpenAI, "text-embedding-3-small");
+        assert!(caps.embeddings);
+        assert!(!caps.streaming);
+        assert!(!caps.tools);
+        assert_eq!(caps.get("streaming"), Some(false));
+        assert_eq!(caps.get("json_mode"), Some(false));
+        assert_eq!(caps.get("telepathy"), None);
+    }
+
+    #[tokio::test]
+    async fn test_openai_response_without_usage_reports_zero_tokens() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "no usage here" },
+                    "finish_reason": "stop"
+                }]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = OpenAIClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let messages = vec![Message::user("Hi")];
+        let response = client.chat(&messages, "gpt-test", None).await.unwrap();
+        assert_eq!(response.content, "no usage here");
+        assert_eq!(response.usage.prompt_tokens, 0);
+        assert_eq!(response.usage.completion_tokens, 0);
+        assert_eq!(response.usage.total_tokens, 0);
+    }
+
+    #[tokio::test]
+    async fn test_openai_merge_system_prompts_sends_one_system_message() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "ok" },
+                    "finish_reason": "stop"
+                }]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = OpenAIClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            merge_system_prompts: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let messages = vec![
+            Message::system("Be brief"),
+            Message::user("Hi"),
+            Message::system("Answer in French"),
+        ];
+        client.chat(&messages, "gpt-test", None).await.unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        let sent = body["messages"].as_array().unwrap();
+        let system: Vec<_> = sent.iter().filter(|m| m["role"] == "system").collect();
+        assert_eq!(system.len(), 1);
+        assert_eq!(sent[0]["content"], "Be brief\n\nAnswer in French");
+        assert_eq!(sent[1]["role"], "user");
+        assert_eq!(sent.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_openai_html_200_reports_proxy_error() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let page = format!("<html><body><h1>Bad Gateway</h1>{}</body></html>", "x".repeat(500));
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(page))
+            .mount(&server)
+            .await;
+
+        let client = crate::create_client(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let err = client.chat(&[Message::user("Hi")], "gpt-test", None).await.unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("non-JSON 200 response, likely a proxy error"), "{}", message);
+        assert!(message.contains("<h1>Bad Gateway</h1>"));
+        assert!(!message.contains("</html>"), "body should be truncated: {}", message);
+    }
+
+    #[tokio::test]
+    async fn test_openai_null_content_with_tool_call() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{
+                    "index": 0,
+                    "message": {
+                        "role": "assistant",
+                        "content": null,
+                        "tool_calls": [{
+                            "id": "call_1",
+                            "type": "function",
+                            "function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" }
+                        }]
+                    },
+                    "finish_reason": "tool_calls"
+                }],
+                "usage": { "prompt_tokens": 8, "completion_tokens": 4, "total_tokens": 12 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = OpenAIClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let messages = vec![Message::user("Weather in Paris?")];
+        let response = client.chat(&messages, "gpt-test", None).await.unwrap();
+        assert_eq!(response.content, "");
+        let calls = response.tool_calls.expect("tool calls");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].name, "get_weather");
+        assert_eq!(calls[0].arguments, r#"{"city":"Paris"}"#);
+        assert_eq!(response.finish_reason.as_deref(), Some("tool_calls"));
+    }
+
+    #[tokio::test]
+    async fn test_openai_uses_configured_chat_path() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/openai/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "proxied" },
+                    "finish_reason": "stop"
+                }],
+                "usage": { "prompt_tokens": 3, "completion_tokens": 1, "total_tokens": 4 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = OpenAIClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: format!("{}/", server.uri()),
+            api_key: "test-key".to_string(),
+            chat_path: Some("/v1/openai/chat/completions".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let messages = vec![Message::user("Hi")];
+        let text = client.chat(&messages, "gpt-test", None).await.unwrap().content;
+        assert_eq!(text, "proxied");
+    }
+
+    #[tokio::test]
+    async fn test_azure_deployment_url_and_api_key_header() {
+        use wiremock::matchers::{header, header_exists, method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/openai/deployments/gpt-4o-prod/chat/completions"))
+            .and(query_param("api-version", crate::config::DEFAULT_AZURE_API_VERSION))
+            .and(header("api-key", "azure-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "from azure" },
+                    "finish_reason": "stop"
+                }],
+                "usage": { "prompt_tokens": 3, "completion_tokens": 2, "total_tokens": 5 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(header_exists("authorization"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let client = OpenAIClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: server.uri(),
+            api_key: "azure-key".to_string(),
+            azure_deployment: Some("gpt-4o-prod".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let messages = vec![Message::user("Hi")];
+        let text = client.chat(&messages, "gpt-4o", None).await.unwrap().content;
+        assert_eq!(text, "from azure");
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_version_and_beta_headers() {
+        use wiremock::matchers::{header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .and(header("anthropic-version", "2024-10-22"))
+            .and(header("anthropic-beta", "prompt-caching-2024-07-31,token-counting-2024-11-01"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "content": [{ "type": "text", "text": "beta" }],
+                "stop_reason": "end_turn",
+                "usage": { "input_tokens": 3, "output_tokens": 1 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = AnthropicClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::Anthropic,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            anthropic_version: Some("2024-10-22".to_string()),
+            anthropic_beta: vec![
+                "prompt-caching-2024-07-31".to_string(),
+                "token-counting-2024-11-01".to_string(),
+            ],
+            ..Default::default()
+        })
+        .unwrap();
+
+        let messages = vec![Message::user("Hi")];
+        let text = client.chat(&messages, "claude-3-5-sonnet", None).await.unwrap().content;
+        assert_eq!(text, "beta");
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_prompt_caching_marks_blocks_and_reads_cache_usage() {
+        use wiremock::matchers::{header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .and(header("anthropic-beta", PROMPT_CACHING_BETA))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "content": [{ "type": "text", "text": "cached" }],
+                "stop_reason": "end_turn",
+                "usage": {
+                    "input_tokens": 12,
+                    "output_tokens": 3,
+                    "cache_creation_input_tokens": 2048,
+                    "cache_read_input_tokens": 0
+                }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = AnthropicClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::Anthropic,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let messages = vec![
+            Message::system("A long style guide").cacheable(),
+            Message::user("The whole manual").cacheable(),
+            Message::assistant("Read it."),
+            Message::user("Summarize it"),
+        ];
+        let response = client.chat(&messages, "claude-3-5-sonnet", None).await.unwrap();
+        assert_eq!(response.usage.cache_creation_tokens, Some(2048));
+        assert_eq!(response.usage.cached_tokens, Some(0));
+
+        let requests = server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        let marker = json!({"type": "ephemeral"});
+        assert_eq!(body["system"][0]["text"], "A long style guide");
+        assert_eq!(body["system"][0]["cache_control"], marker);
+        assert_eq!(body["messages"][0]["content"][0]["cache_control"], marker);
+        assert_eq!(body["messages"][0]["content"][0]["text"], "The whole manual");
+        assert_eq!(body["messages"][2]["content"], "Summarize it");
+    }
+
+    #[test]
+    fn test_chat_options_merge_field_by_field() {
+        let defaults = ChatOptions {
+            temperature: Some(0.2),
+            top_p: None,
+            max_tokens: Some(1024),
+            response_format: None,
+            stop: vec!["END".to_string()],
+            logprobs: Some(true),
+            top_logprobs: None,
+            extra_body: None,
+            strip_reasoning: None,
+        };
+        let call = ChatOptions {
+            top_p: Some(0.9),
+            max_tokens: Some(64),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            call.merged_over(&defaults),
+            ChatOptions {
+                temperature: Some(0.2),
+                top_p: Some(0.9),
+                max_tokens: Some(64),
+                response_format: None,
+                stop: vec!["END".to_string()],
+                logprobs: Some(true),
+                top_logprobs: None,
+                extra_body: None,
+                strip_reasoning: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_stop_sequences_use_provider_key_and_omit_when_empty() {
+        let openai = ChatRequest {
+            model: "gpt-test".to_string(),
+            messages: Vec::new(),
+            stream: false,
+            tools: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            response_format: None,
+            stop: vec!["###".to_string()],
+            logprobs: None,
+            top_logprobs: None,
+            stream_options: None,
+            extra_body: None,
+        };
+        let value = serde_json::to_value(&openai).unwrap();
+        assert_eq!(value["stop"], json!(["###"]));
+        assert!(value.get("stop_sequences").is_none());
+
+        let anthropic = AnthropicMessageRequest {
+            model: "claude-test".to_string(),
+            messages: Vec::new(),
+            system: None,
+            max_tokens: 16,
+            stream: None,
+            tools: None,
+            temperature: None,
+            top_p: None,
+            stop_sequences: vec!["###".to_string()],
+            extra_body: None,
+        };
+        let value = serde_json::to_value(&anthropic).unwrap();
+        assert_eq!(value["stop_sequences"], json!(["###"]));
+        assert!(value.get("stop").is_none());
+
+        let value = serde_json::to_value(ChatRequest { stop: Vec::new(), ..openai }).unwrap();
+        assert!(value.get("stop").is_none());
+        let value = serde_json::to_value(AnthropicMessageRequest { stop_sequences: Vec::new(), ..anthropic }).unwrap();
+        assert!(value.get("stop_sequences").is_none());
+    }
+
+    #[test]
+    fn test_extra_body_fields_are_merged_into_request() {
+        let mut extra = serde_json::Map::new();
+        extra.insert("enable_thinking".to_string(), json!(true));
+        let options = ChatOptions {
+            extra_body: Some(extra),
+            ..Default::default()
+        }
+        .merged_over(&ChatOptions::default());
+
+        let openai = ChatRequest {
+            model: "gpt-test".to_string(),
+            messages: Vec::new(),
+            stream: false,
+            tools: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            response_format: None,
+            stop: Vec::new(),
+            logprobs: None,
+            top_logprobs: None,
+            stream_options: None,
+            extra_body: options.extra_body.clone(),
+        };
+        let value = serde_json::to_value(&openai).unwrap();
+        assert_eq!(value["enable_thinking"], json!(true));
+        assert_eq!(value["model"], "gpt-test");
+
+        let anthropic = AnthropicMessageRequest {
+            model: "claude-test".to_string(),
+            messages: Vec::new(),
+            system: None,
+            max_tokens: 16,
+            stream: None,
+            tools: None,
+            temperature: None,
+            top_p: None,
+            stop_sequences: Vec::new(),
+            extra_body: options.extra_body,
+        };
+        let value = serde_json::to_value(&anthropic).unwrap();
+        assert_eq!(value["enable_thinking"], json!(true));
+
+        let value = serde_json::to_value(ChatRequest { extra_body: None, ..openai }).unwrap();
+        assert!(value.get("enable_thinking").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_stop_sequence_hit_sets_finish_reason() {
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .and(body_partial_json(json!({ "stop_sequences": ["###"] })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "content": [{ "type": "text", "text": "partial" }],
+                "stop_reason": "stop_sequence",
+                "stop_sequence": "###",
+                "usage": { "input_tokens": 3, "output_tokens": 1 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = AnthropicClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::Anthropic,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let options = ChatOptions {
+            stop: vec!["###".to_string()],
+            ..Default::default()
+        };
+        let messages = vec![Message::user("Hi")];
+        let response = client
+            .chat_with_options(&messages, "claude-test", None, &options)
+            .await
+            .unwrap();
+        assert_eq!(response.content, "partial");
+        assert_eq!(response.finish_reason.as_deref(), Some("stop_sequence"));
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_keeps_trailing_assistant_prefill_last() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "content": [{ "type": "text", "text": " 42}" }],
+                "stop_reason": "end_turn",
+                "usage": { "input_tokens": 9, "output_tokens": 2 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = AnthropicClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::Anthropic,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let messages = vec![
+            Message::system("Answer in JSON"),
+            Message::user("What is 6*7?"),
+            Message::assistant("{\"answer\": "),
+        ];
+        let response = client.chat(&messages, "claude-test", None).await.unwrap();
+        assert_eq!(response.content, " 42}");
+
+        let requests = server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body["system"], "Answer in JSON");
+        let sent = body["messages"].as_array().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[1]["role"], "assistant");
+        assert_eq!(sent[1]["content"], "{\"answer\":");
+    }
+
+    #[tokio::test]
+    async fn test_call_options_merge_over_model_defaults() {
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(body_partial_json(json!({ "temperature": 0.2, "top_p": 0.5 })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "ok" },
+                    "finish_reason": "stop"
+                }],
+                "usage": { "prompt_tokens": 3, "completion_tokens": 1, "total_tokens": 4 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = OpenAIClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            temperature: Some(0.2),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let options = ChatOptions {
+            top_p: Some(0.5),
+            ..Default::default()
+        };
+        let messages = vec![Message::user("Hi")];
+        let response = client
+            .chat_with_options(&messages, "gpt-test", None, &options)
+            .await
+            .unwrap();
+        assert_eq!(response.content, "ok");
+        assert_eq!(response.finish_reason.as_deref(), Some("stop"));
+    }
+
+    #[tokio::test]
+    async fn test_openai_logprobs_are_requested_and_parsed() {
+        let mock = crate::mock_server::OpenAIMockServer::start().await;
+        mock.mock_chat_completion_with_logprobs(
+            "Yes",
+            json!({
+                "content": [{
+                    "token": "Yes",
+                    "logprob": -0.01,
+                    "bytes": [89, 101, 115],
+                    "top_logprobs": [
+                        { "token": "Yes", "logprob": -0.01, "bytes": [89, 101, 115] },
+                        { "token": "No", "logprob": -4.6, "bytes": null }
+                    ]
+                }]
+            }),
+        )
+        .await;
+
+        let client = OpenAIClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: mock.base_url(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let options = ChatOptions {
+            logprobs: Some(true),
+            top_logprobs: Some(2),
+            ..Default::default()
+        };
+        let messages = vec![Message::user("Is water wet?")];
+        let response = client
+            .chat_with_options(&messages, "gpt-test", None, &options)
+            .await
+            .unwrap();
+
+        let tokens = response.logprobs.and_then(|l| l.content).expect("logprobs");
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token, "Yes");
+        assert_eq!(tokens[0].logprob, -0.01);
+        assert_eq!(tokens[0].bytes.as_deref(), Some(&b"Yes"[..]));
+        assert_eq!(tokens[0].top_logprobs.len(), 2);
+        assert_eq!(tokens[0].top_logprobs[1].token, "No");
+        assert_eq!(tokens[0].top_logprobs[1].bytes, None);
+    }
+
+    async fn json_client(content: &str) -> (wiremock::MockServer, Box<dyn Client>) {
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(body_partial_json(json!({ "response_format": { "type": "json_schema" } })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": content },
+                    "finish_reason": "stop"
+                }],
+                "usage": { "prompt_tokens": 5, "completion_tokens": 7, "total_tokens": 12 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = crate::create_client(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        (server, client)
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct City {
+        name: String,
+        population: u64,
+    }
+
+    fn city_schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "population": { "type": "integer" }
+            },
+            "required": ["name", "population"]
+        })
+    }
+
+    #[tokio::test]
+    async fn test_chat_json_deserializes_content() {
+        let (_server, client) = json_client(r#"{"name":"Paris","population":2100000}"#).await;
+
+        let messages = vec![Message::user("Largest city in France?")];
+        let (city, usage) = client
+            .chat_json::<City>(&messages, "gpt-test", &city_schema())
+            .await
+            .unwrap();
+        assert_eq!(
+            city,
+            City {
+                name: "Paris".to_string(),
+                population: 2_100_000
+            }
+        );
+        assert_eq!(usage.total_tokens, 12);
+    }
+
+    #[tokio::test]
+    async fn test_chat_json_error_includes_raw_content() {
+        let (_server, client) = json_client("Sorry, I can't answer that.").await;
+
+        let messages = vec![Message::user("Largest city in France?")];
+        let err = client
+            .chat_json::<City>(&messages, "gpt-test", &city_schema())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Json(_)));
+        assert!(err.to_string().contains("Sorry, I can't answer that."));
+    }
+
+    #[tokio::test]
+    async fn test_chat_batch_keeps_order_and_isolates_failures() {
+        use wiremock::matchers::{body_string_contains, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let answer = |content: &str| {
+            ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": content },
+                    "finish_reason": "stop"
+                }],
+                "usage": { "prompt_tokens": 2, "completion_tokens": 1, "total_tokens": 3 }
+            }))
+        };
+        // The first prompt answers last, so completion order differs from input order
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(body_string_contains("first"))
+            .respond_with(answer("one").set_delay(Duration::from_millis(200)))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(body_string_contains("second"))
+            .respond_with(ResponseTemplate::new(500).set_body_string("upstream exploded"))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(body_string_contains("third"))
+            .respond_with(answer("three"))
+            .mount(&server)
+            .await;
+
+        let client = OpenAIClient::new(ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: server.uri(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap()
+        .with_retry(immediate_retry());
+        let client: &dyn Client = &client;
+
+        let requests = ["first", "second", "third"]
+            .iter()
+            .map(|prompt| vec![Message::user(*prompt)])
+            .collect();
+        let results = client.chat_batch(requests, "gpt-test", 3).await;
+
+        assert_eq!(results.len(), 3);
+        let (content, usage) = results[0].as_ref().unwrap();
+        assert_eq!(content, "one");
+        assert_eq!(usage.total_tokens, 3);
+        assert!(results[1].as_ref().unwrap_err().to_string().contains("upstream exploded"));
+        assert_eq!(results[2].as_ref().unwrap().0, "three");
+    }
+
+    #[test]
+    fn test_chat_url_defaults_per_provider() {
+        let mut config = ProviderConfig {
+            provider_type: crate::ProviderType::Anthropic,
+            api_base: "https://api.anthropic.com/".to_string(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(config.chat_url(), "https://api.anthropic.com/v1/messages");
+
+        config.provider_type = crate::ProviderType::OpenAI;
+        config.api_base = "https://api.openai.com/v1".to_string();
+        assert_eq!(config.chat_url(), "https://api.openai.com/v1/chat/completions");
+    }
+
+    /// Self-signed certificate used to exercise `ca_bundle_path`
+    const TEST_CA_PEM: &str = "-----BEGIN CERTIFICATE-----
+MIIBizCCATGgAwIBAgIUMywY0I666ZfLvDv+H26ZyI+U81UwCgYIKoZIzj0EAwIw
+GjEYMBYGA1UEAwwPZW14LWxsbSB0ZXN0IENBMCAXDTI2MTAxNjA5MDgwOVoYDzIx
+MjYwOTIyMDkwODA5WjAaMRgwFgYDVQQDDA9lbXgtbGxtIHRlc3QgQ0EwWTATBgcq
+hkjOPQIBBggqhkjOPQMBBwNCAATRh64tHbfg/oD02PyAVBC3qwXfzUzdu/NSI51l
++JJpYYJJO+M6doK8+XxD7u9zcMLwLpNRw4Z2AwmH7Ezl56Fco1MwUTAdBgNVHQ4E
+FgQUyj5NJsLIzTGRFC4j43FHMuLFBMUwHwYDVR0jBBgwFoAUyj5NJsLIzTGRFC4j
+43FHMuLFBMUwDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNIADBFAiBn1pfe
+Il5vVTIprvbd+PACh8V8n2qu6zaZmHMOtUX4JAIhAMrwo3eMUJf1/nA3RgF8nKNV
+C8P276AGvwBlW07X1sPX
+-----END CERTIFICATE-----
+";
+
+    fn config_with_ca_bundle(path: &std::path::Path) -> ProviderConfig {
+        ProviderConfig {
+            provider_type: crate::ProviderType::OpenAI,
+            api_base: "https://proxy.internal/v1".to_string(),
+            api_key: "test-key".to_string(),
+            ca_bundle_path: Some(path.to_string_lossy().into_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_client_loads_ca_bundle() {
+        let path = std::env::temp_dir().join(format!("emx-llm-ca-{}.pem", std::process::id()));
+        std::fs::write(&path, TEST_CA_PEM).unwrap();
+
+        let result = OpenAIClient::new(config_with_ca_bundle(&path));
+        std::fs::remove_file(&path).ok();
+        assert!(result.is_ok(), "failed to load CA bundle: {:?}", result.err());
+    }
+
+    #[test]
+    fn test_client_rejects_bad_ca_bundle() {
+        let missing = std::env::temp_dir().join("emx-llm-ca-does-not-exist.pem");
+        let err = OpenAIClient::new(config_with_ca_bundle(&missing)).err().unwrap();
+        assert!(err.to_string().contains("Failed to read CA bundle"));
+
+        let path = std::env::temp_dir().join(format!("emx-llm-ca-bad-{}.pem", std::process::id()));
+        std::fs::write(&path, "not a certificate").unwrap();
+        let err = OpenAIClient::new(config_with_ca_bundle(&path)).err().unwrap();
+        std::fs::remove_file(&path).ok();
+        assert!(err.to_string().contains("CA bundle"));
+    }
+
+    #[test]
+    fn test_client_applies_pool_settings() {
+        let path = std::env::temp_dir().join("emx-llm-ca-unused.pem");
+        let config = ProviderConfig {
+            ca_bundle_path: None,
+            ..config_with_ca_bundle(&path)
+        };
+        assert_eq!(config.pool_idle_timeout(), Duration::from_secs(crate::config::DEFAULT_POOL_IDLE_TIMEOUT_SECS));
+        assert_eq!(config.pool_max_idle_per_host(), crate::config::DEFAULT_POOL_MAX_IDLE_PER_HOST);
+
+        let config = ProviderConfig {
+            pool_idle_timeout_secs: Some(15),
+            pool_max_idle_per_host: Some(2),
+            ..config
+        };
+        assert_eq!(config.pool_idle_timeout(), Duration::from_secs(15));
+        assert_eq!(config.pool_max_idle_per_host(), 2);
+        assert!(OpenAIClient::new(config.clone()).is_ok());
+        assert!(AnthropicClient::new(config).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_connect_timeout_fails_fast() {
+        let path = std::env::temp_dir().join("emx-llm-ca-unused.pem");
+        let config = ProviderConfig {
+            ca_bundle_path: None,
+            ..config_with_ca_bundle(&path)
+        };
+        assert_eq!(config.connect_timeout(), Duration::from_secs(crate::config::DEFAULT_CONNECT_TIMEOUT_SECS));
+
+        // Nothing answers on this non-routable address, so only the connect
+        // timeout (not the 120s request timeout) bounds the attempt
+        let config = ProviderConfig {
+            api_base: "http://10.255.255.1:81/v1".to_string(),
+            connect_timeout_secs: Some(1),
+            ..config
+        };
+        let client = OpenAIClient::new(config).unwrap();
+        let started = Instant::now();
+        let result = client.chat(&[Message::user("Hi")], "gpt-test", None).await;
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        match result {
+            Err(Error::Http(e)) => assert!(e.is_connect() || e.is_timeout(), "unexpected error: {}", e),
+            other => panic!("expected a connect failure, got {:?}", other.map(|r| r.content)),
+        }
+    }
+
     #[test]
     fn test_sse_line_parsing() {
         // Test data: line stripping
@@ -1451,6 +4161,7 @@ mod tests {
             prompt_tokens: 1000,
             completion_tokens: 500,
             total_tokens: 1500,
+            ..Default::default()
         };
 
         let cost = usage.cost(0.50, 1.50);
@@ -63,6 +63,17 @@ impl ProviderType {
         }
     }
 
+    /// Get the default chat endpoint path, appended to the API base
+    pub fn default_chat_path(&self) -> &str {
+        match self {
+            ProviderType::OpenAI => "/chat/completions",
+            ProviderType::Anthropic => "/v1/messages",
+        }
+    }
+
+    /// Every supported provider type
+    pub const ALL: [ProviderType; 2] = [ProviderType::OpenAI, ProviderType::Anthropic];
+
     /// Get the config key for this provider
     pub fn config_key(&self) -> &str {
         match self {
@@ -96,6 +107,141 @@ pub struct ProviderConfig {
     /// Request timeout in seconds (default: 120)
     #[serde(default = "default_timeout")]
     pub timeout_secs: Option<u64>,
+
+    /// PEM file with extra root certificates to trust (e.g. the CA of a
+    /// TLS-inspecting corporate proxy)
+    #[serde(default)]
+    pub ca_bundle_path: Option<String>,
+
+    /// Chat endpoint path appended to `api_base`, for proxies that mount the
+    /// API under a prefix (default: provider-specific, see
+    /// [`ProviderType::default_chat_path`])
+    #[serde(default)]
+    pub chat_path: Option<String>,
+
+    /// Azure OpenAI deployment name; when set, requests go to
+    /// `{api_base}/openai/deployments/{deployment}/chat/completions` and
+    /// authenticate with an `api-key` header instead of a bearer token
+    #[serde(default)]
+    pub azure_deployment: Option<String>,
+
+    /// Azure OpenAI `api-version` query parameter (default:
+    /// [`DEFAULT_AZURE_API_VERSION`])
+    #[serde(default)]
+    pub azure_api_version: Option<String>,
+
+    /// Anthropic `anthropic-version` header (default:
+    /// [`DEFAULT_ANTHROPIC_VERSION`])
+    #[serde(default)]
+    pub anthropic_version: Option<String>,
+
+    /// Anthropic beta features to enable, sent comma-joined as the
+    /// `anthropic-beta` header, e.g. `["prompt-caching-2024-07-31"]`
+    #[serde(default)]
+    pub anthropic_beta: Vec<String>,
+
+    /// Seconds an idle pooled connection is kept before being closed
+    /// (default: [`DEFAULT_POOL_IDLE_TIMEOUT_SECS`])
+    #[serde(default)]
+    pub pool_idle_timeout_secs: Option<u64>,
+
+    /// Maximum idle pooled connections kept per host
+    /// (default: [`DEFAULT_POOL_MAX_IDLE_PER_HOST`])
+    #[serde(default)]
+    pub pool_max_idle_per_host: Option<usize>,
+
+    /// Seconds allowed for establishing a connection, separate from the
+    /// request timeout (default: [`DEFAULT_CONNECT_TIMEOUT_SECS`])
+    #[serde(default)]
+    pub connect_timeout_secs: Option<u64>,
+
+    /// Default sampling temperature, overridable per call via `ChatOptions`
+    #[serde(default)]
+    pub temperature: Option<f32>,
+
+    /// Default nucleus sampling mass, overridable per call via `ChatOptions`
+    #[serde(default)]
+    pub top_p: Option<f32>,
+
+    /// Send all system messages as one leading system message, joined with
+    /// a blank line, for OpenAI-compatible backends that reject more than one
+    #[serde(default)]
+    pub merge_system_prompts: bool,
+}
+
+impl Default for ProviderConfig {
+    /// An OpenAI provider at the public API base with no key and every
+    /// optional setting unset
+    fn default() -> Self {
+        Self {
+            provider_type: ProviderType::OpenAI,
+            api_base: ProviderType::OpenAI.default_base_url().to_string(),
+            api_key: String::new(),
+            model: None,
+            max_tokens: None,
+            timeout_secs: None,
+            ca_bundle_path: None,
+            chat_path: None,
+            azure_deployment: None,
+            azure_api_version: None,
+            anthropic_version: None,
+            anthropic_beta: Vec::new(),
+            pool_idle_timeout_secs: None,
+            pool_max_idle_per_host: None,
+            connect_timeout_secs: None,
+            temperature: None,
+            top_p: None,
+            merge_system_prompts: false,
+        }
+    }
+}
+
+/// Azure OpenAI API version used when `azure_api_version` is not configured
+pub const DEFAULT_AZURE_API_VERSION: &str = "2024-02-01";
+
+/// Anthropic API version used when `anthropic_version` is not configured
+pub const DEFAULT_ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Connect timeout used when `connect_timeout_secs` is not configured
+pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// Idle connection lifetime used when `pool_idle_timeout_secs` is not configured
+pub const DEFAULT_POOL_IDLE_TIMEOUT_SECS: u64 = 90;
+
+/// Idle connections per host kept when `pool_max_idle_per_host` is not configured
+pub const DEFAULT_POOL_MAX_IDLE_PER_HOST: usize = 32;
+
+/// Environment variable holding the API key when no config key is set
+fn legacy_api_key_var(provider_type: ProviderType) -> &'static str {
+    match provider_type {
+        ProviderType::OpenAI => "OPENAI_API_KEY",
+        ProviderType::Anthropic => "ANTHROPIC_AUTH_TOKEN",
+    }
+}
+
+/// Pick the provider type when `llm.provider.type` is not set
+///
+/// Keeps `default` unless it has no API key configured and exactly one
+/// other provider type does, e.g. a setup with only Anthropic configured.
+fn infer_provider_type(default: ProviderType, is_configured: impl Fn(ProviderType) -> bool) -> ProviderType {
+    if is_configured(default) {
+        return default;
+    }
+    let others: Vec<ProviderType> = ProviderType::ALL
+        .into_iter()
+        .filter(|candidate| *candidate != default && is_configured(*candidate))
+        .collect();
+    match others.as_slice() {
+        [only] => {
+            tracing::debug!(
+                "llm.provider.type not set and {} is not configured; using {}",
+                default.config_key(),
+                only.config_key()
+            );
+            *only
+        }
+        _ => default,
+    }
 }
 
 fn default_timeout() -> Option<u64> {
@@ -120,10 +266,37 @@ impl std::fmt::Debug for ProviderConfig {
             .field("model", &self.model)
             .field("max_tokens", &self.max_tokens)
             .field("timeout_secs", &self.timeout_secs)
+            .field("ca_bundle_path", &self.ca_bundle_path)
+            .field("chat_path", &self.chat_path)
+            .field("azure_deployment", &self.azure_deployment)
+            .field("azure_api_version", &self.azure_api_version)
+            .field("anthropic_version", &self.anthropic_version)
+            .field("anthropic_beta", &self.anthropic_beta)
+            .field("pool_idle_timeout_secs", &self.pool_idle_timeout_secs)
+            .field("pool_max_idle_per_host", &self.pool_max_idle_per_host)
+            .field("connect_timeout_secs", &self.connect_timeout_secs)
+            .field("temperature", &self.temperature)
+            .field("top_p", &self.top_p)
+            .field("merge_system_prompts", &self.merge_system_prompts)
             .finish()
     }
 }
 
+/// Split a comma-separated config value into its trimmed, non-empty items
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Contents of a TOML config file, `None` if it is missing or malformed
+fn read_toml_file(path: &std::path::Path) -> Option<toml::Value> {
+    std::fs::read_to_string(path).ok()?.parse::<toml::Value>().ok()
+}
+
 fn default_max_tokens() -> Option<u32> {
     None
 }
@@ -139,24 +312,149 @@ impl ProviderConfig {
         std::time::Duration::from_secs(self.timeout_secs.unwrap_or(120))
     }
 
+    /// How long an idle pooled connection is kept
+    pub fn pool_idle_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.pool_idle_timeout_secs.unwrap_or(DEFAULT_POOL_IDLE_TIMEOUT_SECS))
+    }
+
+    /// Maximum idle pooled connections kept per host
+    pub fn pool_max_idle_per_host(&self) -> usize {
+        self.pool_max_idle_per_host.unwrap_or(DEFAULT_POOL_MAX_IDLE_PER_HOST)
+    }
+
+    /// How long establishing a connection may take
+    pub fn connect_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.connect_timeout_secs.unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS))
+    }
+
+    /// Request parameters configured for this provider/model, used as the
+    /// base that per-call [`ChatOptions`](crate::ChatOptions) are merged over
+    pub fn default_options(&self) -> crate::ChatOptions {
+        crate::ChatOptions {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_tokens: self.max_tokens,
+            response_format: None,
+            stop: Vec::new(),
+            logprobs: None,
+            top_logprobs: None,
+            extra_body: None,
+            strip_reasoning: None,
+        }
+    }
+
+    /// Get the full chat endpoint URL (`{api_base}{chat_path}`, or the
+    /// deployment URL with its `api-version` query for Azure OpenAI)
+    pub fn chat_url(&self) -> String {
+        if let Some(deployment) = &self.azure_deployment {
+            return format!(
+                "{}/openai/deployments/{}/chat/completions?api-version={}",
+                self.api_base.trim_end_matches('/'),
+                deployment,
+                self.azure_api_version.as_deref().unwrap_or(DEFAULT_AZURE_API_VERSION)
+            );
+        }
+        let path = self
+            .chat_path
+            .as_deref()
+            .unwrap_or_else(|| self.provider_type.default_chat_path());
+        format!(
+            "{}/{}",
+            self.api_base.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    }
+
+    /// Get the embeddings endpoint URL (`{api_base}/embeddings`, or the
+    /// deployment's embeddings URL for Azure OpenAI)
+    pub fn embeddings_url(&self) -> String {
+        let base = self.api_base.trim_end_matches('/');
+        match &self.azure_deployment {
+            Some(deployment) => format!(
+                "{}/openai/deployments/{}/embeddings?api-version={}",
+                base,
+                deployment,
+                self.azure_api_version.as_deref().unwrap_or(DEFAULT_AZURE_API_VERSION)
+            ),
+            None => format!("{}/embeddings", base),
+        }
+    }
+
+    /// Value of the `anthropic-version` header
+    pub fn anthropic_version(&self) -> &str {
+        self.anthropic_version.as_deref().unwrap_or(DEFAULT_ANTHROPIC_VERSION)
+    }
+
+    /// Authentication header for OpenAI-style requests: `api-key` for Azure
+    /// OpenAI deployments, a bearer token otherwise
+    pub fn openai_auth_header(&self) -> (&'static str, String) {
+        if self.azure_deployment.is_some() {
+            ("api-key", self.api_key.clone())
+        } else {
+            ("Authorization", format!("Bearer {}", self.api_key))
+        }
+    }
+
+    /// Check the config for likely mistakes
+    ///
+    /// Offline checks cover the API base URL and key. With `online`, the API
+    /// host is also probed with a TCP connect (no request is sent).
+    pub fn validate(&self, online: bool) -> ValidationReport {
+        let mut issues = Vec::new();
+
+        match reqwest::Url::parse(&self.api_base) {
+            Ok(url) if !matches!(url.scheme(), "http" | "https") => {
+                issues.push(ValidationIssue::MalformedUrl(format!(
+                    "unsupported scheme '{}'",
+                    url.scheme()
+                )));
+            }
+            Ok(url) if url.host_str().is_none() => {
+                issues.push(ValidationIssue::MalformedUrl("missing host".to_string()));
+            }
+            Ok(url) => {
+                if online {
+                    if let Err(reason) = probe_host(&url) {
+                        issues.push(ValidationIssue::Unreachable(reason));
+                    }
+                }
+            }
+            Err(e) => issues.push(ValidationIssue::MalformedUrl(e.to_string())),
+        }
+
+        let key_len = self.api_key.trim().len();
+        if key_len == 0 {
+            issues.push(ValidationIssue::EmptyApiKey);
+        } else if key_len < MIN_API_KEY_LEN {
+            issues.push(ValidationIssue::ShortApiKey(key_len));
+        }
+
+        ValidationReport { issues }
+    }
+
     /// Load configuration from emx-config
     pub fn load() -> anyhow::Result<Self> {
         Self::load_with_args(None)
     }
 
-    /// Load configuration with CLI argument overrides
-    pub fn load_with_args(args: Option<HashMap<String, toml::Value>>) -> anyhow::Result<Self> {
-        // Set up default values
-        let mut defaults = HashMap::new();
-        defaults.insert(
-            "llm.provider.type".to_string(),
-            toml::Value::String("openai".to_string()),
+    /// Load the `[llm.provider.<type>]` configuration for `provider_type`,
+    /// whatever `llm.provider.type` selects
+    pub fn load_for_provider(provider_type: ProviderType) -> anyhow::Result<Self> {
+        let mut provider_table = toml::value::Table::new();
+        provider_table.insert(
+            "type".to_string(),
+            toml::Value::String(provider_type.config_key().to_string()),
         );
+        let mut llm_table = toml::value::Table::new();
+        llm_table.insert("provider".to_string(), toml::Value::Table(provider_table));
+        Self::load_with_args(Some(HashMap::from([("llm".to_string(), toml::Value::Table(llm_table))])))
+    }
 
-        // Build config with emx-config
-        let mut builder = ConfigBuilder::new()
-            .with_prefix("EMX_LLM")
-            .with_defaults(defaults);
+    /// Load configuration with CLI argument overrides
+    pub fn load_with_args(args: Option<HashMap<String, toml::Value>>) -> anyhow::Result<Self> {
+        // Build config with emx-config; `llm.provider.type` is left unset here
+        // so an explicit choice can be told apart from the default
+        let mut builder = ConfigBuilder::new().with_prefix("EMX_LLM");
 
         if let Some(args) = args {
             builder = builder.with_args(args);
@@ -165,9 +463,8 @@ impl ProviderConfig {
         let config = builder.build()?;
 
         // Get provider type
-        let provider_type_str = config
-            .get_string("llm.provider.type")
-            .unwrap_or_else(|_| "openai".to_string());
+        let explicit_type = config.get_string("llm.provider.type").ok();
+        let provider_type_str = explicit_type.clone().unwrap_or_else(|| "openai".to_string());
 
         let provider_type = match provider_type_str.to_lowercase().as_str() {
             "openai" => ProviderType::OpenAI,
@@ -179,6 +476,16 @@ impl ProviderConfig {
                 ))
             }
         };
+        let provider_type = if explicit_type.is_some() {
+            provider_type
+        } else {
+            infer_provider_type(provider_type, |candidate| {
+                config
+                    .get_string(&format!("llm.provider.{}.api_key", candidate.config_key()))
+                    .is_ok()
+                    || std::env::var(legacy_api_key_var(candidate)).is_ok()
+            })
+        };
 
         let provider_key = provider_type.config_key();
         let base_key = format!("llm.provider.{}", provider_key);
@@ -187,11 +494,7 @@ impl ProviderConfig {
         let api_key = config
             .get_string(&format!("{}.api_key", base_key))
             .or_else(|_| {
-                let legacy_key = match provider_type {
-                    ProviderType::OpenAI => "OPENAI_API_KEY",
-                    ProviderType::Anthropic => "ANTHROPIC_AUTH_TOKEN",
-                };
-                std::env::var(legacy_key).map_err(|_| {
+                std::env::var(legacy_api_key_var(provider_type)).map_err(|_| {
                     anyhow::anyhow!(
                         "{} not found in config or environment",
                         format!("{}.api_key", base_key)
@@ -235,6 +538,64 @@ impl ProviderConfig {
             .or_else(|| config.get_int("llm.provider.timeout_secs").ok())
             .map(|v| v as u64);
 
+        // Get ca_bundle_path
+        let ca_bundle_path = config
+            .get_string(&format!("{}.ca_bundle_path", base_key))
+            .ok()
+            .or_else(|| config.get_string("llm.provider.ca_bundle_path").ok());
+
+        // Get chat_path
+        let chat_path = config
+            .get_string(&format!("{}.chat_path", base_key))
+            .ok();
+
+        // Get Azure OpenAI deployment settings
+        let azure_deployment = config
+            .get_string(&format!("{}.azure_deployment", base_key))
+            .ok();
+        let azure_api_version = config
+            .get_string(&format!("{}.azure_api_version", base_key))
+            .ok();
+
+        // Get Anthropic API version and beta features
+        let anthropic_version = config
+            .get_string(&format!("{}.anthropic_version", base_key))
+            .ok();
+        let anthropic_beta = config
+            .get_string(&format!("{}.anthropic_beta", base_key))
+            .map(|v| split_list(&v))
+            .unwrap_or_default();
+
+        // Get connection settings
+        let pool_idle_timeout_secs = config
+            .get_int(&format!("{}.pool_idle_timeout_secs", base_key))
+            .ok()
+            .or_else(|| config.get_int("llm.provider.pool_idle_timeout_secs").ok())
+            .map(|v| v as u64);
+        let pool_max_idle_per_host = config
+            .get_int(&format!("{}.pool_max_idle_per_host", base_key))
+            .ok()
+            .or_else(|| config.get_int("llm.provider.pool_max_idle_per_host").ok())
+            .map(|v| v as usize);
+        let connect_timeout_secs = config
+            .get_int(&format!("{}.connect_timeout_secs", base_key))
+            .ok()
+            .or_else(|| config.get_int("llm.provider.connect_timeout_secs").ok())
+            .map(|v| v as u64);
+
+        // Get sampling defaults
+        let temperature = config
+            .get_string(&format!("{}.temperature", base_key))
+            .ok()
+            .and_then(|v| v.parse::<f32>().ok());
+        let top_p = config
+            .get_string(&format!("{}.top_p", base_key))
+            .ok()
+            .and_then(|v| v.parse::<f32>().ok());
+        let merge_system_prompts = config
+            .get_bool(&format!("{}.merge_system_prompts", base_key))
+            .unwrap_or(false);
+
         Ok(ProviderConfig {
             provider_type,
             api_base,
@@ -242,6 +603,18 @@ impl ProviderConfig {
             model,
             max_tokens,
             timeout_secs,
+            ca_bundle_path,
+            chat_path,
+            azure_deployment,
+            azure_api_version,
+            anthropic_version,
+            anthropic_beta,
+            pool_idle_timeout_secs,
+            pool_max_idle_per_host,
+            connect_timeout_secs,
+            temperature,
+            top_p,
+            merge_system_prompts,
         })
     }
 
@@ -274,11 +647,13 @@ impl ProviderConfig {
     /// ProviderConfig
     /// load_for_model
     pub fn load_for_model(model_ref: &str) -> anyhow::Result<(ModelConfig, String)> {
-        let parsed = ModelReference::parse(model_ref)?;
-
         // Load TOML config for hierarchical lookup
         let toml_value = Self::load_toml_config()?;
 
+        let aliased = Self::resolve_alias(&toml_value, model_ref);
+        let model_ref = aliased.as_deref().unwrap_or(model_ref);
+        let parsed = ModelReference::parse(model_ref)?;
+
         // Set up default values
         let mut defaults = HashMap::new();
         defaults.insert(
@@ -352,24 +727,39 @@ impl ProviderConfig {
         }
     }
 
+    /// Look `model_ref` up in `[llm.aliases]` (case-insensitively, one level only)
+    ///
+    /// ```toml
+    /// [llm.aliases]
+    /// gpt4 = "openai.gpt-4"
+    /// ```
+    ///
+    /// An alias takes precedence over a model section of the same name.
+    fn resolve_alias(toml_value: &toml::Value, model_ref: &str) -> Option<String> {
+        let aliases = toml_value.get("llm")?.get("aliases")?.as_table()?;
+        let name = model_ref.trim();
+        let (alias, target) = aliases
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(name))?;
+        let target = target.as_str()?;
+
+        let shadowed = Self::find_sections_by_key(toml_value, &name.to_lowercase());
+        if !shadowed.is_empty() {
+            tracing::warn!(
+                "Model alias '{}' shadows configured section(s) {}; using '{}'",
+                alias,
+                shadowed.join(", "),
+                target
+            );
+        }
+        Some(target.to_string())
+    }
+
     /// Load TOML config file once, trying local then home directory
     fn load_toml_config() -> anyhow::Result<toml::Value> {
-        let home_config = dirs::home_dir()
-            .map(|p| {
-                let mut path = p;
-                path.push(".emx");
-                path.push("config.toml");
-                path.display().to_string()
-            })
-            .unwrap_or_default();
-
-        let config_sources: Vec<&str> = vec!["./config.toml", &home_config];
-
-        for source in config_sources {
-            if let Ok(content) = std::fs::read_to_string(source) {
-                if let Ok(toml_value) = content.parse::<toml::Value>() {
-                    return Ok(toml_value);
-                }
+        for source in Self::config_search_paths() {
+            if let Some(toml_value) = read_toml_file(&source) {
+                return Ok(toml_value);
             }
         }
 
@@ -377,6 +767,24 @@ impl ProviderConfig {
         Ok(toml::Value::Table(toml::map::Map::new()))
     }
 
+    /// Config files searched for model sections, in priority order:
+    /// `./config.toml`, then `~/.emx/config.toml`
+    pub fn config_search_paths() -> Vec<std::path::PathBuf> {
+        let mut paths = vec![std::path::PathBuf::from("./config.toml")];
+        if let Some(home) = dirs::home_dir() {
+            paths.push(home.join(".emx").join("config.toml"));
+        }
+        paths
+    }
+
+    /// The config file in use: the first of [`config_search_paths`](Self::config_search_paths)
+    /// that exists and parses
+    pub fn active_config_path() -> Option<std::path::PathBuf> {
+        Self::config_search_paths()
+            .into_iter()
+            .find(|path| read_toml_file(path).is_some())
+    }
+
     /// Find all sections under that end with the given key
     /// Returns list of full paths (e.g., ["anthropic.glm.glm-5", "openai.models.glm-5"])
     fn find_sections_by_key(toml_value: &toml::Value, key: &str) -> Vec<String> {
@@ -452,15 +860,7 @@ impl ProviderConfig {
             .collect();
 
         // Determine provider type from explicit reference
-        let explicit_provider_type = if let Some(pt) = &model_ref.provider_type {
-            match pt.to_lowercase().as_str() {
-                "openai" => Some(ProviderType::OpenAI),
-                "anthropic" => Some(ProviderType::Anthropic),
-                _ => None,
-            }
-        } else {
-            None
-        };
+        let explicit_provider_type = model_ref.provider();
 
         // Try paths in order of specificity:
         // 1. Full path (e.g., ["anthropic", "glm", "glm-4-7"])
@@ -511,15 +911,7 @@ impl ProviderConfig {
             .map(|s| s.to_string())
             .collect();
 
-        let explicit_provider_type =
-            model_ref
-                .provider_type
-                .as_ref()
-                .and_then(|pt| match pt.to_lowercase().as_str() {
-                    "openai" => Some(ProviderType::OpenAI),
-                    "anthropic" => Some(ProviderType::Anthropic),
-                    _ => None,
-                });
+        let explicit_provider_type = model_ref.provider();
 
         // Try full path first
         if path_parts.len() > 1 {
@@ -615,12 +1007,59 @@ impl ProviderConfig {
             .and_then(|v| v.as_integer())
             .map(|v| v as u32);
 
+        // Get max_output_tokens
+        let max_output_tokens = section
+            .get("max_output_tokens")
+            .and_then(|v| v.as_integer())
+            .map(|v| v as u32);
+
+        // Get ca_bundle_path - search current level and up
+        let ca_bundle_path = Self::find_toml_key(toml_value, &key_parts, "ca_bundle_path");
+
+        // Get chat_path - search current level and up
+        let chat_path = Self::find_toml_key(toml_value, &key_parts, "chat_path");
+
+        // Get Azure OpenAI deployment settings - search current level and up
+        let azure_deployment = Self::find_toml_key(toml_value, &key_parts, "azure_deployment");
+        let azure_api_version = Self::find_toml_key(toml_value, &key_parts, "azure_api_version");
+
+        // Get Anthropic API version and beta features - search current level and up
+        let anthropic_version = Self::find_toml_key(toml_value, &key_parts, "anthropic_version");
+        let anthropic_beta = Self::find_toml_list(toml_value, &key_parts, "anthropic_beta").unwrap_or_default();
+
+        // Get connection settings - search current level and up
+        let pool_idle_timeout_secs =
+            Self::find_toml_int(toml_value, &key_parts, "pool_idle_timeout_secs").map(|v| v as u64);
+        let pool_max_idle_per_host =
+            Self::find_toml_int(toml_value, &key_parts, "pool_max_idle_per_host").map(|v| v as usize);
+        let connect_timeout_secs =
+            Self::find_toml_int(toml_value, &key_parts, "connect_timeout_secs").map(|v| v as u64);
+
+        // Get sampling defaults - search current level and up
+        let temperature = Self::find_toml_float(toml_value, &key_parts, "temperature");
+        let top_p = Self::find_toml_float(toml_value, &key_parts, "top_p");
+        let merge_system_prompts =
+            Self::find_toml_bool(toml_value, &key_parts, "merge_system_prompts").unwrap_or(false);
+
         Some(ModelConfig {
             provider_type,
             api_base,
             api_key,
             model,
             max_tokens,
+            max_output_tokens,
+            ca_bundle_path,
+            chat_path,
+            azure_deployment,
+            azure_api_version,
+            anthropic_version,
+            anthropic_beta,
+            pool_idle_timeout_secs,
+            pool_max_idle_per_host,
+            connect_timeout_secs,
+            temperature,
+            top_p,
+            merge_system_prompts,
         })
     }
 
@@ -659,6 +1098,59 @@ impl ProviderConfig {
         None
     }
 
+    /// Find a numeric key in TOML by searching up the hierarchy
+    fn find_toml_float(toml_value: &toml::Value, key_parts: &[String], key: &str) -> Option<f32> {
+        (2..=key_parts.len()).rev().find_map(|i| {
+            let mut current = Some(toml_value);
+            for part in &key_parts[..i] {
+                current = current.and_then(|v| v.get(part.as_str()));
+            }
+            let value = current?.get(key)?;
+            value
+                .as_float()
+                .or_else(|| value.as_integer().map(|v| v as f64))
+                .map(|v| v as f32)
+        })
+    }
+
+    /// Find a boolean key in TOML by searching up the hierarchy
+    fn find_toml_bool(toml_value: &toml::Value, key_parts: &[String], key: &str) -> Option<bool> {
+        (2..=key_parts.len()).rev().find_map(|i| {
+            let mut current = Some(toml_value);
+            for part in &key_parts[..i] {
+                current = current.and_then(|v| v.get(part.as_str()));
+            }
+            current?.get(key)?.as_bool()
+        })
+    }
+
+    /// Find a string list key in TOML by searching up the hierarchy; a
+    /// single string is read as a comma-separated list
+    fn find_toml_list(toml_value: &toml::Value, key_parts: &[String], key: &str) -> Option<Vec<String>> {
+        (2..=key_parts.len()).rev().find_map(|i| {
+            let mut current = Some(toml_value);
+            for part in &key_parts[..i] {
+                current = current.and_then(|v| v.get(part.as_str()));
+            }
+            match current?.get(key)? {
+                toml::Value::Array(items) => Some(items.iter().filter_map(|v| v.as_str()).map(String::from).collect()),
+                toml::Value::String(s) => Some(split_list(s)),
+                _ => None,
+            }
+        })
+    }
+
+    /// Find an integer key in TOML by searching up the hierarchy
+    fn find_toml_int(toml_value: &toml::Value, key_parts: &[String], key: &str) -> Option<i64> {
+        (2..=key_parts.len()).rev().find_map(|i| {
+            let mut current = Some(toml_value);
+            for part in &key_parts[..i] {
+                current = current.and_then(|v| v.get(part.as_str()));
+            }
+            current?.get(key)?.as_integer()
+        })
+    }
+
     /// Try to resolve configuration at a specific level in the hierarchy
     fn try_resolve_at_level(
         config: &emx_config_core::Config,
@@ -731,12 +1223,54 @@ impl ProviderConfig {
         // Get max_tokens
         let max_tokens = find_key("max_tokens").and_then(|s| s.parse::<u32>().ok());
 
+        // Get max_output_tokens
+        let max_output_tokens = find_key("max_output_tokens").and_then(|s| s.parse::<u32>().ok());
+
+        // Get ca_bundle_path with hierarchical fallback
+        let ca_bundle_path = find_key("ca_bundle_path");
+
+        // Get chat_path with hierarchical fallback
+        let chat_path = find_key("chat_path");
+
+        // Get Azure OpenAI deployment settings with hierarchical fallback
+        let azure_deployment = find_key("azure_deployment");
+        let azure_api_version = find_key("azure_api_version");
+
+        // Get Anthropic API version and beta features with hierarchical fallback
+        let anthropic_version = find_key("anthropic_version");
+        let anthropic_beta = find_key("anthropic_beta").map(|v| split_list(&v)).unwrap_or_default();
+
+        // Get connection settings with hierarchical fallback
+        let pool_idle_timeout_secs = find_key("pool_idle_timeout_secs").and_then(|s| s.parse::<u64>().ok());
+        let pool_max_idle_per_host = find_key("pool_max_idle_per_host").and_then(|s| s.parse::<usize>().ok());
+        let connect_timeout_secs = find_key("connect_timeout_secs").and_then(|s| s.parse::<u64>().ok());
+
+        // Get sampling defaults with hierarchical fallback
+        let temperature = find_key("temperature").and_then(|s| s.parse::<f32>().ok());
+        let top_p = find_key("top_p").and_then(|s| s.parse::<f32>().ok());
+        let merge_system_prompts = find_key("merge_system_prompts")
+            .and_then(|s| s.parse::<bool>().ok())
+            .unwrap_or(false);
+
         Some(ModelConfig {
             provider_type,
             api_base,
             api_key,
             model,
             max_tokens,
+            max_output_tokens,
+            ca_bundle_path,
+            chat_path,
+            azure_deployment,
+            azure_api_version,
+            anthropic_version,
+            anthropic_beta,
+            pool_idle_timeout_secs,
+            pool_max_idle_per_host,
+            connect_timeout_secs,
+            temperature,
+            top_p,
+            merge_system_prompts,
         })
     }
 
@@ -755,6 +1289,64 @@ impl ProviderConfig {
         self.model.as_deref()
     }
 
+    /// Expand `@group` entries in a list of model references
+    ///
+    /// Groups are arrays of model references under `[llm.group]`:
+    ///
+    /// ```toml
+    /// [llm.group]
+    /// frontier = ["openai.gpt-4o", "anthropic.claude-3-opus"]
+    /// ```
+    ///
+    /// Plain references are passed through unchanged, so `["@frontier", "glm-5"]`
+    /// expands to all three models in order.
+    pub fn expand_model_refs(refs: &[String]) -> anyhow::Result<Vec<String>> {
+        if !refs.iter().any(|r| r.starts_with('@')) {
+            return Ok(refs.to_vec());
+        }
+        let toml_value = Self::load_toml_config()?;
+        Self::expand_model_refs_in(&toml_value, refs)
+    }
+
+    fn expand_model_refs_in(toml_value: &toml::Value, refs: &[String]) -> anyhow::Result<Vec<String>> {
+        let groups = toml_value
+            .get("llm")
+            .and_then(|v| v.get("group"))
+            .and_then(|v| v.as_table());
+
+        let mut expanded = Vec::new();
+        for model_ref in refs {
+            let Some(name) = model_ref.strip_prefix('@') else {
+                expanded.push(model_ref.clone());
+                continue;
+            };
+
+            let members = groups
+                .and_then(|g| g.get(name))
+                .ok_or_else(|| {
+                    let known: Vec<&str> = groups
+                        .map(|g| g.keys().map(String::as_str).collect())
+                        .unwrap_or_default();
+                    anyhow::anyhow!(
+                        "Model group '{}' not found in [llm.group] (available: {})",
+                        name,
+                        if known.is_empty() { "none".to_string() } else { known.join(", ") }
+                    )
+                })?
+                .as_array()
+                .ok_or_else(|| anyhow::anyhow!("llm.group.{} must be an array of model references", name))?;
+
+            for member in members {
+                let member = member.as_str().ok_or_else(|| {
+                    anyhow::anyhow!("llm.group.{} must contain only strings", name)
+                })?;
+                expanded.push(member.to_string());
+            }
+        }
+
+        Ok(expanded)
+    }
+
     /// List all configured models from TOML config
     /// Returns a list of (full_model_ref, model_config) tuples
     pub fn list_models() -> anyhow::Result<Vec<(String, ModelConfig)>> {
@@ -861,11 +1453,322 @@ impl ProviderConfig {
     }
 }
 
+/// API keys shorter than this are reported as suspicious
+const MIN_API_KEY_LEN: usize = 16;
+
+/// How long [`ProviderConfig::validate`] waits for the API host to accept
+const PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// A problem found by [`ProviderConfig::validate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// `api_base` is not an http(s) URL with a host
+    MalformedUrl(String),
+    /// `api_key` is empty
+    EmptyApiKey,
+    /// `api_key` is shorter than real keys usually are (holds its length)
+    ShortApiKey(usize),
+    /// The API host could not be resolved or connected to
+    Unreachable(String),
+}
+
+impl ValidationIssue {
+    /// Whether the issue is only suspicious rather than certainly wrong
+    pub fn is_warning(&self) -> bool {
+        matches!(self, ValidationIssue::ShortApiKey(_))
+    }
+}
+
+impl std::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationIssue::MalformedUrl(reason) => write!(f, "malformed API base URL: {}", reason),
+            ValidationIssue::EmptyApiKey => write!(f, "API key is empty"),
+            ValidationIssue::ShortApiKey(len) => {
+                write!(f, "API key is suspiciously short ({} characters)", len)
+            }
+            ValidationIssue::Unreachable(reason) => write!(f, "API host is unreachable: {}", reason),
+        }
+    }
+}
+
+/// Result of [`ProviderConfig::validate`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Problems found, in check order
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// True when no issue other than warnings was found
+    pub fn is_valid(&self) -> bool {
+        self.issues.iter().all(ValidationIssue::is_warning)
+    }
+}
+
+/// Open (and close) a TCP connection to the URL's host and port
+fn probe_host(url: &reqwest::Url) -> Result<(), String> {
+    use std::net::ToSocketAddrs;
+
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs: Vec<_> = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("cannot resolve {}: {}", host, e))?
+        .collect();
+
+    let mut last_error = format!("no addresses found for {}", host);
+    for addr in addrs {
+        match std::net::TcpStream::connect_timeout(&addr, PROBE_TIMEOUT) {
+            Ok(_) => return Ok(()),
+            Err(e) => last_error = format!("cannot connect to {}:{}: {}", host, port, e),
+        }
+    }
+    Err(last_error)
+}
+
 /// Load configuration with default settings
 pub fn load_with_default() -> anyhow::Result<ProviderConfig> {
     ProviderConfig::load()
 }
 
+/// Settings for the `env` command, from the `[llm.env]` section
+///
+/// ```toml
+/// [llm.env]
+/// safe_vars = ["MY_TOOL_HOME"]     # shown in addition to the built-in whitelist
+/// redact = ["*_TOKEN", "*SECRET*"] # values masked, even with --verbose
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EnvConfig {
+    /// Extra variable names to report alongside the built-in whitelist
+    #[serde(default)]
+    pub safe_vars: Vec<String>,
+
+    /// Variable name patterns (`*` wildcard, case-insensitive) whose values are masked
+    #[serde(default)]
+    pub redact: Vec<String>,
+}
+
+impl EnvConfig {
+    /// Load `[llm.env]` from the config file, or defaults when absent
+    pub fn load() -> anyhow::Result<Self> {
+        Self::from_toml(&ProviderConfig::load_toml_config()?)
+    }
+
+    fn from_toml(toml_value: &toml::Value) -> anyhow::Result<Self> {
+        match toml_value.get("llm").and_then(|v| v.get("env")) {
+            Some(section) => section
+                .clone()
+                .try_into()
+                .map_err(|e| anyhow::anyhow!("Invalid [llm.env] section: {}", e)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Whether the value of `name` must be masked
+    pub fn is_redacted(&self, name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        self.redact
+            .iter()
+            .any(|pattern| wildcard_match(&pattern.to_ascii_lowercase(), &name))
+    }
+}
+
+/// Settings for the `dev` command, from `[[llm.dev.profile]]` entries
+///
+/// ```toml
+/// [[llm.dev.profile]]
+/// name = "dotnet"
+/// detect = ["global.json"]
+/// tools = [{ name = "dotnet", cmd = "dotnet --version" }]
+/// env_vars = ["DOTNET_ROOT"]
+/// ```
+///
+/// A profile named like a built-in one replaces it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DevConfig {
+    /// User-defined profiles
+    #[serde(default, rename = "profile")]
+    pub profiles: Vec<DevProfile>,
+}
+
+/// A development profile definition
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DevProfile {
+    /// Profile name
+    pub name: String,
+    /// Files to detect (any one triggers the profile)
+    #[serde(default)]
+    pub detect: Vec<String>,
+    /// Tools to check versions
+    #[serde(default)]
+    pub tools: Vec<DevTool>,
+    /// Environment variables to show
+    #[serde(default)]
+    pub env_vars: Vec<String>,
+}
+
+/// Tool definition for version checking
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DevTool {
+    /// Tool name (for display)
+    pub name: String,
+    /// Command to run for version
+    pub cmd: String,
+}
+
+impl DevConfig {
+    /// Load `[llm.dev]` from the config file, or defaults when absent
+    pub fn load() -> anyhow::Result<Self> {
+        Self::from_toml(&ProviderConfig::load_toml_config()?)
+    }
+
+    fn from_toml(toml_value: &toml::Value) -> anyhow::Result<Self> {
+        match toml_value.get("llm").and_then(|v| v.get("dev")) {
+            Some(section) => section
+                .clone()
+                .try_into()
+                .map_err(|e| anyhow::anyhow!("Invalid [llm.dev] section: {}", e)),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+/// Gateway fallback models, from the `[llm.fallback]` section
+///
+/// ```toml
+/// [llm.fallback]
+/// "openai.gpt-4o" = ["openai.gpt-4o-mini", "openai.glm-4"]
+/// ```
+///
+/// When the upstream reports a model as not found, the gateway retries the
+/// request with each listed model in order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FallbackConfig {
+    /// Ordered fallback model references, keyed by the requested model reference
+    pub chains: HashMap<String, Vec<String>>,
+}
+
+impl FallbackConfig {
+    /// Load `[llm.fallback]` from the config file, or defaults when absent
+    pub fn load() -> anyhow::Result<Self> {
+        Self::from_toml(&ProviderConfig::load_toml_config()?)
+    }
+
+    fn from_toml(toml_value: &toml::Value) -> anyhow::Result<Self> {
+        match toml_value.get("llm").and_then(|v| v.get("fallback")) {
+            Some(section) => section
+                .clone()
+                .try_into()
+                .map_err(|e| anyhow::anyhow!("Invalid [llm.fallback] section: {}", e)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Fallbacks for the first of `model_refs` that has any configured
+    pub fn fallbacks_for(&self, model_refs: &[&str]) -> &[String] {
+        model_refs
+            .iter()
+            .find_map(|model_ref| self.chains.get(*model_ref))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+/// How the gateway's mandatory system prompt combines with the client's
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SystemPromptMode {
+    /// Put the gateway text before the client's system prompt
+    #[default]
+    Prepend,
+    /// Put the gateway text after the client's system prompt
+    Append,
+    /// Drop the client's system prompt and use only the gateway text
+    Replace,
+}
+
+/// Mandatory system prompt the gateway applies to every request, from the
+/// `[llm.gateway.system_prompt]` section
+///
+/// ```toml
+/// [llm.gateway.system_prompt]
+/// mode = "prepend"   # or "append", "replace"
+/// text = "Answer in English."
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SystemPromptConfig {
+    /// Where the text goes relative to the client's system prompt (default: prepend)
+    #[serde(default)]
+    pub mode: SystemPromptMode,
+
+    /// System prompt text
+    pub text: String,
+}
+
+impl SystemPromptConfig {
+    /// Load `[llm.gateway.system_prompt]` from the config file, `None` when absent
+    pub fn load() -> anyhow::Result<Option<Self>> {
+        Self::from_toml(&ProviderConfig::load_toml_config()?)
+    }
+
+    fn from_toml(toml_value: &toml::Value) -> anyhow::Result<Option<Self>> {
+        match toml_value
+            .get("llm")
+            .and_then(|v| v.get("gateway"))
+            .and_then(|v| v.get("system_prompt"))
+        {
+            Some(section) => section
+                .clone()
+                .try_into()
+                .map(Some)
+                .map_err(|e| anyhow::anyhow!("Invalid [llm.gateway.system_prompt] section: {}", e)),
+            None => Ok(None),
+        }
+    }
+
+    /// Merge the text into the first system message of `messages` (the one
+    /// providers send), adding one at the front when there is none
+    pub fn apply(&self, messages: &mut Vec<crate::Message>) {
+        if self.mode == SystemPromptMode::Replace {
+            messages.retain(|m| m.role != crate::MessageRole::System);
+        }
+        let Some(system) = messages.iter_mut().find(|m| m.role == crate::MessageRole::System) else {
+            messages.insert(0, crate::Message::system(self.text.clone()));
+            return;
+        };
+        let existing = system.get_content().unwrap_or_default();
+        let merged = match self.mode {
+            SystemPromptMode::Append => format!("{}\n\n{}", existing, self.text),
+            _ => format!("{}\n\n{}", self.text, existing),
+        };
+        system.content = crate::MessageContent::Text(merged);
+    }
+}
+
+/// Match `text` against `pattern`, where `*` matches any run of characters
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*` at all: exact match
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
 /// Model-specific configuration resolved from hierarchical config
 #[derive(Clone)]
 pub struct ModelConfig {
@@ -883,6 +1786,46 @@ pub struct ModelConfig {
 
     /// Maximum tokens for response
     pub max_tokens: Option<u32>,
+
+    /// Most output tokens the model supports; the gateway lowers larger
+    /// client `max_tokens` requests to this
+    pub max_output_tokens: Option<u32>,
+
+    /// PEM file with extra root certificates to trust
+    pub ca_bundle_path: Option<String>,
+
+    /// Chat endpoint path appended to `api_base`
+    pub chat_path: Option<String>,
+
+    /// Azure OpenAI deployment name
+    pub azure_deployment: Option<String>,
+
+    /// Azure OpenAI `api-version` query parameter
+    pub azure_api_version: Option<String>,
+
+    /// Anthropic `anthropic-version` header
+    pub anthropic_version: Option<String>,
+
+    /// Anthropic beta features (`anthropic-beta` header)
+    pub anthropic_beta: Vec<String>,
+
+    /// Seconds an idle pooled connection is kept
+    pub pool_idle_timeout_secs: Option<u64>,
+
+    /// Maximum idle pooled connections kept per host
+    pub pool_max_idle_per_host: Option<usize>,
+
+    /// Seconds allowed for establishing a connection
+    pub connect_timeout_secs: Option<u64>,
+
+    /// Default sampling temperature
+    pub temperature: Option<f32>,
+
+    /// Default nucleus sampling mass
+    pub top_p: Option<f32>,
+
+    /// Merge system messages into one for OpenAI-compatible backends
+    pub merge_system_prompts: bool,
 }
 
 impl std::fmt::Debug for ModelConfig {
@@ -902,6 +1845,19 @@ impl std::fmt::Debug for ModelConfig {
             .field("api_key", &api_key_display)
             .field("model", &self.model)
             .field("max_tokens", &self.max_tokens)
+            .field("max_output_tokens", &self.max_output_tokens)
+            .field("ca_bundle_path", &self.ca_bundle_path)
+            .field("chat_path", &self.chat_path)
+            .field("azure_deployment", &self.azure_deployment)
+            .field("azure_api_version", &self.azure_api_version)
+            .field("anthropic_version", &self.anthropic_version)
+            .field("anthropic_beta", &self.anthropic_beta)
+            .field("pool_idle_timeout_secs", &self.pool_idle_timeout_secs)
+            .field("pool_max_idle_per_host", &self.pool_max_idle_per_host)
+            .field("connect_timeout_secs", &self.connect_timeout_secs)
+            .field("temperature", &self.temperature)
+            .field("top_p", &self.top_p)
+            .field("merge_system_prompts", &self.merge_system_prompts)
             .finish()
     }
 }
@@ -921,6 +1877,30 @@ impl ModelConfig {
                 ProviderType::Anthropic => "claude-3-opus-20240229".to_string(),
             })
     }
+
+    /// Provider settings for creating a client that sends `model_id`
+    pub fn into_provider_config(self, model_id: &str) -> ProviderConfig {
+        ProviderConfig {
+            provider_type: self.provider_type,
+            api_base: self.api_base,
+            api_key: self.api_key,
+            model: Some(model_id.to_string()),
+            max_tokens: self.max_tokens,
+            timeout_secs: None, // Use default timeout
+            ca_bundle_path: self.ca_bundle_path,
+            chat_path: self.chat_path,
+            azure_deployment: self.azure_deployment,
+            azure_api_version: self.azure_api_version,
+            anthropic_version: self.anthropic_version,
+            anthropic_beta: self.anthropic_beta,
+            pool_idle_timeout_secs: self.pool_idle_timeout_secs,
+            pool_max_idle_per_host: self.pool_max_idle_per_host,
+            connect_timeout_secs: self.connect_timeout_secs,
+            temperature: self.temperature,
+            top_p: self.top_p,
+            merge_system_prompts: self.merge_system_prompts,
+        }
+    }
 }
 
 /// A parsed model reference (e.g., "glm-5" or "anthropic.glm.glm-5")
@@ -984,6 +1964,34 @@ impl ModelReference {
             model_name,
         })
     }
+
+    /// Canonical reference string, the inverse of [`parse`](Self::parse)
+    ///
+    /// `parse(x).to_full_ref() == x` for any trimmed, lowercase `x`; other
+    /// spellings of the same reference come back in that canonical form.
+    ///
+    /// ```
+    /// # use emx_llm::ModelReference;
+    /// let parsed = ModelReference::parse(" OpenAI.Azure.GPT-4 ").unwrap();
+    /// assert_eq!(parsed.to_full_ref(), "openai.azure.gpt-4");
+    /// ```
+    pub fn to_full_ref(&self) -> String {
+        match &self.provider_type {
+            Some(provider) if !self.full_path.starts_with(&format!("{}.", provider)) => {
+                format!("{}.{}", provider, self.full_path)
+            }
+            _ => self.full_path.clone(),
+        }
+    }
+
+    /// The explicitly named provider, if the reference has one
+    pub fn provider(&self) -> Option<ProviderType> {
+        match self.provider_type.as_ref()?.to_lowercase().as_str() {
+            "openai" => Some(ProviderType::OpenAI),
+            "anthropic" => Some(ProviderType::Anthropic),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1008,6 +2016,37 @@ mod tests {
         );
     }
 
+    fn provider_config(api_base: &str, api_key: &str) -> ProviderConfig {
+        ProviderConfig {
+            provider_type: ProviderType::OpenAI,
+            api_base: api_base.to_string(),
+            api_key: api_key.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_offline_reports_url_and_key_issues() {
+        let report = provider_config("https://api.openai.com/v1", "sk-0123456789abcdef0123").validate(false);
+        assert_eq!(report, ValidationReport::default());
+
+        let report = provider_config("api.openai.com/v1", "").validate(false);
+        assert!(matches!(report.issues[0], ValidationIssue::MalformedUrl(_)));
+        assert_eq!(report.issues[1], ValidationIssue::EmptyApiKey);
+        assert!(!report.is_valid());
+
+        let report = provider_config("ftp://example.com", "sk-test").validate(false);
+        assert!(matches!(report.issues[0], ValidationIssue::MalformedUrl(_)));
+        assert_eq!(report.issues[1], ValidationIssue::ShortApiKey(7));
+    }
+
+    #[test]
+    fn test_validate_short_key_is_only_a_warning() {
+        let report = provider_config("http://localhost:8080", "test-key").validate(false);
+        assert_eq!(report.issues, vec![ValidationIssue::ShortApiKey(8)]);
+        assert!(report.is_valid());
+    }
+
     #[test]
     fn test_model_reference_parse_simple() {
         let ref1 = ModelReference::parse("glm-5").unwrap();
@@ -1042,9 +2081,190 @@ mod tests {
         assert_eq!(ref2.full_path, "anthropic.glm.glm-5");
     }
 
+    #[test]
+    fn test_model_reference_full_ref_roundtrips() {
+        for input in ["glm-5", "openai.gpt-4", "openai.azure.gpt-4", "anthropic.glm.glm-5"] {
+            assert_eq!(ModelReference::parse(input).unwrap().to_full_ref(), input);
+        }
+        assert_eq!(ModelReference::parse("  Anthropic.GLM-5 ").unwrap().to_full_ref(), "anthropic.glm-5");
+    }
+
+    #[test]
+    fn test_model_reference_provider() {
+        let parsed = ModelReference::parse("openai.azure.gpt-4").unwrap();
+        assert_eq!(parsed.provider(), Some(ProviderType::OpenAI));
+        assert_eq!(parsed.model_name, "gpt-4");
+        assert_eq!(ModelReference::parse("gpt-4").unwrap().provider(), None);
+    }
+
+    #[test]
+    fn test_infer_provider_type_uses_only_configured_provider() {
+        let only_anthropic = |pt: ProviderType| pt == ProviderType::Anthropic;
+        assert_eq!(infer_provider_type(ProviderType::OpenAI, only_anthropic), ProviderType::Anthropic);
+
+        // With both configured, or neither, the default stands
+        assert_eq!(infer_provider_type(ProviderType::OpenAI, |_| true), ProviderType::OpenAI);
+        assert_eq!(infer_provider_type(ProviderType::OpenAI, |_| false), ProviderType::OpenAI);
+        assert_eq!(infer_provider_type(ProviderType::Anthropic, |_| true), ProviderType::Anthropic);
+    }
+
     #[test]
     fn test_model_reference_parse_empty() {
         let result = ModelReference::parse("");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_expand_model_refs_resolves_groups() {
+        let toml_value: toml::Value = r#"
+            [llm.group]
+            frontier = ["openai.gpt-4o", "anthropic.claude-3-opus"]
+        "#
+        .parse()
+        .unwrap();
+
+        let refs = vec!["@frontier".to_string(), "glm-5".to_string()];
+        let expanded = ProviderConfig::expand_model_refs_in(&toml_value, &refs).unwrap();
+        assert_eq!(expanded, vec!["openai.gpt-4o", "anthropic.claude-3-opus", "glm-5"]);
+
+        let err = ProviderConfig::expand_model_refs_in(&toml_value, &["@missing".to_string()])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'missing' not found"));
+        assert!(err.contains("frontier"));
+    }
+
+    #[test]
+    fn test_env_config_reads_safe_vars_and_redact_patterns() {
+        let toml_value: toml::Value = r#"
+            [llm.env]
+            safe_vars = ["MY_TOOL_HOME"]
+            redact = ["*_TOKEN", "aws_*", "DB_PASSWORD"]
+        "#
+        .parse()
+        .unwrap();
+
+        let env = EnvConfig::from_toml(&toml_value).unwrap();
+        assert_eq!(env.safe_vars, vec!["MY_TOOL_HOME"]);
+        assert!(env.is_redacted("GITHUB_TOKEN"));
+        assert!(env.is_redacted("AWS_SECRET_ACCESS_KEY"));
+        assert!(env.is_redacted("db_password"));
+        assert!(!env.is_redacted("DB_PASSWORD_FILE"));
+        assert!(!env.is_redacted("TOKEN_URL"));
+
+        let empty = EnvConfig::from_toml(&toml::Value::Table(Default::default())).unwrap();
+        assert_eq!(empty, EnvConfig::default());
+    }
+
+    #[test]
+    fn test_dev_config_reads_profiles() {
+        let toml_value: toml::Value = r#"
+            [[llm.dev.profile]]
+            name = "ruby"
+            detect = ["Gemfile"]
+            tools = [{ name = "ruby", cmd = "ruby --version" }]
+
+            [[llm.dev.profile]]
+            name = "rust"
+            env_vars = ["CARGO_TARGET_DIR"]
+        "#
+        .parse()
+        .unwrap();
+
+        let dev = DevConfig::from_toml(&toml_value).unwrap();
+        assert_eq!(dev.profiles.len(), 2);
+        assert_eq!(dev.profiles[0].tools[0].cmd, "ruby --version");
+        assert!(dev.profiles[0].env_vars.is_empty());
+        assert!(dev.profiles[1].detect.is_empty());
+
+        let empty = DevConfig::from_toml(&toml::Value::Table(Default::default())).unwrap();
+        assert_eq!(empty, DevConfig::default());
+    }
+
+    #[test]
+    fn test_system_prompt_config_modes() {
+        let toml_value: toml::Value = r#"
+            [llm.gateway.system_prompt]
+            text = "Policy"
+        "#
+        .parse()
+        .unwrap();
+        let mut policy = SystemPromptConfig::from_toml(&toml_value).unwrap().unwrap();
+        assert_eq!(policy.mode, SystemPromptMode::Prepend);
+
+        let conversation = vec![crate::Message::system("Client"), crate::Message::user("Hi")];
+        let system_of = |policy: &SystemPromptConfig, messages: &[crate::Message]| {
+            let mut messages = messages.to_vec();
+            policy.apply(&mut messages);
+            assert_eq!(messages.iter().filter(|m| m.role == crate::MessageRole::System).count(), 1);
+            messages[0].get_content().unwrap().to_string()
+        };
+
+        assert_eq!(system_of(&policy, &conversation), "Policy\n\nClient");
+        policy.mode = SystemPromptMode::Append;
+        assert_eq!(system_of(&policy, &conversation), "Client\n\nPolicy");
+        policy.mode = SystemPromptMode::Replace;
+        assert_eq!(system_of(&policy, &conversation), "Policy");
+        assert_eq!(system_of(&policy, &conversation[1..]), "Policy");
+
+        assert!(SystemPromptConfig::from_toml(&toml::Value::Table(Default::default())).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_fallback_config_reads_ordered_chains() {
+        let toml_value: toml::Value = r#"
+            [llm.fallback]
+            "openai.gpt-4o" = ["openai.gpt-4o-mini", "openai.glm-4"]
+        "#
+        .parse()
+        .unwrap();
+
+        let fallback = FallbackConfig::from_toml(&toml_value).unwrap();
+        assert_eq!(
+            fallback.fallbacks_for(&["gpt-4o", "openai.gpt-4o"]),
+            ["openai.gpt-4o-mini", "openai.glm-4"]
+        );
+        assert!(fallback.fallbacks_for(&["openai.other"]).is_empty());
+
+        let empty = FallbackConfig::from_toml(&toml::Value::Table(Default::default())).unwrap();
+        assert_eq!(empty, FallbackConfig::default());
+    }
+
+    #[test]
+    fn test_alias_resolves_to_full_ref() {
+        let toml_value: toml::Value = r#"
+            [llm.aliases]
+            gpt4 = "openai.gpt-4"
+            loop = "gpt4"
+        "#
+        .parse()
+        .unwrap();
+
+        assert_eq!(ProviderConfig::resolve_alias(&toml_value, "GPT4").as_deref(), Some("openai.gpt-4"));
+        // Aliases do not recurse
+        assert_eq!(ProviderConfig::resolve_alias(&toml_value, "loop").as_deref(), Some("gpt4"));
+        assert_eq!(ProviderConfig::resolve_alias(&toml_value, "gpt-4"), None);
+    }
+
+    #[test]
+    fn test_alias_wins_over_colliding_section() {
+        let toml_value: toml::Value = r#"
+            [llm.aliases]
+            glm-5 = "anthropic.glm.glm-5"
+
+            [llm.provider.openai.glm-5]
+            model = "glm-5"
+
+            [llm.provider.anthropic.glm.glm-5]
+            model = "glm-5"
+        "#
+        .parse()
+        .unwrap();
+
+        assert_eq!(ProviderConfig::find_sections_by_key(&toml_value, "glm-5").len(), 2);
+        assert_eq!(
+            ProviderConfig::resolve_alias(&toml_value, "glm-5").as_deref(),
+            Some("anthropic.glm.glm-5")
+        );
+    }
 }
@@ -5,11 +5,22 @@
 //!
 //! Uses the `emx-txtar` crate for proper txtar encoding/decoding instead of
 //! hand-rolling the format.
+//!
+//! Streaming responses are captured as a [`StreamRecording`] (the raw SSE
+//! bytes plus chunk boundaries and arrival times, one txtar file per stream)
+//! and replayed through [`ReplayClient::chat_stream`](crate::Client::chat_stream),
+//! which runs them through the same SSE parser as the real clients.
 
 use std::path::Path;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use emx_txtar::{Archive, Decoder, Encoder, File as TxtarFile};
+use futures::{Stream, StreamExt};
+
+use crate::client::{anthropic_sse_events, openai_sse_events};
+use crate::{Capabilities, ChatOptions, ChatResponse, Client, Message, ProviderType, StreamEvent, ToolDefinition, Usage};
 
 /// HTTP fixture recorder that saves responses to txtar format
 pub struct FixtureRecorder {
@@ -76,6 +87,227 @@ impl Default for FixtureRecorder {
     }
 }
 
+/// One chunk of a recorded byte stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedChunk {
+    /// Milliseconds since the response headers arrived
+    pub offset_ms: u64,
+    /// Raw bytes as received
+    pub data: Vec<u8>,
+}
+
+/// A complete SSE response body, chunk by chunk
+///
+/// Stored as a txtar archive with three files: `provider` (`openai` or
+/// `anthropic`), `chunks` (one `<offset_ms> <len>` line per chunk) and
+/// `stream.sse` (the concatenated body, kept readable for review).
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamRecording {
+    pub provider_type: ProviderType,
+    pub chunks: Vec<RecordedChunk>,
+}
+
+impl StreamRecording {
+    /// Read a streaming HTTP response to the end, keeping chunk boundaries
+    /// and arrival times
+    pub async fn capture(provider_type: ProviderType, response: reqwest::Response) -> Result<Self> {
+        let started = Instant::now();
+        let mut body = response.bytes_stream();
+        let mut chunks = Vec::new();
+        while let Some(chunk) = body.next().await {
+            chunks.push(RecordedChunk {
+                offset_ms: started.elapsed().as_millis() as u64,
+                data: chunk?.to_vec(),
+            });
+        }
+        Ok(Self { provider_type, chunks })
+    }
+
+    /// Write the recording to a txtar file
+    pub fn write_to_txtar<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let index: String = self
+            .chunks
+            .iter()
+            .map(|c| format!("{} {}\n", c.offset_ms, c.data.len()))
+            .collect();
+        let body: Vec<u8> = self.chunks.iter().flat_map(|c| c.data.iter().copied()).collect();
+
+        let mut archive = Archive::new();
+        archive.add_file(TxtarFile::new(
+            "provider".to_string(),
+            format!("{}\n", self.provider_type.config_key()).into_bytes(),
+        ))?;
+        archive.add_file(TxtarFile::new("chunks".to_string(), index.into_bytes()))?;
+        archive.add_file(TxtarFile::new("stream.sse".to_string(), body))?;
+
+        Encoder::new().encode_to_file(&archive, path)?;
+        Ok(())
+    }
+
+    /// Load a recording written by [`StreamRecording::write_to_txtar`]
+    pub fn load_from_txtar<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let archive = Decoder::new().decode(&std::fs::read_to_string(path)?)?;
+        let file = |name: &str| {
+            archive
+                .files
+                .iter()
+                .find(|f| f.name == name)
+                .map(|f| f.data.as_slice())
+                .ok_or_else(|| anyhow::anyhow!("{}: missing '{}' section", path.display(), name))
+        };
+
+        let provider_type = match String::from_utf8_lossy(file("provider")?).trim() {
+            "openai" => ProviderType::OpenAI,
+            "anthropic" => ProviderType::Anthropic,
+            other => anyhow::bail!("{}: unknown provider '{}'", path.display(), other),
+        };
+
+        let mut body = file("stream.sse")?;
+        let mut chunks = Vec::new();
+        for line in String::from_utf8_lossy(file("chunks")?).lines().filter(|l| !l.trim().is_empty()) {
+            let (offset, len) = line
+                .split_once(' ')
+                .and_then(|(o, l)| Some((o.parse::<u64>().ok()?, l.trim().parse::<usize>().ok()?)))
+                .ok_or_else(|| anyhow::anyhow!("{}: malformed chunk line '{}'", path.display(), line))?;
+            // The txtar encoding may drop a trailing newline from the body
+            let (data, rest) = body.split_at(len.min(body.len()));
+            chunks.push(RecordedChunk {
+                offset_ms: offset,
+                data: data.to_vec(),
+            });
+            body = rest;
+        }
+
+        Ok(Self { provider_type, chunks })
+    }
+}
+
+/// [`Client`] that serves a recorded stream instead of calling an API
+///
+/// Every call replays the same recording; messages, model and options are
+/// ignored.
+pub struct ReplayClient {
+    recording: StreamRecording,
+    realtime: bool,
+}
+
+impl ReplayClient {
+    /// Replay `recording` with no delay between chunks
+    pub fn new(recording: StreamRecording) -> Self {
+        Self {
+            recording,
+            realtime: false,
+        }
+    }
+
+    /// Reproduce the recorded gaps between chunks
+    pub fn with_timing(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+
+    fn replay(&self) -> Pin<Box<dyn Stream<Item = crate::Result<StreamEvent>> + Send>> {
+        let chunks = self.recording.chunks.clone();
+        let realtime = self.realtime;
+        let bytes = async_stream::stream! {
+            let mut elapsed = 0;
+            for chunk in chunks {
+                if realtime {
+                    tokio::time::sleep(Duration::from_millis(chunk.offset_ms.saturating_sub(elapsed))).await;
+                    elapsed = chunk.offset_ms;
+                }
+                yield Ok::<_, crate::Error>(chunk.data);
+            }
+        };
+        match self.recording.provider_type {
+            ProviderType::OpenAI => openai_sse_events(bytes),
+            ProviderType::Anthropic => anthropic_sse_events(bytes),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Client for ReplayClient {
+    async fn chat_with_options(
+        &self,
+        _messages: &[Message],
+        _model: &str,
+        _tools: Option<&[ToolDefinition]>,
+        _options: &ChatOptions,
+    ) -> crate::Result<ChatResponse> {
+        let started = Instant::now();
+        let mut events = self.replay();
+        let mut content = String::new();
+        while let Some(event) = events.next().await {
+            let event = event?;
+            content.push_str(&event.delta);
+            if event.done {
+                let usage = event.usage.unwrap_or(Usage {
+                    prompt_tokens: 0,
+                    completion_tokens: 0,
+                    total_tokens: 0,
+                    ..Default::default()
+                });
+                let latency = started.elapsed();
+                return Ok(ChatResponse {
+                    content,
+                    reasoning: None,
+                    tool_calls: event.tool_calls,
+                    usage,
+                    finish_reason: None,
+                    latency,
+                    total_latency: latency,
+                    logprobs: None,
+                    raw_content: None,
+                });
+            }
+        }
+        Err(crate::Error::Api("recorded stream ended without completion".to_string()))
+    }
+
+    async fn chat_raw(&self, _messages: &[Message], _model: &str, _tools: Option<&[ToolDefinition]>) -> crate::Result<reqwest::Response> {
+        Err(crate::Error::Api("ReplayClient does not serve raw responses".to_string()))
+    }
+
+    fn chat_stream_with_options(
+        &self,
+        _messages: &[Message],
+        _model: &str,
+        _tools: Option<&[ToolDefinition]>,
+        _options: &ChatOptions,
+    ) -> Pin<Box<dyn Stream<Item = crate::Result<StreamEvent>> + Send>> {
+        self.replay()
+    }
+
+    async fn chat_stream_raw(&self, _messages: &[Message], _model: &str, _tools: Option<&[ToolDefinition]>) -> crate::Result<reqwest::Response> {
+        Err(crate::Error::Api("ReplayClient does not serve raw responses".to_string()))
+    }
+
+    fn api_base(&self) -> &str {
+        "replay://"
+    }
+
+    fn max_tokens(&self) -> u32 {
+        0
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            streaming: true,
+            tools: false,
+            vision: false,
+            embeddings: false,
+            json_mode: false,
+        }
+    }
+}
+
 /// Example fixture file structure for OpenAI chat completion
 ///
 /// ```text
@@ -149,4 +381,50 @@ mod tests {
 
         std::fs::remove_file(&txtar_path).ok();
     }
+
+    async fn collect_text(client: &dyn Client) -> String {
+        let messages = vec![Message::user("Hi")];
+        let mut stream = client.chat_stream(&messages, "glm-4-flash", None);
+        let mut text = String::new();
+        while let Some(event) = stream.next().await {
+            let event = event.unwrap();
+            text.push_str(&event.delta);
+            if event.done {
+                break;
+            }
+        }
+        text
+    }
+
+    #[tokio::test]
+    async fn test_stream_recording_replays_through_chat_stream() {
+        let server = crate::mock_server::OpenAIMockServer::start().await;
+        server.mock_chat_streaming(vec!["Hello", ", ", "world!"]).await;
+
+        let client = crate::create_client(crate::ProviderConfig {
+            provider_type: ProviderType::OpenAI,
+            api_base: server.base_url(),
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        let live_text = collect_text(client.as_ref()).await;
+
+        let messages = vec![Message::user("Hi")];
+        let response = client.chat_stream_raw(&messages, "glm-4-flash", None).await.unwrap();
+        let recording = StreamRecording::capture(ProviderType::OpenAI, response).await.unwrap();
+
+        let txtar_path = std::env::temp_dir().join("test_stream_recording.txtar");
+        recording.write_to_txtar(&txtar_path).unwrap();
+        let loaded = StreamRecording::load_from_txtar(&txtar_path).unwrap();
+        std::fs::remove_file(&txtar_path).ok();
+        assert_eq!(loaded.provider_type, ProviderType::OpenAI);
+        assert_eq!(loaded.chunks.len(), recording.chunks.len());
+
+        let replay = ReplayClient::new(loaded).with_timing(true);
+        assert_eq!(live_text, "Hello, world!");
+        assert_eq!(collect_text(&replay).await, live_text);
+        let response = replay.chat(&messages, "glm-4-flash", None).await.unwrap();
+        assert_eq!(response.content, live_text);
+    }
 }
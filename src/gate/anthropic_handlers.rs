@@ -1,9 +1,9 @@
 //! Anthropic-compatible handlers
 
-use crate::gate::handlers::GatewayState;
-use crate::gate::router::resolve_model_for_provider;
+use crate::gate::anthropic_sse::stop_reason;
+use crate::gate::handlers::{client_for, stream_flag, uuid_simple, GatewayState};
 use crate::message::Message;
-use crate::{create_client_for_model, ProviderType, ToolDefinition};
+use crate::{ChatResponse, ProviderType, ToolDefinition};
 use axum::{
     extract::State,
     http::StatusCode,
@@ -15,14 +15,6 @@ use serde_json::json;
 use serde_json::Value;
 use tracing::{error, info};
 
-fn uuid_simple() -> String {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    let duration = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default();
-    format!("{:x}{:x}", duration.as_secs(), duration.subsec_nanos())
-}
-
 fn event_with_type(event_type: &str, data: Value) -> Event {
     Event::default()
         .event(event_type)
@@ -31,13 +23,10 @@ fn event_with_type(event_type: &str, data: Value) -> Event {
 
 /// Handle Anthropic messages (streaming and non-streaming)
 pub async fn messages_handler(
-    State(_state): State<GatewayState>,
+    State(state): State<GatewayState>,
     Json(request): Json<Value>,
 ) -> Result<Sse<impl futures::Stream<Item = Result<Event, std::io::Error>>>, StatusCode> {
-    let stream = request
-        .get("stream")
-        .and_then(|s| s.as_bool())
-        .unwrap_or(false);
+    let stream = stream_flag(request.get("stream"));
 
     let model = match request.get("model").and_then(|m| m.as_str()) {
         Some(m) => m,
@@ -47,14 +36,12 @@ pub async fn messages_handler(
     info!("Anthropic request for model: {} (stream: {})", model, stream);
 
     // For Anthropic endpoint, always use Anthropic provider type
-    let resolved = resolve_model_for_provider(model, ProviderType::Anthropic)
+    let resolved = state.resolver.resolve(model, ProviderType::Anthropic)
         .map_err(|e| {
             error!("Failed to resolve model '{}': {}", model, e);
             StatusCode::NOT_FOUND
         })?;
 
-    let model_ref = resolved.model_ref;
-
     let messages_value = request
         .get("messages")
         .ok_or(StatusCode::BAD_REQUEST)?;
@@ -71,7 +58,7 @@ pub async fn messages_handler(
         .and_then(|t| serde_json::from_value(t.clone()).ok());
     let tools_ref = tools.as_deref();
 
-    match create_client_for_model(&model_ref) {
+    match client_for(&resolved) {
         Ok((client, model_id)) => {
             if stream {
                 // Streaming - match GLM's exact format
@@ -90,7 +77,10 @@ pub async fn messages_handler(
                                 if let Some(usage) = &event.usage {
                                     let delta_json = json!({
                                         "type": "message_delta",
-                                        "delta": {"stop_reason": "end_turn", "stop_sequence": null},
+                                        "delta": {
+                                            "stop_reason": stop_reason(event.finish_reason.as_ref(), event.tool_calls.is_some()),
+                                            "stop_sequence": null
+                                        },
                                         "usage": {
                                             "input_tokens": usage.prompt_tokens,
                                             "output_tokens": usage.completion_tokens,
@@ -133,7 +123,9 @@ pub async fn messages_handler(
             } else {
                 // Non-streaming
                 match client.chat(&messages, &model_id, tools_ref).await {
-                    Ok((content, tool_calls, usage)) => {
+                    Ok(response) => {
+                        let finish_reason = response.finish();
+                        let ChatResponse { content, tool_calls, usage, .. } = response;
                         // Build content blocks
                         let mut content_blocks: Vec<serde_json::Value> = Vec::new();
                         if !content.is_empty() {
@@ -150,7 +142,7 @@ pub async fn messages_handler(
                                 }));
                             }
                         }
-                        let stop_reason = if tool_calls.is_some() { "tool_use" } else { "end_turn" };
+                        let stop_reason = stop_reason(finish_reason.as_ref(), tool_calls.is_some());
                         let json = json!({
                             "id": format!("msg_{}", uuid_simple()),
                             "type": "message",
@@ -174,6 +166,10 @@ pub async fn messages_handler(
                 }
             }
         }
+        Err(e) if !state.allow_mock_responses => {
+            info!("Model '{}' not configured: {}", model, e);
+            Err(StatusCode::NOT_FOUND)
+        }
         Err(e) => {
             info!("Model '{}' not configured, returning mock: {}", model, e);
             let id = format!("msg_{}", uuid_simple());
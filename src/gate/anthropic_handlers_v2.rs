@@ -1,52 +1,155 @@
 //! Anthropic-compatible handlers with raw HTTP passthrough support
 
-use crate::gate::handlers::GatewayState;
-use crate::gate::router::resolve_model_for_provider;
-use crate::message::Message;
-use crate::{create_client_for_model, ProviderType, ToolDefinition};
+use crate::gate::anthropic_sse::{stop_reason, AnthropicSseEncoder};
+use crate::gate::audit::AuditRecord;
+use crate::gate::coalesce::coalesce;
+use crate::gate::disconnect::DisconnectGuard;
+use crate::gate::fallback::{with_fallbacks, FALLBACK_MODEL_HEADER};
+use crate::gate::frame_filter::FrameFilter;
+use crate::gate::handlers::{
+    client_for, model_not_found, stream_flag, unsupported_feature, upstream_error_response, upstream_status,
+    uuid_simple, GatewayState, UPSTREAM_LATENCY_HEADER,
+};
+use crate::gate::idempotency::{idempotency_key, replay, Lookup, Reservation};
+use crate::gate::keepalive::with_keepalive;
+use crate::gate::limits::{clamp_request, with_clamp_header};
+use crate::gate::metrics::MetricsModel;
+use crate::gate::request::anthropic_options;
+use crate::gate::usage::{completion_from_body, usage_from_body, SseUsageScanner};
+use crate::message::{validate_for, Message};
+use crate::{ChatOptions, ChatResponse, Client, ProviderType, StreamEvent, ToolDefinition};
 use axum::{
-    body::Body,
+    body::{Body, Bytes},
     extract::State,
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::Response,
     Json,
 };
-use futures::stream::StreamExt;
+use futures::stream::{Stream, StreamExt};
 use serde_json::json;
 use serde_json::Value;
+use std::time::Instant;
 use tracing::{error, info};
 
 /// Handle Anthropic messages with raw HTTP passthrough
 /// This forwards the upstream response without parsing/rewriting, preserving all fields
 pub async fn messages_handler_passthrough(
-    State(_state): State<GatewayState>,
+    State(state): State<GatewayState>,
+    headers: HeaderMap,
     Json(request): Json<Value>,
+) -> Response {
+    let mut label = MetricsModel::default();
+    let response = messages_passthrough(state, headers, request, &mut label).await;
+    MetricsModel::attach(label, response)
+}
+
+/// Text of an Anthropic `system` field, given as a string or as text blocks
+fn system_text(system: &Value) -> Option<String> {
+    match system {
+        Value::String(text) => Some(text.clone()),
+        Value::Array(blocks) => {
+            let texts: Vec<&str> = blocks
+                .iter()
+                .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+                .collect();
+            (!texts.is_empty()).then(|| texts.join("\n"))
+        }
+        _ => None,
+    }
+}
+
+/// Anthropic-style 400 `invalid_request_error` response
+fn invalid_request(message: &str) -> Response {
+    invalid_request_with_status(StatusCode::BAD_REQUEST, message)
+}
+
+/// Anthropic-style `invalid_request_error` response with another status
+fn invalid_request_with_status(status: StatusCode, message: &str) -> Response {
+    let json = json!({"type": "error", "error": {"type": "invalid_request_error", "message": message}});
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(json.to_string()))
+        .unwrap()
+}
+
+async fn messages_passthrough(
+    state: GatewayState,
+    headers: HeaderMap,
+    mut request: Value,
+    label: &mut MetricsModel,
 ) -> Result<Response, StatusCode> {
-    let stream = request
-        .get("stream")
-        .and_then(|s| s.as_bool())
-        .unwrap_or(false);
+    let stream = stream_flag(request.get("stream"));
 
     let model = match request.get("model").and_then(|m| m.as_str()) {
-        Some(m) => m,
+        Some(m) => m.to_string(),
         None => return Err(StatusCode::BAD_REQUEST),
     };
+    let model = model.as_str();
+
+    // Retries of a non-streaming request reuse the first successful response
+    let cache_key = if stream { None } else { idempotency_key("anthropic", &headers, &request) };
+    let reservation = match cache_key.map(|key| state.idempotency.begin(key)) {
+        None => None,
+        Some(Lookup::Proceed(reservation)) => Some(reservation),
+        Some(Lookup::Replay(body)) => {
+            info!("Replaying cached response for idempotency key");
+            return Ok(replay(body));
+        }
+        Some(Lookup::Reject(status, message)) => return Ok(invalid_request_with_status(status, message)),
+    };
 
     info!("Anthropic request for model: {} (stream: {})", model, stream);
 
-    let resolved = resolve_model_for_provider(model, ProviderType::Anthropic).map_err(|e| {
-        error!("Failed to resolve model '{}': {}", model, e);
-        StatusCode::NOT_FOUND
-    })?;
+    let resolved = match state.resolver.resolve(model, ProviderType::Anthropic) {
+        Ok(resolved) => resolved,
+        Err(e) if !state.allow_mock_responses => {
+            info!("Model '{}' not configured: {}", model, e);
+            return Ok(model_not_found(model, ProviderType::Anthropic));
+        }
+        Err(e) => {
+            info!("Model '{}' not configured, returning mock: {}", model, e);
+            return Ok(mock_response(model));
+        }
+    };
+    let model_ref = resolved.model_ref.as_str();
+    *label = MetricsModel::resolved(model_ref);
 
-    let model_ref = resolved.model_ref;
+    // Models served by an OpenAI provider are translated into the Anthropic
+    // wire format instead of passed through
+    let target = resolved.provider_type;
+    let openai_backed = target == ProviderType::OpenAI;
+    let received = openai_backed.then(|| request.clone());
+    state.transform_request(&mut request, target);
+    let clamped = clamp_request(&mut request, &resolved);
+    let mut options = anthropic_options(&request);
+    // An OpenAI upstream would reject the Anthropic-only fields clients
+    // send; of the extra fields, only those the transforms set are forwarded
+    if let Some(received) = received {
+        options.extra_body = options.extra_body.take().and_then(|mut extra| {
+            extra.retain(|name, value| received.get(name) != Some(value));
+            (!extra.is_empty()).then_some(extra)
+        });
+    }
 
     let messages_value = request.get("messages").ok_or(StatusCode::BAD_REQUEST)?;
 
-    let messages: Vec<Message> = serde_json::from_value(messages_value.clone()).map_err(|e| {
+    let mut messages: Vec<Message> = serde_json::from_value(messages_value.clone()).map_err(|e| {
         error!("Failed to parse messages: {}", e);
         StatusCode::BAD_REQUEST
     })?;
+    // Anthropic clients send their system prompt in `system`, outside `messages`
+    if let Some(system) = request.get("system").and_then(system_text) {
+        messages.insert(0, Message::system(system));
+    }
+    if let Some(system_prompt) = &state.system_prompt {
+        system_prompt.apply(&mut messages);
+    }
+    // Reject locally what the upstream would answer with a 400 anyway
+    if let Err(e) = validate_for(&messages, target) {
+        return Ok(invalid_request(&e.to_string()));
+    }
+    let audit = AuditRecord::start(&state, &headers, model, &messages);
 
     // Extract tools from request if present
     let tools: Option<Vec<ToolDefinition>> = request
@@ -54,23 +157,77 @@ pub async fn messages_handler_passthrough(
         .and_then(|t| serde_json::from_value(t.clone()).ok());
     let tools_ref = tools.as_deref();
 
-    match create_client_for_model(&model_ref) {
+    let created = client_for(&resolved);
+    if let Ok((client, _)) = &created {
+        if let Some(feature) = unsupported_feature(&client.capabilities(), &request) {
+            return Ok(invalid_request(&format!("Model '{}' does not support {}", model, feature)));
+        }
+    }
+
+    let response = match created {
+        Ok((client, model_id)) if openai_backed => {
+            translated_response(&state, client, &messages, model, model_ref, &model_id, tools_ref, &options, stream, reservation, audit).await
+        }
         Ok((client, model_id)) => {
             if stream {
                 // Streaming with raw passthrough
-                match client.chat_stream_raw(&messages, &model_id, tools_ref).await {
+                match client.chat_stream_raw_with_options(&messages, &model_id, tools_ref, &options).await {
+                    Ok(upstream_response) if state.coalesce_stream.is_some() => {
+                        // Merging deltas needs the parsed events
+                        let upstream_headers = upstream_response.headers().clone();
+                        let events = crate::client::anthropic_sse_events(upstream_response.bytes_stream());
+                        encoded_stream_response(&state, events, model, model_ref, audit)
+                            .map(|response| state.with_upstream_headers(response, &upstream_headers))
+                    }
                     Ok(upstream_response) => {
-                        // Forward the upstream response body stream directly
-                        let upstream_body = upstream_response.bytes_stream();
+                        // Forward the upstream response body stream directly,
+                        // watching for the usage frames as they pass through unless
+                        // `tee_stream_usage` is off
+                        let upstream_headers = upstream_response.headers().clone();
+                        let mut upstream_body = Box::pin(upstream_response.bytes_stream());
+                        let recorder = state.clone();
+                        let usage_model = model.to_string();
+                        let metrics_model = model_ref.to_string();
+                        let mut pings = state.strip_ping_events.then(|| FrameFilter::new(true, false));
+                        let mut scanner = state.tee_stream_usage.then(SseUsageScanner::new);
 
                         // Create a properly typed stream for Axum
-                        let body_stream = upstream_body.map(|result| {
-                            result
-                                .map(|bytes| bytes.to_vec())
-                                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
-                        });
+                        let body_stream = async_stream::stream! {
+                            let mut guard = DisconnectGuard::new(usage_model.as_str());
+                            while let Some(result) = upstream_body.next().await {
+                                match result {
+                                    Ok(bytes) => {
+                                        if let Some(scanner) = &mut scanner {
+                                            scanner.observe(&bytes);
+                                        }
+                                        match &mut pings {
+                                            Some(filter) => {
+                                                let frames = filter.filter(&bytes);
+                                                if !frames.is_empty() {
+                                                    yield Ok(frames);
+                                                }
+                                            }
+                                            None => yield Ok(bytes.to_vec()),
+                                        }
+                                    }
+                                    Err(e) => {
+                                        yield Err(std::io::Error::new(std::io::ErrorKind::Other, e));
+                                        break;
+                                    }
+                                }
+                            }
+                            if let Some(rest) = pings.take().map(FrameFilter::finish).filter(|rest| !rest.is_empty()) {
+                                yield Ok(rest);
+                            }
+                            let usage = scanner.as_ref().and_then(SseUsageScanner::finish);
+                            if let Some(usage) = &usage {
+                                recorder.record_usage(&metrics_model, usage);
+                            }
+                            audit.complete(usage.as_ref(), scanner.as_ref().map(SseUsageScanner::text));
+                            guard.finish();
+                        };
 
-                        let body = Body::from_stream(body_stream);
+                        let body = Body::from_stream(with_keepalive(body_stream, state.sse_keepalive));
 
                         // Build response with SSE headers
                         let response = Response::builder()
@@ -85,63 +242,247 @@ pub async fn messages_handler_passthrough(
                                 StatusCode::INTERNAL_SERVER_ERROR
                             })?;
 
-                        Ok(response)
+                        Ok(state.with_upstream_headers(response, &upstream_headers))
                     }
                     Err(e) => {
                         error!("Upstream stream request failed: {}", e);
-                        let json = json!({"type": "error", "error": {"type": "api_error", "message": e.to_string()}});
-                        Ok(Response::builder()
-                            .status(500)
-                            .header("Content-Type", "application/json")
-                            .body(Body::from(json.to_string()))
-                            .unwrap())
+                        Ok(upstream_error_response(&e, anthropic_api_error))
                     }
                 }
             } else {
                 // Non-streaming with raw passthrough
-                match client.chat_raw(&messages, &model_id, tools_ref).await {
+                let started = Instant::now();
+                let (result, fallback) = match client.chat_raw_with_options(&messages, &model_id, tools_ref, &options).await {
+                    Err(e) => {
+                        let messages = &messages;
+                        let options = &options;
+                        with_fallbacks(&state, &[model, model_ref], ProviderType::Anthropic, e, |client, model_id| async move {
+                            client.chat_raw_with_options(messages, &model_id, tools_ref, options).await
+                        })
+                        .await
+                    }
+                    ok => (ok, None),
+                };
+                match result {
                     Ok(upstream_response) => {
+                        let upstream_headers = upstream_response.headers().clone();
                         // Get the response body bytes
                         let body_bytes = upstream_response.bytes().await.map_err(|e| {
                             error!("Failed to read upstream response body: {}", e);
                             StatusCode::BAD_GATEWAY
                         })?;
+                        let latency = started.elapsed();
+
+                        let usage = usage_from_body(&body_bytes);
+                        if let Some(usage) = &usage {
+                            state.record_usage(model_ref, usage);
+                        }
+                        audit.complete(usage.as_ref(), completion_from_body(&body_bytes).as_deref());
+                        if let Some(reservation) = reservation {
+                            reservation.complete(body_bytes.clone());
+                        }
 
                         // Forward the raw response body
-                        Ok(Response::builder()
+                        let mut builder = Response::builder()
                             .status(200)
                             .header("Content-Type", "application/json")
-                            .body(Body::from(body_bytes))
-                            .unwrap())
+                            .header(UPSTREAM_LATENCY_HEADER, latency.as_millis().to_string());
+                        if let Some(fallback) = fallback {
+                            builder = builder.header(FALLBACK_MODEL_HEADER, fallback);
+                        }
+                        let response = builder.body(Body::from(body_bytes)).unwrap();
+                        Ok(state.with_upstream_headers(response, &upstream_headers))
                     }
                     Err(e) => {
                         error!("Upstream request failed: {}", e);
-                        let json = json!({"type": "error", "error": {"type": "api_error", "message": e.to_string()}});
-                        Ok(Response::builder()
-                            .status(500)
-                            .header("Content-Type", "application/json")
-                            .body(Body::from(json.to_string()))
-                            .unwrap())
+                        Ok(upstream_error_response(&e, anthropic_api_error))
                     }
                 }
             }
         }
         Err(e) => {
-            info!("Model '{}' not configured, returning mock: {}", model, e);
+            error!("Failed to create client for '{}': {}", model_ref, e);
+            let json = anthropic_api_error(&format!("Failed to create client: {}", e));
+            Ok(Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .header("Content-Type", "application/json")
+                .body(Body::from(json.to_string()))
+                .unwrap())
+        }
+    };
+    response.map(|response| with_clamp_header(response, clamped))
+}
+
+/// Anthropic-style `api_error` body for failures the upstream did not answer
+fn anthropic_api_error(message: &str) -> Value {
+    json!({"type": "error", "error": {"type": "api_error", "message": message}})
+}
+
+/// Canned message for unconfigured models when `allow_mock_responses` is set
+fn mock_response(model: &str) -> Response {
+    let json = json!({
+        "id": "msg-mock",
+        "type": "message",
+        "role": "assistant",
+        "content": [{"type": "text", "text": "Mock response"}],
+        "model": model,
+        "stop_reason": "end_turn",
+        "usage": {"input_tokens": 10, "output_tokens": 10}
+    });
+    Response::builder()
+        .status(200)
+        .header("Content-Type", "application/json")
+        .body(Body::from(json.to_string()))
+        .unwrap()
+}
+
+/// Serve an Anthropic request from an OpenAI-backed client
+///
+/// Unlike the passthrough path the upstream response is parsed, so streaming
+/// events are re-encoded by [`AnthropicSseEncoder`] and non-streaming replies
+/// are rebuilt as an Anthropic `message` object.
+#[allow(clippy::too_many_arguments)]
+async fn translated_response(
+    state: &GatewayState,
+    client: Box<dyn Client>,
+    messages: &[Message],
+    model: &str,
+    model_ref: &str,
+    model_id: &str,
+    tools: Option<&[ToolDefinition]>,
+    options: &ChatOptions,
+    stream: bool,
+    reservation: Option<Reservation>,
+    audit: AuditRecord,
+) -> Result<Response, StatusCode> {
+    if stream {
+        let events = client.chat_stream_with_options(messages, model_id, tools, options);
+        return encoded_stream_response(state, events, model, model_ref, audit);
+    }
+
+    let message_id = format!("msg_{}", uuid_simple());
+
+    let (result, fallback) = match client.chat_with_options(messages, model_id, tools, options).await {
+        Err(e) => {
+            with_fallbacks(state, &[model, model_ref], ProviderType::OpenAI, e, |client, model_id| async move {
+                client.chat_with_options(messages, &model_id, tools, options).await
+            })
+            .await
+        }
+        ok => (ok, None),
+    };
+    match result {
+        Ok(response) => {
+            let finish_reason = response.finish();
+            let ChatResponse { content: text, tool_calls, usage, latency, .. } = response;
+            state.record_usage(model_ref, &usage);
+            audit.complete(Some(&usage), Some(&text));
+
+            let mut content = Vec::new();
+            if !text.is_empty() {
+                content.push(json!({"type": "text", "text": text}));
+            }
+            let tool_calls = tool_calls.unwrap_or_default();
+            for call in &tool_calls {
+                let input: Value = serde_json::from_str(&call.arguments).unwrap_or_else(|_| json!({}));
+                content.push(json!({"type": "tool_use", "id": call.id, "name": call.name, "input": input}));
+            }
+
             let json = json!({
-                "id": "msg-mock",
+                "id": message_id,
                 "type": "message",
                 "role": "assistant",
-                "content": [{"type": "text", "text": "Mock response"}],
+                "content": content,
                 "model": model,
-                "stop_reason": "end_turn",
-                "usage": {"input_tokens": 10, "output_tokens": 10}
+                "stop_reason": stop_reason(finish_reason.as_ref(), !tool_calls.is_empty()),
+                "stop_sequence": null,
+                "usage": {"input_tokens": usage.prompt_tokens, "output_tokens": usage.completion_tokens}
             });
-            Ok(Response::builder()
+            let body = Bytes::from(json.to_string());
+            if let Some(reservation) = reservation {
+                reservation.complete(body.clone());
+            }
+            let mut builder = Response::builder()
                 .status(200)
                 .header("Content-Type", "application/json")
-                .body(Body::from(json.to_string()))
+                .header(UPSTREAM_LATENCY_HEADER, latency.as_millis().to_string());
+            if let Some(fallback) = fallback {
+                builder = builder.header(FALLBACK_MODEL_HEADER, fallback);
+            }
+            Ok(builder.body(Body::from(body)).unwrap())
+        }
+        Err(e) => {
+            // The upstream spoke OpenAI, so its error body is rewrapped
+            error!("Upstream request failed: {}", e);
+            Ok(Response::builder()
+                .status(upstream_status(&e))
+                .header("Content-Type", "application/json")
+                .body(Body::from(anthropic_api_error(&e.to_string()).to_string()))
                 .unwrap())
         }
     }
 }
+
+/// Stream parsed `events` to an Anthropic client as re-encoded SSE
+///
+/// Serves OpenAI-backed models and, with `coalesce_stream_ms` set, Anthropic
+/// upstreams whose deltas are merged before being encoded again.
+fn encoded_stream_response<S>(
+    state: &GatewayState,
+    events: S,
+    model: &str,
+    model_ref: &str,
+    audit: AuditRecord,
+) -> Result<Response, StatusCode>
+where
+    S: Stream<Item = crate::Result<StreamEvent>> + Send + 'static,
+{
+    let mut events = Box::pin(coalesce(events, state.coalesce_stream));
+    let mut encoder = AnthropicSseEncoder::new(format!("msg_{}", uuid_simple()), model);
+    let recorder = state.clone();
+    let usage_model = model.to_string();
+    let metrics_model = model_ref.to_string();
+
+    let body_stream = async_stream::stream! {
+        let mut guard = DisconnectGuard::new(usage_model.as_str());
+        let mut completion = String::new();
+        while let Some(result) = events.next().await {
+            match result {
+                Ok(event) => {
+                    completion.push_str(&event.delta);
+                    if let Some(usage) = event.usage.as_ref().filter(|_| event.done) {
+                        recorder.record_usage(&metrics_model, usage);
+                    }
+                    if event.done {
+                        audit.complete(event.usage.as_ref(), Some(&completion));
+                    }
+                    yield Ok::<Vec<u8>, std::io::Error>(encoder.encode(&event).into_bytes());
+                    if encoder.is_finished() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!("Upstream stream failed: {}", e);
+                    yield Ok(encoder.error(&e.to_string()).into_bytes());
+                    break;
+                }
+            }
+        }
+        if !encoder.is_finished() {
+            yield Ok(encoder.error("upstream stream ended without completion").into_bytes());
+        }
+        guard.finish();
+    };
+
+    Response::builder()
+        .status(200)
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .header("Connection", "keep-alive")
+        .header("X-Accel-Buffering", "no")
+        .body(Body::from_stream(with_keepalive(body_stream, state.sse_keepalive)))
+        .map_err(|e| {
+            error!("Failed to build response: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
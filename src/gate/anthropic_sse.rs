@@ -0,0 +1,291 @@
+//! Re-encode parsed stream events as Anthropic SSE frames
+//!
+//! Lets `/anthropic/v1/messages` serve a model that lives behind an OpenAI
+//! provider: the OpenAI stream is parsed by `client.chat_stream` and each
+//! [`StreamEvent`] is turned back into the event sequence an Anthropic client
+//! expects (`message_start`, content blocks, `message_delta`, `message_stop`).
+
+use crate::{FinishReason, StreamEvent};
+use serde_json::{json, Value};
+
+/// Stateful encoder for one Anthropic message stream
+pub struct AnthropicSseEncoder {
+    message_id: String,
+    model: String,
+    next_index: u32,
+    text_block: Option<u32>,
+    started: bool,
+    finished: bool,
+}
+
+impl AnthropicSseEncoder {
+    pub fn new(message_id: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            message_id: message_id.into(),
+            model: model.into(),
+            next_index: 0,
+            text_block: None,
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// Opening `message_start` frame
+    ///
+    /// Sent ahead of the first event's frames, so `input_tokens` is filled
+    /// in when the upstream already reported usage by then (e.g. a
+    /// coalesced reply arriving as one event).
+    fn start(&mut self, input_tokens: u32) -> String {
+        self.started = true;
+        frame(
+            "message_start",
+            json!({
+                "type": "message_start",
+                "message": {
+                    "id": self.message_id,
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [],
+                    "model": self.model,
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": {"input_tokens": input_tokens, "output_tokens": 0}
+                }
+            }),
+        )
+    }
+
+    /// Frames for one upstream event; the final event closes the message
+    pub fn encode(&mut self, event: &StreamEvent) -> String {
+        let mut out = String::new();
+        if self.finished {
+            return out;
+        }
+        if !self.started {
+            let input_tokens = event.usage.as_ref().map_or(0, |u| u.prompt_tokens);
+            out.push_str(&self.start(input_tokens));
+        }
+
+        if !event.delta.is_empty() {
+            let index = match self.text_block {
+                Some(index) => index,
+                None => {
+                    let index = self.open_block();
+                    out.push_str(&frame(
+                        "content_block_start",
+                        json!({
+                            "type": "content_block_start",
+                            "index": index,
+                            "content_block": {"type": "text", "text": ""}
+                        }),
+                    ));
+                    self.text_block = Some(index);
+                    index
+                }
+            };
+            out.push_str(&frame(
+                "content_block_delta",
+                json!({
+                    "type": "content_block_delta",
+                    "index": index,
+                    "delta": {"type": "text_delta", "text": event.delta}
+                }),
+            ));
+        }
+
+        if !event.done {
+            return out;
+        }
+
+        if let Some(index) = self.text_block.take() {
+            out.push_str(&block_stop(index));
+        }
+
+        let tool_calls = event.tool_calls.as_deref().unwrap_or_default();
+        for call in tool_calls {
+            let index = self.open_block();
+            out.push_str(&frame(
+                "content_block_start",
+                json!({
+                    "type": "content_block_start",
+                    "index": index,
+                    "content_block": {"type": "tool_use", "id": call.id, "name": call.name, "input": {}}
+                }),
+            ));
+            out.push_str(&frame(
+                "content_block_delta",
+                json!({
+                    "type": "content_block_delta",
+                    "index": index,
+                    "delta": {"type": "input_json_delta", "partial_json": call.arguments}
+                }),
+            ));
+            out.push_str(&block_stop(index));
+        }
+
+        let stop_reason = stop_reason(event.finish_reason.as_ref(), !tool_calls.is_empty());
+        let (input_tokens, output_tokens) = event
+            .usage
+            .as_ref()
+            .map(|u| (u.prompt_tokens, u.completion_tokens))
+            .unwrap_or((0, 0));
+        out.push_str(&frame(
+            "message_delta",
+            json!({
+                "type": "message_delta",
+                "delta": {"stop_reason": stop_reason, "stop_sequence": null},
+                "usage": {"input_tokens": input_tokens, "output_tokens": output_tokens}
+            }),
+        ));
+        out.push_str(&frame("message_stop", json!({"type": "message_stop"})));
+        self.finished = true;
+        out
+    }
+
+    /// Whether the closing `message_stop` has been emitted
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// In-band `error` frame for failures after the stream has started
+    pub fn error(&mut self, message: &str) -> String {
+        self.finished = true;
+        frame(
+            "error",
+            json!({
+                "type": "error",
+                "error": {"type": "api_error", "message": message}
+            }),
+        )
+    }
+
+    fn open_block(&mut self) -> u32 {
+        let index = self.next_index;
+        self.next_index += 1;
+        index
+    }
+}
+
+/// Anthropic `stop_reason` for an upstream finish reason
+///
+/// Without a more telling reason, a turn that ends in tool calls is
+/// `tool_use` and any other `end_turn`.
+pub fn stop_reason(finish_reason: Option<&FinishReason>, has_tool_calls: bool) -> &'static str {
+    match finish_reason {
+        Some(FinishReason::ContentFilter) => "refusal",
+        Some(FinishReason::Length) => "max_tokens",
+        Some(FinishReason::ToolCalls) => "tool_use",
+        _ if has_tool_calls => "tool_use",
+        _ => "end_turn",
+    }
+}
+
+fn block_stop(index: u32) -> String {
+    frame(
+        "content_block_stop",
+        json!({"type": "content_block_stop", "index": index}),
+    )
+}
+
+fn frame(event: &str, data: Value) -> String {
+    format!("event: {}\ndata: {}\n\n", event, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ToolCall, Usage};
+
+    fn event(delta: &str, done: bool) -> StreamEvent {
+        StreamEvent {
+            delta: delta.to_string(),
+            done,
+            usage: None,
+            tool_calls: None,
+            reasoning: None,
+            finish_reason: None,
+        }
+    }
+
+    fn event_names(sse: &str) -> Vec<&str> {
+        sse.lines().filter_map(|l| l.strip_prefix("event: ")).collect()
+    }
+
+    #[test]
+    fn test_text_stream_event_sequence() {
+        let mut encoder = AnthropicSseEncoder::new("msg_1", "gpt-test");
+        let mut sse = encoder.encode(&event("Hel", false));
+        sse.push_str(&encoder.encode(&event("lo", false)));
+        let mut last = event("", true);
+        last.usage = Some(Usage { prompt_tokens: 5, completion_tokens: 2, total_tokens: 7, ..Default::default() });
+        sse.push_str(&encoder.encode(&last));
+
+        assert_eq!(
+            event_names(&sse),
+            vec![
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        assert!(sse.contains(r#""stop_reason":"end_turn""#));
+        assert!(sse.contains(r#""output_tokens":2"#));
+        assert!(encoder.is_finished());
+        assert!(encoder.encode(&event("late", true)).is_empty());
+    }
+
+    #[test]
+    fn test_message_start_reports_known_input_tokens() {
+        let mut encoder = AnthropicSseEncoder::new("msg_1", "gpt-test");
+        let mut only = event("Hi", true);
+        only.usage = Some(Usage { prompt_tokens: 12, completion_tokens: 1, total_tokens: 13, ..Default::default() });
+        let sse = encoder.encode(&only);
+
+        let start = sse.split("\n\n").next().unwrap();
+        assert!(start.starts_with("event: message_start"));
+        assert!(start.contains(r#""input_tokens":12"#));
+    }
+
+    #[test]
+    fn test_tool_calls_become_tool_use_blocks() {
+        let mut encoder = AnthropicSseEncoder::new("msg_1", "gpt-test");
+        let mut last = event("", true);
+        last.tool_calls = Some(vec![ToolCall {
+            id: "call_1".to_string(),
+            name: "glob".to_string(),
+            arguments: r#"{"pattern":"*.rs"}"#.to_string(),
+        }]);
+        let sse = encoder.encode(&last);
+
+        assert!(sse.contains(r#""type":"tool_use""#));
+        assert!(sse.contains(r#""index":0"#));
+        assert!(sse.contains("input_json_delta"));
+        assert!(sse.contains(r#""stop_reason":"tool_use""#));
+    }
+
+    #[test]
+    fn test_content_filter_finish_is_refusal() {
+        let mut encoder = AnthropicSseEncoder::new("msg_1", "gpt-test");
+        let mut sse = encoder.encode(&event("Partial", false));
+        let mut last = event("", true);
+        last.finish_reason = Some(FinishReason::ContentFilter);
+        sse.push_str(&encoder.encode(&last));
+
+        assert!(sse.contains(r#""stop_reason":"refusal""#));
+        assert!(encoder.is_finished());
+    }
+
+    #[test]
+    fn test_length_finish_is_max_tokens() {
+        let mut encoder = AnthropicSseEncoder::new("msg_1", "gpt-test");
+        let mut last = event("Cut o", true);
+        last.finish_reason = Some(FinishReason::Length);
+        let sse = encoder.encode(&last);
+
+        assert!(sse.contains(r#""stop_reason":"max_tokens""#));
+    }
+}
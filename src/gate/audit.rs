@@ -0,0 +1,102 @@
+//! Append-only audit log of completed requests
+//!
+//! With `audit_log_path` set, every chat request that completes appends one
+//! JSON line with its timestamp, request id, model and token usage, plus the
+//! prompt messages and completion text when `audit_log_contents` is on.
+//! Handlers only queue the line; a background task does the file writes.
+
+use crate::gate::handlers::GatewayState;
+use crate::message::Message;
+use crate::Usage;
+use axum::http::HeaderMap;
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::error;
+
+/// Writer for the audit log file
+pub struct AuditLog {
+    lines: mpsc::UnboundedSender<String>,
+    include_contents: bool,
+}
+
+impl AuditLog {
+    /// Open `path` for appending (creating it if missing) and start the
+    /// background writer
+    pub async fn open(path: &Path, include_contents: bool) -> std::io::Result<Self> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        let (lines, mut queued) = mpsc::unbounded_channel::<String>();
+        let path = path.display().to_string();
+        tokio::spawn(async move {
+            while let Some(line) = queued.recv().await {
+                if let Err(e) = async {
+                    file.write_all(line.as_bytes()).await?;
+                    file.flush().await
+                }
+                .await
+                {
+                    error!("Failed to write audit log {}: {}", path, e);
+                }
+            }
+        });
+        Ok(Self { lines, include_contents })
+    }
+}
+
+/// Audit details of one request, written once its response has completed
+///
+/// Cheap to create and clone when auditing is off; [`complete`](Self::complete)
+/// is then a no-op.
+#[derive(Clone, Default)]
+pub struct AuditRecord {
+    log: Option<Arc<AuditLog>>,
+    request_id: String,
+    model: String,
+    messages: Option<Value>,
+}
+
+impl AuditRecord {
+    /// Begin a record for a request for `model` with the prompt `messages`
+    /// as sent upstream
+    pub fn start(state: &GatewayState, headers: &HeaderMap, model: &str, messages: &[Message]) -> Self {
+        let Some(log) = &state.audit else {
+            return Self::default();
+        };
+        let request_id = headers
+            .get("x-request-id")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        Self {
+            messages: log.include_contents.then(|| json!(messages)),
+            log: Some(log.clone()),
+            request_id,
+            model: model.to_string(),
+        }
+    }
+
+    /// Queue the log line for a completed response
+    pub fn complete(&self, usage: Option<&Usage>, completion: Option<&str>) {
+        let Some(log) = &self.log else {
+            return;
+        };
+        let mut entry = json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "request_id": self.request_id,
+            "model": self.model,
+            "usage": usage,
+        });
+        if let Some(messages) = &self.messages {
+            entry["messages"] = messages.clone();
+            entry["completion"] = json!(completion);
+        }
+        // The writer task only stops with the runtime, so a failed send can be ignored
+        let _ = log.lines.send(format!("{}\n", entry));
+    }
+}
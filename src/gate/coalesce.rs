@@ -0,0 +1,121 @@
+//! Coalescing of streamed text deltas
+//!
+//! Token-by-token SSE frames carry a lot of overhead on high-latency links.
+//! [`coalesce`] merges the text of [`StreamEvent`]s that arrive within a
+//! window of the first buffered one, so the encoder writes one frame per
+//! window instead of one per token. Any event carrying more than text (tool
+//! calls, usage, the final `done`) flushes the buffer and passes through.
+
+use crate::{Result, StreamEvent};
+use futures::stream::{Stream, StreamExt};
+use std::time::Duration;
+
+/// Merge consecutive text-only events of `events` arriving within `window`
+/// of the first of them (`None` passes `events` through unchanged)
+pub fn coalesce<S>(events: S, window: Option<Duration>) -> impl Stream<Item = Result<StreamEvent>>
+where
+    S: Stream<Item = Result<StreamEvent>>,
+{
+    async_stream::stream! {
+        let mut events = std::pin::pin!(events);
+        let mut pending: Option<StreamEvent> = None;
+        let mut deadline: Option<tokio::time::Instant> = None;
+        loop {
+            let next = match deadline {
+                Some(at) => match tokio::time::timeout_at(at, events.next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        // Window over: emit what was gathered
+                        deadline = None;
+                        if let Some(event) = pending.take() {
+                            yield Ok(event);
+                        }
+                        continue;
+                    }
+                },
+                None => events.next().await,
+            };
+
+            match next {
+                Some(Ok(event)) if window.is_some() && is_text_only(&event) => match &mut pending {
+                    Some(buffered) => merge(buffered, event),
+                    None => {
+                        deadline = window.map(|window| tokio::time::Instant::now() + window);
+                        pending = Some(event);
+                    }
+                },
+                Some(item) => {
+                    deadline = None;
+                    if let Some(event) = pending.take() {
+                        yield Ok(event);
+                    }
+                    yield item;
+                }
+                None => {
+                    if let Some(event) = pending.take() {
+                        yield Ok(event);
+                    }
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Whether `event` carries nothing but answer or reasoning text
+fn is_text_only(event: &StreamEvent) -> bool {
+    !event.done && event.usage.is_none() && event.tool_calls.is_none() && event.finish_reason.is_none()
+}
+
+fn merge(buffered: &mut StreamEvent, event: StreamEvent) {
+    buffered.delta.push_str(&event.delta);
+    if let Some(reasoning) = event.reasoning {
+        buffered.reasoning.get_or_insert_with(String::new).push_str(&reasoning);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(delta: &str, done: bool) -> Result<StreamEvent> {
+        Ok(StreamEvent {
+            delta: delta.to_string(),
+            done,
+            usage: None,
+            tool_calls: None,
+            reasoning: None,
+            finish_reason: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_ready_deltas_merge_and_done_flushes() {
+        let events = futures::stream::iter(vec![event("Hel", false), event("lo", false), event("", true)]);
+        let out: Vec<StreamEvent> = coalesce(events, Some(Duration::from_millis(50)))
+            .map(|e| e.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].delta, "Hello");
+        assert!(!out[0].done);
+        assert!(out[1].done);
+    }
+
+    #[tokio::test]
+    async fn test_window_bounds_latency() {
+        let events = async_stream::stream! {
+            yield event("a", false);
+            tokio::time::sleep(Duration::from_millis(120)).await;
+            yield event("b", false);
+            yield event("", true);
+        };
+        let out: Vec<String> = coalesce(events, Some(Duration::from_millis(30)))
+            .map(|e| e.unwrap().delta)
+            .collect()
+            .await;
+
+        assert_eq!(out, vec!["a", "b", ""]);
+    }
+}
@@ -1,6 +1,10 @@
 //! Gateway configuration
 
+use crate::gate::transform::FieldFilter;
+use crate::gate::usage::ModelPrice;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
 
 /// Gateway configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,6 +20,96 @@ pub struct GatewayConfig {
     /// Request timeout in seconds (default: 120)
     #[serde(default = "default_timeout")]
     pub timeout_secs: u64,
+
+    /// Maximum request body size in bytes (default: 10 MB)
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size_bytes: usize,
+
+    /// Origins allowed to call the gateway from a browser ("*" for any).
+    /// CORS is disabled when empty.
+    #[serde(default)]
+    pub cors_allowed_origins: Vec<String>,
+
+    /// How long a response is replayed for a repeated `Idempotency-Key`
+    /// (default: 300)
+    #[serde(default = "default_idempotency_ttl")]
+    pub idempotency_ttl_secs: u64,
+
+    /// Most responses kept for `Idempotency-Key` replay; the oldest is
+    /// dropped to make room (default: 10000)
+    #[serde(default = "default_idempotency_max_entries")]
+    pub idempotency_max_entries: usize,
+
+    /// Expose Prometheus metrics on `/metrics` (default: false)
+    #[serde(default)]
+    pub metrics_enabled: bool,
+
+    /// Seconds of upstream silence after which a streaming response gets an
+    /// SSE `: keep-alive` comment (default: 15, 0 disables)
+    #[serde(default = "default_sse_keepalive")]
+    pub sse_keepalive_secs: u64,
+
+    /// Merge streamed text deltas arriving within this many milliseconds
+    /// into one SSE frame (default: 0, off). Coalesced streams are parsed
+    /// and re-encoded rather than passed through byte for byte.
+    #[serde(default)]
+    pub coalesce_stream_ms: u64,
+
+    /// Drop upstream `event: ping` frames from passed-through streams
+    /// (default: false)
+    #[serde(default)]
+    pub strip_ping_events: bool,
+
+    /// Watch passed-through streams for their usage frames as they are
+    /// forwarded, so `/v1/usage`, metrics and cost totals include them
+    /// (default: true)
+    #[serde(default = "default_tee_stream_usage")]
+    pub tee_stream_usage: bool,
+
+    /// Token prices per million by model reference, for the cost totals on
+    /// `/v1/usage`, e.g. `[model_prices."openai.gpt-4o"]` with
+    /// `prompt = 2.5` and `completion = 10.0` (default: none)
+    #[serde(default)]
+    pub model_prices: HashMap<String, ModelPrice>,
+
+    /// Upstream response headers the passthrough copies onto its responses,
+    /// by name or by prefix ending in `*`, e.g.
+    /// `["x-ratelimit-*", "anthropic-ratelimit-*"]` (default: none)
+    #[serde(default)]
+    pub forward_response_headers: Vec<String>,
+
+    /// Request fields to keep or drop per upstream provider type, e.g.
+    /// `[field_filters.openai]` with `deny = ["logit_bias"]`
+    #[serde(default)]
+    pub field_filters: HashMap<String, FieldFilter>,
+
+    /// Upstream paths (relative to the provider's `api_base`) that
+    /// `/proxy/{provider}/{path}` may forward, e.g. `["embeddings"]`; a path
+    /// matches an entry equal to it or to one of its parent paths. The proxy
+    /// refuses everything when empty.
+    #[serde(default)]
+    pub proxy_allowed_paths: Vec<String>,
+
+    /// Answer requests for unconfigured models with a fabricated "Mock
+    /// response" instead of a 404 `model_not_found` (default: false; only
+    /// meant for tests)
+    #[serde(default)]
+    pub allow_mock_responses: bool,
+
+    /// Append one JSON line per completed chat request (timestamp, request
+    /// id, model, usage) to this file; auditing is off when unset
+    #[serde(default)]
+    pub audit_log_path: Option<PathBuf>,
+
+    /// Also record prompt messages and completion text in the audit log
+    /// (default: false)
+    #[serde(default)]
+    pub audit_log_contents: bool,
+
+    /// After a shutdown signal, how long in-flight requests may keep running
+    /// before the gateway exits anyway (default: 30)
+    #[serde(default = "default_shutdown_drain")]
+    pub shutdown_drain_secs: u64,
 }
 
 impl Default for GatewayConfig {
@@ -24,6 +118,23 @@ impl Default for GatewayConfig {
             host: default_host(),
             port: default_port(),
             timeout_secs: default_timeout(),
+            max_body_size_bytes: default_max_body_size(),
+            cors_allowed_origins: Vec::new(),
+            idempotency_ttl_secs: default_idempotency_ttl(),
+            idempotency_max_entries: default_idempotency_max_entries(),
+            metrics_enabled: false,
+            sse_keepalive_secs: default_sse_keepalive(),
+            coalesce_stream_ms: 0,
+            strip_ping_events: false,
+            tee_stream_usage: default_tee_stream_usage(),
+            model_prices: HashMap::new(),
+            forward_response_headers: Vec::new(),
+            field_filters: HashMap::new(),
+            proxy_allowed_paths: Vec::new(),
+            allow_mock_responses: false,
+            audit_log_path: None,
+            audit_log_contents: false,
+            shutdown_drain_secs: default_shutdown_drain(),
         }
     }
 }
@@ -39,3 +150,27 @@ fn default_port() -> u16 {
 fn default_timeout() -> u64 {
     120
 }
+
+fn default_max_body_size() -> usize {
+    10 * 1024 * 1024
+}
+
+fn default_idempotency_ttl() -> u64 {
+    300
+}
+
+fn default_idempotency_max_entries() -> usize {
+    10_000
+}
+
+fn default_sse_keepalive() -> u64 {
+    15
+}
+
+fn default_tee_stream_usage() -> bool {
+    true
+}
+
+fn default_shutdown_drain() -> u64 {
+    30
+}
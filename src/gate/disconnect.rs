@@ -0,0 +1,83 @@
+//! Upstream cancellation when a streaming client disconnects
+//!
+//! Once a client goes away mid-stream, hyper fails its next write and drops
+//! the response body. The streaming handlers move the upstream response into
+//! that body, so the drop also closes the upstream connection and the
+//! provider stops generating (and billing) tokens nobody will read. Keep-alive
+//! frames (`sse_keepalive_secs`) bound how long a disconnect goes unnoticed
+//! while the upstream is silent.
+//!
+//! [`DisconnectGuard`] lives in each streaming body and reports the streams
+//! that were cut short this way.
+
+use tracing::info;
+
+/// Marker owned by a streaming response body; logs the upstream abort when
+/// the body is dropped before [`finish`](Self::finish) was called
+pub struct DisconnectGuard {
+    model: String,
+    finished: bool,
+}
+
+impl DisconnectGuard {
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            finished: false,
+        }
+    }
+
+    /// The upstream stream ended normally
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    /// Whether the body was dropped mid-stream
+    pub fn disconnected(&self) -> bool {
+        !self.finished
+    }
+}
+
+impl Drop for DisconnectGuard {
+    fn drop(&mut self) {
+        if self.disconnected() {
+            info!("Client disconnected from {} stream, closing the upstream connection", self.model);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::StreamExt;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_dropped_body_releases_upstream() {
+        // Stands in for the upstream response moved into the body
+        let upstream = Arc::new(());
+        let held = upstream.clone();
+        let body = async_stream::stream! {
+            let _upstream = held;
+            let mut guard = DisconnectGuard::new("mock");
+            for i in 0..10 {
+                yield i;
+            }
+            guard.finish();
+        };
+        let mut body = Box::pin(body);
+
+        assert_eq!(body.next().await, Some(0));
+        assert_eq!(Arc::strong_count(&upstream), 2);
+        drop(body);
+        assert_eq!(Arc::strong_count(&upstream), 1);
+    }
+
+    #[test]
+    fn test_finish_marks_normal_end() {
+        let mut guard = DisconnectGuard::new("mock");
+        assert!(guard.disconnected());
+        guard.finish();
+        assert!(!guard.disconnected());
+    }
+}
@@ -0,0 +1,103 @@
+//! Model fallback chains
+//!
+//! When an upstream answers that the requested model does not exist, the
+//! handlers hand the error to [`with_fallbacks`], which retries the request on
+//! each model listed for it under `[llm.fallback]`
+//! (see [`FallbackConfig`](crate::FallbackConfig)), loaded once at startup
+//! into [`GatewayState::fallbacks`].
+
+use super::handlers::{client_for, GatewayState};
+use crate::{Client, Error, ProviderType};
+use serde_json::Value;
+use std::future::Future;
+use tracing::{info, warn};
+
+/// Response header naming the fallback model that served the request
+pub const FALLBACK_MODEL_HEADER: &str = "x-fallback-model";
+
+/// Whether `error` is an upstream "model not found": HTTP 404, or an error
+/// body whose `error.code` is `model_not_found` (OpenAI) or whose
+/// `error.type` is `not_found_error` (Anthropic)
+pub fn is_model_not_found(error: &Error) -> bool {
+    let Error::Upstream { status, body, .. } = error else {
+        return false;
+    };
+    if *status == reqwest::StatusCode::NOT_FOUND {
+        return true;
+    }
+    let Ok(body) = serde_json::from_str::<Value>(body) else {
+        return false;
+    };
+    body["error"]["code"] == "model_not_found" || body["error"]["type"] == "not_found_error"
+}
+
+/// Retry a request that failed with `error` on the fallbacks configured for
+/// `model_refs` (the first that has any), in order, resolving each through
+/// the state's resolver
+///
+/// Returns the original error untouched unless it is a model-not-found. On
+/// success also returns the fallback model reference that served the request.
+pub async fn with_fallbacks<T, F, Fut>(
+    state: &GatewayState,
+    model_refs: &[&str],
+    provider_type: ProviderType,
+    error: Error,
+    mut call: F,
+) -> (crate::Result<T>, Option<String>)
+where
+    F: FnMut(Box<dyn Client>, String) -> Fut,
+    Fut: Future<Output = crate::Result<T>>,
+{
+    if !is_model_not_found(&error) {
+        return (Err(error), None);
+    }
+
+    let mut error = error;
+    for fallback in state.fallbacks.fallbacks_for(model_refs) {
+        let created = state
+            .resolver
+            .resolve(fallback, provider_type)
+            .and_then(|resolved| client_for(&resolved).map_err(|e| e.to_string()));
+        let (client, model_id) = match created {
+            Ok(created) => created,
+            Err(e) => {
+                warn!("Skipping fallback '{}': {}", fallback, e);
+                continue;
+            }
+        };
+
+        info!("Model {:?} not found upstream, trying fallback '{}'", model_refs, fallback);
+        match call(client, model_id).await {
+            Ok(value) => return (Ok(value), Some(fallback.clone())),
+            Err(e) if is_model_not_found(&e) => error = e,
+            Err(e) => return (Err(e), None),
+        }
+    }
+
+    (Err(error), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upstream(status: u16, body: &str) -> Error {
+        Error::Upstream {
+            provider: "OpenAI",
+            status: reqwest::StatusCode::from_u16(status).unwrap(),
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_model_not_found_detection() {
+        assert!(is_model_not_found(&upstream(404, "{}")));
+        assert!(is_model_not_found(&upstream(400, r#"{"error":{"code":"model_not_found"}}"#)));
+        assert!(is_model_not_found(&upstream(400, r#"{"type":"error","error":{"type":"not_found_error"}}"#)));
+        assert!(!is_model_not_found(&upstream(500, "boom")));
+        // A message that merely mentions the code is not a match
+        assert!(!is_model_not_found(&upstream(400, r#"{"error":{"message":"model_not_found is not a tool"}}"#)));
+        assert!(!is_model_not_found(&Error::Api("OpenAI API error (404 Not Found): {}".to_string())));
+        assert!(!is_model_not_found(&Error::Config("missing".to_string())));
+    }
+}
@@ -0,0 +1,126 @@
+//! Removal of selected upstream SSE frames from passthrough streams
+//!
+//! Anthropic interleaves `event: ping` frames into its streams. Our own
+//! client skips them, but some downstream SSE clients fail on event types
+//! they do not know. With `strip_ping_events` set, the passthrough drops
+//! them.
+//!
+//! When the gateway asks an OpenAI stream for usage on the client's behalf
+//! (`tee_stream_usage`), the extra `choices: []` usage chunk is dropped the
+//! same way, so the client sees the stream it asked for.
+//!
+//! A [`FrameFilter`] splits the upstream bytes into frames and forwards
+//! every frame except the dropped ones.
+
+/// Incremental SSE frame filter dropping ping and usage-only frames
+pub struct FrameFilter {
+    pending: Vec<u8>,
+    pings: bool,
+    usage_chunks: bool,
+}
+
+impl FrameFilter {
+    /// A filter dropping `event: ping` frames when `pings` is set and
+    /// `choices: []` usage chunks when `usage_chunks` is
+    pub fn new(pings: bool, usage_chunks: bool) -> Self {
+        Self { pending: Vec::new(), pings, usage_chunks }
+    }
+
+    /// Feed upstream `bytes`; returns the complete kept frames they end
+    ///
+    /// A frame split across chunks is held back until its blank line arrives.
+    pub fn filter(&mut self, bytes: &[u8]) -> Vec<u8> {
+        self.pending.extend_from_slice(bytes);
+        let mut out = Vec::new();
+        while let Some(end) = frame_end(&self.pending) {
+            let frame: Vec<u8> = self.pending.drain(..end).collect();
+            if !self.drops(&frame) {
+                out.extend_from_slice(&frame);
+            }
+        }
+        out
+    }
+
+    /// Whatever is left once the upstream ends (an unterminated last frame)
+    pub fn finish(self) -> Vec<u8> {
+        if self.drops(&self.pending) {
+            Vec::new()
+        } else {
+            self.pending
+        }
+    }
+
+    fn drops(&self, frame: &[u8]) -> bool {
+        (self.pings && is_ping(frame)) || (self.usage_chunks && is_usage_chunk(frame))
+    }
+}
+
+/// Length of the first complete frame in `buf`, blank line included
+fn frame_end(buf: &[u8]) -> Option<usize> {
+    (1..buf.len()).find_map(|i| match (buf[i - 1], buf[i]) {
+        (b'\n', b'\n') => Some(i + 1),
+        (b'\n', b'\r') if buf.get(i + 1) == Some(&b'\n') => Some(i + 2),
+        _ => None,
+    })
+}
+
+fn is_ping(frame: &[u8]) -> bool {
+    String::from_utf8_lossy(frame).lines().any(|line| {
+        line.trim_end()
+            .strip_prefix("event:")
+            .is_some_and(|event| event.trim() == "ping")
+    })
+}
+
+/// Whether `frame` is an OpenAI chunk carrying only usage
+fn is_usage_chunk(frame: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(frame);
+    let data: String = text
+        .lines()
+        .filter_map(|line| line.trim_end().strip_prefix("data:"))
+        .map(str::trim)
+        .collect();
+    serde_json::from_str::<serde_json::Value>(&data).is_ok_and(|chunk| {
+        chunk.get("choices").and_then(|c| c.as_array()).is_some_and(Vec::is_empty)
+            && chunk.get("usage").is_some_and(|u| !u.is_null())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ping_frames_removed_across_chunks() {
+        let mut filter = FrameFilter::new(true, false);
+        let mut out = filter.filter(b"event: message_start\ndata: {}\n\nevent: pi");
+        out.extend(filter.filter(b"ng\ndata: {\"type\":\"ping\"}\n\nevent: content_block_delta\n"));
+        out.extend(filter.filter(b"data: {\"text\":\"hi\"}\n\n"));
+        out.extend(filter.finish());
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "event: message_start\ndata: {}\n\nevent: content_block_delta\ndata: {\"text\":\"hi\"}\n\n"
+        );
+    }
+
+    #[test]
+    fn test_crlf_frames_and_unterminated_tail() {
+        let mut filter = FrameFilter::new(true, false);
+        let mut out = filter.filter(b"event: ping\r\ndata: {}\r\n\r\ndata: [DONE]");
+        out.extend(filter.finish());
+
+        assert_eq!(String::from_utf8(out).unwrap(), "data: [DONE]");
+    }
+
+    #[test]
+    fn test_usage_only_chunk_removed() {
+        let finish = "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}],\"usage\":null}\n\n";
+        let usage = "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":3,\"completion_tokens\":1,\"total_tokens\":4}}\n\n";
+        let mut filter = FrameFilter::new(false, true);
+        let mut out = filter.filter(format!("{}{}data: [DONE]\n\n", finish, usage).as_bytes());
+        out.extend(filter.finish());
+
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}data: [DONE]\n\n", finish));
+    }
+}
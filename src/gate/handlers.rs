@@ -1,37 +1,100 @@
 //! HTTP request handlers for the gateway
 
-use super::router::resolve_model;
+use super::anthropic_sse::stop_reason;
+use super::audit::AuditLog;
+use super::idempotency::IdempotencyCache;
+use super::metrics::Metrics;
+use super::proxy::ProxyUpstreams;
+use super::response_headers::with_upstream_headers;
+use super::router::{ModelResolver, ResolvedModel};
+use super::transform::RequestTransform;
+use super::usage::UsageTracker;
 use crate::message::Message;
-use crate::{create_client_for_model, ProviderConfig, ProviderType, ToolDefinition};
+use crate::{
+    create_client, Capabilities, ChatResponse, Client, FallbackConfig, ProviderConfig, ProviderType, SystemPromptConfig,
+    ToolDefinition,
+};
 use axum::{
+    body::Body,
     extract::State,
-    http::StatusCode,
-    response::sse::{Event, Sse},
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, Sse},
+        Response,
+    },
     Json,
 };
 use futures::stream::StreamExt;
 use serde_json::json;
 use serde_json::Value;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tracing::{error, info};
 
-/// Generate a simple UUID-like string
-fn uuid_simple() -> String {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    let duration = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default();
-    format!("{:x}{:x}", duration.as_secs(), duration.subsec_nanos())
+/// Random 32-digit hex id for responses the gateway builds itself
+pub(crate) fn uuid_simple() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Whether a request's `stream` field asks for streaming
+///
+/// Some SDKs send the flag as the string `"true"`/`"false"` rather than a
+/// JSON boolean; both forms are accepted, anything else means no streaming.
+pub(crate) fn stream_flag(stream: Option<&Value>) -> bool {
+    match stream {
+        Some(Value::Bool(b)) => *b,
+        Some(Value::String(s)) => s.eq_ignore_ascii_case("true"),
+        _ => false,
+    }
+}
+
+/// Response header carrying how long the upstream call took, in milliseconds
+pub(crate) const UPSTREAM_LATENCY_HEADER: &str = "x-upstream-latency-ms";
+
+/// First feature a chat request body asks for that `capabilities` lacks
+pub(crate) fn unsupported_feature(capabilities: &Capabilities, request: &Value) -> Option<&'static str> {
+    let wants_stream = stream_flag(request.get("stream"));
+    let wants_tools = request
+        .get("tools")
+        .and_then(|t| t.as_array())
+        .is_some_and(|t| !t.is_empty());
+    let wants_json = request
+        .get("response_format")
+        .and_then(|f| f.get("type"))
+        .and_then(|t| t.as_str())
+        .is_some_and(|t| t != "text");
+    let wants_vision = request
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .is_some_and(|messages| {
+            messages.iter().any(|m| {
+                m.get("content").and_then(|c| c.as_array()).is_some_and(|parts| {
+                    parts
+                        .iter()
+                        .any(|p| matches!(p.get("type").and_then(|t| t.as_str()), Some("image_url" | "image")))
+                })
+            })
+        });
+
+    [
+        (wants_stream, capabilities.streaming, "streaming"),
+        (wants_tools, capabilities.tools, "tools"),
+        (wants_json, capabilities.json_mode, "JSON response_format"),
+        (wants_vision, capabilities.vision, "image input"),
+    ]
+    .into_iter()
+    .find(|(wanted, supported, _)| *wanted && !supported)
+    .map(|(_, _, feature)| feature)
 }
 
-#[allow(dead_code)]
 /// Create an OpenAI-compatible error response
-fn openai_error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+pub(crate) fn openai_error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
     let error_type = match status {
         StatusCode::BAD_REQUEST => "invalid_request_error",
         StatusCode::UNAUTHORIZED => "authentication_error",
         StatusCode::FORBIDDEN => "permission_error",
         StatusCode::NOT_FOUND => "invalid_request_error",
+        StatusCode::PAYLOAD_TOO_LARGE => "invalid_request_error",
         StatusCode::TOO_MANY_REQUESTS => "rate_limit_error",
         StatusCode::SERVICE_UNAVAILABLE => "server_error",
         _ => "server_error",
@@ -46,6 +109,52 @@ fn openai_error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>)
     })))
 }
 
+/// 404 `model_not_found` response for a model with no configuration, in the
+/// error format `provider_type` clients expect
+pub(crate) fn model_not_found(model: &str, provider_type: ProviderType) -> Response {
+    let message = format!("Model '{}' is not configured", model);
+    let json = match provider_type {
+        ProviderType::OpenAI => json!({
+            "error": {"message": message, "type": "invalid_request_error", "code": "model_not_found"}
+        }),
+        ProviderType::Anthropic => json!({"type": "error", "error": {"type": "not_found_error", "message": message}}),
+    };
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .header("Content-Type", "application/json")
+        .body(Body::from(json.to_string()))
+        .unwrap()
+}
+
+/// HTTP status a failed upstream call answered with; 500 when the upstream
+/// never answered
+pub(crate) fn upstream_status(error: &crate::Error) -> StatusCode {
+    match error {
+        crate::Error::Upstream { status, .. } => StatusCode::from_u16(status.as_u16())
+            .ok()
+            .filter(|status| status.is_client_error() || status.is_server_error())
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Response for a failed upstream call on a passthrough route
+///
+/// An upstream error is forwarded with its own status and body, so clients
+/// see exactly what the provider said; a failure before any answer (e.g. a
+/// connection error) becomes `wrap(message)` with a 500.
+pub(crate) fn upstream_error_response(error: &crate::Error, wrap: impl FnOnce(&str) -> Value) -> Response {
+    let body = match error {
+        crate::Error::Upstream { body, .. } => body.clone(),
+        other => wrap(&other.to_string()).to_string(),
+    };
+    Response::builder()
+        .status(upstream_status(error))
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
 #[allow(dead_code)]
 /// Create an Anthropic-compatible error response
 fn anthropic_error(message: &str) -> (StatusCode, Json<Value>) {
@@ -57,10 +166,77 @@ fn anthropic_error(message: &str) -> (StatusCode, Json<Value>) {
     })))
 }
 
+/// Client and upstream model id for a resolved model
+///
+/// Built from the resolved config so the upstream gets the configured
+/// model id, never the client's own string.
+pub(crate) fn client_for(resolved: &ResolvedModel) -> crate::Result<(Box<dyn Client>, String)> {
+    create_client(resolved.config.clone()).map(|client| (client, resolved.model_name.clone()))
+}
+
 /// Gateway state shared across handlers
 #[derive(Clone)]
 pub struct GatewayState {
     pub config: Arc<ProviderConfig>,
+    pub usage: Arc<UsageTracker>,
+    pub idempotency: Arc<IdempotencyCache>,
+    /// Prometheus metrics, `None` unless `metrics_enabled` is set
+    pub metrics: Option<Arc<Metrics>>,
+    /// Idle time before a streaming response gets a keep-alive comment,
+    /// `None` when `sse_keepalive_secs` is 0
+    pub sse_keepalive: Option<Duration>,
+    /// Window for merging streamed deltas, `None` when `coalesce_stream_ms`
+    /// is 0 (see [`crate::gate::coalesce`])
+    pub coalesce_stream: Option<Duration>,
+    /// Filter `event: ping` frames out of passthrough streams
+    pub strip_ping_events: bool,
+    /// Scan passthrough streams for their usage frames as they are forwarded
+    pub tee_stream_usage: bool,
+    /// Upstream response headers the passthrough forwards (see
+    /// [`crate::gate::response_headers`])
+    pub forward_response_headers: Arc<Vec<String>>,
+    /// Mandatory system prompt from `[llm.gateway.system_prompt]`
+    pub system_prompt: Option<Arc<SystemPromptConfig>>,
+    /// Rewrites applied to request bodies, in order, before forwarding
+    pub transforms: Arc<Vec<Box<dyn RequestTransform>>>,
+    /// When the gateway started, for `/health` uptime
+    pub started_at: Instant,
+    /// Paths `/proxy/{provider}/{path}` may forward (see [`crate::gate::proxy`])
+    pub proxy_allowed_paths: Arc<Vec<String>>,
+    /// Providers `/proxy/{provider}/{path}` forwards to
+    pub proxy_upstreams: Arc<ProxyUpstreams>,
+    /// Serve mock completions for unconfigured models instead of a 404
+    pub allow_mock_responses: bool,
+    /// Audit log writer, `None` unless `audit_log_path` is set
+    pub audit: Option<Arc<AuditLog>>,
+    /// Maps requested model names to configured models
+    pub resolver: Arc<dyn ModelResolver>,
+    /// Fallback chains from `[llm.fallback]` (see [`crate::gate::fallback`])
+    pub fallbacks: Arc<FallbackConfig>,
+}
+
+impl GatewayState {
+    /// Account one request's token usage for `model`
+    pub fn record_usage(&self, model: &str, usage: &crate::Usage) {
+        self.usage.record(model, usage);
+        if let Some(metrics) = &self.metrics {
+            metrics.record_tokens(model, usage);
+        }
+    }
+
+    /// Run the configured [`RequestTransform`]s over a request bound for a
+    /// `provider_type` upstream
+    pub fn transform_request(&self, body: &mut Value, provider_type: ProviderType) {
+        for transform in self.transforms.iter() {
+            transform.transform(body, provider_type);
+        }
+    }
+
+    /// Copy the `upstream` response headers allowed by
+    /// `forward_response_headers` onto a passthrough `response`
+    pub(crate) fn with_upstream_headers(&self, response: Response, upstream: &HeaderMap) -> Response {
+        with_upstream_headers(response, upstream, &self.forward_response_headers)
+    }
 }
 
 /// Handle OpenAI-compatible chat completions (non-streaming)
@@ -77,7 +253,7 @@ pub async fn openai_chat_handler(
     info!("OpenAI chat request for model: {}", model);
 
     // Resolve model to provider
-    let resolved = resolve_model(model, &state.config)
+    let resolved = state.resolver.resolve(model, ProviderType::OpenAI)
         .map_err(|e| {
             error!("Failed to resolve model '{}': {}", model, e);
             StatusCode::NOT_FOUND
@@ -111,11 +287,11 @@ pub async fn openai_chat_handler(
     let tools_ref = tools.as_deref();
 
     // Try to create client and call the API
-    match create_client_for_model(model) {
+    match client_for(&resolved) {
         Ok((client, model_id)) => {
             // Call the actual API
             match client.chat(&messages, &model_id, tools_ref).await {
-                Ok((content, tool_calls, usage)) => {
+                Ok(ChatResponse { content, tool_calls, usage, .. }) => {
                     // Build choices with tool_calls if present
                     let finish_reason = if tool_calls.is_some() { "tool_calls" } else { "stop" };
                     let mut message_json = json!({
@@ -158,6 +334,10 @@ pub async fn openai_chat_handler(
                 }
             }
         }
+        Err(e) if !state.allow_mock_responses => {
+            info!("Model '{}' not configured: {}", model, e);
+            Err(StatusCode::NOT_FOUND)
+        }
         Err(e) => {
             // Model not configured, return mock response
             info!("Model '{}' not configured, returning mock response: {}", model, e);
@@ -196,7 +376,7 @@ pub async fn openai_chat_stream_handler(
 
     info!("OpenAI streaming request for model: {}", model);
 
-    let resolved = resolve_model(model, &state.config)
+    let resolved = state.resolver.resolve(model, ProviderType::OpenAI)
         .map_err(|e| {
             error!("Failed to resolve model '{}': {}", model, e);
             StatusCode::NOT_FOUND
@@ -226,7 +406,7 @@ pub async fn openai_chat_stream_handler(
         .and_then(|t| serde_json::from_value(t.clone()).ok());
     let tools_ref = tools.as_deref();
 
-    match create_client_for_model(model) {
+    match client_for(&resolved) {
         Ok((client, model_id)) => {
             let stream = client.chat_stream(&messages, &model_id, tools_ref);
             let model = model.to_string();
@@ -306,6 +486,10 @@ pub async fn openai_chat_stream_handler(
             let stream = futures::stream::iter(events);
             Ok(Sse::new(Box::pin(stream)))
         }
+        Err(e) if !state.allow_mock_responses => {
+            info!("Model '{}' not configured: {}", model, e);
+            Err(StatusCode::NOT_FOUND)
+        }
         Err(e) => {
             info!("Model '{}' not configured, returning mock stream: {}", model, e);
             let id = format!("chatcmpl-{}", uuid_simple());
@@ -340,7 +524,7 @@ pub async fn anthropic_messages_handler(
 
     info!("Anthropic messages request for model: {}", model);
 
-    let resolved = match resolve_model(model, &state.config) {
+    let resolved = match state.resolver.resolve(model, ProviderType::Anthropic) {
         Ok(r) => r,
         Err(e) => {
             error!("Failed to resolve model '{}': {}", model, e);
@@ -372,10 +556,12 @@ pub async fn anthropic_messages_handler(
         .and_then(|t| serde_json::from_value(t.clone()).ok());
     let tools_ref = tools.as_deref();
 
-    match create_client_for_model(model) {
+    match client_for(&resolved) {
         Ok((client, model_id)) => {
             match client.chat(&messages, &model_id, tools_ref).await {
-                Ok((content, tool_calls, usage)) => {
+                Ok(response) => {
+                    let finish_reason = response.finish();
+                    let ChatResponse { content, tool_calls, usage, .. } = response;
                     // Build content blocks
                     let mut content_blocks: Vec<serde_json::Value> = Vec::new();
                     if !content.is_empty() {
@@ -392,7 +578,7 @@ pub async fn anthropic_messages_handler(
                             }));
                         }
                     }
-                    let stop_reason = if tool_calls.is_some() { "tool_use" } else { "end_turn" };
+                    let stop_reason = stop_reason(finish_reason.as_ref(), tool_calls.is_some());
                     Ok(Json(json!({
                         "id": format!("msg_{}", uuid_simple()),
                         "type": "message",
@@ -412,6 +598,10 @@ pub async fn anthropic_messages_handler(
                 }
             }
         }
+        Err(e) if !state.allow_mock_responses => {
+            info!("Model '{}' not configured: {}", model, e);
+            Err(StatusCode::NOT_FOUND)
+        }
         Err(e) => {
             info!("Model '{}' not configured, returning mock response: {}", model, e);
             Ok(Json(json!({
@@ -570,3 +760,101 @@ pub async fn list_providers(
         }
     }
 }
+
+/// Handle usage totals request
+pub async fn usage_stats(
+    State(state): State<GatewayState>,
+) -> Json<Value> {
+    let snapshot = state.usage.snapshot();
+    Json(json!({
+        "object": "usage",
+        "requests": snapshot.requests,
+        "prompt_tokens": snapshot.prompt_tokens,
+        "completion_tokens": snapshot.completion_tokens,
+        "total_tokens": snapshot.total_tokens,
+        "cost": snapshot.cost
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uuid_simple_is_unique() {
+        let ids: std::collections::HashSet<String> = (0..10_000).map(|_| uuid_simple()).collect();
+        assert_eq!(ids.len(), 10_000);
+        assert!(ids.iter().all(|id| id.len() == 32));
+    }
+
+    #[test]
+    fn test_stream_flag_accepts_bool_and_string() {
+        assert!(stream_flag(Some(&json!(true))));
+        assert!(!stream_flag(Some(&json!(false))));
+        assert!(stream_flag(Some(&json!("true"))));
+        assert!(stream_flag(Some(&json!("TRUE"))));
+        assert!(!stream_flag(Some(&json!("false"))));
+        assert!(!stream_flag(Some(&json!(1))));
+        assert!(!stream_flag(None));
+    }
+
+    #[test]
+    fn test_unsupported_feature_names_first_missing_capability() {
+        let text_only = Capabilities {
+            streaming: true,
+            tools: true,
+            vision: false,
+            embeddings: false,
+            json_mode: false,
+        };
+
+        let plain = json!({"model": "m", "stream": true, "messages": [{"role": "user", "content": "hi"}]});
+        assert_eq!(unsupported_feature(&text_only, &plain), None);
+
+        let json_mode = json!({"model": "m", "messages": [], "response_format": {"type": "json_object"}});
+        assert_eq!(unsupported_feature(&text_only, &json_mode), Some("JSON response_format"));
+
+        let image = json!({"model": "m", "messages": [{"role": "user", "content": [
+            {"type": "text", "text": "what is this?"},
+            {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+        ]}]});
+        assert_eq!(unsupported_feature(&text_only, &image), Some("image input"));
+    }
+
+    fn upstream_error(status: u16, body: &str) -> crate::Error {
+        crate::Error::Upstream {
+            provider: "Anthropic",
+            status: reqwest::StatusCode::from_u16(status).unwrap(),
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_upstream_status_from_upstream_error() {
+        assert_eq!(upstream_status(&upstream_error(429, "slow down")), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(upstream_status(&upstream_error(529, r#"{"type":"error"}"#)).as_u16(), 529);
+
+        // An in-band message mentioning a status is not an upstream answer
+        let in_band = crate::Error::Api("stream error (429 Too Many Requests)".to_string());
+        assert_eq!(upstream_status(&in_band), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let local = crate::Error::Config("no api key".to_string());
+        assert_eq!(upstream_status(&local), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_upstream_error_is_forwarded_unwrapped() {
+        let body = r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        let wrap = |message: &str| json!({"error": {"message": message}});
+        let response = upstream_error_response(&upstream_error(529, body), wrap);
+        assert_eq!(response.status().as_u16(), 529);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(bytes, body.as_bytes());
+
+        let response = upstream_error_response(&crate::Error::Config("no api key".to_string()), wrap);
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let wrapped: Value = serde_json::from_slice(&bytes).unwrap();
+        assert!(wrapped["error"]["message"].as_str().unwrap().contains("no api key"));
+    }
+}
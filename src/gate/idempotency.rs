@@ -0,0 +1,260 @@
+//! Replay cache for requests carrying an `Idempotency-Key` header
+//!
+//! Clients that retry a non-streaming request after a timeout would otherwise
+//! pay for the upstream call twice. Successful responses are kept for a short
+//! TTL and replayed verbatim for any request that reuses the same key.
+//!
+//! Keys are scoped by endpoint and caller (the credential the request was
+//! sent with), so one caller can never replay another's completion. Each
+//! entry remembers a hash of the request body: reusing a key with a
+//! different body is answered with 422, and a retry that arrives while the
+//! first request is still running with 409, instead of calling the upstream
+//! again.
+
+use axum::{
+    body::{Body, Bytes},
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Request header clients use to mark retries of the same logical request
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Response header set on replayed responses
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// Request headers identifying the caller, in order of preference
+const CALLER_HEADERS: [&str; 2] = ["authorization", "x-api-key"];
+
+/// A request's idempotency key together with a hash of its body
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdempotencyKey {
+    key: String,
+    body_hash: u64,
+}
+
+struct Entry {
+    stored_at: Instant,
+    body_hash: u64,
+    /// `None` while the first request with the key is still running
+    response: Option<Bytes>,
+}
+
+/// What to do with a request that carries an idempotency key
+pub enum Lookup {
+    /// Answer with the stored response body
+    Replay(Bytes),
+    /// Call the upstream; store the response through the reservation
+    Proceed(Reservation),
+    /// Refuse the request with this status and message
+    Reject(StatusCode, &'static str),
+}
+
+/// In-memory cache of successful response bodies, keyed by idempotency key
+pub struct IdempotencyCache {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl IdempotencyCache {
+    /// Create a cache that keeps up to `max_entries` responses for `ttl`
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Look `key` up, reserving it for this request when it is new
+    pub fn begin(self: &Arc<Self>, key: IdempotencyKey) -> Lookup {
+        let mut entries = self.entries.lock().unwrap();
+        self.sweep(&mut entries);
+        match entries.get(&key.key) {
+            Some(entry) if entry.body_hash != key.body_hash => Lookup::Reject(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Idempotency-Key was already used with a different request body",
+            ),
+            Some(Entry { response: Some(body), .. }) => Lookup::Replay(body.clone()),
+            Some(_) => Lookup::Reject(
+                StatusCode::CONFLICT,
+                "A request with this Idempotency-Key is still being processed",
+            ),
+            None => {
+                if entries.len() >= self.max_entries {
+                    let oldest = entries.iter().min_by_key(|(_, e)| e.stored_at).map(|(k, _)| k.clone());
+                    if let Some(oldest) = oldest {
+                        entries.remove(&oldest);
+                    }
+                }
+                entries.insert(
+                    key.key.clone(),
+                    Entry { stored_at: Instant::now(), body_hash: key.body_hash, response: None },
+                );
+                Lookup::Proceed(Reservation { cache: Arc::clone(self), key: Some(key) })
+            }
+        }
+    }
+
+    /// Drop completed entries older than the TTL
+    fn sweep(&self, entries: &mut HashMap<String, Entry>) {
+        entries.retain(|_, entry| entry.response.is_none() || entry.stored_at.elapsed() < self.ttl);
+    }
+}
+
+/// A key held by the request currently running under it
+///
+/// Dropping the reservation without [`Reservation::complete`] (the upstream
+/// call failed) releases the key, so the client may retry.
+pub struct Reservation {
+    cache: Arc<IdempotencyCache>,
+    key: Option<IdempotencyKey>,
+}
+
+impl Reservation {
+    /// Store the successful response body for replay
+    pub fn complete(mut self, body: Bytes) {
+        let Some(key) = self.key.take() else { return };
+        let mut entries = self.cache.entries.lock().unwrap();
+        entries.insert(
+            key.key,
+            Entry { stored_at: Instant::now(), body_hash: key.body_hash, response: Some(body) },
+        );
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let mut entries = self.cache.entries.lock().unwrap();
+            if entries.get(&key.key).is_some_and(|entry| entry.response.is_none()) {
+                entries.remove(&key.key);
+            }
+        }
+    }
+}
+
+fn hash_of(value: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Cache key for a request, scoped by endpoint (so the OpenAI and Anthropic
+/// routes never replay each other's bodies) and by caller
+pub fn idempotency_key(scope: &str, headers: &HeaderMap, body: &Value) -> Option<IdempotencyKey> {
+    let key = headers.get(IDEMPOTENCY_KEY_HEADER)?.to_str().ok()?.trim();
+    if key.is_empty() {
+        return None;
+    }
+    // Only a hash of the credential is kept in memory
+    let caller = CALLER_HEADERS
+        .iter()
+        .find_map(|name| headers.get(*name))
+        .map(|value| hash_of(value.as_bytes()))
+        .unwrap_or(0);
+    Some(IdempotencyKey {
+        key: format!("{}:{:016x}:{}", scope, caller, key),
+        body_hash: hash_of(body.to_string()),
+    })
+}
+
+/// Build the response for a cache hit
+pub fn replay(body: Bytes) -> Response {
+    Response::builder()
+        .status(200)
+        .header("Content-Type", "application/json")
+        .header(IDEMPOTENT_REPLAYED_HEADER, "true")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn proceed(lookup: Lookup) -> Reservation {
+        match lookup {
+            Lookup::Proceed(reservation) => reservation,
+            _ => panic!("expected the key to be reserved"),
+        }
+    }
+
+    #[test]
+    fn test_entries_expire_after_ttl() {
+        let cache = Arc::new(IdempotencyCache::new(Duration::from_millis(20), 10));
+        let key = idempotency_key("openai", &headers(&[(IDEMPOTENCY_KEY_HEADER, "abc")]), &json!({})).unwrap();
+        proceed(cache.begin(key.clone())).complete(Bytes::from_static(b"{}"));
+        assert!(matches!(cache.begin(key.clone()), Lookup::Replay(body) if body == Bytes::from_static(b"{}")));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(matches!(cache.begin(key), Lookup::Proceed(_)));
+    }
+
+    #[test]
+    fn test_key_is_scoped_and_ignores_blank_headers() {
+        let body = json!({"model": "m"});
+        assert_eq!(idempotency_key("openai", &HeaderMap::new(), &body), None);
+        assert_eq!(idempotency_key("openai", &headers(&[(IDEMPOTENCY_KEY_HEADER, " ")]), &body), None);
+
+        let request = headers(&[(IDEMPOTENCY_KEY_HEADER, "req-1")]);
+        let openai = idempotency_key("openai", &request, &body).unwrap();
+        let anthropic = idempotency_key("anthropic", &request, &body).unwrap();
+        assert!(anthropic.key.starts_with("anthropic:"));
+        assert_ne!(openai, anthropic);
+    }
+
+    #[test]
+    fn test_key_is_bound_to_caller_and_body() {
+        let cache = Arc::new(IdempotencyCache::new(Duration::from_secs(60), 10));
+        let alice = headers(&[(IDEMPOTENCY_KEY_HEADER, "req-1"), ("authorization", "Bearer alice")]);
+        let bob = headers(&[(IDEMPOTENCY_KEY_HEADER, "req-1"), ("authorization", "Bearer bob")]);
+        let body = json!({"messages": [{"role": "user", "content": "Hi"}]});
+
+        let first = proceed(cache.begin(idempotency_key("openai", &alice, &body).unwrap()));
+        // The same key from another caller is a different request
+        let other = proceed(cache.begin(idempotency_key("openai", &bob, &body).unwrap()));
+        drop(other);
+
+        // A concurrent retry waits for the first request instead of going upstream
+        let retry = idempotency_key("openai", &alice, &body).unwrap();
+        assert!(matches!(cache.begin(retry.clone()), Lookup::Reject(status, _) if status == StatusCode::CONFLICT));
+        first.complete(Bytes::from_static(b"{\"id\":1}"));
+        assert!(matches!(cache.begin(retry), Lookup::Replay(_)));
+
+        let changed = idempotency_key("openai", &alice, &json!({"messages": []})).unwrap();
+        assert!(matches!(cache.begin(changed), Lookup::Reject(status, _) if status == StatusCode::UNPROCESSABLE_ENTITY));
+    }
+
+    #[test]
+    fn test_failed_request_releases_key_and_cache_is_capped() {
+        let cache = Arc::new(IdempotencyCache::new(Duration::from_secs(60), 2));
+        let key = |k: &str| idempotency_key("openai", &headers(&[(IDEMPOTENCY_KEY_HEADER, k)]), &json!({})).unwrap();
+
+        drop(proceed(cache.begin(key("failed"))));
+        assert!(matches!(cache.begin(key("failed")), Lookup::Proceed(_)));
+
+        for k in ["a", "b", "c"] {
+            proceed(cache.begin(key(k))).complete(Bytes::from_static(b"{}"));
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        assert_eq!(cache.entries.lock().unwrap().len(), 2);
+        assert!(matches!(cache.begin(key("c")), Lookup::Replay(_)));
+    }
+}
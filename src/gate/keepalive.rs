@@ -0,0 +1,137 @@
+//! SSE keep-alive comments
+//!
+//! Some clients and proxies drop SSE connections that stay idle, which
+//! happens while a slow upstream works on its first token. [`with_keepalive`]
+//! fills such gaps with comment frames that SSE parsers ignore.
+
+use futures::stream::{Stream, StreamExt};
+use std::time::Duration;
+
+/// Comment frame sent while the upstream is quiet
+pub const KEEPALIVE_FRAME: &[u8] = b": keep-alive\n\n";
+
+/// Interleave [`KEEPALIVE_FRAME`] into `body` whenever it yields nothing for
+/// `interval` (`None` passes `body` through unchanged)
+///
+/// `body` chunks need not line up with SSE events, so a keep-alive is only
+/// sent once the bytes so far end on an event boundary (a blank line). No
+/// keep-alives follow a terminal frame (`data: [DONE]` or Anthropic's
+/// `message_stop`), nor the end of `body`.
+pub fn with_keepalive<S, E>(body: S, interval: Option<Duration>) -> impl Stream<Item = Result<Vec<u8>, E>>
+where
+    S: Stream<Item = Result<Vec<u8>, E>>,
+{
+    async_stream::stream! {
+        let mut body = std::pin::pin!(body);
+        let mut sent = SentBytes::default();
+        loop {
+            let next = match interval.filter(|_| !sent.done) {
+                Some(interval) => match tokio::time::timeout(interval, body.next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        if sent.at_boundary() {
+                            yield Ok(KEEPALIVE_FRAME.to_vec());
+                        }
+                        continue;
+                    }
+                },
+                None => body.next().await,
+            };
+            let Some(item) = next else { break };
+            if let Ok(bytes) = &item {
+                sent.push(bytes);
+            }
+            yield item;
+        }
+    }
+}
+
+/// Frames that end an OpenAI or Anthropic stream
+const TERMINAL_MARKERS: [&[u8]; 2] = [b"data: [DONE]", b"event: message_stop"];
+
+/// What [`with_keepalive`] needs to know about the bytes passed through
+///
+/// Only a short tail is kept, long enough to see a terminal marker or an
+/// event boundary split across chunks.
+#[derive(Default)]
+struct SentBytes {
+    tail: Vec<u8>,
+    done: bool,
+}
+
+impl SentBytes {
+    const TAIL_LEN: usize = 32;
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.tail.extend_from_slice(bytes);
+        self.done |= TERMINAL_MARKERS
+            .iter()
+            .any(|marker| self.tail.windows(marker.len()).any(|window| window == *marker));
+        let excess = self.tail.len().saturating_sub(Self::TAIL_LEN);
+        self.tail.drain(..excess);
+    }
+
+    /// Whether nothing was sent yet or the last event is complete
+    fn at_boundary(&self) -> bool {
+        self.tail.is_empty() || self.tail.ends_with(b"\n\n") || self.tail.ends_with(b"\r\n\r\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_keepalive_fills_gaps_until_done() {
+        let body = async_stream::stream! {
+            tokio::time::sleep(Duration::from_millis(120)).await;
+            yield Ok::<_, std::io::Error>(b"data: {}\n\n".to_vec());
+            yield Ok(b"data: [DONE]\n\n".to_vec());
+            tokio::time::sleep(Duration::from_millis(120)).await;
+        };
+
+        let frames: Vec<Vec<u8>> = with_keepalive(body, Some(Duration::from_millis(50)))
+            .map(|frame| frame.unwrap())
+            .collect()
+            .await;
+
+        let first_data = frames.iter().position(|f| f.starts_with(b"data:")).unwrap();
+        assert!(first_data >= 1);
+        assert!(frames[..first_data].iter().all(|f| f == KEEPALIVE_FRAME));
+        assert!(frames[first_data..].iter().all(|f| f != KEEPALIVE_FRAME));
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_waits_for_event_boundary() {
+        let body = async_stream::stream! {
+            yield Ok::<_, std::io::Error>(b"data: {\"a\":".to_vec());
+            tokio::time::sleep(Duration::from_millis(120)).await;
+            yield Ok(b"1}\n".to_vec());
+            tokio::time::sleep(Duration::from_millis(120)).await;
+            yield Ok(b"\n".to_vec());
+            tokio::time::sleep(Duration::from_millis(120)).await;
+            yield Ok(b"data: [DO".to_vec());
+            yield Ok(b"NE]\n\n".to_vec());
+            tokio::time::sleep(Duration::from_millis(120)).await;
+        };
+
+        let frames: Vec<Vec<u8>> = with_keepalive(body, Some(Duration::from_millis(50)))
+            .map(|frame| frame.unwrap())
+            .collect()
+            .await;
+
+        let keepalives: Vec<usize> = (0..frames.len()).filter(|&i| frames[i] == KEEPALIVE_FRAME).collect();
+        assert!(!keepalives.is_empty());
+        // Only between the completed event and the next one
+        let completed = frames.iter().position(|f| f == b"\n").unwrap();
+        let next_event = frames.iter().position(|f| f == b"data: [DO").unwrap();
+        assert!(keepalives.iter().all(|&i| completed < i && i < next_event), "{:?}", frames);
+    }
+
+    #[tokio::test]
+    async fn test_no_interval_passes_through() {
+        let body = futures::stream::iter([Ok::<_, std::io::Error>(b"data: {}\n\n".to_vec())]);
+        let frames: Vec<_> = with_keepalive(body, None).collect().await;
+        assert_eq!(frames.len(), 1);
+    }
+}
@@ -0,0 +1,86 @@
+//! Per-model output token limits
+//!
+//! A model section may set `max_output_tokens`; a client asking for more
+//! (`max_tokens` or `max_completion_tokens`) has the request body lowered to
+//! that limit before it is sent, instead of getting a 400 from the upstream,
+//! and the response carries [`MAX_TOKENS_CLAMPED_HEADER`].
+
+use super::router::ResolvedModel;
+use axum::http::HeaderValue;
+use axum::response::Response;
+use serde_json::Value;
+
+/// Response header naming the limit a request's `max_tokens` was lowered to
+pub const MAX_TOKENS_CLAMPED_HEADER: &str = "x-max-tokens-clamped";
+
+/// Token budget fields a client may send
+const MAX_TOKENS_FIELDS: [&str; 2] = ["max_tokens", "max_completion_tokens"];
+
+/// Lower the token budgets in `request` that exceed `limit`
+///
+/// Returns the limit when any field was lowered to it.
+fn clamp_max_tokens(request: &mut Value, limit: Option<u32>) -> Option<u32> {
+    let limit = limit?;
+    let mut clamped = None;
+    for field in MAX_TOKENS_FIELDS {
+        if let Some(value) = request.get_mut(field) {
+            if value.as_u64().is_some_and(|requested| requested > u64::from(limit)) {
+                *value = Value::from(limit);
+                clamped = Some(limit);
+            }
+        }
+    }
+    clamped
+}
+
+/// Cap the request body's token budget at the `max_output_tokens` of the
+/// model it was resolved to
+///
+/// Returns the limit when the request was lowered to it.
+pub fn clamp_request(request: &mut Value, resolved: &ResolvedModel) -> Option<u32> {
+    let clamped = clamp_max_tokens(request, resolved.max_output_tokens);
+    if let Some(limit) = clamped {
+        tracing::info!("Lowering max_tokens for '{}' to its limit of {}", resolved.model_ref, limit);
+    }
+    clamped
+}
+
+/// Add [`MAX_TOKENS_CLAMPED_HEADER`] to `response` when the request was lowered
+pub fn with_clamp_header(mut response: Response, clamped: Option<u32>) -> Response {
+    if let Some(limit) = clamped {
+        response
+            .headers_mut()
+            .insert(MAX_TOKENS_CLAMPED_HEADER, HeaderValue::from(limit));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_over_limit_request_is_lowered() {
+        let mut request = json!({"model": "m", "max_tokens": 100000});
+        assert_eq!(clamp_max_tokens(&mut request, Some(8192)), Some(8192));
+        assert_eq!(request["max_tokens"], 8192);
+
+        let mut request = json!({"model": "m", "max_completion_tokens": 512, "max_tokens": 100000});
+        assert_eq!(clamp_max_tokens(&mut request, Some(8192)), Some(8192));
+        assert_eq!(request["max_completion_tokens"], 512);
+        assert_eq!(request["max_tokens"], 8192);
+
+        let mut request = json!({"model": "m", "max_tokens": 512});
+        assert_eq!(clamp_max_tokens(&mut request, Some(8192)), None);
+        assert_eq!(request["max_tokens"], 512);
+
+        let mut request = json!({"model": "m"});
+        assert_eq!(clamp_max_tokens(&mut request, Some(8192)), None);
+        assert_eq!(request, json!({"model": "m"}));
+
+        let mut request = json!({"model": "m", "max_tokens": 100000});
+        assert_eq!(clamp_max_tokens(&mut request, None), None);
+        assert_eq!(request["max_tokens"], 100000);
+    }
+}
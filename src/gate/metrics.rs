@@ -0,0 +1,252 @@
+//! Prometheus metrics for gateway traffic
+//!
+//! Chat handlers tag their response with a [`MetricsModel`] extension; the
+//! [`metrics_middleware`] picks it up to count the request and time it, so
+//! every return path is covered without touching each one. Token counters
+//! are fed from the same places that update the [`UsageTracker`](super::usage::UsageTracker).
+//!
+//! Series are labelled with the resolved model reference, never the string
+//! the client sent, so arbitrary model names cannot grow the registry;
+//! requests whose model does not resolve share the [`UNKNOWN_MODEL`] label.
+
+use super::handlers::GatewayState;
+use crate::Usage;
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Upper bounds (seconds) of the request duration histogram buckets
+const DURATION_BUCKETS: [f64; 11] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+/// Label for chat requests whose model could not be resolved
+pub const UNKNOWN_MODEL: &str = "unknown";
+
+/// Model a chat handler served, attached to its response for labelling
+#[derive(Debug, Clone)]
+pub struct MetricsModel(pub String);
+
+impl Default for MetricsModel {
+    fn default() -> Self {
+        Self(UNKNOWN_MODEL.to_string())
+    }
+}
+
+impl MetricsModel {
+    /// Label for a request served by the resolved `model_ref`
+    pub fn resolved(model_ref: &str) -> Self {
+        Self(model_ref.to_string())
+    }
+
+    /// Attach the label to a handler's response
+    pub fn attach(label: Self, response: impl IntoResponse) -> Response {
+        let mut response = response.into_response();
+        response.extensions_mut().insert(label);
+        response
+    }
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [u64; DURATION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    requests: BTreeMap<(String, u16), u64>,
+    durations: BTreeMap<String, Histogram>,
+    prompt_tokens: BTreeMap<String, u64>,
+    completion_tokens: BTreeMap<String, u64>,
+}
+
+/// Per-model request, latency and token counters
+#[derive(Debug, Default)]
+pub struct Metrics {
+    registry: Mutex<Registry>,
+}
+
+impl Metrics {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count one finished request and observe its duration
+    pub fn record_request(&self, model: &str, status: StatusCode, duration: Duration) {
+        let mut registry = self.registry.lock().unwrap();
+        *registry
+            .requests
+            .entry((model.to_string(), status.as_u16()))
+            .or_default() += 1;
+        registry
+            .durations
+            .entry(model.to_string())
+            .or_default()
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Add one request's token usage
+    pub fn record_tokens(&self, model: &str, usage: &Usage) {
+        let mut registry = self.registry.lock().unwrap();
+        *registry.prompt_tokens.entry(model.to_string()).or_default() += usage.prompt_tokens as u64;
+        *registry
+            .completion_tokens
+            .entry(model.to_string())
+            .or_default() += usage.completion_tokens as u64;
+    }
+
+    /// Render all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let registry = self.registry.lock().unwrap();
+        let mut out = String::new();
+
+        header(&mut out, "emx_gate_requests_total", "counter", "Chat requests served, by model and HTTP status");
+        for ((model, status), count) in &registry.requests {
+            let _ = writeln!(
+                out,
+                "emx_gate_requests_total{{model=\"{}\",status=\"{}\"}} {}",
+                escape(model),
+                status,
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "emx_gate_request_duration_seconds",
+            "histogram",
+            "Time until the response headers were sent",
+        );
+        for (model, histogram) in &registry.durations {
+            let model = escape(model);
+            for (bound, count) in DURATION_BUCKETS.iter().zip(histogram.buckets) {
+                let _ = writeln!(
+                    out,
+                    "emx_gate_request_duration_seconds_bucket{{model=\"{}\",le=\"{}\"}} {}",
+                    model, bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "emx_gate_request_duration_seconds_bucket{{model=\"{}\",le=\"+Inf\"}} {}",
+                model, histogram.count
+            );
+            let _ = writeln!(out, "emx_gate_request_duration_seconds_sum{{model=\"{}\"}} {}", model, histogram.sum);
+            let _ = writeln!(out, "emx_gate_request_duration_seconds_count{{model=\"{}\"}} {}", model, histogram.count);
+        }
+
+        for (name, help, counters) in [
+            ("emx_gate_prompt_tokens_total", "Prompt tokens reported by upstreams", &registry.prompt_tokens),
+            (
+                "emx_gate_completion_tokens_total",
+                "Completion tokens reported by upstreams",
+                &registry.completion_tokens,
+            ),
+        ] {
+            header(&mut out, name, "counter", help);
+            for (model, count) in counters {
+                let _ = writeln!(out, "{}{{model=\"{}\"}} {}", name, escape(model), count);
+            }
+        }
+
+        header(
+            &mut out,
+            "emx_gate_upstream_attempts_total",
+            "counter",
+            "HTTP attempts made to upstreams for chat requests, retries included",
+        );
+        let _ = writeln!(out, "emx_gate_upstream_attempts_total {}", crate::upstream_attempts());
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escape a label value per the exposition format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Count and time requests whose handler attached a [`MetricsModel`]
+pub async fn metrics_middleware(State(metrics): State<Arc<Metrics>>, req: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let response = next.run(req).await;
+    if let Some(MetricsModel(model)) = response.extensions().get::<MetricsModel>() {
+        metrics.record_request(model, response.status(), start.elapsed());
+    }
+    response
+}
+
+/// `GET /metrics` in Prometheus text format
+pub async fn metrics_handler(State(state): State<GatewayState>) -> Response {
+    match &state.metrics {
+        Some(metrics) => (
+            [("Content-Type", "text/plain; version=0.0.4")],
+            metrics.render(),
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_counts_requests_and_tokens_per_model() {
+        let metrics = Metrics::new();
+        metrics.record_request("gpt-test", StatusCode::OK, Duration::from_millis(80));
+        metrics.record_request("gpt-test", StatusCode::OK, Duration::from_secs(3));
+        metrics.record_request("gpt-test", StatusCode::INTERNAL_SERVER_ERROR, Duration::from_millis(10));
+        metrics.record_tokens(
+            "gpt-test",
+            &Usage { prompt_tokens: 3, completion_tokens: 2, total_tokens: 5, ..Default::default() },
+        );
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE emx_gate_requests_total counter"));
+        assert!(text.contains(r#"emx_gate_requests_total{model="gpt-test",status="200"} 2"#));
+        assert!(text.contains(r#"emx_gate_requests_total{model="gpt-test",status="500"} 1"#));
+        assert!(text.contains(r#"emx_gate_request_duration_seconds_bucket{model="gpt-test",le="0.1"} 2"#));
+        assert!(text.contains(r#"emx_gate_request_duration_seconds_bucket{model="gpt-test",le="+Inf"} 3"#));
+        assert!(text.contains(r#"emx_gate_request_duration_seconds_count{model="gpt-test"} 3"#));
+        assert!(text.contains(r#"emx_gate_prompt_tokens_total{model="gpt-test"} 3"#));
+        assert!(text.contains(r#"emx_gate_completion_tokens_total{model="gpt-test"} 2"#));
+        assert!(text.contains("# TYPE emx_gate_upstream_attempts_total counter"));
+    }
+
+    #[test]
+    fn test_label_values_are_escaped() {
+        let metrics = Metrics::new();
+        metrics.record_request("a\"b\\c", StatusCode::OK, Duration::ZERO);
+        assert!(metrics.render().contains(r#"model="a\"b\\c""#));
+    }
+}
@@ -4,12 +4,28 @@
 
 pub mod anthropic_handlers;
 pub mod anthropic_handlers_v2;
+pub mod anthropic_sse;
+pub mod audit;
+pub mod coalesce;
 pub mod config;
+pub mod disconnect;
+pub mod fallback;
+pub mod frame_filter;
 pub mod handlers;
+pub mod idempotency;
+pub mod keepalive;
+pub mod limits;
+pub mod metrics;
 pub mod openai_handlers;
 pub mod openai_handlers_v2;
+pub mod openai_sse;
 pub mod provider_handlers;
+pub mod proxy;
+pub mod request;
+pub mod response_headers;
 pub mod router;
 pub mod server;
+pub mod transform;
+pub mod usage;
 
 pub use config::GatewayConfig;
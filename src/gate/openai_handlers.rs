@@ -1,9 +1,8 @@
 //! OpenAI-compatible handlers
 
-use crate::gate::handlers::GatewayState;
-use crate::gate::router::resolve_model_for_provider;
+use crate::gate::handlers::{client_for, stream_flag, uuid_simple, GatewayState};
 use crate::message::Message;
-use crate::{create_client_for_model, ProviderType, ToolDefinition};
+use crate::{ChatResponse, ProviderType, ToolDefinition};
 use axum::{
     extract::State,
     http::StatusCode,
@@ -15,23 +14,12 @@ use serde_json::json;
 use serde_json::Value;
 use tracing::{error, info};
 
-fn uuid_simple() -> String {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    let duration = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default();
-    format!("{:x}{:x}", duration.as_secs(), duration.subsec_nanos())
-}
-
 /// Handle OpenAI chat completions (streaming and non-streaming)
 pub async fn chat_handler(
-    State(_state): State<GatewayState>,
+    State(state): State<GatewayState>,
     Json(request): Json<Value>,
 ) -> Result<Sse<impl futures::Stream<Item = Result<Event, std::io::Error>>>, StatusCode> {
-    let stream = request
-        .get("stream")
-        .and_then(|s| s.as_bool())
-        .unwrap_or(false);
+    let stream = stream_flag(request.get("stream"));
 
     let model = match request.get("model").and_then(|m| m.as_str()) {
         Some(m) => m,
@@ -41,14 +29,12 @@ pub async fn chat_handler(
     info!("OpenAI chat request for model: {} (stream: {})", model, stream);
 
     // For OpenAI endpoint, always use OpenAI provider type
-    let resolved = resolve_model_for_provider(model, ProviderType::OpenAI)
+    let resolved = state.resolver.resolve(model, ProviderType::OpenAI)
         .map_err(|e| {
             error!("Failed to resolve model '{}': {}", model, e);
             StatusCode::NOT_FOUND
         })?;
 
-    let model_ref = resolved.model_ref;
-
     let messages_value = request
         .get("messages")
         .ok_or(StatusCode::BAD_REQUEST)?;
@@ -65,7 +51,7 @@ pub async fn chat_handler(
         .and_then(|t| serde_json::from_value(t.clone()).ok());
     let tools_ref = tools.as_deref();
 
-    match create_client_for_model(&model_ref) {
+    match client_for(&resolved) {
         Ok((client, model_id)) => {
             if stream {
                 // Streaming
@@ -122,7 +108,7 @@ pub async fn chat_handler(
             } else {
                 // Non-streaming
                 match client.chat(&messages, &model_id, tools_ref).await {
-                    Ok((content, tool_calls, usage)) => {
+                    Ok(ChatResponse { content, tool_calls, usage, .. }) => {
                         let finish_reason = if tool_calls.is_some() { "tool_calls" } else { "stop" };
                         let mut message_json = json!({
                             "role": "assistant",
@@ -158,6 +144,10 @@ pub async fn chat_handler(
                 }
             }
         }
+        Err(e) if !state.allow_mock_responses => {
+            info!("Model '{}' not configured: {}", model, e);
+            Err(StatusCode::NOT_FOUND)
+        }
         Err(e) => {
             info!("Model '{}' not configured, returning mock: {}", model, e);
             let id = format!("chatcmpl-{}", uuid_simple());
@@ -1,76 +1,196 @@
 //! OpenAI-compatible handlers with raw passthrough support
 
-use crate::gate::handlers::GatewayState;
-use crate::gate::router::resolve_model_for_provider;
-use crate::message::Message;
-use crate::{create_client_for_model, ProviderType, ToolDefinition};
+use crate::gate::audit::AuditRecord;
+use crate::gate::coalesce::coalesce;
+use crate::gate::disconnect::DisconnectGuard;
+use crate::gate::fallback::{with_fallbacks, FALLBACK_MODEL_HEADER};
+use crate::gate::frame_filter::FrameFilter;
+use crate::gate::handlers::{
+    client_for, model_not_found, openai_error, stream_flag, unsupported_feature, upstream_error_response,
+    uuid_simple, GatewayState, UPSTREAM_LATENCY_HEADER,
+};
+use crate::gate::idempotency::{idempotency_key, replay, Lookup};
+use crate::gate::keepalive::with_keepalive;
+use crate::gate::limits::{clamp_request, with_clamp_header};
+use crate::gate::metrics::MetricsModel;
+use crate::gate::openai_sse::OpenAISseEncoder;
+use crate::gate::request::OpenAIChatRequest;
+use crate::gate::usage::{completion_from_body, usage_from_body, SseUsageScanner};
+use crate::message::validate_for;
+use crate::{ProviderType, StreamEvent};
 use axum::{
     body::Body,
     extract::State,
-    http::StatusCode,
-    response::Response,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
-use futures::stream::StreamExt;
+use futures::stream::{Stream, StreamExt};
 use serde_json::json;
 use serde_json::Value;
+use std::time::Instant;
 use tracing::{error, info};
 
 /// Handle OpenAI chat completions with raw HTTP passthrough
 /// This forwards the upstream response without parsing/rewriting, preserving all fields
 pub async fn chat_handler_passthrough(
-    State(_state): State<GatewayState>,
+    State(state): State<GatewayState>,
+    headers: HeaderMap,
     Json(request): Json<Value>,
+) -> Response {
+    let mut label = MetricsModel::default();
+    let response = chat_passthrough(state, headers, request, &mut label).await;
+    MetricsModel::attach(label, response)
+}
+
+async fn chat_passthrough(
+    state: GatewayState,
+    headers: HeaderMap,
+    mut request: Value,
+    label: &mut MetricsModel,
 ) -> Result<Response, StatusCode> {
-    let stream = request
-        .get("stream")
-        .and_then(|s| s.as_bool())
-        .unwrap_or(false);
-
-    let model = match request.get("model").and_then(|m| m.as_str()) {
-        Some(m) => m,
-        None => return Err(StatusCode::BAD_REQUEST),
+    state.transform_request(&mut request, ProviderType::OpenAI);
+
+    let Some(model) = request.get("model").and_then(Value::as_str).map(str::to_string) else {
+        return Ok(openai_error(StatusCode::BAD_REQUEST, "Invalid request: missing field `model`").into_response());
     };
+    let model = model.as_str();
+    let stream = stream_flag(request.get("stream"));
 
-    info!("OpenAI chat request for model: {} (stream: {})", model, stream);
+    // Retries of a non-streaming request reuse the first successful response
+    let cache_key = if stream { None } else { idempotency_key("openai", &headers, &request) };
+    let reservation = match cache_key.map(|key| state.idempotency.begin(key)) {
+        None => None,
+        Some(Lookup::Proceed(reservation)) => Some(reservation),
+        Some(Lookup::Replay(body)) => {
+            info!("Replaying cached response for idempotency key");
+            return Ok(replay(body));
+        }
+        Some(Lookup::Reject(status, message)) => return Ok(openai_error(status, message).into_response()),
+    };
 
-    let resolved = resolve_model_for_provider(model, ProviderType::OpenAI).map_err(|e| {
-        error!("Failed to resolve model '{}': {}", model, e);
-        StatusCode::NOT_FOUND
-    })?;
+    info!("OpenAI chat request for model: {} (stream: {})", model, stream);
 
-    let model_ref = resolved.model_ref;
+    let resolved = match state.resolver.resolve(model, ProviderType::OpenAI) {
+        Ok(resolved) => resolved,
+        Err(e) if !state.allow_mock_responses => {
+            info!("Model '{}' not configured: {}", model, e);
+            return Ok(model_not_found(model, ProviderType::OpenAI));
+        }
+        Err(e) => {
+            info!("Model '{}' not configured, returning mock: {}", model, e);
+            return Ok(mock_response(model));
+        }
+    };
+    if resolved.provider_type != ProviderType::OpenAI {
+        let message = format!("Model '{}' is not served by an OpenAI provider", model);
+        return Ok(openai_error(StatusCode::BAD_REQUEST, &message).into_response());
+    }
+    let model_ref = resolved.model_ref.as_str();
+    *label = MetricsModel::resolved(model_ref);
+    let clamped = clamp_request(&mut request, &resolved);
 
-    let messages_value = request.get("messages").ok_or(StatusCode::BAD_REQUEST)?;
+    let inbound: OpenAIChatRequest = match serde_json::from_value(request.clone()) {
+        Ok(inbound) => inbound,
+        Err(e) => {
+            error!("Failed to parse request: {}", e);
+            return Ok(openai_error(StatusCode::BAD_REQUEST, &format!("Invalid request: {}", e)).into_response());
+        }
+    };
+    let mut options = inbound.options();
+    // Ask the stream for usage on the client's behalf when usage is teed and
+    // the client did not set `stream_options`; the extra usage chunk is
+    // dropped again before the client sees it
+    let injected_usage = stream && state.tee_stream_usage && !inbound.extra.contains_key("stream_options");
+    if injected_usage {
+        options
+            .extra_body
+            .get_or_insert_with(Default::default)
+            .insert("stream_options".to_string(), json!({"include_usage": true}));
+    }
+    let tools = inbound.tools();
+    let tools_ref = tools.as_deref();
+    let OpenAIChatRequest { mut messages, .. } = inbound;
 
-    let messages: Vec<Message> = serde_json::from_value(messages_value.clone()).map_err(|e| {
-        error!("Failed to parse messages: {}", e);
-        StatusCode::BAD_REQUEST
-    })?;
+    if let Some(system_prompt) = &state.system_prompt {
+        system_prompt.apply(&mut messages);
+    }
+    // Reject locally what the upstream would answer with a 400 anyway
+    if let Err(e) = validate_for(&messages, ProviderType::OpenAI) {
+        return Ok(openai_error(StatusCode::BAD_REQUEST, &e.to_string()).into_response());
+    }
+    let audit = AuditRecord::start(&state, &headers, model, &messages);
 
-    // Extract tools from request if present
-    let tools: Option<Vec<ToolDefinition>> = request
-        .get("tools")
-        .and_then(|t| serde_json::from_value(t.clone()).ok());
-    let tools_ref = tools.as_deref();
+    let created = client_for(&resolved);
+    if let Ok((client, _)) = &created {
+        if let Some(feature) = unsupported_feature(&client.capabilities(), &request) {
+            let message = format!("Model '{}' does not support {}", model, feature);
+            return Ok(openai_error(StatusCode::BAD_REQUEST, &message).into_response());
+        }
+    }
 
-    match create_client_for_model(&model_ref) {
+    let response = match created {
         Ok((client, model_id)) => {
             if stream {
                 // Streaming with raw passthrough
-                match client.chat_stream_raw(&messages, &model_id, tools_ref).await {
+                match client.chat_stream_raw_with_options(&messages, &model_id, tools_ref, &options).await {
+                    Ok(upstream_response) if state.coalesce_stream.is_some() => {
+                        // Merging deltas needs the parsed events
+                        let upstream_headers = upstream_response.headers().clone();
+                        let events = crate::client::openai_sse_events(upstream_response.bytes_stream());
+                        encoded_stream_response(&state, events, model, model_ref, audit)
+                            .map(|response| state.with_upstream_headers(response, &upstream_headers))
+                    }
                     Ok(upstream_response) => {
-                        // Forward the upstream response body stream directly
-                        let upstream_body = upstream_response.bytes_stream();
+                        // Forward the upstream response body stream directly,
+                        // watching for the usage frames as they pass through unless
+                        // `tee_stream_usage` is off
+                        let upstream_headers = upstream_response.headers().clone();
+                        let mut upstream_body = Box::pin(upstream_response.bytes_stream());
+                        let recorder = state.clone();
+                        let usage_model = model.to_string();
+                        let metrics_model = model_ref.to_string();
+                        let mut frames = (state.strip_ping_events || injected_usage)
+                            .then(|| FrameFilter::new(state.strip_ping_events, injected_usage));
+                        let mut scanner = state.tee_stream_usage.then(SseUsageScanner::new);
 
                         // Create a properly typed stream for Axum
-                        let body_stream = upstream_body.map(|result| {
-                            result
-                                .map(|bytes| bytes.to_vec())
-                                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
-                        });
+                        let body_stream = async_stream::stream! {
+                            let mut guard = DisconnectGuard::new(usage_model.as_str());
+                            while let Some(result) = upstream_body.next().await {
+                                match result {
+                                    Ok(bytes) => {
+                                        if let Some(scanner) = &mut scanner {
+                                            scanner.observe(&bytes);
+                                        }
+                                        match &mut frames {
+                                            Some(filter) => {
+                                                let frames = filter.filter(&bytes);
+                                                if !frames.is_empty() {
+                                                    yield Ok(frames);
+                                                }
+                                            }
+                                            None => yield Ok(bytes.to_vec()),
+                                        }
+                                    }
+                                    Err(e) => {
+                                        yield Err(std::io::Error::new(std::io::ErrorKind::Other, e));
+                                        break;
+                                    }
+                                }
+                            }
+                            if let Some(rest) = frames.take().map(FrameFilter::finish).filter(|rest| !rest.is_empty()) {
+                                yield Ok(rest);
+                            }
+                            let usage = scanner.as_ref().and_then(SseUsageScanner::finish);
+                            if let Some(usage) = &usage {
+                                recorder.record_usage(&metrics_model, usage);
+                            }
+                            audit.complete(usage.as_ref(), scanner.as_ref().map(SseUsageScanner::text));
+                            guard.finish();
+                        };
 
-                        let body = Body::from_stream(body_stream);
+                        let body = Body::from_stream(with_keepalive(body_stream, state.sse_keepalive));
 
                         // Build response with SSE headers
                         let response = Response::builder()
@@ -85,62 +205,153 @@ pub async fn chat_handler_passthrough(
                                 StatusCode::INTERNAL_SERVER_ERROR
                             })?;
 
-                        Ok(response)
+                        Ok(state.with_upstream_headers(response, &upstream_headers))
                     }
                     Err(e) => {
                         error!("Upstream stream request failed: {}", e);
-                        let json = json!({"error": {"message": e.to_string(), "type": "api_error"}});
-                        Ok(Response::builder()
-                            .status(500)
-                            .header("Content-Type", "application/json")
-                            .body(Body::from(json.to_string()))
-                            .unwrap())
+                        Ok(upstream_error_response(&e, openai_api_error))
                     }
                 }
             } else {
                 // Non-streaming with raw passthrough
-                match client.chat_raw(&messages, &model_id, tools_ref).await {
+                let started = Instant::now();
+                let (result, fallback) = match client.chat_raw_with_options(&messages, &model_id, tools_ref, &options).await {
+                    Err(e) => {
+                        let messages = &messages;
+                        let options = &options;
+                        with_fallbacks(&state, &[model, model_ref], ProviderType::OpenAI, e, |client, model_id| async move {
+                            client.chat_raw_with_options(messages, &model_id, tools_ref, options).await
+                        })
+                        .await
+                    }
+                    ok => (ok, None),
+                };
+                match result {
                     Ok(upstream_response) => {
+                        let upstream_headers = upstream_response.headers().clone();
                         // Get the response body bytes
                         let body_bytes = upstream_response.bytes().await.map_err(|e| {
                             error!("Failed to read upstream response body: {}", e);
                             StatusCode::BAD_GATEWAY
                         })?;
+                        let latency = started.elapsed();
+
+                        let usage = usage_from_body(&body_bytes);
+                        if let Some(usage) = &usage {
+                            state.record_usage(model_ref, usage);
+                        }
+                        audit.complete(usage.as_ref(), completion_from_body(&body_bytes).as_deref());
+                        if let Some(reservation) = reservation {
+                            reservation.complete(body_bytes.clone());
+                        }
 
                         // Forward the raw response body
-                        Ok(Response::builder()
+                        let mut builder = Response::builder()
                             .status(200)
                             .header("Content-Type", "application/json")
-                            .body(Body::from(body_bytes))
-                            .unwrap())
+                            .header(UPSTREAM_LATENCY_HEADER, latency.as_millis().to_string());
+                        if let Some(fallback) = fallback {
+                            builder = builder.header(FALLBACK_MODEL_HEADER, fallback);
+                        }
+                        let response = builder.body(Body::from(body_bytes)).unwrap();
+                        Ok(state.with_upstream_headers(response, &upstream_headers))
                     }
                     Err(e) => {
                         error!("Upstream request failed: {}", e);
-                        let json = json!({"error": {"message": e.to_string(), "type": "api_error"}});
-                        Ok(Response::builder()
-                            .status(500)
-                            .header("Content-Type", "application/json")
-                            .body(Body::from(json.to_string()))
-                            .unwrap())
+                        Ok(upstream_error_response(&e, openai_api_error))
                     }
                 }
             }
         }
         Err(e) => {
-            info!("Model '{}' not configured, returning mock: {}", model, e);
-            let json = json!({
-                "id": "chatcmpl-mock",
-                "object": "chat.completion",
-                "created": chrono::Utc::now().timestamp(),
-                "model": model,
-                "choices": [{"index": 0, "message": {"role": "assistant", "content": "Mock response"}, "finish_reason": "stop"}],
-                "usage": {"prompt_tokens": 10, "completion_tokens": 10, "total_tokens": 20}
-            });
-            Ok(Response::builder()
-                .status(200)
-                .header("Content-Type", "application/json")
-                .body(Body::from(json.to_string()))
-                .unwrap())
+            error!("Failed to create client for '{}': {}", model_ref, e);
+            let message = format!("Failed to create client: {}", e);
+            Ok(openai_error(StatusCode::INTERNAL_SERVER_ERROR, &message).into_response())
         }
-    }
+    };
+    response.map(|response| with_clamp_header(response, clamped))
+}
+
+/// OpenAI-style `api_error` body for failures the upstream did not answer
+fn openai_api_error(message: &str) -> Value {
+    json!({"error": {"message": message, "type": "api_error"}})
+}
+
+/// Canned completion for unconfigured models when `allow_mock_responses` is set
+fn mock_response(model: &str) -> Response {
+    let json = json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion",
+        "created": chrono::Utc::now().timestamp(),
+        "model": model,
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": "Mock response"}, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 10, "completion_tokens": 10, "total_tokens": 20}
+    });
+    Response::builder()
+        .status(200)
+        .header("Content-Type", "application/json")
+        .body(Body::from(json.to_string()))
+        .unwrap()
+}
+
+/// Stream parsed `events` to an OpenAI client as re-encoded SSE, merging
+/// deltas per `coalesce_stream_ms`
+fn encoded_stream_response<S>(
+    state: &GatewayState,
+    events: S,
+    model: &str,
+    model_ref: &str,
+    audit: AuditRecord,
+) -> Result<Response, StatusCode>
+where
+    S: Stream<Item = crate::Result<StreamEvent>> + Send + 'static,
+{
+    let mut events = Box::pin(coalesce(events, state.coalesce_stream));
+    let mut encoder = OpenAISseEncoder::new(format!("chatcmpl-{}", uuid_simple()), model);
+    let recorder = state.clone();
+    let usage_model = model.to_string();
+    let metrics_model = model_ref.to_string();
+
+    let body_stream = async_stream::stream! {
+        let mut guard = DisconnectGuard::new(usage_model.as_str());
+        let mut completion = String::new();
+        while let Some(result) = events.next().await {
+            match result {
+                Ok(event) => {
+                    completion.push_str(&event.delta);
+                    if let Some(usage) = event.usage.as_ref().filter(|_| event.done) {
+                        recorder.record_usage(&metrics_model, usage);
+                    }
+                    if event.done {
+                        audit.complete(event.usage.as_ref(), Some(&completion));
+                    }
+                    yield Ok::<Vec<u8>, std::io::Error>(encoder.encode(&event).into_bytes());
+                    if encoder.is_finished() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!("Upstream stream failed: {}", e);
+                    yield Ok(encoder.error(&e.to_string()).into_bytes());
+                    break;
+                }
+            }
+        }
+        if !encoder.is_finished() {
+            yield Ok(encoder.error("upstream stream ended without completion").into_bytes());
+        }
+        guard.finish();
+    };
+
+    Response::builder()
+        .status(200)
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .header("Connection", "keep-alive")
+        .header("X-Accel-Buffering", "no")
+        .body(Body::from_stream(with_keepalive(body_stream, state.sse_keepalive)))
+        .map_err(|e| {
+            error!("Failed to build response: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
 }
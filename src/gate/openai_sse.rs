@@ -0,0 +1,176 @@
+//! Re-encode parsed stream events as OpenAI SSE frames
+//!
+//! The OpenAI passthrough normally forwards upstream bytes untouched; when
+//! the gateway rewrites the stream (see `coalesce_stream_ms`) the upstream is
+//! parsed into [`StreamEvent`]s and each one is turned back into a
+//! `chat.completion.chunk` frame, ending with `data: [DONE]`.
+
+use crate::{FinishReason, StreamEvent};
+use serde_json::{json, Value};
+
+/// Stateful encoder for one OpenAI chat completion stream
+pub struct OpenAISseEncoder {
+    id: String,
+    model: String,
+    created: i64,
+    finished: bool,
+}
+
+impl OpenAISseEncoder {
+    pub fn new(id: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            model: model.into(),
+            created: chrono::Utc::now().timestamp(),
+            finished: false,
+        }
+    }
+
+    /// Frames for one upstream event; the final event ends the stream
+    pub fn encode(&mut self, event: &StreamEvent) -> String {
+        let mut out = String::new();
+        if self.finished {
+            return out;
+        }
+
+        let mut delta = serde_json::Map::new();
+        if !event.delta.is_empty() {
+            delta.insert("content".to_string(), json!(event.delta));
+        }
+        if let Some(reasoning) = event.reasoning.as_deref().filter(|r| !r.is_empty()) {
+            delta.insert("reasoning_content".to_string(), json!(reasoning));
+        }
+        if !delta.is_empty() {
+            out.push_str(&self.chunk(json!([{"index": 0, "delta": delta, "finish_reason": null}])));
+        }
+
+        if !event.done {
+            return out;
+        }
+
+        let tool_calls = event.tool_calls.as_deref().unwrap_or_default();
+        let mut delta = json!({});
+        if !tool_calls.is_empty() {
+            delta["tool_calls"] = tool_calls
+                .iter()
+                .enumerate()
+                .map(|(index, call)| {
+                    json!({
+                        "index": index,
+                        "id": call.id,
+                        "type": "function",
+                        "function": {"name": call.name, "arguments": call.arguments}
+                    })
+                })
+                .collect();
+        }
+        let finish_reason = match &event.finish_reason {
+            Some(FinishReason::Stop) => "stop",
+            Some(FinishReason::Length) => "length",
+            Some(FinishReason::ToolCalls) => "tool_calls",
+            Some(FinishReason::ContentFilter) => "content_filter",
+            Some(FinishReason::Other(raw)) => raw.as_str(),
+            None if tool_calls.is_empty() => "stop",
+            None => "tool_calls",
+        };
+        out.push_str(&self.chunk(json!([{"index": 0, "delta": delta, "finish_reason": finish_reason}])));
+
+        if let Some(usage) = &event.usage {
+            let mut chunk = self.chunk_value(json!([]));
+            chunk["usage"] = json!({
+                "prompt_tokens": usage.prompt_tokens,
+                "completion_tokens": usage.completion_tokens,
+                "total_tokens": usage.total_tokens
+            });
+            out.push_str(&data(&chunk.to_string()));
+        }
+        out.push_str(&data("[DONE]"));
+        self.finished = true;
+        out
+    }
+
+    /// Whether the closing `data: [DONE]` has been emitted
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// In-band error frame for failures after the stream has started
+    pub fn error(&mut self, message: &str) -> String {
+        self.finished = true;
+        let error = json!({"error": {"message": message, "type": "api_error"}});
+        format!("{}{}", data(&error.to_string()), data("[DONE]"))
+    }
+
+    fn chunk(&self, choices: Value) -> String {
+        data(&self.chunk_value(choices).to_string())
+    }
+
+    fn chunk_value(&self, choices: Value) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": choices
+        })
+    }
+}
+
+fn data(payload: &str) -> String {
+    format!("data: {}\n\n", payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ToolCall, Usage};
+
+    fn event(delta: &str, done: bool) -> StreamEvent {
+        StreamEvent {
+            delta: delta.to_string(),
+            done,
+            usage: None,
+            tool_calls: None,
+            reasoning: None,
+            finish_reason: None,
+        }
+    }
+
+    fn payloads(sse: &str) -> Vec<&str> {
+        sse.lines().filter_map(|l| l.strip_prefix("data: ")).collect()
+    }
+
+    #[test]
+    fn test_text_stream_chunks() {
+        let mut encoder = OpenAISseEncoder::new("chatcmpl-1", "gpt-test");
+        let mut sse = encoder.encode(&event("Hello", false));
+        let mut last = event("", true);
+        last.usage = Some(Usage { prompt_tokens: 5, completion_tokens: 2, total_tokens: 7, ..Default::default() });
+        sse.push_str(&encoder.encode(&last));
+
+        let payloads = payloads(&sse);
+        assert_eq!(payloads.len(), 4);
+        assert!(payloads[0].contains(r#""content":"Hello""#));
+        assert!(payloads[1].contains(r#""finish_reason":"stop""#));
+        assert!(payloads[2].contains(r#""total_tokens":7"#));
+        assert_eq!(payloads[3], "[DONE]");
+        assert!(encoder.is_finished());
+        assert!(encoder.encode(&event("late", true)).is_empty());
+    }
+
+    #[test]
+    fn test_tool_calls_in_final_chunk() {
+        let mut encoder = OpenAISseEncoder::new("chatcmpl-1", "gpt-test");
+        let mut last = event("", true);
+        last.tool_calls = Some(vec![ToolCall {
+            id: "call_1".to_string(),
+            name: "glob".to_string(),
+            arguments: r#"{"pattern":"*.rs"}"#.to_string(),
+        }]);
+        let sse = encoder.encode(&last);
+
+        assert!(sse.contains(r#""id":"call_1""#));
+        assert!(sse.contains(r#""finish_reason":"tool_calls""#));
+        assert!(sse.ends_with("data: [DONE]\n\n"));
+    }
+}
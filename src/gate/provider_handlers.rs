@@ -1,11 +1,50 @@
 //! Provider-specific handlers
 
 use crate::gate::handlers::GatewayState;
-use crate::{ProviderConfig, ProviderType};
-use axum::{extract::State, Json};
+use crate::{Capabilities, ModelConfig, ProviderConfig, ProviderType};
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::Deserialize;
 use serde_json::json;
 use serde_json::Value;
 
+/// Filters accepted by the model list endpoints, e.g.
+/// `?capability=vision&provider=openai`
+#[derive(Debug, Default, Deserialize)]
+pub struct ModelsQuery {
+    /// Only models with this [`Capabilities`] field set (`streaming`,
+    /// `tools`, `vision`, `embeddings`, `json_mode`); an unknown name
+    /// matches nothing
+    pub capability: Option<String>,
+
+    /// Only models of this provider type (`openai`, `anthropic`)
+    pub provider: Option<String>,
+}
+
+impl ModelsQuery {
+    /// Whether the model configured at `model_ref` passes every filter
+    fn matches(&self, model_ref: &str, config: &ModelConfig) -> bool {
+        if let Some(provider) = &self.provider {
+            if !config.provider_type.config_key().eq_ignore_ascii_case(provider) {
+                return false;
+            }
+        }
+        if let Some(capability) = &self.capability {
+            let model = config
+                .model
+                .as_deref()
+                .unwrap_or_else(|| model_ref.rsplit('.').next().unwrap_or(model_ref));
+            let capabilities = Capabilities::for_model(config.provider_type, model);
+            if capabilities.get(capability) != Some(true) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
 /// Strip provider type prefix from model_ref
 fn strip_provider_prefix(model_ref: &str, provider_type: ProviderType) -> String {
     let prefix = format!("{}.", provider_type.config_key());
@@ -15,12 +54,14 @@ fn strip_provider_prefix(model_ref: &str, provider_type: ProviderType) -> String
 /// Handle OpenAI models list request
 pub async fn list_openai_models(
     State(_state): State<GatewayState>,
+    Query(query): Query<ModelsQuery>,
 ) -> Json<Value> {
     match ProviderConfig::list_models() {
         Ok(models) => {
             let models_data: Vec<Value> = models
                 .iter()
                 .filter(|(_, config)| config.provider_type == ProviderType::OpenAI)
+                .filter(|(model_ref, config)| query.matches(model_ref, config))
                 .map(|(model_ref, config)| {
                     let id = strip_provider_prefix(model_ref, ProviderType::OpenAI);
                     json!({
@@ -50,12 +91,14 @@ pub async fn list_openai_models(
 /// Handle Anthropic models list request
 pub async fn list_anthropic_models(
     State(_state): State<GatewayState>,
+    Query(query): Query<ModelsQuery>,
 ) -> Json<Value> {
     match ProviderConfig::list_models() {
         Ok(models) => {
             let models_data: Vec<Value> = models
                 .iter()
                 .filter(|(_, config)| config.provider_type == ProviderType::Anthropic)
+                .filter(|(model_ref, config)| query.matches(model_ref, config))
                 .map(|(model_ref, _config)| {
                     let id = strip_provider_prefix(model_ref, ProviderType::Anthropic);
                     json!({
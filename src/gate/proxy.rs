@@ -0,0 +1,183 @@
+//! Generic provider passthrough
+//!
+//! `/proxy/{provider}/{path}` forwards a request to `{api_base}/{path}` of the
+//! configured `openai` or `anthropic` provider with that provider's auth
+//! headers, for endpoints without a dedicated route (embeddings, image
+//! generation, ...). The upstream response is returned as-is. Only paths
+//! allowed by `proxy_allowed_paths` are forwarded.
+//!
+//! Both provider configs, and an HTTP client for each, are loaded once at
+//! startup into [`ProxyUpstreams`].
+
+use crate::client::{anthropic_headers, build_http_client};
+use crate::gate::handlers::{openai_error, GatewayState};
+use crate::{ProviderConfig, ProviderType};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, RawQuery, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use tracing::{error, info, warn};
+
+/// A configured provider and the HTTP client requests to it go through
+struct ProxyUpstream {
+    config: ProviderConfig,
+    client: reqwest::Client,
+}
+
+/// The providers `/proxy` can forward to; `None` when not configured
+pub struct ProxyUpstreams {
+    openai: Option<ProxyUpstream>,
+    anthropic: Option<ProxyUpstream>,
+}
+
+impl ProxyUpstreams {
+    /// Load the `openai` and `anthropic` provider configs and build a client
+    /// for each
+    pub fn load() -> Self {
+        Self {
+            openai: ProxyUpstream::load(ProviderType::OpenAI),
+            anthropic: ProxyUpstream::load(ProviderType::Anthropic),
+        }
+    }
+
+    fn get(&self, provider_type: ProviderType) -> Option<&ProxyUpstream> {
+        match provider_type {
+            ProviderType::OpenAI => self.openai.as_ref(),
+            ProviderType::Anthropic => self.anthropic.as_ref(),
+        }
+    }
+}
+
+impl ProxyUpstream {
+    fn load(provider_type: ProviderType) -> Option<Self> {
+        let config = match ProviderConfig::load_for_provider(provider_type) {
+            Ok(config) => config,
+            Err(e) => {
+                info!("Proxy disabled for {}: {}", provider_type.config_key(), e);
+                return None;
+            }
+        };
+        match build_http_client(&config) {
+            Ok(client) => Some(Self { config, client }),
+            Err(e) => {
+                warn!("Proxy disabled for {}: {}", provider_type.config_key(), e);
+                None
+            }
+        }
+    }
+}
+
+/// Forward a request to an allowed path of a configured provider
+pub async fn proxy_handler(
+    State(state): State<GatewayState>,
+    Path((provider, path)): Path<(String, String)>,
+    RawQuery(query): RawQuery,
+    method: Method,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let provider_type = match provider.as_str() {
+        "openai" => ProviderType::OpenAI,
+        "anthropic" => ProviderType::Anthropic,
+        _ => {
+            let message = format!("Unknown provider '{}'", provider);
+            return openai_error(StatusCode::NOT_FOUND, &message).into_response();
+        }
+    };
+
+    let path = path.trim_matches('/');
+    if !path_allowed(&state.proxy_allowed_paths, path) {
+        let message = format!("Path '{}' is not allowed by proxy_allowed_paths", path);
+        return openai_error(StatusCode::FORBIDDEN, &message).into_response();
+    }
+
+    let Some(upstream) = state.proxy_upstreams.get(provider_type) else {
+        let message = format!("Provider '{}' is not configured", provider);
+        return openai_error(StatusCode::SERVICE_UNAVAILABLE, &message).into_response();
+    };
+
+    let mut url = format!("{}/{}", upstream.config.api_base.trim_end_matches('/'), path);
+    if let Some(query) = query {
+        url.push('?');
+        url.push_str(&query);
+    }
+    info!("Proxying {} /{}/{} to {}", method, provider, path, url);
+
+    match forward(upstream, method, &url, &headers, body).await {
+        Ok(response) => response,
+        Err(e) => {
+            error!("Proxy request to {} failed: {}", url, e);
+            openai_error(StatusCode::BAD_GATEWAY, &format!("Upstream request failed: {}", e)).into_response()
+        }
+    }
+}
+
+/// Send the request upstream and stream its response back unchanged
+async fn forward(
+    upstream: &ProxyUpstream,
+    method: Method,
+    url: &str,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Result<Response, reqwest::Error> {
+    let config = &upstream.config;
+    let mut request = upstream.client.request(method, url);
+
+    if let Some(content_type) = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()) {
+        request = request.header("content-type", content_type);
+    }
+    request = match config.provider_type {
+        ProviderType::OpenAI => {
+            let (name, value) = config.openai_auth_header();
+            request.header(name, value)
+        }
+        // The configured version and betas, as for chat requests
+        ProviderType::Anthropic => anthropic_headers(request, config, false),
+    };
+    if !body.is_empty() {
+        request = request.body(body);
+    }
+
+    let upstream = request.send().await?;
+    let mut response = Response::builder().status(upstream.status().as_u16());
+    if let Some(content_type) = upstream.headers().get(reqwest::header::CONTENT_TYPE) {
+        response = response.header(header::CONTENT_TYPE, content_type.as_bytes());
+    }
+    Ok(response
+        .body(Body::from_stream(upstream.bytes_stream()))
+        .unwrap_or_else(|_| StatusCode::BAD_GATEWAY.into_response()))
+}
+
+/// Whether `path` is an allowed entry or lies below one; `..` segments are
+/// never allowed
+fn path_allowed(allowed: &[String], path: &str) -> bool {
+    if path.split('/').any(|segment| segment == "..") {
+        return false;
+    }
+    allowed.iter().any(|entry| {
+        let entry = entry.trim_matches('/');
+        !entry.is_empty()
+            && (path == entry
+                || path
+                    .strip_prefix(entry)
+                    .is_some_and(|rest| rest.starts_with('/')))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_allowed_matches_entries_and_their_subpaths() {
+        let allowed = vec!["embeddings".to_string(), "/images/".to_string()];
+        assert!(path_allowed(&allowed, "embeddings"));
+        assert!(path_allowed(&allowed, "images/generations"));
+        assert!(!path_allowed(&allowed, "embeddings-v2"));
+        assert!(!path_allowed(&allowed, "chat/completions"));
+        assert!(!path_allowed(&allowed, "images/../files"));
+        assert!(!path_allowed(&[], "embeddings"));
+    }
+}
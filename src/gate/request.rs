@@ -0,0 +1,176 @@
+//! Typed inbound chat requests
+//!
+//! The OpenAI endpoint deserializes client bodies into [`OpenAIChatRequest`]
+//! so sampling parameters reach the upstream through [`ChatOptions`]. Fields
+//! without a typed counterpart are kept in `extra` and sent as
+//! [`ChatOptions::extra_body`]. [`anthropic_options`] does the same for
+//! `/anthropic/v1/messages` bodies.
+
+use super::handlers::stream_flag;
+use crate::{ChatOptions, Message, ToolDefinition};
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+/// Body of a `/openai/v1/chat/completions` request
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpenAIChatRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    /// Also accepts the strings `"true"`/`"false"` some SDKs send
+    #[serde(default, deserialize_with = "deserialize_stream")]
+    pub stream: bool,
+    /// Tool definitions, ignored when they do not parse
+    #[serde(default)]
+    pub tools: Option<Value>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    /// Token budgets, already capped at the model's limit (see
+    /// [`clamp_request`](crate::gate::limits::clamp_request))
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub max_completion_tokens: Option<u32>,
+    #[serde(default)]
+    pub stop: Option<Stop>,
+    #[serde(default)]
+    pub response_format: Option<Value>,
+    #[serde(default)]
+    pub logprobs: Option<bool>,
+    #[serde(default)]
+    pub top_logprobs: Option<u32>,
+    /// Every other field, forwarded unchanged
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+fn deserialize_stream<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    Value::deserialize(deserializer).map(|v| stream_flag(Some(&v)))
+}
+
+/// OpenAI `stop`: one sequence or a list
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Stop {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OpenAIChatRequest {
+    /// Tool definitions, if present and well-formed
+    pub fn tools(&self) -> Option<Vec<ToolDefinition>> {
+        self.tools.clone().and_then(|t| serde_json::from_value(t).ok())
+    }
+
+    /// Per-call options for the upstream request
+    ///
+    /// The token budget prefers `max_completion_tokens` over `max_tokens`.
+    pub fn options(&self) -> ChatOptions {
+        ChatOptions {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_tokens: self.max_completion_tokens.or(self.max_tokens),
+            response_format: self.response_format.clone(),
+            stop: match &self.stop {
+                Some(Stop::One(stop)) => vec![stop.clone()],
+                Some(Stop::Many(stops)) => stops.clone(),
+                None => Vec::new(),
+            },
+            logprobs: self.logprobs,
+            top_logprobs: self.top_logprobs,
+            extra_body: (!self.extra.is_empty()).then(|| self.extra.clone()),
+            strip_reasoning: None,
+        }
+    }
+}
+
+/// Fields of a `/anthropic/v1/messages` body the handler reads itself
+const ANTHROPIC_HANDLED_FIELDS: [&str; 5] = ["model", "messages", "system", "stream", "tools"];
+
+/// Per-call options for a `/anthropic/v1/messages` request body
+///
+/// Sampling parameters map onto their typed [`ChatOptions`] counterparts
+/// (`stop_sequences` onto `stop`); every field the handler does not read
+/// itself is sent as [`ChatOptions::extra_body`].
+pub fn anthropic_options(request: &Value) -> ChatOptions {
+    let mut options = ChatOptions::default();
+    let mut extra = Map::new();
+    for (name, value) in request.as_object().into_iter().flatten() {
+        match name.as_str() {
+            "temperature" => options.temperature = value.as_f64().map(|v| v as f32),
+            "top_p" => options.top_p = value.as_f64().map(|v| v as f32),
+            "max_tokens" => options.max_tokens = value.as_u64().and_then(|n| u32::try_from(n).ok()),
+            "stop_sequences" => options.stop = serde_json::from_value(value.clone()).unwrap_or_default(),
+            name if ANTHROPIC_HANDLED_FIELDS.contains(&name) => {}
+            _ => {
+                extra.insert(name.clone(), value.clone());
+            }
+        }
+    }
+    options.extra_body = (!extra.is_empty()).then_some(extra);
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parameters_become_options() {
+        let request: OpenAIChatRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "Hi"}],
+            "temperature": 0.3,
+            "max_tokens": 64,
+            "stop": "END",
+            "repetition_penalty": 1.1
+        }))
+        .unwrap();
+
+        let options = request.options();
+        assert_eq!(options.temperature, Some(0.3));
+        assert_eq!(options.max_tokens, Some(64));
+        assert_eq!(options.stop, vec!["END"]);
+        assert_eq!(options.extra_body.unwrap()["repetition_penalty"], json!(1.1));
+        assert!(!request.stream);
+    }
+
+    #[test]
+    fn test_string_stream_flag_is_coerced() {
+        let request: OpenAIChatRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "Hi"}],
+            "stream": "true"
+        }))
+        .unwrap();
+        assert!(request.stream);
+    }
+
+    #[test]
+    fn test_anthropic_parameters_become_options() {
+        let options = anthropic_options(&json!({
+            "model": "claude-3-5-sonnet",
+            "system": "Be brief",
+            "messages": [{"role": "user", "content": "Hi"}],
+            "stream": true,
+            "max_tokens": 256,
+            "temperature": 0.5,
+            "stop_sequences": ["END"],
+            "metadata": {"user_id": "u-1"}
+        }));
+
+        assert_eq!(options.max_tokens, Some(256));
+        assert_eq!(options.temperature, Some(0.5));
+        assert_eq!(options.stop, vec!["END"]);
+        let extra = options.extra_body.unwrap();
+        assert_eq!(extra.len(), 1);
+        assert_eq!(extra["metadata"], json!({"user_id": "u-1"}));
+    }
+
+    #[test]
+    fn test_missing_messages_is_rejected() {
+        assert!(serde_json::from_value::<OpenAIChatRequest>(json!({"model": "gpt-4o"})).is_err());
+    }
+}
@@ -0,0 +1,66 @@
+//! Upstream response headers kept by the passthrough
+//!
+//! The passthrough answers with its own `Content-Type` and SSE headers and
+//! drops whatever else the upstream sent. Headers matching an entry of
+//! `forward_response_headers` — an exact name, or a prefix ending in `*`
+//! such as `anthropic-ratelimit-*` — are copied onto the forwarded response,
+//! so clients can see rate-limit state.
+
+use axum::http::HeaderMap;
+use axum::response::Response;
+
+/// Copy the `upstream` headers matching `allowlist` onto `response`,
+/// leaving the headers the gateway set itself alone
+pub(crate) fn with_upstream_headers(mut response: Response, upstream: &HeaderMap, allowlist: &[String]) -> Response {
+    if allowlist.is_empty() {
+        return response;
+    }
+    let own = response.headers().clone();
+    for (name, value) in upstream {
+        if header_allowed(allowlist, name.as_str()) && !own.contains_key(name) {
+            response.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+    response
+}
+
+fn header_allowed(allowlist: &[String], name: &str) -> bool {
+    allowlist.iter().any(|entry| match entry.strip_suffix('*') {
+        Some(prefix) => name
+            .get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix)),
+        None => name.eq_ignore_ascii_case(entry),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    #[test]
+    fn test_allowed_headers_are_copied() {
+        let mut upstream = HeaderMap::new();
+        upstream.insert("x-ratelimit-remaining", "5".parse().unwrap());
+        upstream.insert("anthropic-ratelimit-tokens-limit", "1000".parse().unwrap());
+        upstream.insert("set-cookie", "session=1".parse().unwrap());
+        upstream.insert("content-type", "text/plain".parse().unwrap());
+
+        let response = Response::builder()
+            .header("Content-Type", "application/json")
+            .body(Body::empty())
+            .unwrap();
+        let allowlist = vec![
+            "X-RateLimit-Remaining".to_string(),
+            "anthropic-ratelimit-*".to_string(),
+            "content-type".to_string(),
+        ];
+        let response = with_upstream_headers(response, &upstream, &allowlist);
+
+        let headers = response.headers();
+        assert_eq!(headers["x-ratelimit-remaining"], "5");
+        assert_eq!(headers["anthropic-ratelimit-tokens-limit"], "1000");
+        assert!(!headers.contains_key("set-cookie"));
+        assert_eq!(headers["content-type"], "application/json");
+    }
+}
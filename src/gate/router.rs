@@ -1,38 +1,90 @@
 //! Router module for resolving model references to provider configurations
 
-use crate::{ProviderConfig, ProviderType};
-use serde::{Deserialize, Serialize};
+use crate::{ModelConfig, ProviderConfig, ProviderType};
 
 /// Resolved model information
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone)]
 pub struct ResolvedModel {
     /// Provider type
     pub provider_type: ProviderType,
 
-    /// Model name
+    /// Model id sent upstream
     pub model_name: String,
 
     /// Full model reference (e.g., "openai.gpt-4")
     pub model_ref: String,
+
+    /// Provider settings the gateway creates the client from
+    pub config: ProviderConfig,
+
+    /// Most output tokens the model supports, if limited
+    pub max_output_tokens: Option<u32>,
 }
 
-/// Resolve a model reference string to provider configuration
-pub fn resolve_model(model: &str, _config: &ProviderConfig) -> Result<ResolvedModel, String> {
-    // Parse model reference
-    let model_ref = parse_model_reference(model)?;
+impl ResolvedModel {
+    /// Resolve `model_ref` through the TOML configuration
+    /// (see [`ProviderConfig::load_for_model`])
+    pub fn load(model_ref: &str) -> Result<Self, String> {
+        let (model_config, model_id) = ProviderConfig::load_for_model(model_ref).map_err(|e| e.to_string())?;
+        Ok(Self::from_model_config(model_ref, model_config, model_id))
+    }
 
-    // Get provider type from model reference
-    let provider_type = model_ref.provider_type;
+    /// Build from a model section loaded for `model_ref`
+    pub fn from_model_config(model_ref: &str, model_config: ModelConfig, model_id: String) -> Self {
+        let max_output_tokens = model_config.max_output_tokens;
+        Self {
+            provider_type: model_config.provider_type,
+            config: model_config.into_provider_config(&model_id),
+            model_name: model_id,
+            model_ref: model_ref.to_string(),
+            max_output_tokens,
+        }
+    }
+}
 
-    Ok(ResolvedModel {
-        provider_type,
-        model_name: model_ref.model_name,
-        model_ref: model.to_string(),
-    })
+/// Source of model resolutions for the provider-specific gateway endpoints
+///
+/// The gateway uses [`ConfigModelResolver`] unless another resolver is passed
+/// to [`start_server_with_resolver`](crate::gate::server::start_server_with_resolver),
+/// e.g. one that looks models up in a database or a remote registry. The
+/// gateway creates the upstream client from the returned
+/// [`ResolvedModel::config`], so the model need not appear in any config file.
+pub trait ModelResolver: Send + Sync {
+    /// Resolve `model` as requested on an endpoint for `provider_type`
+    fn resolve(&self, model: &str, provider_type: ProviderType) -> Result<ResolvedModel, String>;
+}
+
+/// Default [`ModelResolver`] scanning the TOML provider configuration
+/// (see [`resolve_model_for_provider`])
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConfigModelResolver;
+
+impl ModelResolver for ConfigModelResolver {
+    fn resolve(&self, model: &str, provider_type: ProviderType) -> Result<ResolvedModel, String> {
+        resolve_model_for_provider(model, provider_type)
+    }
+}
+
+/// Resolve a provider-qualified model reference to its configuration
+pub fn resolve_model(model: &str, _config: &ProviderConfig) -> Result<ResolvedModel, String> {
+    let qualified = parse_model_reference(model)?;
+    let resolved = ResolvedModel::load(&qualified.full_ref)?;
+    if resolved.provider_type != qualified.provider_type {
+        return Err(format!(
+            "Model '{}' is configured for the {} provider",
+            model,
+            resolved.provider_type.config_key()
+        ));
+    }
+    Ok(resolved)
 }
 
 /// Resolve model for a specific provider type
 /// This is used when the endpoint already indicates the provider (e.g., /openai/... or /anthropic/...)
+///
+/// A reference qualified with another provider (e.g. `openai.gpt-4o` on the
+/// Anthropic endpoint) keeps that provider; the handler translates between
+/// the two wire formats.
 pub fn resolve_model_for_provider(
     model: &str,
     provider_type: ProviderType,
@@ -41,90 +93,59 @@ pub fn resolve_model_for_provider(
 
     // Try to find a matching model in config
     if let Ok(models) = ProviderConfig::list_models() {
-        // Look for a model that ends with the provided model name
-        for (model_ref, model_config) in &models {
-            if model_ref.ends_with(&format!(".{}", model)) || model_ref == model {
-                // Check if it matches our provider type
-                if model_ref.starts_with(&format!("{}.", provider_prefix)) {
-                    return Ok(ResolvedModel {
-                        provider_type,
-                        model_name: model_config
-                            .model
-                            .clone()
-                            .unwrap_or_else(|| model.to_string()),
-                        model_ref: model_ref.clone(),
-                    });
-                }
-            }
+        if let Some((model_ref, _)) = models.iter().find(|(model_ref, _)| model_ref == model) {
+            return ResolvedModel::load(model_ref);
+        }
+        // Look for a model of this provider that ends with the provided model name
+        let suffix = format!(".{}", model);
+        let prefix = format!("{}.", provider_prefix);
+        if let Some((model_ref, _)) = models
+            .iter()
+            .find(|(model_ref, _)| model_ref.ends_with(&suffix) && model_ref.starts_with(&prefix))
+        {
+            return ResolvedModel::load(model_ref);
         }
     }
 
     // Fall back: construct the model_ref
-    let model_name = model.split('.').last().unwrap_or(model).to_string();
-    let full_ref = format!("{}.{}", provider_prefix, model_name);
-    Ok(ResolvedModel {
-        provider_type,
-        model_name,
-        model_ref: full_ref,
-    })
+    let full_ref = match parse_model_reference(model) {
+        Ok(qualified) => qualified.full_ref,
+        Err(_) => format!("{}.{}", provider_prefix, model.split('.').last().unwrap_or(model)),
+    };
+    ResolvedModel::load(&full_ref)
 }
 
-/// Parse model reference string
+/// Parse a provider-qualified model reference
 ///
-/// Supports three formats:
-/// - Short name: "gpt-4"
+/// Uses [`crate::ModelReference::parse`], so the gateway splits references
+/// exactly like config resolution does:
+/// - Short name: "gpt-4" (rejected here; the provider is unknown)
 /// - Qualified name: "openai.gpt-4"
 /// - Fully qualified name: "openai.some_provider.gpt-4"
-fn parse_model_reference(model: &str) -> Result<ModelReference, String> {
-    let parts: Vec<&str> = model.split('.').collect();
-
-    match parts.len() {
-        1 => {
-            // Short name: "gpt-4"
-            // Need to look up in configuration to find provider
-            Err(format!(
+fn parse_model_reference(model: &str) -> Result<QualifiedModel, String> {
+    let parsed = crate::ModelReference::parse(model).map_err(|e| e.to_string())?;
+
+    let Some(provider_type) = parsed.provider() else {
+        return match parsed.full_path.split_once('.') {
+            None => Err(format!(
                 "Ambiguous model reference '{}'. Please use qualified name (e.g., 'openai.{}')",
                 model, model
-            ))
-        }
-        2 => {
-            // Qualified name: "openai.gpt-4"
-            let provider_type = parse_provider_type(parts[0])?;
-
-            Ok(ModelReference {
-                provider_type,
-                model_name: parts[1].to_string(),
-            })
-        }
-        _ => {
-            // Fully qualified name: "openai.some_provider.gpt-4"
-            let provider_type = parse_provider_type(parts[0])?;
-
-            // The model name is the last part
-            let model_name = parts.last().unwrap().to_string();
-
-            Ok(ModelReference {
-                provider_type,
-                model_name,
-            })
-        }
-    }
-}
+            )),
+            Some((provider, _)) => Err(format!("Unknown provider type: {}", provider)),
+        };
+    };
 
-/// Parse provider type from string
-fn parse_provider_type(s: &str) -> Result<ProviderType, String> {
-    match s.to_lowercase().as_str() {
-        "openai" | "glm" => Ok(ProviderType::OpenAI),
-        "anthropic" | "claude" => Ok(ProviderType::Anthropic),
-        _ => Err(format!("Unknown provider type: {}", s)),
-    }
+    Ok(QualifiedModel {
+        provider_type,
+        full_ref: parsed.to_full_ref(),
+    })
 }
 
-/// Internal model reference representation
+/// Model reference with a known provider
 #[derive(Debug, Clone)]
-struct ModelReference {
+struct QualifiedModel {
     pub provider_type: ProviderType,
-    pub model_name: String,
+    pub full_ref: String,
 }
 
 #[cfg(test)]
@@ -137,7 +158,7 @@ mod tests {
         assert!(result.is_ok());
         let model_ref = result.unwrap();
         assert_eq!(model_ref.provider_type, ProviderType::OpenAI);
-        assert_eq!(model_ref.model_name, "gpt-4");
+        assert_eq!(model_ref.full_ref, "openai.gpt-4");
     }
 
     #[test]
@@ -146,7 +167,7 @@ mod tests {
         assert!(result.is_ok());
         let model_ref = result.unwrap();
         assert_eq!(model_ref.provider_type, ProviderType::OpenAI);
-        assert_eq!(model_ref.model_name, "gpt-4");
+        assert_eq!(model_ref.full_ref, "openai.azure.gpt-4");
     }
 
     #[test]
@@ -160,4 +181,45 @@ mod tests {
         let result = parse_model_reference("unknown.gpt-4");
         assert!(result.is_err());
     }
+
+    struct StubResolver;
+
+    impl ModelResolver for StubResolver {
+        fn resolve(&self, model: &str, provider_type: ProviderType) -> Result<ResolvedModel, String> {
+            match model {
+                "house-special" => Ok(ResolvedModel {
+                    provider_type,
+                    model_name: "gpt-4o".to_string(),
+                    model_ref: "openai.gpt-4o".to_string(),
+                    config: ProviderConfig {
+                        provider_type,
+                        model: Some("gpt-4o".to_string()),
+                        ..Default::default()
+                    },
+                    max_output_tokens: None,
+                }),
+                _ => Err(format!("Unknown model: {}", model)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_resolver_maps_made_up_model() {
+        let resolver: std::sync::Arc<dyn ModelResolver> = std::sync::Arc::new(StubResolver);
+
+        let resolved = resolver.resolve("house-special", ProviderType::OpenAI).unwrap();
+        assert_eq!(resolved.model_ref, "openai.gpt-4o");
+        assert_eq!(resolved.model_name, "gpt-4o");
+        assert!(resolver.resolve("gpt-4", ProviderType::OpenAI).is_err());
+    }
+
+    #[test]
+    fn test_parse_agrees_with_config_model_reference() {
+        for input in ["openai.gpt-4", "OpenAI.Azure.GPT-4", "anthropic.glm.glm-5"] {
+            let routed = parse_model_reference(input).unwrap();
+            let parsed = crate::ModelReference::parse(input).unwrap();
+            assert_eq!(Some(routed.provider_type), parsed.provider());
+            assert_eq!(routed.full_ref, parsed.to_full_ref());
+        }
+    }
 }
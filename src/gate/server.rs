@@ -1,28 +1,87 @@
 //! Gateway HTTP server
 
 use crate::gate::anthropic_handlers_v2;
+use crate::gate::audit::AuditLog;
 use crate::gate::config::GatewayConfig;
-use crate::gate::handlers::{self, GatewayState};
+use crate::gate::fallback::FALLBACK_MODEL_HEADER;
+use crate::gate::handlers::{self, GatewayState, UPSTREAM_LATENCY_HEADER};
+use crate::gate::idempotency::{IdempotencyCache, IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAYED_HEADER};
+use crate::gate::limits::MAX_TOKENS_CLAMPED_HEADER;
+use crate::gate::metrics::{self, Metrics};
 use crate::gate::openai_handlers_v2;
 use crate::gate::provider_handlers;
-use crate::load_with_default;
+use crate::gate::proxy::{self, ProxyUpstreams};
+use crate::gate::router::{ConfigModelResolver, ModelResolver};
+use crate::gate::transform::{FieldFilterTransform, RequestTransform};
+use crate::gate::usage::UsageTracker;
+use crate::{load_with_default, FallbackConfig, SystemPromptConfig};
 use crate::ProviderConfig;
 use axum::{
-    extract::Request,
+    extract::{Request, State},
+    http::{header, HeaderName, HeaderValue, Method, StatusCode},
     middleware::{self, Next},
-    response::Response,
-    routing::{get, post},
+    response::{IntoResponse, Response},
+    routing::{any, get, post},
     Router,
 };
+use std::future::{Future, IntoFuture};
 use std::net::SocketAddr;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::signal;
-use tracing::info;
+use tower_http::cors::{AllowOrigin, CorsLayer, ExposeHeaders};
+use tracing::{info, warn};
 use uuid::Uuid;
 
 /// Start the gateway server
 pub async fn start_server(config: GatewayConfig) -> anyhow::Result<()> {
+    start_server_with_transforms(config, Vec::new()).await
+}
+
+/// Start the gateway server with extra request body transforms
+///
+/// `transforms` run after the built-in field filters from
+/// `config.field_filters`, in the given order.
+pub async fn start_server_with_transforms(
+    config: GatewayConfig,
+    transforms: Vec<Box<dyn RequestTransform>>,
+) -> anyhow::Result<()> {
+    start_server_with_resolver(config, transforms, Arc::new(ConfigModelResolver)).await
+}
+
+/// Start the gateway server with extra request body transforms and a custom
+/// model resolver
+///
+/// `resolver` replaces the TOML config lookup for every model the gateway
+/// resolves: on the `/openai` and `/anthropic` endpoints, in the v1 chat
+/// handlers, and for fallbacks. `/v1/providers` lists the configured
+/// providers and resolves no model, so it still reads the TOML config.
+pub async fn start_server_with_resolver(
+    config: GatewayConfig,
+    transforms: Vec<Box<dyn RequestTransform>>,
+    resolver: Arc<dyn ModelResolver>,
+) -> anyhow::Result<()> {
+    serve(config, transforms, resolver, shutdown_signal()).await
+}
+
+/// Start the gateway server, shutting down once `shutdown` completes instead
+/// of on Ctrl+C / SIGTERM
+///
+/// As with the signals, in-flight requests get `config.shutdown_drain_secs`
+/// to finish before this returns regardless.
+pub async fn start_server_until(
+    config: GatewayConfig,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> anyhow::Result<()> {
+    serve(config, Vec::new(), Arc::new(ConfigModelResolver), shutdown).await
+}
+
+async fn serve(
+    config: GatewayConfig,
+    transforms: Vec<Box<dyn RequestTransform>>,
+    resolver: Arc<dyn ModelResolver>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> anyhow::Result<()> {
     // Load provider configuration from config file
     let provider_config = load_with_default().map_err(|e| {
         tracing::warn!("Failed to load provider config, using default: {}", e);
@@ -32,13 +91,36 @@ pub async fn start_server(config: GatewayConfig) -> anyhow::Result<()> {
     // Create GatewayState with loaded config
     let state = GatewayState {
         config: Arc::new(provider_config),
+        usage: Arc::new(UsageTracker::with_prices(config.model_prices.clone())),
+        idempotency: Arc::new(IdempotencyCache::new(
+            Duration::from_secs(config.idempotency_ttl_secs),
+            config.idempotency_max_entries,
+        )),
+        metrics: config.metrics_enabled.then(|| Arc::new(Metrics::new())),
+        sse_keepalive: (config.sse_keepalive_secs > 0).then(|| Duration::from_secs(config.sse_keepalive_secs)),
+        coalesce_stream: (config.coalesce_stream_ms > 0).then(|| Duration::from_millis(config.coalesce_stream_ms)),
+        strip_ping_events: config.strip_ping_events,
+        tee_stream_usage: config.tee_stream_usage,
+        forward_response_headers: Arc::new(config.forward_response_headers.clone()),
+        system_prompt: SystemPromptConfig::load()?.map(Arc::new),
+        transforms: Arc::new(build_transforms(&config, transforms)),
+        started_at: Instant::now(),
+        proxy_allowed_paths: Arc::new(config.proxy_allowed_paths.clone()),
+        proxy_upstreams: Arc::new(ProxyUpstreams::load()),
+        allow_mock_responses: config.allow_mock_responses,
+        audit: match &config.audit_log_path {
+            Some(path) => Some(Arc::new(AuditLog::open(path, config.audit_log_contents).await?)),
+            None => None,
+        },
+        resolver,
+        fallbacks: Arc::new(FallbackConfig::load()?),
     };
 
-    // Maximum request body size (10 MB) to prevent DoS attacks
-    const MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+    let max_body_size = config.max_body_size_bytes;
+    let metrics = state.metrics.clone();
 
     // Build our application with routes
-    let app = Router::new()
+    let routes = Router::new()
         // OpenAI-compatible endpoints (using new passthrough handler)
         .route(
             "/openai/v1/chat/completions",
@@ -51,15 +133,38 @@ pub async fn start_server(config: GatewayConfig) -> anyhow::Result<()> {
             post(anthropic_handlers_v2::messages_handler_passthrough),
         )
         .route("/anthropic/v1/models", get(provider_handlers::list_anthropic_models))
+        // Other provider endpoints (embeddings, images, ...), per proxy_allowed_paths
+        .route("/proxy/:provider/*path", any(proxy::proxy_handler))
         // Utility endpoints
         .route("/health", get(health_check))
         .route("/v1/providers", get(handlers::list_providers))
+        .route("/v1/usage", get(handlers::usage_stats));
+    let routes = if metrics.is_some() {
+        routes.route("/metrics", get(metrics::metrics_handler))
+    } else {
+        routes
+    };
+
+    let app = routes
         .with_state(state)
         // Apply request body size limit to prevent DoS
-        .layer(axum::extract::DefaultBodyLimit::max(MAX_BODY_SIZE))
+        .layer(axum::extract::DefaultBodyLimit::max(max_body_size))
+        .layer(middleware::from_fn(payload_too_large_to_json))
         .layer(middleware::from_fn(request_id_middleware))
         .layer(middleware::from_fn(logging_middleware));
 
+    // Count and time chat requests when metrics are enabled
+    let app = match metrics {
+        Some(metrics) => app.layer(middleware::from_fn_with_state(metrics, metrics::metrics_middleware)),
+        None => app,
+    };
+
+    // Allow browser clients when origins are configured
+    let app = match build_cors_layer(&config.cors_allowed_origins, &config.forward_response_headers) {
+        Some(cors) => app.layer(cors),
+        None => app,
+    };
+
     // Create socket address
     let addr: SocketAddr = format!("{}:{}", config.host, config.port)
         .parse()
@@ -70,10 +175,28 @@ pub async fn start_server(config: GatewayConfig) -> anyhow::Result<()> {
     // Create TCP listener
     let listener = tokio::net::TcpListener::bind(addr).await?;
 
-    // Start server with graceful shutdown
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    // Start server with graceful shutdown, bounded by the drain timeout so a
+    // hung stream cannot keep the process alive
+    let drain = Duration::from_secs(config.shutdown_drain_secs);
+    let (signalled_tx, signalled_rx) = tokio::sync::oneshot::channel();
+    let server = axum::serve(listener, app).with_graceful_shutdown(async move {
+        shutdown.await;
+        let _ = signalled_tx.send(());
+    });
+    let drain_expired = async move {
+        if signalled_rx.await.is_ok() {
+            tokio::time::sleep(drain).await;
+        } else {
+            std::future::pending::<()>().await;
+        }
+    };
+
+    tokio::select! {
+        result = server.into_future() => result?,
+        _ = drain_expired => {
+            warn!("In-flight requests still running after {}s, shutting down anyway", drain.as_secs());
+        }
+    }
 
     info!("Gateway shutdown complete");
     Ok(())
@@ -106,8 +229,8 @@ async fn shutdown_signal() {
     info!("Received shutdown signal, stopping server...");
 }
 
-/// Health check handler with provider status
-async fn health_check() -> axum::Json<serde_json::Value> {
+/// Health check handler with provider status and build info
+async fn health_check(State(state): State<GatewayState>) -> axum::Json<serde_json::Value> {
     // Try to get provider count
     let providers_count = ProviderConfig::list_models()
         .map(|m| m.len())
@@ -116,10 +239,122 @@ async fn health_check() -> axum::Json<serde_json::Value> {
     axum::Json(serde_json::json!({
         "status": "ok",
         "timestamp": chrono::Utc::now().to_rfc3339(),
-        "providers": providers_count
+        "providers": providers_count,
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_sha": env!("EMX_LLM_GIT_SHA"),
+        "uptime_secs": state.started_at.elapsed().as_secs()
     }))
 }
 
+/// Built-in transforms for `config` followed by the caller's own
+fn build_transforms(
+    config: &GatewayConfig,
+    extra: Vec<Box<dyn RequestTransform>>,
+) -> Vec<Box<dyn RequestTransform>> {
+    let mut transforms: Vec<Box<dyn RequestTransform>> = Vec::new();
+    if !config.field_filters.is_empty() {
+        transforms.push(Box::new(FieldFilterTransform::new(config.field_filters.clone())));
+    }
+    transforms.extend(extra);
+    transforms
+}
+
+/// Response headers the gateway sets itself, exposed to browser clients
+const GATEWAY_RESPONSE_HEADERS: [&str; 6] = [
+    "content-type",
+    "x-request-id",
+    FALLBACK_MODEL_HEADER,
+    UPSTREAM_LATENCY_HEADER,
+    MAX_TOKENS_CLAMPED_HEADER,
+    IDEMPOTENT_REPLAYED_HEADER,
+];
+
+/// Build the CORS layer from the configured origins, or `None` when CORS is off
+///
+/// Exposes the gateway's own response headers and the forwarded upstream
+/// ones. A forwarded prefix such as `x-ratelimit-*` cannot be listed name by
+/// name, so it exposes every header instead.
+fn build_cors_layer(origins: &[String], forwarded: &[String]) -> Option<CorsLayer> {
+    if origins.is_empty() {
+        return None;
+    }
+
+    let allow_origin = if origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(origins.iter().filter_map(|o| match o.parse::<HeaderValue>() {
+            Ok(v) => Some(v),
+            Err(_) => {
+                tracing::warn!("Ignoring invalid CORS origin: {}", o);
+                None
+            }
+        }))
+    };
+
+    Some(
+        CorsLayer::new()
+            .allow_origin(allow_origin)
+            .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+            .allow_headers([
+                header::AUTHORIZATION,
+                header::CONTENT_TYPE,
+                HeaderName::from_static("anthropic-version"),
+                HeaderName::from_static("anthropic-beta"),
+                HeaderName::from_static("x-api-key"),
+                HeaderName::from_static(IDEMPOTENCY_KEY_HEADER),
+            ])
+            .expose_headers(expose_headers(forwarded)),
+    )
+}
+
+fn expose_headers(forwarded: &[String]) -> ExposeHeaders {
+    if forwarded.iter().any(|entry| entry.ends_with('*')) {
+        return ExposeHeaders::any();
+    }
+    let forwarded = forwarded.iter().filter_map(|name| match name.parse::<HeaderName>() {
+        Ok(name) => Some(name),
+        Err(_) => {
+            tracing::warn!("Ignoring invalid forwarded header name: {}", name);
+            None
+        }
+    });
+    ExposeHeaders::list(GATEWAY_RESPONSE_HEADERS.into_iter().map(HeaderName::from_static).chain(forwarded))
+}
+
+/// Rewrite axum's plain-text 413 body-limit rejection into a JSON error in
+/// the shape of the endpoint's API
+///
+/// Only the gateway's own rejection is rewritten: upstream 413s come back
+/// from the handlers with a JSON body, and proxied responses are forwarded
+/// as the upstream sent them.
+async fn payload_too_large_to_json(req: Request, next: Next) -> Response {
+    let path = req.uri().path();
+    let anthropic = path.starts_with("/anthropic/");
+    let proxied = path.starts_with("/proxy/");
+    let response = next.run(req).await;
+
+    let body_limit_rejection = response.status() == StatusCode::PAYLOAD_TOO_LARGE
+        && response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|content_type| content_type.starts_with("text/plain"));
+    if proxied || !body_limit_rejection {
+        return response;
+    }
+
+    let message = "Request body exceeds the configured max_body_size_bytes limit";
+    if anthropic {
+        let body = serde_json::json!({
+            "type": "error",
+            "error": {"type": "request_too_large", "message": message}
+        });
+        (StatusCode::PAYLOAD_TOO_LARGE, axum::Json(body)).into_response()
+    } else {
+        handlers::openai_error(StatusCode::PAYLOAD_TOO_LARGE, message).into_response()
+    }
+}
+
 /// Logging middleware
 async fn logging_middleware(
     req: Request,
@@ -155,16 +390,16 @@ async fn logging_middleware(
     response
 }
 
-/// Request ID middleware - adds a unique ID to each request for tracing
+/// Request ID middleware - adds a unique ID to each request for tracing,
+/// and returns it to the client on the response
 async fn request_id_middleware(
     mut req: Request,
     next: Next,
 ) -> Response {
-    let request_id = Uuid::new_v4().to_string();
-    req.headers_mut().insert(
-        "x-request-id",
-        request_id.parse().unwrap(),
-    );
+    let request_id: HeaderValue = Uuid::new_v4().to_string().parse().unwrap();
+    req.headers_mut().insert("x-request-id", request_id.clone());
 
-    next.run(req).await
+    let mut response = next.run(req).await;
+    response.headers_mut().insert("x-request-id", request_id);
+    response
 }
@@ -0,0 +1,101 @@
+//! Request body transforms
+//!
+//! Upstreams differ in which request fields they accept. A
+//! [`RequestTransform`] rewrites the client's JSON body before the gateway
+//! builds the upstream request from it; [`FieldFilterTransform`] is the
+//! built-in one, driven by `[field_filters.<provider>]` in the gateway config.
+
+use crate::ProviderType;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Fields every chat request needs; never removed by [`FieldFilterTransform`]
+const REQUIRED_FIELDS: [&str; 2] = ["model", "messages"];
+
+/// Rewrites a request body bound for a `provider_type` upstream
+pub trait RequestTransform: Send + Sync {
+    /// Modify `body` in place
+    fn transform(&self, body: &mut Value, provider_type: ProviderType);
+}
+
+/// Top-level request fields to keep or drop for one provider type
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FieldFilter {
+    /// Keep only these fields (all fields when empty)
+    #[serde(default)]
+    pub allow: Vec<String>,
+
+    /// Drop these fields
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+/// Strips the fields each provider type rejects
+///
+/// Filters are keyed by provider config key (`openai`, `anthropic`).
+/// `model` and `messages` are always kept.
+pub struct FieldFilterTransform {
+    filters: HashMap<String, FieldFilter>,
+}
+
+impl FieldFilterTransform {
+    /// Create a transform from per-provider filters
+    pub fn new(filters: HashMap<String, FieldFilter>) -> Self {
+        Self { filters }
+    }
+}
+
+impl RequestTransform for FieldFilterTransform {
+    fn transform(&self, body: &mut Value, provider_type: ProviderType) {
+        let Some(filter) = self.filters.get(provider_type.config_key()) else {
+            return;
+        };
+        let Some(fields) = body.as_object_mut() else {
+            return;
+        };
+        fields.retain(|name, _| {
+            if REQUIRED_FIELDS.contains(&name.as_str()) {
+                return true;
+            }
+            let allowed = filter.allow.is_empty() || filter.allow.iter().any(|a| a == name);
+            allowed && !filter.deny.iter().any(|d| d == name)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn filters(provider: &str, allow: &[&str], deny: &[&str]) -> FieldFilterTransform {
+        let filter = FieldFilter {
+            allow: allow.iter().map(|s| s.to_string()).collect(),
+            deny: deny.iter().map(|s| s.to_string()).collect(),
+        };
+        FieldFilterTransform::new(HashMap::from([(provider.to_string(), filter)]))
+    }
+
+    #[test]
+    fn test_deny_list_strips_fields_for_its_provider_only() {
+        let transform = filters("openai", &[], &["logit_bias", "model"]);
+        let request = json!({"model": "m", "messages": [], "logit_bias": {"1": 5}, "top_p": 0.5});
+
+        let mut body = request.clone();
+        transform.transform(&mut body, ProviderType::OpenAI);
+        assert_eq!(body, json!({"model": "m", "messages": [], "top_p": 0.5}));
+
+        let mut body = request.clone();
+        transform.transform(&mut body, ProviderType::Anthropic);
+        assert_eq!(body, request);
+    }
+
+    #[test]
+    fn test_allow_list_keeps_required_fields() {
+        let transform = filters("anthropic", &["max_tokens"], &[]);
+        let mut body = json!({"model": "m", "messages": [], "max_tokens": 16, "metadata": {}});
+        transform.transform(&mut body, ProviderType::Anthropic);
+        assert_eq!(body, json!({"model": "m", "messages": [], "max_tokens": 16}));
+    }
+}
@@ -0,0 +1,268 @@
+//! Token usage accounting for gateway traffic
+//!
+//! Passthrough handlers forward upstream bytes untouched, so usage has to be
+//! picked out of the response as it flows by. For streams the totals only
+//! arrive in the last frames, which [`SseUsageScanner`] watches for without
+//! holding anything back from the client (unless `tee_stream_usage` is off).
+
+use crate::Usage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Price of a model's tokens, per million, from `[model_prices."<model ref>"]`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    /// Price per million prompt tokens
+    #[serde(default)]
+    pub prompt: f64,
+
+    /// Price per million completion tokens
+    #[serde(default)]
+    pub completion: f64,
+}
+
+/// Running token and cost totals across all requests served by the gateway
+#[derive(Debug, Default)]
+pub struct UsageTracker {
+    requests: AtomicU64,
+    prompt_tokens: AtomicU64,
+    completion_tokens: AtomicU64,
+    /// Prices by model reference; usage of unpriced models costs nothing
+    prices: HashMap<String, ModelPrice>,
+    cost: Mutex<f64>,
+}
+
+/// Point-in-time copy of the tracker totals
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct UsageSnapshot {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    pub cost: f64,
+}
+
+impl UsageTracker {
+    /// Create an empty tracker
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty tracker that prices usage with `prices`, keyed by
+    /// model reference
+    pub fn with_prices(prices: HashMap<String, ModelPrice>) -> Self {
+        Self { prices, ..Self::default() }
+    }
+
+    /// Add one request's usage of `model` (a model reference) to the totals
+    pub fn record(&self, model: &str, usage: &Usage) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.prompt_tokens
+            .fetch_add(usage.prompt_tokens as u64, Ordering::Relaxed);
+        self.completion_tokens
+            .fetch_add(usage.completion_tokens as u64, Ordering::Relaxed);
+        if let Some(price) = self.prices.get(model) {
+            *self.cost.lock().unwrap() += usage.cost(price.prompt, price.completion);
+        }
+    }
+
+    /// Read the current totals
+    pub fn snapshot(&self) -> UsageSnapshot {
+        let prompt_tokens = self.prompt_tokens.load(Ordering::Relaxed);
+        let completion_tokens = self.completion_tokens.load(Ordering::Relaxed);
+        UsageSnapshot {
+            requests: self.requests.load(Ordering::Relaxed),
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            cost: *self.cost.lock().unwrap(),
+        }
+    }
+}
+
+/// Extract usage from a non-streaming OpenAI or Anthropic response body
+pub fn usage_from_body(body: &[u8]) -> Option<Usage> {
+    let value: Value = serde_json::from_slice(body).ok()?;
+    let usage = value.get("usage")?;
+    let mut scanner = SseUsageScanner::new();
+    scanner.apply_usage(usage);
+    scanner.finish()
+}
+
+/// Extract the completion text from a non-streaming OpenAI or Anthropic
+/// response body
+pub fn completion_from_body(body: &[u8]) -> Option<String> {
+    let value: Value = serde_json::from_slice(body).ok()?;
+    if let Some(content) = value.pointer("/choices/0/message/content").and_then(|c| c.as_str()) {
+        return Some(content.to_string());
+    }
+    let blocks = value.get("content")?.as_array()?;
+    Some(blocks.iter().filter_map(|b| b.get("text").and_then(|t| t.as_str())).collect())
+}
+
+/// Watches forwarded SSE bytes for usage fields
+///
+/// Understands both wire formats:
+/// - OpenAI: `usage` object on the final chunk
+/// - Anthropic: `message.usage` on `message_start`, `usage` on `message_delta`
+///
+/// Text deltas are collected along the way, for the audit log.
+pub struct SseUsageScanner {
+    buf: Vec<u8>,
+    prompt_tokens: Option<u32>,
+    completion_tokens: Option<u32>,
+    text: String,
+}
+
+impl SseUsageScanner {
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            prompt_tokens: None,
+            completion_tokens: None,
+            text: String::new(),
+        }
+    }
+
+    /// Feed a chunk of forwarded bytes
+    pub fn observe(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+        while let Some(pos) = self.buf.iter().position(|&b| b == b'\n') {
+            let raw: Vec<u8> = self.buf.drain(..=pos).collect();
+            let Ok(line) = std::str::from_utf8(&raw) else {
+                continue;
+            };
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            let Ok(value) = serde_json::from_str::<Value>(data.trim()) else {
+                continue;
+            };
+
+            if let Some(usage) = value.get("usage").filter(|u| !u.is_null()) {
+                self.apply_usage(usage);
+            }
+            if let Some(usage) = value.get("message").and_then(|m| m.get("usage")) {
+                self.apply_usage(usage);
+            }
+
+            let delta = value
+                .pointer("/choices/0/delta/content")
+                .or_else(|| value.pointer("/delta/text"))
+                .and_then(|t| t.as_str());
+            if let Some(delta) = delta {
+                self.text.push_str(delta);
+            }
+        }
+    }
+
+    /// Completion text streamed so far
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn apply_usage(&mut self, usage: &Value) {
+        let field = |name: &str| usage.get(name).and_then(|v| v.as_u64()).map(|v| v as u32);
+
+        if let Some(v) = field("prompt_tokens").or_else(|| field("input_tokens")) {
+            self.prompt_tokens = Some(v);
+        }
+        if let Some(v) = field("completion_tokens").or_else(|| field("output_tokens")) {
+            self.completion_tokens = Some(v);
+        }
+    }
+
+    /// Usage seen so far, if the upstream reported any
+    pub fn finish(&self) -> Option<Usage> {
+        if self.prompt_tokens.is_none() && self.completion_tokens.is_none() {
+            return None;
+        }
+        let prompt_tokens = self.prompt_tokens.unwrap_or(0);
+        let completion_tokens = self.completion_tokens.unwrap_or(0);
+        Some(Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            ..Default::default()
+        })
+    }
+}
+
+impl Default for SseUsageScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openai_stream_usage_split_across_chunks() {
+        let mut scanner = SseUsageScanner::new();
+        scanner.observe(b"data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\n");
+        scanner.observe(b"data: {\"choices\":[],\"usage\":{\"prompt_tok");
+        scanner.observe(b"ens\":12,\"completion_tokens\":3,\"total_tokens\":15}}\n\n");
+        scanner.observe(b"data: [DONE]\n\n");
+
+        let usage = scanner.finish().unwrap();
+        assert_eq!(usage.prompt_tokens, 12);
+        assert_eq!(usage.completion_tokens, 3);
+        assert_eq!(usage.total_tokens, 15);
+        assert_eq!(scanner.text(), "Hi");
+    }
+
+    #[test]
+    fn test_anthropic_stream_usage() {
+        let mut scanner = SseUsageScanner::new();
+        scanner.observe(b"event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":20,\"output_tokens\":1}}}\n\n");
+        scanner.observe(b"event: message_delta\ndata: {\"type\":\"message_delta\",\"usage\":{\"output_tokens\":9}}\n\n");
+
+        let usage = scanner.finish().unwrap();
+        assert_eq!(usage.prompt_tokens, 20);
+        assert_eq!(usage.completion_tokens, 9);
+    }
+
+    #[test]
+    fn test_completion_text_from_both_formats() {
+        let openai = br#"{"choices":[{"message":{"role":"assistant","content":"Hello"}}]}"#;
+        assert_eq!(completion_from_body(openai).as_deref(), Some("Hello"));
+        let anthropic = br#"{"content":[{"type":"text","text":"Hel"},{"type":"text","text":"lo"}]}"#;
+        assert_eq!(completion_from_body(anthropic).as_deref(), Some("Hello"));
+
+        let mut scanner = SseUsageScanner::new();
+        scanner.observe(b"data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n");
+        assert_eq!(scanner.text(), "Hi");
+    }
+
+    #[test]
+    fn test_tracker_accumulates() {
+        let tracker = UsageTracker::new();
+        let usage = usage_from_body(br#"{"usage":{"prompt_tokens":5,"completion_tokens":2,"total_tokens":7}}"#).unwrap();
+        tracker.record("openai.gpt-4o", &usage);
+        tracker.record("openai.gpt-4o", &usage);
+
+        let snapshot = tracker.snapshot();
+        assert_eq!(snapshot.requests, 2);
+        assert_eq!(snapshot.total_tokens, 14);
+        assert_eq!(snapshot.cost, 0.0);
+    }
+
+    #[test]
+    fn test_tracker_prices_known_models() {
+        let prices = HashMap::from([("openai.gpt-4o".to_string(), ModelPrice { prompt: 1000.0, completion: 2000.0 })]);
+        let tracker = UsageTracker::with_prices(prices);
+        let usage = Usage { prompt_tokens: 10, completion_tokens: 5, total_tokens: 15, ..Default::default() };
+        tracker.record("openai.gpt-4o", &usage);
+        tracker.record("openai.unpriced", &usage);
+
+        let snapshot = tracker.snapshot();
+        assert_eq!(snapshot.requests, 2);
+        assert_eq!(snapshot.total_tokens, 30);
+        assert!((snapshot.cost - 0.02).abs() < 1e-12);
+    }
+}
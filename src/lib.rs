@@ -1,10 +1,18 @@
 //! Re-exports from all modules
+mod cache;
 mod client;
 mod config;
+#[cfg(test)]
+mod fixture_recorder;
 mod message;
+#[cfg(test)]
+mod mock_server;
 mod provider;
+mod reasoning;
+pub mod sse;
 #[cfg(feature = "cli")]
 mod session;
+mod tool_call;
 
 #[cfg(feature = "gate")]
 pub mod gate;
@@ -21,6 +29,17 @@ pub enum Error {
     #[error("API error: {0}")]
     Api(String),
 
+    /// The upstream API answered with a non-success HTTP status
+    #[error("API error: {provider} API error ({status}): {body}")]
+    Upstream {
+        /// Provider that answered (`"OpenAI"`, `"Anthropic"`)
+        provider: &'static str,
+        /// Response status
+        status: reqwest::StatusCode,
+        /// Response body, unchanged
+        body: String,
+    },
+
     /// HTTP client error
     #[error("HTTP error: {0}")]
     Http(#[from] reqwest::Error),
@@ -32,11 +51,17 @@ pub enum Error {
     /// Configuration error
     #[error("Configuration error: {0}")]
     Config(String),
+
+    /// Messages violate the target provider's structural rules
+    #[error("Invalid messages: {0}")]
+    InvalidMessages(String),
 }
 
-pub use client::{Client, StreamEvent, ToolDefinition, load_tools_from_dir};
-pub use config::{load_with_default, ModelConfig, ModelReference, ProviderConfig, ProviderType};
-pub use message::{Message, MessageContent, MessageRole, ToolCall, Usage};
-pub use provider::{create_client, create_client_for_model};
+pub use cache::CachingClient;
+pub use client::{estimate_tokens, upstream_attempts, Capabilities, ChatOptions, ChatResponse, Client, FinishReason, LogProbs, StreamEvent, TokenLogProb, ToolDefinition, TopLogProb, load_tools_from_dir};
+pub use config::{load_with_default, DevConfig, DevProfile, DevTool, EnvConfig, FallbackConfig, ModelConfig, ModelReference, ProviderConfig, ProviderType, SystemPromptConfig, SystemPromptMode, ValidationIssue, ValidationReport};
+pub use message::{validate_for, Message, MessageContent, MessageRole, ToolCall, Usage};
+pub use provider::{create_caching_client, create_client, create_client_for_model, create_client_for_model_config};
 #[cfg(feature = "cli")]
 pub use session::{FromInfo, Session, validate_session_name};
+pub use tool_call::{CompletedToolCall, ToolCallAccumulator, ToolCallDelta};
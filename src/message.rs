@@ -1,5 +1,6 @@
 //! Message types for LLM communication
 
+use crate::{Error, ProviderType};
 use serde::{Deserialize, Serialize};
 
 /// Role of a message sender
@@ -171,6 +172,10 @@ pub struct Message {
 
     /// Tool calls (when assistant requests tool execution)
     pub tool_calls: Option<Vec<ToolCall>>,
+
+    /// Mark the prompt up to and including this message for Anthropic
+    /// prompt caching (see [`Message::cacheable`])
+    pub cacheable: bool,
 }
 
 // Serialization support for Message
@@ -240,6 +245,16 @@ mod message_serde {
                 }
             }
 
+            // Cacheable text is sent as a block carrying the cache marker
+            if self.cacheable {
+                if let Some(text) = self.get_content() {
+                    let mut map = serializer.serialize_map(Some(2))?;
+                    map.serialize_entry("role", &self.role)?;
+                    map.serialize_entry("content", &[cached_text_block(text)])?;
+                    return map.end();
+                }
+            }
+
             // Default serialization for other message types
             let helper = MessageHelper {
                 role: self.role.clone(),
@@ -270,6 +285,7 @@ mod message_serde {
                 content,
                 tool_call_id: helper.tool_call_id,
                 tool_calls: helper.tool_calls,
+                cacheable: false,
             })
         }
     }
@@ -283,6 +299,7 @@ impl Message {
             content: MessageContent::Text(content.into()),
             tool_call_id: None,
             tool_calls: None,
+            cacheable: false,
         }
     }
 
@@ -293,6 +310,7 @@ impl Message {
             content: MessageContent::Text(content.into()),
             tool_call_id: None,
             tool_calls: None,
+            cacheable: false,
         }
     }
 
@@ -303,6 +321,7 @@ impl Message {
             content: MessageContent::Text(content.into()),
             tool_call_id: None,
             tool_calls: None,
+            cacheable: false,
         }
     }
 
@@ -313,6 +332,7 @@ impl Message {
             content: MessageContent::Text(content.into()),
             tool_call_id: None,
             tool_calls: None,
+            cacheable: false,
         }
     }
 
@@ -323,6 +343,7 @@ impl Message {
             content: MessageContent::Text(String::new()),
             tool_call_id: None,
             tool_calls: Some(tool_calls),
+            cacheable: false,
         }
     }
 
@@ -333,6 +354,7 @@ impl Message {
             content: MessageContent::Text(content.into()),
             tool_call_id: Some(tool_call_id),
             tool_calls: None,
+            cacheable: false,
         }
     }
 
@@ -343,9 +365,22 @@ impl Message {
             content: MessageContent::Text(content.into()),
             tool_call_id: None,
             tool_calls: None,
+            cacheable: false,
         }
     }
 
+    /// Ask Anthropic to cache the prompt up to and including this message
+    ///
+    /// The message is sent as a text block carrying a
+    /// `cache_control: {"type": "ephemeral"}` marker (for a system message,
+    /// the `system` field is), and the client enables the prompt caching
+    /// beta. Useful on a large system prompt or document reused across
+    /// requests. OpenAI caches long prompts automatically and ignores this.
+    pub fn cacheable(mut self) -> Self {
+        self.cacheable = true;
+        self
+    }
+
     /// Get the text content if present
     pub fn get_content(&self) -> Option<&str> {
         match &self.content {
@@ -370,8 +405,57 @@ impl Message {
     }
 }
 
+/// Anthropic text block marked for prompt caching
+pub(crate) fn cached_text_block(text: &str) -> serde_json::Value {
+    serde_json::json!({"type": "text", "text": text, "cache_control": {"type": "ephemeral"}})
+}
+
+/// Check that a conversation satisfies the structural rules of a provider.
+///
+/// Catches problems locally so callers get a descriptive error instead of an
+/// opaque upstream 400:
+/// - Both providers need at least one non-system message.
+/// - Anthropic needs at least one user turn, and the first non-system message
+///   to be one. Tool results are sent as user turns, so they count.
+///
+/// Consecutive turns with the same role are allowed; Anthropic merges them.
+pub fn validate_for(messages: &[Message], provider_type: ProviderType) -> crate::Result<()> {
+    let conversation: Vec<&Message> = messages
+        .iter()
+        .filter(|m| m.role != MessageRole::System)
+        .collect();
+
+    if conversation.is_empty() {
+        return Err(Error::InvalidMessages(
+            "messages must contain at least one non-system message".to_string(),
+        ));
+    }
+
+    if provider_type == ProviderType::OpenAI {
+        return Ok(());
+    }
+
+    let is_user_turn = |m: &&Message| matches!(m.role, MessageRole::User | MessageRole::Tool);
+    if !conversation.iter().any(is_user_turn) {
+        return Err(Error::InvalidMessages(
+            "Anthropic requires at least one user message".to_string(),
+        ));
+    }
+
+    if conversation[0].role == MessageRole::Assistant {
+        return Err(Error::InvalidMessages(
+            "Anthropic requires messages to start with a user turn".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
 /// Token usage statistics
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Usages add up field by field (`+`, `+=`, [`Usage::merge`]), starting from
+/// the all-zero [`Usage::default`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Usage {
     /// Number of tokens in the prompt
     pub prompt_tokens: u32,
@@ -381,6 +465,14 @@ pub struct Usage {
 
     /// Total number of tokens
     pub total_tokens: u32,
+
+    /// Prompt tokens served from the provider's prompt cache, when reported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_tokens: Option<u32>,
+
+    /// Prompt tokens written to the provider's prompt cache, when reported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation_tokens: Option<u32>,
 }
 
 impl Usage {
@@ -391,6 +483,38 @@ impl Usage {
             (self.completion_tokens as f64 / 1_000_000.0) * completion_per_million;
         prompt_cost + completion_cost
     }
+
+    /// Add `other`'s token counts to this usage
+    pub fn merge(&mut self, other: &Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+        self.cached_tokens = add_counts(self.cached_tokens, other.cached_tokens);
+        self.cache_creation_tokens = add_counts(self.cache_creation_tokens, other.cache_creation_tokens);
+    }
+}
+
+/// Sum of two optional counts, `None` only when neither was reported
+fn add_counts(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+    match (a, b) {
+        (None, None) => None,
+        _ => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+    }
+}
+
+impl std::ops::Add for Usage {
+    type Output = Usage;
+
+    fn add(mut self, other: Usage) -> Usage {
+        self.merge(&other);
+        self
+    }
+}
+
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        self.merge(&other);
+    }
 }
 
 #[cfg(test)]
@@ -404,15 +528,115 @@ mod tests {
         assert_eq!(msg.get_content(), Some("Hello"));
     }
 
+    #[test]
+    fn test_tool_result_serializes_as_anthropic_block() {
+        let msg = Message::tool_result("toolu_1".to_string(), "42");
+        assert_eq!(
+            serde_json::to_value(&msg).unwrap(),
+            serde_json::json!({
+                "role": "user",
+                "content": [{"type": "tool_result", "tool_use_id": "toolu_1", "content": "42"}]
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate_anthropic_rejects_leading_assistant() {
+        let messages = vec![
+            Message::system("Be brief"),
+            Message::assistant("Hello"),
+            Message::user("Hi"),
+        ];
+        let err = validate_for(&messages, ProviderType::Anthropic).unwrap_err();
+        assert!(err.to_string().contains("start with a user turn"));
+        assert!(validate_for(&messages, ProviderType::OpenAI).is_ok());
+    }
+
+    #[test]
+    fn test_validate_anthropic_accepts_repeated_roles() {
+        let messages = vec![Message::user("One"), Message::user("Two")];
+        assert!(validate_for(&messages, ProviderType::Anthropic).is_ok());
+
+        let messages = vec![
+            Message::user("One"),
+            Message::assistant("Two"),
+            Message::assistant("Three"),
+        ];
+        assert!(validate_for(&messages, ProviderType::Anthropic).is_ok());
+    }
+
+    #[test]
+    fn test_validate_anthropic_accepts_tool_round() {
+        let call = |id: &str| ToolCall {
+            id: id.to_string(),
+            name: "glob".to_string(),
+            arguments: "{}".to_string(),
+        };
+        let messages = vec![
+            Message::system("sys"),
+            Message::user("List files"),
+            Message::assistant_with_tools(vec![call("a"), call("b")]),
+            Message::tool_result("a".to_string(), "x.rs"),
+            Message::tool_result("b".to_string(), "y.rs"),
+            Message::assistant("Found two files"),
+        ];
+        assert!(validate_for(&messages, ProviderType::Anthropic).is_ok());
+    }
+
+    #[test]
+    fn test_validate_rejects_system_only() {
+        let messages = vec![Message::system("sys")];
+        assert!(validate_for(&messages, ProviderType::OpenAI).is_err());
+        assert!(validate_for(&messages, ProviderType::Anthropic).is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_empty() {
+        for provider_type in [ProviderType::OpenAI, ProviderType::Anthropic] {
+            let err = validate_for(&[], provider_type).unwrap_err();
+            assert!(matches!(err, Error::InvalidMessages(_)));
+            assert!(err.to_string().contains("at least one non-system message"));
+        }
+    }
+
+    #[test]
+    fn test_validate_anthropic_requires_a_user_turn() {
+        let messages = vec![Message::system("sys"), Message::assistant("Hello")];
+        let err = validate_for(&messages, ProviderType::Anthropic).unwrap_err();
+        assert!(err.to_string().contains("at least one user message"));
+
+        // A tool result is sent as a user turn
+        let messages = vec![Message::tool_result("a".to_string(), "x.rs")];
+        assert!(validate_for(&messages, ProviderType::Anthropic).is_ok());
+    }
+
     #[test]
     fn test_usage_calculation() {
         let usage = Usage {
             prompt_tokens: 1000,
             completion_tokens: 500,
             total_tokens: 1500,
+            ..Default::default()
         };
 
         let cost = usage.cost(0.50, 1.50);
         assert!((cost - 0.00125).abs() < 0.0001);
     }
+
+    #[test]
+    fn test_usage_add_sums_each_field() {
+        let a = Usage { prompt_tokens: 10, completion_tokens: 5, total_tokens: 15, ..Default::default() };
+        let b = Usage { prompt_tokens: 3, completion_tokens: 4, total_tokens: 7, ..Default::default() };
+        assert_eq!(a + b, Usage { prompt_tokens: 13, completion_tokens: 9, total_tokens: 22, ..Default::default() });
+    }
+
+    #[test]
+    fn test_usage_add_assign_accumulates_from_default() {
+        let mut total = Usage::default();
+        assert_eq!(total, Usage { prompt_tokens: 0, completion_tokens: 0, total_tokens: 0, ..Default::default() });
+
+        total += Usage { prompt_tokens: 10, completion_tokens: 5, total_tokens: 15, ..Default::default() };
+        total.merge(&Usage { prompt_tokens: 1, completion_tokens: 2, total_tokens: 3, ..Default::default() });
+        assert_eq!(total, Usage { prompt_tokens: 11, completion_tokens: 7, total_tokens: 18, ..Default::default() });
+    }
 }
@@ -1,23 +1,87 @@
 //! Mock HTTP server for testing LLM clients offline
 //!
 //! This module provides wiremock-based mock servers for OpenAI and Anthropic APIs,
-//! allowing tests to run without real API keys.
+//! allowing tests to run without real API keys. Besides clean success
+//! responses they can simulate rate limiting and streams that fail partway,
+//! to exercise the clients' retry and error paths.
+//!
+//! `expect_header`/`expect_body_contains` restrict every mock a server mounts
+//! afterwards to requests carrying the given header or body value; anything
+//! else falls through to a catch-all 400, so tests can assert what was sent.
 
 use wiremock::{
-    matchers::{method, path},
-    Mock, MockServer, ResponseTemplate,
+    matchers::{body_partial_json, method, path},
+    Match, Mock, MockBuilder, MockServer, Request, ResponseTemplate,
 };
 
+/// Headers and body values every mocked request must carry
+#[derive(Debug, Clone, Default)]
+struct RequestExpectations {
+    headers: Vec<(String, String)>,
+    body: Vec<(String, serde_json::Value)>,
+}
+
+impl RequestExpectations {
+    /// Start a POST mock for `route` that only matches expected requests
+    fn given(&self, route: &str) -> MockBuilder {
+        Mock::given(method("POST")).and(path(route)).and(self.clone())
+    }
+}
+
+impl Match for RequestExpectations {
+    fn matches(&self, request: &Request) -> bool {
+        let headers_match = self.headers.iter().all(|(name, value)| {
+            request
+                .headers
+                .get(name.as_str())
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v == value)
+        });
+        if !headers_match {
+            return false;
+        }
+        if self.body.is_empty() {
+            return true;
+        }
+        let Ok(body) = serde_json::from_slice::<serde_json::Value>(&request.body) else {
+            return false;
+        };
+        self.body
+            .iter()
+            .all(|(pointer, value)| body.pointer(pointer) == Some(value))
+    }
+}
+
+/// Start a server whose unmatched POSTs get a 400 instead of wiremock's 404
+async fn start_server() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+            "error": {
+                "type": "invalid_request_error",
+                "message": "request did not match the mock's expectations"
+            }
+        })))
+        .with_priority(u8::MAX)
+        .mount(&server)
+        .await;
+    server
+}
+
 /// OpenAI mock server for testing
 pub struct OpenAIMockServer {
     server: MockServer,
+    expectations: RequestExpectations,
 }
 
 impl OpenAIMockServer {
     /// Create a new OpenAI mock server
     pub async fn start() -> Self {
-        let server = MockServer::start().await;
-        Self { server }
+        let server = start_server().await;
+        Self {
+            server,
+            expectations: RequestExpectations::default(),
+        }
     }
 
     /// Get the base URL of this mock server
@@ -25,10 +89,28 @@ impl OpenAIMockServer {
         self.server.uri()
     }
 
+    /// Only answer requests whose `name` header equals `value`
+    pub fn expect_header(mut self, name: &str, value: &str) -> Self {
+        self.expectations.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Only answer requests whose JSON body holds `value` at `json_pointer`
+    /// (e.g. `/temperature` or `/messages/0/role`)
+    pub fn expect_body_contains(mut self, json_pointer: &str, value: serde_json::Value) -> Self {
+        self.expectations.body.push((json_pointer.to_string(), value));
+        self
+    }
+
+    /// Number of requests this server has received so far
+    pub async fn request_count(&self) -> usize {
+        self.server.received_requests().await.map_or(0, |requests| requests.len())
+    }
+
     /// Setup a mock response for non-streaming chat completion
     pub async fn mock_chat_completion(&self, content: &str, total_tokens: u32) {
-        Mock::given(method("POST"))
-            .and(path("/chat/completions"))
+        self.expectations
+            .given("/chat/completions")
             .respond_with(
                 ResponseTemplate::new(200).set_body_json(serde_json::json!({
                     "id": "chatcmpl-mock",
@@ -54,6 +136,103 @@ impl OpenAIMockServer {
             .await;
     }
 
+    /// Setup a non-streaming completion that answers only requests asking for
+    /// `logprobs`, returning `logprobs` as the choice's log probabilities
+    pub async fn mock_chat_completion_with_logprobs(&self, content: &str, logprobs: serde_json::Value) {
+        self.expectations
+            .given("/chat/completions")
+            .and(body_partial_json(serde_json::json!({ "logprobs": true })))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "id": "chatcmpl-mock",
+                    "object": "chat.completion",
+                    "model": "glm-4-flash",
+                    "choices": [{
+                        "index": 0,
+                        "message": {
+                            "role": "assistant",
+                            "content": content
+                        },
+                        "logprobs": logprobs,
+                        "finish_reason": "stop"
+                    }],
+                    "usage": {
+                        "prompt_tokens": 5,
+                        "completion_tokens": 2,
+                        "total_tokens": 7
+                    }
+                })),
+            )
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Setup a non-streaming completion whose message carries
+    /// `reasoning_content` besides the answer
+    pub async fn mock_chat_completion_with_reasoning(&self, reasoning: &str, content: &str) {
+        self.expectations
+            .given("/chat/completions")
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "id": "chatcmpl-mock",
+                    "object": "chat.completion",
+                    "model": "glm-4-flash",
+                    "choices": [{
+                        "index": 0,
+                        "message": {
+                            "role": "assistant",
+                            "reasoning_content": reasoning,
+                            "content": content
+                        },
+                        "finish_reason": "stop"
+                    }],
+                    "usage": {
+                        "prompt_tokens": 5,
+                        "completion_tokens": 5,
+                        "total_tokens": 10
+                    }
+                })),
+            )
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Stream one delta per `(field, text)` pair, where `field` is
+    /// `reasoning_content` or `content`, then finish with `stop`
+    pub async fn mock_chat_streaming_with_reasoning(&self, deltas: Vec<(&str, &str)>) {
+        let mut sse_response = String::new();
+
+        for (field, text) in &deltas {
+            let chunk_json = serde_json::json!({
+                "id": "chatcmpl-mock",
+                "object": "chat.completion.chunk",
+                "model": "glm-4-flash",
+                "choices": [{
+                    "index": 0,
+                    "delta": { *field: text }
+                }]
+            });
+            sse_response.push_str(&format!("data: {}\n\n", chunk_json));
+        }
+        sse_response.push_str(&format!(
+            "data: {}\n\n",
+            serde_json::json!({
+                "id": "chatcmpl-mock",
+                "object": "chat.completion.chunk",
+                "model": "glm-4-flash",
+                "choices": [{ "index": 0, "delta": {}, "finish_reason": "stop" }]
+            })
+        ));
+        sse_response.push_str(&usage_chunk(deltas.len() as u32));
+        sse_response.push_str("data: [DONE]\n\n");
+
+        self.expectations
+            .given("/chat/completions")
+            .respond_with(ResponseTemplate::new(200).set_body_string(sse_response))
+            .mount(&self.server)
+            .await;
+    }
+
     /// Setup a mock response for streaming chat completion (SSE)
     pub async fn mock_chat_streaming(&self, chunks: Vec<&str>) {
         let mut sse_response = String::new();
@@ -98,10 +277,63 @@ impl OpenAIMockServer {
             sse_response.push_str(&format!("data: {}\n\n", chunk_json));
         }
 
+        sse_response.push_str(&usage_chunk(chunks.len() as u32));
         sse_response.push_str("data: [DONE]\n\n");
 
-        Mock::given(method("POST"))
-            .and(path("/chat/completions"))
+        self.expectations
+            .given("/chat/completions")
+            .respond_with(ResponseTemplate::new(200).set_body_string(sse_response))
+            .mount(&self.server)
+            .await;
+    }
+    /// Respond 429 (with `Retry-After`) to the first request, then succeed
+    /// with `content` for every request after that
+    pub async fn mock_rate_limit_then_success(&self, retry_after_secs: u64, content: &str) {
+        self.expectations
+            .given("/chat/completions")
+            .respond_with(rate_limit_response(retry_after_secs))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&self.server)
+            .await;
+
+        self.mock_chat_completion(content, 20).await;
+    }
+
+    /// Stream `chunks` as content deltas, then end with an in-band error
+    /// event carrying `status` instead of a finish reason and `[DONE]`
+    pub async fn mock_stream_then_error(&self, chunks: Vec<&str>, status: u16) {
+        let mut sse_response = String::new();
+
+        for chunk in &chunks {
+            let chunk_json = serde_json::json!({
+                "id": "chatcmpl-mock",
+                "object": "chat.completion.chunk",
+                "created": 1234567890,
+                "model": "glm-4-flash",
+                "choices": [{
+                    "index": 0,
+                    "delta": {
+                        "content": chunk
+                    }
+                }]
+            });
+            sse_response.push_str(&format!("data: {}\n\n", chunk_json));
+        }
+
+        sse_response.push_str(&format!(
+            "data: {}\n\n",
+            serde_json::json!({
+                "error": {
+                    "message": format!("upstream failed with status {}", status),
+                    "type": "server_error",
+                    "code": status
+                }
+            })
+        ));
+
+        self.expectations
+            .given("/chat/completions")
             .respond_with(ResponseTemplate::new(200).set_body_string(sse_response))
             .mount(&self.server)
             .await;
@@ -111,13 +343,17 @@ impl OpenAIMockServer {
 /// Anthropic mock server for testing
 pub struct AnthropicMockServer {
     server: MockServer,
+    expectations: RequestExpectations,
 }
 
 impl AnthropicMockServer {
     /// Create a new Anthropic mock server
     pub async fn start() -> Self {
-        let server = MockServer::start().await;
-        Self { server }
+        let server = start_server().await;
+        Self {
+            server,
+            expectations: RequestExpectations::default(),
+        }
     }
 
     /// Get the base URL of this mock server
@@ -125,10 +361,23 @@ impl AnthropicMockServer {
         self.server.uri()
     }
 
+    /// Only answer requests whose `name` header equals `value`
+    pub fn expect_header(mut self, name: &str, value: &str) -> Self {
+        self.expectations.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Only answer requests whose JSON body holds `value` at `json_pointer`
+    /// (e.g. `/temperature` or `/messages/0/role`)
+    pub fn expect_body_contains(mut self, json_pointer: &str, value: serde_json::Value) -> Self {
+        self.expectations.body.push((json_pointer.to_string(), value));
+        self
+    }
+
     /// Setup a mock response for non-streaming message
     pub async fn mock_message(&self, content: &str, total_tokens: u32) {
-        Mock::given(method("POST"))
-            .and(path("/v1/messages"))
+        self.expectations
+            .given("/v1/messages")
             .respond_with(
                 ResponseTemplate::new(200).set_body_json(serde_json::json!({
                     "id": "msg-mock",
@@ -231,20 +480,150 @@ impl AnthropicMockServer {
             }
         }
 
-        Mock::given(method("POST"))
-            .and(path("/v1/messages"))
+        self.expectations
+            .given("/v1/messages")
+            .respond_with(ResponseTemplate::new(200).set_body_string(sse_response))
+            .mount(&self.server)
+            .await;
+    }
+    /// Respond 429 (with `Retry-After`) to the first request, then succeed
+    /// with `content` for every request after that
+    pub async fn mock_rate_limit_then_success(&self, retry_after_secs: u64, content: &str) {
+        self.expectations
+            .given("/v1/messages")
+            .respond_with(rate_limit_response(retry_after_secs))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&self.server)
+            .await;
+
+        self.mock_message(content, 20).await;
+    }
+
+    /// Stream `chunks` as text deltas, then end with an `error` event
+    /// carrying `status` instead of `message_stop`
+    pub async fn mock_stream_then_error(&self, chunks: Vec<&str>, status: u16) {
+        let mut sse_response = String::new();
+
+        sse_response.push_str(&format!(
+            "event: message_start\n\
+             data: {}\n\n",
+            serde_json::json!({
+                "type": "message_start",
+                "message": {
+                    "id": "msg-mock",
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [],
+                    "model": "glm-4-flash",
+                    "usage": {
+                        "input_tokens": 10,
+                        "output_tokens": 0
+                    }
+                }
+            })
+        ));
+
+        for chunk in &chunks {
+            sse_response.push_str(&format!(
+                "event: content_block_delta\n\
+                 data: {}\n\n",
+                serde_json::json!({
+                    "type": "content_block_delta",
+                    "index": 0,
+                    "delta": {
+                        "type": "text_delta",
+                        "text": chunk
+                    }
+                })
+            ));
+        }
+
+        let error_type = if status == 529 { "overloaded_error" } else { "api_error" };
+        sse_response.push_str(&format!(
+            "event: error\n\
+             data: {}\n\n",
+            serde_json::json!({
+                "type": "error",
+                "error": {
+                    "type": error_type,
+                    "message": format!("upstream failed with status {}", status)
+                }
+            })
+        ));
+
+        self.expectations
+            .given("/v1/messages")
             .respond_with(ResponseTemplate::new(200).set_body_string(sse_response))
             .mount(&self.server)
             .await;
     }
 }
 
+/// Trailing OpenAI stream chunk reporting usage, sent after the finish
+/// reason with no choices, as OpenAI does for `stream_options.include_usage`
+fn usage_chunk(completion_tokens: u32) -> String {
+    let chunk_json = serde_json::json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion.chunk",
+        "created": 1234567890,
+        "model": "glm-4-flash",
+        "choices": [],
+        "usage": {
+            "prompt_tokens": 10,
+            "completion_tokens": completion_tokens,
+            "total_tokens": 10 + completion_tokens
+        }
+    });
+    format!("data: {}\n\n", chunk_json)
+}
+
+/// 429 response shared by both mock servers
+fn rate_limit_response(retry_after_secs: u64) -> ResponseTemplate {
+    ResponseTemplate::new(429)
+        .insert_header("retry-after", retry_after_secs.to_string().as_str())
+        .set_body_json(serde_json::json!({
+            "error": {
+                "type": "rate_limit_error",
+                "message": "Rate limit exceeded"
+            }
+        }))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Message, ProviderConfig, create_client};
+    use crate::{create_client, Client, Message, ProviderConfig, ProviderType};
     use futures::StreamExt;
 
+    fn mock_config(provider_type: ProviderType, api_base: String) -> ProviderConfig {
+        ProviderConfig {
+            provider_type,
+            api_base,
+            api_key: "test-key".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Drain a stream, returning the text seen and the first error (if any)
+    async fn collect_stream(client: &dyn Client) -> (String, Option<crate::Error>) {
+        let messages = vec![Message::user("Say hello")];
+        let mut stream = client.chat_stream(&messages, "glm-4-flash", None);
+        let mut text = String::new();
+        while let Some(event) = stream.next().await {
+            match event {
+                Ok(event) => {
+                    text.push_str(&event.delta);
+                    if event.done {
+                        return (text, None);
+                    }
+                }
+                Err(e) => return (text, Some(e)),
+            }
+        }
+        (text, None)
+    }
+
     #[tokio::test]
     async fn test_openai_mock_non_streaming() {
         let mock = OpenAIMockServer::start().await;
@@ -252,17 +631,14 @@ mod tests {
         mock.mock_chat_completion("Hello, world!", 50).await;
 
         // Test with real client
-        let config = ProviderConfig::openai(
-            mock.base_url(),
-            "test-key".to_string(),
-        );
+        let config = mock_config(ProviderType::OpenAI, mock.base_url());
 
         let client = create_client(config).unwrap();
         let messages = vec![Message::user("Say hello")];
-        let (response, usage) = client.chat(&messages, "glm-4-flash").await.unwrap();
+        let response = client.chat(&messages, "glm-4-flash", None).await.unwrap();
 
-        assert_eq!(response, "Hello, world!");
-        assert_eq!(usage.total_tokens, 50);
+        assert_eq!(response.content, "Hello, world!");
+        assert_eq!(response.usage.total_tokens, 50);
     }
 
     #[tokio::test]
@@ -271,25 +647,26 @@ mod tests {
 
         mock.mock_chat_streaming(vec!["Hello", ", ", "world", "!"]).await;
 
-        let config = ProviderConfig::openai(
-            mock.base_url(),
-            "test-key".to_string(),
-        );
+        let config = mock_config(ProviderType::OpenAI, mock.base_url());
 
         let client = create_client(config).unwrap();
         let messages = vec![Message::user("Say hello")];
-        let mut stream = client.chat_stream(&messages, "glm-4-flash");
+        let mut stream = client.chat_stream(&messages, "glm-4-flash", None);
 
         let mut full_response = String::new();
+        let mut usage = None;
         while let Some(event) = stream.next().await {
             let event = event.unwrap();
             full_response.push_str(&event.delta);
             if event.done {
+                usage = event.usage;
                 break;
             }
         }
 
         assert_eq!(full_response, "Hello, world!");
+        // The usage chunk after the finish reason lands on the done event
+        assert_eq!(usage.map(|u| u.total_tokens), Some(14));
     }
 
     #[tokio::test]
@@ -298,17 +675,14 @@ mod tests {
 
         mock.mock_message("Hello from Anthropic!", 50).await;
 
-        let config = ProviderConfig::anthropic(
-            mock.base_url(),
-            "test-key".to_string(),
-        );
+        let config = mock_config(ProviderType::Anthropic, mock.base_url());
 
         let client = create_client(config).unwrap();
         let messages = vec![Message::user("Say hello")];
-        let (response, usage) = client.chat(&messages, "glm-4-flash").await.unwrap();
+        let response = client.chat(&messages, "glm-4-flash", None).await.unwrap();
 
-        assert_eq!(response, "Hello from Anthropic!");
-        assert_eq!(usage.total_tokens, 50);
+        assert_eq!(response.content, "Hello from Anthropic!");
+        assert_eq!(response.usage.total_tokens, 50);
     }
 
     #[tokio::test]
@@ -317,14 +691,11 @@ mod tests {
 
         mock.mock_streaming(vec!["Hello", " from", " Anthropic", "!"]).await;
 
-        let config = ProviderConfig::anthropic(
-            mock.base_url(),
-            "test-key".to_string(),
-        );
+        let config = mock_config(ProviderType::Anthropic, mock.base_url());
 
         let client = create_client(config).unwrap();
         let messages = vec![Message::user("Say hello")];
-        let mut stream = client.chat_stream(&messages, "glm-4-flash");
+        let mut stream = client.chat_stream(&messages, "glm-4-flash", None);
 
         let mut full_response = String::new();
         while let Some(event) = stream.next().await {
@@ -337,4 +708,134 @@ mod tests {
 
         assert_eq!(full_response, "Hello from Anthropic!");
     }
+
+    #[tokio::test]
+    async fn test_openai_rate_limit_then_success_retries() {
+        let mock = OpenAIMockServer::start().await;
+        mock.mock_rate_limit_then_success(1, "after retry").await;
+
+        let client = create_client(mock_config(ProviderType::OpenAI, mock.base_url())).unwrap();
+        let messages = vec![Message::user("Say hello")];
+        let response = client.chat(&messages, "glm-4-flash", None).await.unwrap();
+
+        assert_eq!(response.content, "after retry");
+        assert!(response.latency > std::time::Duration::ZERO);
+        // The retry sleep counts toward the total but not the final attempt
+        assert!(response.total_latency > response.latency);
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_rate_limit_then_success_retries() {
+        let mock = AnthropicMockServer::start().await;
+        mock.mock_rate_limit_then_success(1, "after retry").await;
+
+        let client = create_client(mock_config(ProviderType::Anthropic, mock.base_url())).unwrap();
+        let messages = vec![Message::user("Say hello")];
+        let response = client.chat(&messages, "glm-4-flash", None).await.unwrap();
+
+        assert_eq!(response.content, "after retry");
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_then_error() {
+        let mock = OpenAIMockServer::start().await;
+        mock.mock_stream_then_error(vec!["Hello", ", wor"], 500).await;
+
+        let client = create_client(mock_config(ProviderType::OpenAI, mock.base_url())).unwrap();
+        let (text, error) = collect_stream(client.as_ref()).await;
+
+        assert_eq!(text, "Hello, wor");
+        let error = error.expect("stream should end with an error").to_string();
+        assert!(error.contains("status 500"), "unexpected error: {}", error);
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_stream_then_error() {
+        let mock = AnthropicMockServer::start().await;
+        mock.mock_stream_then_error(vec!["Hello", " from"], 529).await;
+
+        let client = create_client(mock_config(ProviderType::Anthropic, mock.base_url())).unwrap();
+        let (text, error) = collect_stream(client.as_ref()).await;
+
+        assert_eq!(text, "Hello from");
+        let error = error.expect("stream should end with an error").to_string();
+        assert!(error.contains("status 529"), "unexpected error: {}", error);
+    }
+
+    #[tokio::test]
+    async fn test_openai_reasoning_is_kept_apart_from_content() {
+        let mock = OpenAIMockServer::start().await;
+        mock.mock_chat_completion_with_reasoning("Weigh the options.", "Pick B.").await;
+
+        let client = create_client(mock_config(ProviderType::OpenAI, mock.base_url())).unwrap();
+        let messages = vec![Message::user("A or B?")];
+        let response = client.chat(&messages, "glm-4-flash", None).await.unwrap();
+
+        assert_eq!(response.content, "Pick B.");
+        assert_eq!(response.reasoning.as_deref(), Some("Weigh the options."));
+    }
+
+    #[tokio::test]
+    async fn test_openai_stream_routes_reasoning_and_content_deltas() {
+        let mock = OpenAIMockServer::start().await;
+        mock.mock_chat_streaming_with_reasoning(vec![
+            ("reasoning_content", "First, "),
+            ("reasoning_content", "compare."),
+            ("content", "Pick "),
+            ("reasoning_content", " Double-check."),
+            ("content", "B."),
+        ])
+        .await;
+
+        let client = create_client(mock_config(ProviderType::OpenAI, mock.base_url())).unwrap();
+        let messages = vec![Message::user("A or B?")];
+        let mut stream = client.chat_stream(&messages, "glm-4-flash", None);
+
+        let (mut content, mut reasoning) = (String::new(), String::new());
+        while let Some(event) = stream.next().await {
+            let event = event.unwrap();
+            content.push_str(&event.delta);
+            reasoning.push_str(event.reasoning.as_deref().unwrap_or(""));
+            if event.done {
+                break;
+            }
+        }
+
+        assert_eq!(content, "Pick B.");
+        assert_eq!(reasoning, "First, compare. Double-check.");
+    }
+
+    #[tokio::test]
+    async fn test_expectations_match_sent_request() {
+        let mock = OpenAIMockServer::start()
+            .await
+            .expect_header("authorization", "Bearer test-key")
+            .expect_body_contains("/model", serde_json::json!("glm-4-flash"))
+            .expect_body_contains("/messages/0/content", serde_json::json!("Say hello"));
+        mock.mock_chat_completion("matched", 20).await;
+
+        let client = create_client(mock_config(ProviderType::OpenAI, mock.base_url())).unwrap();
+        let messages = vec![Message::user("Say hello")];
+        let response = client.chat(&messages, "glm-4-flash", None).await.unwrap();
+
+        assert_eq!(response.content, "matched");
+    }
+
+    #[tokio::test]
+    async fn test_missing_expected_header_fails_the_match() {
+        let mock = AnthropicMockServer::start()
+            .await
+            .expect_header("x-api-key", "other-key");
+        mock.mock_message("unreachable", 20).await;
+
+        let client = create_client(mock_config(ProviderType::Anthropic, mock.base_url())).unwrap();
+        let messages = vec![Message::user("Say hello")];
+        let error = client
+            .chat(&messages, "glm-4-flash", None)
+            .await
+            .expect_err("request without the expected key must not match")
+            .to_string();
+
+        assert!(error.contains("400"), "unexpected error: {}", error);
+    }
 }
@@ -1,7 +1,10 @@
 //! Provider creation and management
 
+use std::time::Duration;
+
+use super::cache::CachingClient;
 use super::client::{AnthropicClient, Client, OpenAIClient};
-use super::config::ProviderConfig;
+use super::config::{ModelConfig, ProviderConfig};
 use super::Result;
 
 /// Create an LLM client based on the provider configuration.
@@ -15,6 +18,15 @@ pub fn create_client(config: ProviderConfig) -> Result<Box<dyn Client>> {
     }
 }
 
+/// Wrap `inner` so repeated identical non-streaming requests are answered
+/// from memory.
+///
+/// Up to `capacity` responses are kept, each for `ttl`; see [`CachingClient`].
+/// Only worthwhile for deterministic requests (e.g. temperature 0).
+pub fn create_caching_client(inner: Box<dyn Client>, capacity: usize, ttl: Duration) -> Box<dyn Client> {
+    Box::new(CachingClient::new(inner, capacity, ttl))
+}
+
 /// Create an LLM client based on model-specific configuration.
 ///
 /// This function supports hierarchical configuration where model-specific
@@ -37,20 +49,16 @@ pub fn create_client(config: ProviderConfig) -> Result<Box<dyn Client>> {
 /// ```
 pub fn create_client_for_model(model_ref: &str) -> anyhow::Result<(Box<dyn Client>, String)> {
     let (model_config, model_id) = ProviderConfig::load_for_model(model_ref)?;
-
-    let provider_config = ProviderConfig {
-        provider_type: model_config.provider_type,
-        api_base: model_config.api_base,
-        api_key: model_config.api_key,
-        model: Some(model_id.clone()),
-        max_tokens: model_config.max_tokens,
-        timeout_secs: None, // Use default timeout
-    };
-
-    let client = create_client(provider_config)?;
+    let client = create_client_for_model_config(model_config, &model_id)?;
     Ok((client, model_id))
 }
 
+/// Create an LLM client from an already resolved model configuration, as
+/// returned by [`ProviderConfig::load_for_model`]
+pub fn create_client_for_model_config(model_config: ModelConfig, model_id: &str) -> Result<Box<dyn Client>> {
+    create_client(model_config.into_provider_config(model_id))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -61,9 +69,7 @@ mod tests {
             provider_type: crate::ProviderType::OpenAI,
             api_base: "https://api.openai.com/v1".to_string(),
             api_key: "test-key".to_string(),
-            model: None,
-            max_tokens: None,
-            timeout_secs: None,
+            ..Default::default()
         };
         let client = create_client(config);
         assert!(client.is_ok());
@@ -75,9 +81,7 @@ mod tests {
             provider_type: crate::ProviderType::Anthropic,
             api_base: "https://api.anthropic.com".to_string(),
             api_key: "test-key".to_string(),
-            model: None,
-            max_tokens: None,
-            timeout_secs: None,
+            ..Default::default()
         };
         let client = create_client(config);
         assert!(client.is_ok());
@@ -0,0 +1,194 @@
+//! Removing reasoning ("thinking") from model output
+//!
+//! Reasoning models return their chain of thought either in a separate field
+//! (OpenAI-style `reasoning_content`, Anthropic `thinking` deltas, surfaced as
+//! [`StreamEvent::reasoning`]) or inline as `<think>...</think>` segments of
+//! the answer text. With [`ChatOptions::strip_reasoning`](crate::ChatOptions::strip_reasoning)
+//! the clients drop both, so callers see the answer alone.
+
+use crate::{ChatResponse, Result, StreamEvent};
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+
+const OPEN_TAG: &str = "<think>";
+const CLOSE_TAG: &str = "</think>";
+
+/// `text` without its `<think>` segments; an unclosed one runs to the end
+pub(crate) fn strip_think_tags(text: &str) -> String {
+    let mut filter = ThinkTagFilter::default();
+    let mut out = filter.push(text);
+    out.push_str(&filter.finish());
+    out
+}
+
+impl ChatResponse {
+    /// Drop `reasoning` and the `<think>` segments of `content`, keeping the
+    /// original content in `raw_content`
+    pub(crate) fn strip_reasoning(&mut self) {
+        self.reasoning = None;
+        let stripped = strip_think_tags(&self.content);
+        self.raw_content = Some(std::mem::replace(&mut self.content, stripped));
+    }
+}
+
+/// Removes `<think>` segments from text that arrives in pieces
+///
+/// A tag may be split across deltas, so text that could be the start of one
+/// is held back until the next delta settles it.
+#[derive(Debug, Default)]
+struct ThinkTagFilter {
+    in_think: bool,
+    /// Held-back text that may begin a tag
+    pending: String,
+    /// Whether any answer text has been emitted
+    emitted: bool,
+    /// Trim the whitespace separating a leading thought from the answer
+    trim_start: bool,
+}
+
+impl ThinkTagFilter {
+    /// The visible part of `delta`
+    fn push(&mut self, delta: &str) -> String {
+        self.pending.push_str(delta);
+        let mut out = String::new();
+        loop {
+            let tag = if self.in_think { CLOSE_TAG } else { OPEN_TAG };
+            if let Some(at) = self.pending.find(tag) {
+                if !self.in_think {
+                    self.emit(&mut out, at);
+                }
+                self.pending.drain(..at + tag.len());
+                self.trim_start = self.in_think && !self.emitted;
+                self.in_think = !self.in_think;
+                continue;
+            }
+            // Keep a tail that could still grow into `tag`
+            let keep = (1..tag.len())
+                .rev()
+                .find(|&n| self.pending.ends_with(&tag[..n]))
+                .unwrap_or(0);
+            let settled = self.pending.len() - keep;
+            if self.in_think {
+                self.pending.drain(..settled);
+            } else {
+                self.emit(&mut out, settled);
+            }
+            return out;
+        }
+    }
+
+    /// Whatever visible text was held back at the end of the stream
+    fn finish(&mut self) -> String {
+        let mut out = String::new();
+        if !self.in_think {
+            let len = self.pending.len();
+            self.emit(&mut out, len);
+        }
+        self.pending.clear();
+        out
+    }
+
+    /// Move the first `len` bytes of `pending` to `out`
+    fn emit(&mut self, out: &mut String, len: usize) {
+        let text: String = self.pending.drain(..len).collect();
+        let text = if self.trim_start { text.trim_start() } else { text.as_str() };
+        if !text.is_empty() {
+            self.emitted = true;
+            self.trim_start = false;
+            out.push_str(text);
+        }
+    }
+}
+
+/// `events` without reasoning deltas or `<think>` text, when `strip` is set
+pub(crate) fn strip_reasoning_if(
+    strip: bool,
+    events: impl Stream<Item = Result<StreamEvent>> + Send + 'static,
+) -> Pin<Box<dyn Stream<Item = Result<StreamEvent>> + Send>> {
+    if !strip {
+        return Box::pin(events);
+    }
+    let mut filter = ThinkTagFilter::default();
+    Box::pin(events.filter_map(move |event| {
+        let event = event.map(|mut event| {
+            event.reasoning = None;
+            event.delta = filter.push(&event.delta);
+            if event.done {
+                event.delta.push_str(&filter.finish());
+            }
+            event
+        });
+        // Events that only carried reasoning have nothing left to say
+        let keep = match &event {
+            Ok(event) => {
+                !event.delta.is_empty()
+                    || event.done
+                    || event.usage.is_some()
+                    || event.tool_calls.is_some()
+                    || event.finish_reason.is_some()
+            }
+            Err(_) => true,
+        };
+        futures::future::ready(keep.then_some(event))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_event(delta: &str) -> Result<StreamEvent> {
+        Ok(StreamEvent {
+            delta: delta.to_string(),
+            done: false,
+            usage: None,
+            tool_calls: None,
+            reasoning: None,
+            finish_reason: None,
+        })
+    }
+
+    #[test]
+    fn test_strip_think_tags() {
+        assert_eq!(strip_think_tags("<think>plan it</think>\n\nThe answer"), "The answer");
+        assert_eq!(strip_think_tags("A <think>hmm</think>B"), "A B");
+        assert_eq!(strip_think_tags("  indented, no tags"), "  indented, no tags");
+        assert_eq!(strip_think_tags("Answer<think>cut off"), "Answer");
+    }
+
+    #[test]
+    fn test_split_tags_are_recognized_across_deltas() {
+        let mut filter = ThinkTagFilter::default();
+        let out: String = ["<thi", "nk>secret</th", "ink>", "Hello <", "b>"]
+            .iter()
+            .map(|delta| filter.push(delta))
+            .collect::<String>()
+            + &filter.finish();
+        assert_eq!(out, "Hello <b>");
+    }
+
+    #[tokio::test]
+    async fn test_stream_drops_reasoning_field_and_tags() {
+        let mut thinking = text_event("").unwrap();
+        thinking.reasoning = Some("let me think".to_string());
+        let mut last = text_event("").unwrap();
+        last.done = true;
+        let events = futures::stream::iter(vec![
+            Ok(thinking),
+            text_event("<think>inline"),
+            text_event(" thought</think>Hi"),
+            text_event(" there"),
+            Ok(last),
+        ]);
+
+        let kept: Vec<StreamEvent> = strip_reasoning_if(true, events)
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+
+        assert!(kept.iter().all(|event| event.reasoning.is_none()));
+        let text: String = kept.iter().map(|event| event.delta.as_str()).collect();
+        assert_eq!(text, "Hi there");
+        assert!(kept.last().unwrap().done);
+    }
+}
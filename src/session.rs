@@ -97,6 +97,22 @@ fn extract_address(from_value: &str) -> String {
         .to_string()
 }
 
+/// Read back an `X-LLM-Tokens` header (`prompt=11; completion=22; total=33`)
+fn parse_tokens_header(value: &str) -> Option<Usage> {
+    let mut usage = Usage::default();
+    for field in value.split(';') {
+        let (name, count) = field.trim().split_once('=')?;
+        let count = count.trim().parse().ok()?;
+        match name.trim() {
+            "prompt" => usage.prompt_tokens = count,
+            "completion" => usage.completion_tokens = count,
+            "total" => usage.total_tokens = count,
+            _ => {}
+        }
+    }
+    Some(usage)
+}
+
 fn message_content_from_mail(msg: &MailMessage) -> String {
     let mut content = msg.body().trim_end().to_string();
     for attachment in msg.attachments() {
@@ -116,8 +132,11 @@ fn enrich_user_content(content: &str, attachments: &[PathBuf]) -> Result<String>
     let mut merged = content.trim_end().to_string();
 
     for path in attachments {
-        let raw = fs::read(path)?;
-        let text = String::from_utf8_lossy(&raw);
+        let raw = fs::read(path).map_err(|e| anyhow!("cannot read attachment '{}': {}", path.display(), e))?;
+        let text = String::from_utf8(raw)
+            .ok()
+            .filter(|text| !text.contains('\0'))
+            .ok_or_else(|| anyhow!("attachment '{}' is a binary file; only text files can be attached", path.display()))?;
         if !merged.is_empty() {
             merged.push_str("\n\n");
         }
@@ -190,6 +209,8 @@ pub struct Session {
     path: PathBuf,
     history: Vec<Message>,
     system_prompt: Option<String>,
+    /// Tokens used by every recorded assistant turn
+    usage: Usage,
 }
 
 impl Session {
@@ -201,17 +222,24 @@ impl Session {
         fs::create_dir_all(&session_dir)?;
 
         let path = session_dir.join(format!("{}.mbox", name));
-        let history = Self::load_history(&path)?;
+        let (mut history, usage) = Self::load_history(&path)?;
+        // A system prompt set later (`set_system_prompt`) replaces earlier ones
         let system_prompt = history
             .iter()
+            .rev()
             .find(|msg| msg.role == MessageRole::System)
             .and_then(|msg| msg.get_content().map(|s| s.to_string()));
+        history.retain(|msg| msg.role != MessageRole::System);
+        if let Some(content) = &system_prompt {
+            history.insert(0, Message::system(content.clone()));
+        }
 
         Ok(Self {
             name: name.to_string(),
             path,
             history,
             system_prompt,
+            usage,
         })
     }
 
@@ -239,12 +267,17 @@ impl Session {
         PathBuf::from(".emx-llm").join("sessions")
     }
 
-    fn load_history(path: &Path) -> Result<Vec<Message>> {
+    fn load_history(path: &Path) -> Result<(Vec<Message>, Usage)> {
         if !path.exists() {
-            return Ok(Vec::new());
+            return Ok((Vec::new(), Usage::default()));
         }
 
         let mbox = Mbox::load_file(path)?;
+        let usage = mbox
+            .messages()
+            .iter()
+            .filter_map(|mail| mail.header("X-LLM-Tokens").and_then(parse_tokens_header))
+            .fold(Usage::default(), |total, usage| total + usage);
         let messages = mbox
             .messages()
             .iter()
@@ -263,11 +296,12 @@ impl Session {
                     content: MessageContent::Text(content_text),
                     tool_call_id,
                     tool_calls,
+                    cacheable: false,
                 }
             })
             .collect();
 
-        Ok(messages)
+        Ok((messages, usage))
     }
 
     pub fn validate_system_prompt(&self, provided: Option<&str>) -> Result<()> {
@@ -361,6 +395,37 @@ impl Session {
         &self.history
     }
 
+    /// Tokens used by the whole conversation so far
+    pub fn usage(&self) -> &Usage {
+        &self.usage
+    }
+
+    /// Replace the system prompt, keeping the conversation
+    ///
+    /// The new prompt is appended to the session file and supersedes the
+    /// earlier one when the session is reopened.
+    pub fn set_system_prompt(&mut self, content: &str) -> Result<()> {
+        let message = Message::system(content);
+        self.append(&message, None, None, None)?;
+        self.history.retain(|msg| msg.role != MessageRole::System);
+        self.history.insert(0, message);
+        self.system_prompt = Some(content.to_string());
+        Ok(())
+    }
+
+    /// Forget the conversation, deleting the session file
+    ///
+    /// The next `ensure_system_prompt` starts the session afresh.
+    pub fn reset(&mut self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        self.history.clear();
+        self.system_prompt = None;
+        self.usage = Usage::default();
+        Ok(())
+    }
+
     pub fn preview_user_message(&self, content: String, attachments: &[PathBuf]) -> Result<Vec<Message>> {
         let enriched = enrich_user_content(&content, attachments)?;
         let mut messages = self.history.clone();
@@ -368,13 +433,33 @@ impl Session {
         Ok(messages)
     }
 
+    /// Messages for a one-off request that is not recorded in the session
+    ///
+    /// Like `ensure_system_prompt` followed by `preview_user_message`, except
+    /// that a new system prompt only goes into the returned messages and the
+    /// session file is left untouched.
+    pub fn preview_conversation(
+        &self,
+        system_prompt: Option<&str>,
+        content: String,
+        attachments: &[PathBuf],
+    ) -> Result<Vec<Message>> {
+        self.validate_system_prompt(system_prompt)?;
+        let mut messages = self.preview_user_message(content, attachments)?;
+        if self.system_prompt.is_none() {
+            messages.insert(0, Message::system(system_prompt.unwrap_or(DEFAULT_SYSTEM_PROMPT)));
+        }
+        Ok(messages)
+    }
+
     pub fn add_user_message(&mut self, content: String, attachments: &[PathBuf]) -> Result<&[Message]> {
         let domain = get_domain();
 
+        // Enrich first so an unreadable or binary attachment fails before the mbox is touched
+        let enriched = enrich_user_content(&content, attachments)?;
         let mail = build_user_mail(&content, attachments, &domain)?;
         Mbox::append_to_file(&self.path, &mail)?;
 
-        let enriched = enrich_user_content(&content, attachments)?;
         self.history.push(Message::user(enriched));
         Ok(&self.history)
     }
@@ -389,6 +474,7 @@ impl Session {
         let message = Message::assistant(content);
         self.append(&message, Some(model), Some(usage), duration_ms)?;
         self.history.push(message);
+        self.usage.merge(usage);
         Ok(())
     }
 
@@ -410,6 +496,7 @@ impl Session {
         let message = Message::assistant_with_tools(tool_calls);
         self.append(&message, Some(model), Some(usage), duration_ms)?;
         self.history.push(message);
+        self.usage.merge(usage);
         Ok(())
     }
 
@@ -463,6 +550,7 @@ mod tests {
             prompt_tokens: 11,
             completion_tokens: 22,
             total_tokens: 33,
+            ..Default::default()
         };
         session
             .add_assistant_response("world".to_string(), "gpt-4", &usage, Some(3210))
@@ -476,6 +564,38 @@ mod tests {
         assert!(last.from().contains("gpt-4@"));
     }
 
+    #[test]
+    fn reopened_session_sums_recorded_usage() {
+        let _guard = env_lock();
+        let dir = unique_session_dir();
+        std::fs::create_dir_all(&dir).expect("create temp dir");
+        std::env::set_var("EMX_SESSION_DIR", &dir);
+
+        let mut session = Session::open("usage").expect("open session");
+        session.ensure_system_prompt(Some("System")).expect("ensure system");
+        for (prompt, completion) in [(10, 5), (20, 7)] {
+            session.add_user_message("hi".to_string(), &[]).expect("add user");
+            let usage = Usage {
+                prompt_tokens: prompt,
+                completion_tokens: completion,
+                total_tokens: prompt + completion,
+                ..Default::default()
+            };
+            session
+                .add_assistant_response("ok".to_string(), "gpt-4", &usage, None)
+                .expect("add assistant");
+        }
+        assert_eq!(session.usage().total_tokens, 42);
+
+        let mut reopened = Session::open("usage").expect("reopen session");
+        assert_eq!(reopened.usage().prompt_tokens, 30);
+        assert_eq!(reopened.usage().completion_tokens, 12);
+        assert_eq!(reopened.usage().total_tokens, 42);
+
+        reopened.reset().expect("reset");
+        assert_eq!(reopened.usage().total_tokens, 0);
+    }
+
     #[test]
     fn system_prompt_conflict_is_rejected() {
         let _guard = env_lock();
@@ -515,4 +635,86 @@ mod tests {
         assert_eq!(session.messages().len(), before);
         assert_eq!(preview.len(), before + 1);
     }
+
+    #[test]
+    fn preview_conversation_keeps_new_system_prompt_in_memory() {
+        let _guard = env_lock();
+        let dir = unique_session_dir();
+        std::fs::create_dir_all(&dir).expect("create temp dir");
+        std::env::set_var("EMX_SESSION_DIR", &dir);
+
+        let session = Session::open("compare").expect("open session");
+        let preview = session
+            .preview_conversation(Some("System"), "hello".to_string(), &[])
+            .expect("preview");
+
+        assert_eq!(preview.len(), 2);
+        assert_eq!(preview[0].role, MessageRole::System);
+        assert_eq!(preview[0].get_content(), Some("System"));
+        assert!(session.messages().is_empty());
+        assert!(!session.path().exists());
+    }
+
+    #[test]
+    fn reset_allows_a_new_system_prompt() {
+        let _guard = env_lock();
+        let dir = unique_session_dir();
+        std::fs::create_dir_all(&dir).expect("create temp dir");
+        std::env::set_var("EMX_SESSION_DIR", &dir);
+
+        let mut session = Session::open("reset").expect("open session");
+        session
+            .ensure_system_prompt(Some("System A"))
+            .expect("ensure system");
+        session
+            .add_user_message("hello".to_string(), &[])
+            .expect("add user");
+
+        session.reset().expect("reset");
+        session
+            .ensure_system_prompt(Some("System B"))
+            .expect("new system prompt after reset");
+
+        let reopened = Session::open("reset").expect("reopen session");
+        assert_eq!(reopened.messages().len(), 1);
+        assert_eq!(reopened.messages()[0].get_content(), Some("System B"));
+    }
+
+    #[test]
+    fn set_system_prompt_keeps_history() {
+        let _guard = env_lock();
+        let dir = unique_session_dir();
+        std::fs::create_dir_all(&dir).expect("create temp dir");
+        std::env::set_var("EMX_SESSION_DIR", &dir);
+
+        let mut session = Session::open("replace").expect("open session");
+        session
+            .ensure_system_prompt(Some("System A"))
+            .expect("ensure system");
+        session
+            .add_user_message("hello".to_string(), &[])
+            .expect("add user");
+        session.set_system_prompt("System B").expect("replace system prompt");
+
+        let reopened = Session::open("replace").expect("reopen session");
+        let contents: Vec<_> = reopened.messages().iter().map(|m| m.get_content()).collect();
+        assert_eq!(contents, vec![Some("System B"), Some("hello")]);
+        assert!(reopened.validate_system_prompt(Some("System B")).is_ok());
+    }
+
+    #[test]
+    fn binary_attachment_is_rejected() {
+        let dir = unique_session_dir();
+        std::fs::create_dir_all(&dir).expect("create temp dir");
+        let text = dir.join("notes.txt");
+        let binary = dir.join("image.bin");
+        std::fs::write(&text, "plain text").expect("write text");
+        std::fs::write(&binary, [0x89, b'P', b'N', b'G', 0x00, 0xff]).expect("write binary");
+
+        let merged = enrich_user_content("question", &[text.clone()]).expect("text attachment");
+        assert_eq!(merged, "question\n\n[Attachment: notes.txt]\nplain text");
+
+        let err = enrich_user_content("question", &[text, binary]).unwrap_err();
+        assert!(err.to_string().contains("image.bin' is a binary file"));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,236 @@
+//! Server-sent events (SSE) parsing
+//!
+//! [`SseBuffer`] turns the raw bytes of an HTTP response into SSE lines or
+//! whole events as they arrive; [`parse_sse_event`] groups the lines of one
+//! event into an [`SseEvent`]. Both are used by the streaming clients and are usable on
+//! their own for any LLM SSE stream.
+
+/// Parsed SSE line types
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SseLine {
+    /// `data: [DONE]` — OpenAI stream terminator
+    Done,
+    /// `data: <json>` — JSON payload
+    Data(String),
+    /// `event: <name>` — SSE event name
+    Event(String),
+    /// Empty or non-SSE line (skip)
+    Skip,
+}
+
+/// Accumulates bytes from an HTTP response and yields complete SSE lines
+/// ([`next_line`](Self::next_line)) or events ([`next_event`](Self::next_event)).
+///
+/// Use one of the two per stream: lines taken by `next_line` are not seen by
+/// `next_event`.
+#[derive(Debug)]
+pub struct SseBuffer {
+    buf: Vec<u8>,
+    /// Lines of the event read so far, until its terminating blank line
+    pending: Vec<String>,
+}
+
+impl Default for SseBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SseBuffer {
+    /// Create an empty buffer
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::with_capacity(4096),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Append a chunk of response bytes
+    pub fn extend(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// Extract the next complete line (terminated by `\n`) from the buffer.
+    /// Returns `None` when no complete line is available yet.
+    ///
+    /// UTF-8 safety: uses `from_utf8` (strict) instead of `from_utf8_lossy`
+    /// to avoid silently corrupting multi-byte characters split across chunk
+    /// boundaries. Malformed bytes are reported as an error rather than
+    /// replaced with U+FFFD.
+    pub fn next_line(&mut self) -> Option<SseLine> {
+        let pos = self.buf.iter().position(|&b| b == b'\n')?;
+        let raw: Vec<u8> = self.buf.drain(..=pos).collect();
+        let line = match std::str::from_utf8(&raw) {
+            Ok(s) => s.trim().to_string(),
+            Err(_) => {
+                // Server sent non-UTF-8 data — surface as a parseable error
+                // instead of silently corrupting the stream.
+                return Some(SseLine::Data(
+                    r#"{"error":"SSE stream contains invalid UTF-8"}"#.to_string(),
+                ));
+            }
+        };
+
+        if line.is_empty() {
+            return Some(SseLine::Skip);
+        }
+
+        if line == "data: [DONE]" {
+            return Some(SseLine::Done);
+        }
+
+        if let Some(json_str) = line.strip_prefix("data: ") {
+            return Some(SseLine::Data(json_str.to_string()));
+        }
+
+        if let Some(event_name) = line.strip_prefix("event: ") {
+            return Some(SseLine::Event(event_name.to_string()));
+        }
+
+        Some(SseLine::Skip)
+    }
+
+    /// Extract the next complete event (ended by a blank line) from the buffer.
+    /// Returns `None` when no complete event is available yet.
+    ///
+    /// Multi-line `data:` fields are joined per the SSE spec, so a payload
+    /// split across several lines arrives whole. Invalid UTF-8 is reported the
+    /// same way as in [`next_line`](Self::next_line).
+    pub fn next_event(&mut self) -> Option<SseEvent> {
+        loop {
+            let pos = self.buf.iter().position(|&b| b == b'\n')?;
+            let raw: Vec<u8> = self.buf.drain(..=pos).collect();
+            let Ok(line) = String::from_utf8(raw) else {
+                self.pending.clear();
+                return Some(SseEvent {
+                    event: None,
+                    data: r#"{"error":"SSE stream contains invalid UTF-8"}"#.to_string(),
+                });
+            };
+
+            let line = line.trim_end_matches(['\r', '\n']);
+            if !line.is_empty() {
+                self.pending.push(line.to_string());
+            } else if !self.pending.is_empty() {
+                let lines = std::mem::take(&mut self.pending);
+                return Some(parse_sse_event(lines.iter().map(String::as_str)));
+            }
+        }
+    }
+
+    /// Terminate an event the server left open when the stream closed, so
+    /// [`next_event`](Self::next_event) can still return it
+    pub fn finish(&mut self) {
+        self.buf.extend_from_slice(b"\n\n");
+    }
+}
+
+/// One complete SSE event
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SseEvent {
+    /// Value of the `event:` field, if any
+    pub event: Option<String>,
+    /// All `data:` values of the event, joined with `\n`
+    pub data: String,
+}
+
+impl SseEvent {
+    /// Collapse into the single-line form: [`SseLine::Done`] for
+    /// `data: [DONE]`, the data payload, or the event name for an event
+    /// without data
+    pub fn into_line(self) -> SseLine {
+        if self.data == "[DONE]" {
+            SseLine::Done
+        } else if !self.data.is_empty() {
+            SseLine::Data(self.data)
+        } else if let Some(name) = self.event {
+            SseLine::Event(name)
+        } else {
+            SseLine::Skip
+        }
+    }
+}
+
+/// Group the lines of one event (up to, not including, the blank line that
+/// ends it) into an [`SseEvent`]
+///
+/// Follows the SSE spec: one space after the colon is dropped, repeated
+/// `data:` lines are joined with `\n`, and comments (`:` lines) and unknown
+/// fields such as `id:`/`retry:` are ignored.
+pub fn parse_sse_event<'a>(lines: impl IntoIterator<Item = &'a str>) -> SseEvent {
+    let mut event = SseEvent::default();
+    let mut data: Vec<&str> = Vec::new();
+
+    for line in lines {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let (field, value) = match line.split_once(':') {
+            Some(("", _)) => continue, // comment
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "data" => data.push(value),
+            "event" => event.event = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    event.data = data.join("\n");
+    event
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_line_waits_for_complete_lines_across_chunks() {
+        let mut sse = SseBuffer::new();
+        let text = "data: {\"text\":\"héllo\"}\n";
+        let bytes = text.as_bytes();
+        // Split inside the two-byte 'é'
+        let split = text.find('é').unwrap() + 1;
+        sse.extend(&bytes[..split]);
+        assert_eq!(sse.next_line(), None);
+        sse.extend(&bytes[split..]);
+        assert_eq!(sse.next_line(), Some(SseLine::Data("{\"text\":\"héllo\"}".to_string())));
+        assert_eq!(sse.next_line(), None);
+    }
+
+    #[test]
+    fn test_parse_sse_event_joins_repeated_data_lines() {
+        let event = parse_sse_event([
+            "event: completion",
+            ": keep-alive comment",
+            "data: {\"a\":",
+            "data:1}",
+            "id: 7",
+        ]);
+        assert_eq!(event.event.as_deref(), Some("completion"));
+        assert_eq!(event.data, "{\"a\":\n1}");
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&event.data).unwrap()["a"], 1);
+    }
+
+    #[test]
+    fn test_next_event_joins_data_split_across_lines() {
+        let mut sse = SseBuffer::new();
+        sse.extend(b"event: delta\ndata: {\"text\":\n");
+        assert_eq!(sse.next_event(), None);
+        sse.extend(b"data: \"hi\"}\n\n\ndata: [DONE]\n");
+        let event = sse.next_event().unwrap();
+        assert_eq!(event.event.as_deref(), Some("delta"));
+        assert_eq!(event.data, "{\"text\":\n\"hi\"}");
+        assert_eq!(sse.next_event(), None);
+
+        sse.finish();
+        assert_eq!(sse.next_event().map(SseEvent::into_line), Some(SseLine::Done));
+        assert_eq!(sse.next_event(), None);
+    }
+
+    #[test]
+    fn test_parse_sse_event_keeps_empty_data_lines() {
+        let event = parse_sse_event(["data: first", "data", "data: third\r"]);
+        assert_eq!(event.event, None);
+        assert_eq!(event.data, "first\n\nthird");
+    }
+}
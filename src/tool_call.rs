@@ -0,0 +1,175 @@
+//! Reassembling streamed tool calls
+//!
+//! Streaming providers send a tool call's JSON arguments in fragments spread
+//! over many chunks, interleaved across calls by index. [`ToolCallAccumulator`]
+//! collects [`ToolCallDelta`]s and hands back each call as a
+//! [`CompletedToolCall`] once its arguments parse, or when the stream ends.
+
+use crate::{Result, ToolCall};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// One streamed fragment of a tool call
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToolCallDelta {
+    /// Position of the call in the response; fragments of one call share it
+    pub index: usize,
+    /// Call id, usually only on the first fragment
+    pub id: Option<String>,
+    /// Function name, usually only on the first fragment
+    pub name: Option<String>,
+    /// Next piece of the JSON arguments
+    pub arguments: String,
+}
+
+/// A fully received tool call with parsed arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompletedToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: Value,
+}
+
+impl From<CompletedToolCall> for ToolCall {
+    fn from(call: CompletedToolCall) -> Self {
+        ToolCall {
+            id: call.id,
+            name: call.name,
+            arguments: call.arguments.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PendingCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+/// Collects [`ToolCallDelta`]s into [`CompletedToolCall`]s
+#[derive(Debug, Default)]
+pub struct ToolCallAccumulator {
+    pending: BTreeMap<usize, PendingCall>,
+}
+
+impl ToolCallAccumulator {
+    /// Create an empty accumulator
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a fragment, returning its call if the arguments are now a
+    /// complete JSON object
+    pub fn push(&mut self, delta: ToolCallDelta) -> Option<CompletedToolCall> {
+        let call = self.pending.entry(delta.index).or_default();
+        if let Some(id) = delta.id {
+            call.id = id;
+        }
+        if let Some(name) = delta.name {
+            call.name = name;
+        }
+        call.arguments.push_str(&delta.arguments);
+
+        // Only an object can be the whole argument list; a prefix of the
+        // fragments never parses as one
+        if call.name.is_empty() {
+            return None;
+        }
+        match serde_json::from_str::<Value>(&call.arguments) {
+            Ok(arguments @ Value::Object(_)) => {
+                let call = self.pending.remove(&delta.index)?;
+                Some(CompletedToolCall { id: call.id, name: call.name, arguments })
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether any call is still waiting for fragments
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// End of stream: the calls still pending, in index order
+    ///
+    /// A call that never received arguments gets an empty object; one whose
+    /// arguments still do not parse is an error.
+    pub fn finish(self) -> Result<Vec<CompletedToolCall>> {
+        self.pending
+            .into_values()
+            .map(|call| {
+                let arguments = if call.arguments.trim().is_empty() {
+                    Value::Object(Default::default())
+                } else {
+                    serde_json::from_str(&call.arguments)?
+                };
+                Ok(CompletedToolCall { id: call.id, name: call.name, arguments })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fragment(index: usize, arguments: &str) -> ToolCallDelta {
+        ToolCallDelta { index, arguments: arguments.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_reassembles_multi_chunk_call() {
+        let mut accumulator = ToolCallAccumulator::new();
+        let first = ToolCallDelta {
+            index: 0,
+            id: Some("call_1".to_string()),
+            name: Some("get_weather".to_string()),
+            arguments: String::new(),
+        };
+        assert_eq!(accumulator.push(first), None);
+        assert_eq!(accumulator.push(fragment(0, "{\"city\": \"Par")), None);
+        assert_eq!(accumulator.push(fragment(0, "is\", \"days\": ")), None);
+
+        let call = accumulator.push(fragment(0, "3}")).expect("call completes");
+        assert_eq!(call.id, "call_1");
+        assert_eq!(call.name, "get_weather");
+        assert_eq!(call.arguments, json!({"city": "Paris", "days": 3}));
+        assert!(accumulator.is_empty());
+        assert!(accumulator.finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_interleaved_calls_complete_independently() {
+        let mut accumulator = ToolCallAccumulator::new();
+        for (index, id, name) in [(0, "call_a", "search"), (1, "call_b", "lookup")] {
+            accumulator.push(ToolCallDelta {
+                index,
+                id: Some(id.to_string()),
+                name: Some(name.to_string()),
+                arguments: String::new(),
+            });
+        }
+        assert_eq!(accumulator.push(fragment(0, "{\"q\":")), None);
+        assert_eq!(accumulator.push(fragment(1, "{\"key\":")), None);
+
+        let b = accumulator.push(fragment(1, "\"k1\"}")).expect("second call completes");
+        assert_eq!((b.id.as_str(), b.name.as_str()), ("call_b", "lookup"));
+        assert_eq!(b.arguments, json!({"key": "k1"}));
+
+        let a = accumulator.push(fragment(0, "\"rust\"}")).expect("first call completes");
+        assert_eq!(ToolCall::from(a).arguments, r#"{"q":"rust"}"#);
+    }
+
+    #[test]
+    fn test_finish_flushes_pending_calls() {
+        let mut accumulator = ToolCallAccumulator::new();
+        accumulator.push(ToolCallDelta { index: 0, name: Some("now".to_string()), ..Default::default() });
+        let calls = accumulator.finish().unwrap();
+        assert_eq!(calls[0].arguments, json!({}));
+
+        let mut accumulator = ToolCallAccumulator::new();
+        accumulator.push(ToolCallDelta { index: 0, name: Some("cut".to_string()), ..fragment(0, "{\"a\": 1") });
+        assert!(accumulator.finish().is_err());
+    }
+}
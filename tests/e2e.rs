@@ -3,23 +3,48 @@
 //! End-to-end tests using emx-testspec framework
 
 use emx_testspec::{RunConfig, TestRunner};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+/// Held while a script runs: the scripts share the gateway port (8848) and
+/// the mock upstream ports, and clean up with `pkill -f emx-gate`, so two
+/// running at once under cargo's parallel test threads break each other
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// Directory holding the `emx-gate` and `emx-llm` binaries under test
+///
+/// Cargo reports where it built them when the test is compiled with the
+/// binaries' features (`--features gate`); otherwise fall back to the target
+/// directory of the current profile.
+fn bin_dir() -> PathBuf {
+    option_env!("CARGO_BIN_EXE_emx-gate")
+        .or(option_env!("CARGO_BIN_EXE_emx-llm"))
+        .and_then(|exe| Path::new(exe).parent().map(Path::to_path_buf))
+        .unwrap_or_else(|| {
+            let target = std::env::var_os("CARGO_TARGET_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("target"));
+            target.join(if cfg!(debug_assertions) { "debug" } else { "release" })
+        })
+}
 
 fn run_e2e_tests(filter: Option<String>) {
-    let emx_llm_path = std::path::PathBuf::from("/s/src.editor/workspace/emx-llm/target/debug");
+    // A failed script poisons the lock; the next one can still run
+    let _serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+    let emx_llm_path = bin_dir();
 
     let config = RunConfig {
-        dir: "tests/e2e".into(),
+        dir: Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/e2e"),
         filter,
         workdir_root: None,
         preserve_work: false,
         verbose: std::env::var("E2E_VERBOSE").is_ok(),
         extensions: vec![".txtar".into()],
         setup: Some(Box::new(move |setup_env: &mut emx_testspec::SetupEnv| {
-            let current_path = std::env::var("PATH").unwrap_or_default();
-            setup_env.env.push((
-                "PATH".to_string(),
-                format!("{}:{}", emx_llm_path.display(), current_path),
-            ));
+            let current_path = std::env::var_os("PATH").unwrap_or_default();
+            let paths = std::iter::once(emx_llm_path.clone()).chain(std::env::split_paths(&current_path));
+            let path = std::env::join_paths(paths).expect("binary directory is not a valid PATH entry");
+            setup_env.env.push(("PATH".to_string(), path.to_string_lossy().into_owned()));
             Ok(())
         })),
     };
@@ -45,6 +70,12 @@ fn run_e2e_tests(filter: Option<String>) {
     assert!(result.all_passed(), "Some E2E tests failed");
 }
 
+#[test]
+fn test_bin_dir_exists() {
+    let dir = bin_dir();
+    assert!(dir.is_dir(), "binary directory {} does not exist", dir.display());
+}
+
 #[test]
 fn test_e2e_health_check() {
     run_e2e_tests(Some("001".to_string()));
@@ -69,3 +100,148 @@ fn test_e2e_list_endpoints() {
 fn test_e2e_error_handling() {
     run_e2e_tests(Some("005".to_string()));
 }
+
+#[test]
+fn test_e2e_body_size_limit() {
+    run_e2e_tests(Some("006".to_string()));
+}
+
+#[test]
+fn test_e2e_usage_accounting() {
+    run_e2e_tests(Some("007".to_string()));
+}
+
+#[test]
+fn test_e2e_cors() {
+    run_e2e_tests(Some("008".to_string()));
+}
+
+#[test]
+fn test_e2e_anthropic_from_openai_stream() {
+    run_e2e_tests(Some("009".to_string()));
+}
+
+#[test]
+fn test_e2e_idempotency_key() {
+    run_e2e_tests(Some("010".to_string()));
+}
+
+#[test]
+fn test_e2e_metrics() {
+    run_e2e_tests(Some("011".to_string()));
+}
+
+#[test]
+fn test_e2e_chat_stream_newline() {
+    run_e2e_tests(Some("012".to_string()));
+}
+
+#[test]
+fn test_e2e_chat_endpoint() {
+    run_e2e_tests(Some("013".to_string()));
+}
+
+#[test]
+fn test_e2e_chat_api_base() {
+    run_e2e_tests(Some("014".to_string()));
+}
+
+#[test]
+fn test_e2e_chat_show_thinking() {
+    run_e2e_tests(Some("015".to_string()));
+}
+
+#[test]
+fn test_e2e_model_fallback() {
+    run_e2e_tests(Some("016".to_string()));
+}
+
+#[test]
+fn test_e2e_sse_keepalive() {
+    run_e2e_tests(Some("017".to_string()));
+}
+
+#[test]
+fn test_e2e_system_prompt_injection() {
+    run_e2e_tests(Some("018".to_string()));
+}
+
+#[test]
+fn test_e2e_chat_repl() {
+    run_e2e_tests(Some("019".to_string()));
+}
+
+#[test]
+fn test_e2e_request_field_filter() {
+    run_e2e_tests(Some("020".to_string()));
+}
+
+#[test]
+fn test_e2e_list_models_filter() {
+    run_e2e_tests(Some("021".to_string()));
+}
+
+#[test]
+fn test_e2e_chat_attach() {
+    run_e2e_tests(Some("022".to_string()));
+}
+
+#[test]
+fn test_e2e_provider_proxy() {
+    run_e2e_tests(Some("023".to_string()));
+}
+
+#[test]
+fn test_e2e_max_tokens_clamp() {
+    run_e2e_tests(Some("024".to_string()));
+}
+
+#[test]
+fn test_e2e_unknown_model_404() {
+    run_e2e_tests(Some("025".to_string()));
+}
+
+#[test]
+fn test_e2e_audit_log() {
+    run_e2e_tests(Some("026".to_string()));
+}
+
+#[test]
+fn test_e2e_coalesce_stream() {
+    run_e2e_tests(Some("027".to_string()));
+}
+
+#[test]
+fn test_e2e_anthropic_passthrough() {
+    run_e2e_tests(Some("028".to_string()));
+}
+
+#[test]
+fn test_e2e_stream_client_disconnect() {
+    run_e2e_tests(Some("029".to_string()));
+}
+
+#[test]
+fn test_e2e_llm_info() {
+    run_e2e_tests(Some("030".to_string()));
+}
+
+#[test]
+fn test_e2e_strip_ping_events() {
+    run_e2e_tests(Some("031".to_string()));
+}
+
+#[test]
+fn test_e2e_inbound_request_params() {
+    run_e2e_tests(Some("032".to_string()));
+}
+
+#[test]
+fn test_e2e_forward_response_headers() {
+    run_e2e_tests(Some("033".to_string()));
+}
+
+#[test]
+fn test_e2e_chat_session_resume() {
+    run_e2e_tests(Some("034".to_string()));
+}
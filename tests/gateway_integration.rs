@@ -0,0 +1,477 @@
+//! Gateway integration tests
+//!
+//! Boots the real gateway in-process on a free port, in front of a `wiremock`
+//! upstream, and talks to it over HTTP. Unlike the txtar suite in `e2e.rs`,
+//! this needs no prebuilt binaries, python or external test framework, so it
+//! runs anywhere `cargo test --features gate` does.
+//!
+//! The gateway reads its provider settings from `./config.toml`, so each test
+//! runs in a temporary directory holding one; a lock keeps tests from
+//! changing the working directory under each other.
+#![cfg(feature = "gate")]
+
+use emx_llm::gate::config::GatewayConfig;
+use emx_llm::gate::router::{ModelResolver, ResolvedModel};
+use emx_llm::gate::server::{start_server, start_server_until, start_server_with_resolver};
+use emx_llm::gate::transform::RequestTransform;
+use emx_llm::{ProviderConfig, ProviderType};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, MutexGuard};
+use tokio::task::JoinHandle;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// Serializes the tests, which all change the process working directory
+fn cwd_lock() -> &'static Mutex<()> {
+    static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+    LOCK.get_or_init(|| Mutex::new(()))
+}
+
+/// A gateway running against `upstream`, from its own config directory
+struct Gateway {
+    base_url: String,
+    upstream: MockServer,
+    server: JoinHandle<anyhow::Result<()>>,
+    dir: PathBuf,
+    previous_dir: PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Gateway {
+    async fn start() -> Self {
+        Self::launch(|port, _| tokio::spawn(start_server(GatewayConfig { port, ..GatewayConfig::default() }))).await
+    }
+
+    /// Start with `config`, shutting down when `shutdown` completes
+    async fn start_until(config: GatewayConfig, shutdown: impl Future<Output = ()> + Send + 'static) -> Self {
+        Self::launch(|port, _| tokio::spawn(start_server_until(GatewayConfig { port, ..config }, shutdown))).await
+    }
+
+    /// Start with the resolver `make_resolver` builds for the upstream URI
+    async fn start_with_resolver(make_resolver: impl FnOnce(&str) -> Arc<dyn ModelResolver>) -> Self {
+        Self::start_with_transforms(Vec::new(), make_resolver).await
+    }
+
+    /// Start with extra request `transforms` and the resolver `make_resolver`
+    /// builds for the upstream URI
+    async fn start_with_transforms(
+        transforms: Vec<Box<dyn RequestTransform>>,
+        make_resolver: impl FnOnce(&str) -> Arc<dyn ModelResolver>,
+    ) -> Self {
+        Self::launch(|port, upstream| {
+            let config = GatewayConfig { port, ..GatewayConfig::default() };
+            tokio::spawn(start_server_with_resolver(config, transforms, make_resolver(upstream)))
+        })
+        .await
+    }
+
+    async fn launch(spawn: impl FnOnce(u16, &str) -> JoinHandle<anyhow::Result<()>>) -> Self {
+        let lock = cwd_lock().lock().await;
+        let upstream = MockServer::start().await;
+
+        let dir = std::env::temp_dir().join(format!("emx-gate-it-{}", unique_suffix()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = format!(
+            "[llm.provider]\ntype = \"openai\"\n\n\
+             [llm.provider.openai]\napi_base = \"{}/v1\"\napi_key = \"mock\"\n\n\
+             [llm.provider.openai.mock]\nmodel = \"mock-model\"\n\n\
+             [llm.provider.openai.gpt-4]\nmodel = \"glm-4-plus\"\n",
+            upstream.uri()
+        );
+        std::fs::write(dir.join("config.toml"), config).unwrap();
+        let previous_dir = std::env::current_dir().unwrap();
+        std::env::set_current_dir(&dir).unwrap();
+
+        let port = free_port();
+        let server = spawn(port, &upstream.uri());
+        let base_url = format!("http://127.0.0.1:{}", port);
+        wait_until_healthy(&base_url).await;
+
+        Gateway {
+            base_url,
+            upstream,
+            server,
+            dir,
+            previous_dir,
+            _lock: lock,
+        }
+    }
+
+    async fn post_chat(&self, body: Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/openai/v1/chat/completions", self.base_url))
+            .json(&body)
+            .send()
+            .await
+            .unwrap()
+    }
+}
+
+impl Drop for Gateway {
+    fn drop(&mut self) {
+        self.server.abort();
+        let _ = std::env::set_current_dir(&self.previous_dir);
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Distinct suffix for the temporary config directory of each test
+fn unique_suffix() -> String {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    format!("{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// A port nothing listens on right now
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+async fn wait_until_healthy(base_url: &str) {
+    let client = reqwest::Client::new();
+    for _ in 0..50 {
+        if let Ok(response) = client.get(format!("{}/health", base_url)).send().await {
+            if response.status().is_success() {
+                return;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("gateway at {} did not become healthy", base_url);
+}
+
+#[tokio::test]
+async fn test_openai_chat_is_forwarded() {
+    let gateway = Gateway::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .and(body_partial_json(json!({"model": "mock-model"})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "chatcmpl-upstream",
+            "object": "chat.completion",
+            "model": "mock-model",
+            "system_fingerprint": "fp_mock",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Hello from upstream"},
+                "finish_reason": "stop"
+            }],
+            "usage": {"prompt_tokens": 3, "completion_tokens": 3, "total_tokens": 6}
+        })))
+        .expect(1)
+        .mount(&gateway.upstream)
+        .await;
+
+    let response = gateway
+        .post_chat(json!({"model": "mock", "messages": [{"role": "user", "content": "Hello"}]}))
+        .await;
+
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["choices"][0]["message"]["content"], "Hello from upstream");
+    // Passthrough keeps fields the gateway does not model
+    assert_eq!(body["system_fingerprint"], "fp_mock");
+    assert_eq!(body["id"], "chatcmpl-upstream");
+}
+
+#[tokio::test]
+async fn test_openai_chat_stream_is_forwarded() {
+    let gateway = Gateway::start().await;
+    let sse = concat!(
+        "data: {\"id\":\"chatcmpl-upstream\",\"object\":\"chat.completion.chunk\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hel\"}}]}\n\n",
+        "data: {\"id\":\"chatcmpl-upstream\",\"object\":\"chat.completion.chunk\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"lo\"},\"finish_reason\":\"stop\"}]}\n\n",
+        "data: [DONE]\n\n",
+    );
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .and(body_partial_json(json!({"model": "mock-model", "stream": true})))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(sse, "text/event-stream"))
+        .expect(1)
+        .mount(&gateway.upstream)
+        .await;
+
+    let response = gateway
+        .post_chat(json!({
+            "model": "mock",
+            "stream": true,
+            "messages": [{"role": "user", "content": "Hello"}]
+        }))
+        .await;
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let body = response.text().await.unwrap();
+    assert!(body.contains("\"content\":\"Hel\""), "{}", body);
+    assert!(body.contains("\"content\":\"lo\""), "{}", body);
+    assert!(body.trim_end().ends_with("data: [DONE]"), "{}", body);
+}
+
+#[tokio::test]
+async fn test_alias_is_sent_upstream_as_configured_model_id() {
+    let gateway = Gateway::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "chatcmpl-upstream",
+            "object": "chat.completion",
+            "model": "glm-4-plus",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "ok"},
+                "finish_reason": "stop"
+            }],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+        })))
+        .expect(1)
+        .mount(&gateway.upstream)
+        .await;
+
+    let response = gateway
+        .post_chat(json!({"model": "gpt-4", "messages": [{"role": "user", "content": "Hello"}]}))
+        .await;
+
+    assert_eq!(response.status(), 200);
+    let requests = gateway.upstream.received_requests().await.unwrap();
+    let sent: Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(sent["model"], "glm-4-plus");
+}
+
+#[tokio::test]
+async fn test_unknown_model_is_not_found() {
+    let gateway = Gateway::start().await;
+
+    let response = gateway
+        .post_chat(json!({"model": "nope", "messages": [{"role": "user", "content": "Hello"}]}))
+        .await;
+
+    assert_eq!(response.status(), 404);
+}
+
+/// Serves `house-special`, a model no config file knows, from the mock
+/// upstream's API for `provider_type` (OpenAI-compatible unless set) on
+/// either endpoint
+struct HouseResolver {
+    api_base: String,
+    provider_type: ProviderType,
+}
+
+impl HouseResolver {
+    fn openai(api_base: String) -> Self {
+        Self { api_base, provider_type: ProviderType::OpenAI }
+    }
+}
+
+impl ModelResolver for HouseResolver {
+    fn resolve(&self, model: &str, _provider_type: ProviderType) -> Result<ResolvedModel, String> {
+        if model != "house-special" {
+            return Err(format!("Unknown model: {}", model));
+        }
+        Ok(ResolvedModel {
+            provider_type: self.provider_type,
+            model_name: "house-model-v2".to_string(),
+            model_ref: format!("{}.house-special", self.provider_type.config_key()),
+            config: ProviderConfig {
+                provider_type: self.provider_type,
+                api_base: self.api_base.clone(),
+                api_key: "house-key".to_string(),
+                model: Some("house-model-v2".to_string()),
+                ..Default::default()
+            },
+            max_output_tokens: None,
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_custom_resolver_serves_model_missing_from_config() {
+    let gateway = Gateway::start_with_resolver(|upstream| {
+        Arc::new(HouseResolver::openai(format!("{}/house/v1", upstream)))
+    })
+    .await;
+    Mock::given(method("POST"))
+        .and(path("/house/v1/chat/completions"))
+        .and(body_partial_json(json!({"model": "house-model-v2"})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "chatcmpl-house",
+            "object": "chat.completion",
+            "model": "house-model-v2",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "from the house"},
+                "finish_reason": "stop"
+            }],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 3, "total_tokens": 4}
+        })))
+        .expect(1)
+        .mount(&gateway.upstream)
+        .await;
+
+    let response = gateway
+        .post_chat(json!({"model": "house-special", "messages": [{"role": "user", "content": "Hello"}]}))
+        .await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["choices"][0]["message"]["content"], "from the house");
+
+    let requests = gateway.upstream.received_requests().await.unwrap();
+    assert_eq!(requests[0].headers.get("authorization").unwrap(), "Bearer house-key");
+
+    // Models only the config file knows are not served by this resolver
+    let response = gateway
+        .post_chat(json!({"model": "gpt-4", "messages": [{"role": "user", "content": "Hello"}]}))
+        .await;
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn test_anthropic_request_for_openai_backed_model_is_translated() {
+    let gateway = Gateway::start_with_resolver(|upstream| {
+        Arc::new(HouseResolver::openai(format!("{}/house/v1", upstream)))
+    })
+    .await;
+    Mock::given(method("POST"))
+        .and(path("/house/v1/chat/completions"))
+        .and(body_partial_json(json!({"model": "house-model-v2", "max_tokens": 32})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "chatcmpl-house",
+            "object": "chat.completion",
+            "model": "house-model-v2",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "from the house"},
+                "finish_reason": "stop"
+            }],
+            "usage": {"prompt_tokens": 5, "completion_tokens": 3, "total_tokens": 8}
+        })))
+        .expect(1)
+        .mount(&gateway.upstream)
+        .await;
+
+    // No `openai.` prefix: the resolved provider decides the translation
+    let response = reqwest::Client::new()
+        .post(format!("{}/anthropic/v1/messages", gateway.base_url))
+        .json(&json!({
+            "model": "house-special",
+            "max_tokens": 32,
+            "messages": [{"role": "user", "content": "Hello"}]
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["type"], "message");
+    assert_eq!(body["content"][0]["text"], "from the house");
+    assert_eq!(body["usage"]["input_tokens"], 5);
+}
+
+/// Tags every request bound for an Anthropic upstream with a `metadata.user_id`
+struct TagUser;
+
+impl RequestTransform for TagUser {
+    fn transform(&self, body: &mut Value, provider_type: ProviderType) {
+        if provider_type == ProviderType::Anthropic {
+            body["metadata"] = json!({"user_id": "gate"});
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_transformed_anthropic_body_reaches_upstream() {
+    let gateway = Gateway::start_with_transforms(vec![Box::new(TagUser)], |upstream| {
+        Arc::new(HouseResolver {
+            api_base: format!("{}/house", upstream),
+            provider_type: ProviderType::Anthropic,
+        })
+    })
+    .await;
+    Mock::given(method("POST"))
+        .and(path("/house/v1/messages"))
+        .and(body_partial_json(json!({
+            "model": "house-model-v2",
+            "temperature": 0.5,
+            "metadata": {"user_id": "gate"}
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "msg_house",
+            "type": "message",
+            "role": "assistant",
+            "model": "house-model-v2",
+            "content": [{"type": "text", "text": "tagged"}],
+            "stop_reason": "end_turn",
+            "usage": {"input_tokens": 4, "output_tokens": 1}
+        })))
+        .expect(1)
+        .mount(&gateway.upstream)
+        .await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/anthropic/v1/messages", gateway.base_url))
+        .json(&json!({
+            "model": "house-special",
+            "max_tokens": 32,
+            "temperature": 0.5,
+            "messages": [{"role": "user", "content": "Hello"}]
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["content"][0]["text"], "tagged");
+}
+
+#[tokio::test]
+async fn test_shutdown_does_not_wait_past_drain_window() {
+    let (trigger, signal) = tokio::sync::oneshot::channel::<()>();
+    let mut gateway = Gateway::start_until(
+        GatewayConfig { shutdown_drain_secs: 1, ..GatewayConfig::default() },
+        async move {
+            let _ = signal.await;
+        },
+    )
+    .await;
+    // An upstream that never answers in time keeps the request in flight
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(60)))
+        .mount(&gateway.upstream)
+        .await;
+
+    let url = format!("{}/openai/v1/chat/completions", gateway.base_url);
+    let hung = tokio::spawn(async move {
+        let _ = reqwest::Client::new()
+            .post(url)
+            .json(&json!({"model": "mock", "messages": [{"role": "user", "content": "Hello"}]}))
+            .send()
+            .await;
+    });
+    for _ in 0..50 {
+        if !gateway.upstream.received_requests().await.unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let started = Instant::now();
+    trigger.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(10), &mut gateway.server)
+        .await
+        .expect("gateway kept running past its drain window")
+        .unwrap()
+        .unwrap();
+
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_secs(1), "returned before draining: {:?}", elapsed);
+    hung.abort();
+}